@@ -0,0 +1,240 @@
+//! Measures how long a quote takes to reflect an account update, from the
+//! moment the update is received to the moment a fresh quote is available.
+//!
+//! cargo run --release --example latency-probe -- [--updates <n>] [--rate <hz>]
+//!     [--in-atoms <n>] [snapshot ...]
+//!
+//! A feed thread stands in for the Geyser or websocket subscriber. It stamps
+//! each update with its receipt time and hands the raw account data over a
+//! channel, the way a subscription callback would. The quoting thread decodes
+//! it, keeps the newest copy per market and quotes both directions. Snapshots
+//! are raw market account data as written by `solana account <market>
+//! --output-file <path>` and are replayed in a loop. Without snapshots, books
+//! are generated with the model. To measure a deployment, replace `feed` with
+//! the subscription callback and keep the rest.
+
+use anyhow::{bail, Context, Result};
+use manifest::{
+    quantities::{BaseAtoms, QuoteAtomsPerBaseAtom, WrapperU64},
+    state::OrderType,
+};
+use manifest_jupiter::{
+    model::{ModelOperation, ModelRunner},
+    quoting::exact_quote,
+    snapshot::load_market_snapshot,
+    sync::{StampedMarket, StateStamp},
+};
+use solana_program::pubkey::Pubkey;
+use std::{
+    collections::HashMap,
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+const USAGE: &str =
+    "Usage: latency-probe [--updates <n>] [--rate <hz>] [--in-atoms <n>] [snapshot ...]";
+
+/// Books generated when no snapshots are given. Generated up front so the
+/// model does not slow down the feed.
+const NUM_GENERATED_BOOKS: usize = 1_000;
+
+struct AccountUpdate {
+    market_key: Pubkey,
+    stamp: StateStamp,
+    data: Vec<u8>,
+    received_at: Instant,
+}
+
+#[derive(Default)]
+struct Latencies {
+    decode: Vec<Duration>,
+    quote: Vec<Duration>,
+    end_to_end: Vec<Duration>,
+}
+
+fn market_data(runner: &ModelRunner) -> Vec<u8> {
+    [
+        bytemuck::bytes_of(&runner.market.fixed),
+        &runner.market.dynamic[..],
+    ]
+    .concat()
+}
+
+/// Books of a market where three traders place and cancel orders around a
+/// price of 1, one book after each successful operation.
+fn generate_books() -> Result<Vec<Vec<u8>>> {
+    let mut runner: ModelRunner = ModelRunner::new(
+        &Pubkey::new_unique(),
+        &Pubkey::new_unique(),
+        &Pubkey::new_unique(),
+    );
+    let traders: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+    for trader in traders.iter() {
+        runner.claim_seat(trader)?;
+        for is_base in [true, false] {
+            runner.apply(&ModelOperation::Deposit {
+                trader: *trader,
+                amount_atoms: 1_000_000_000,
+                is_base,
+            })?;
+        }
+    }
+    let mut books: Vec<Vec<u8>> = Vec::with_capacity(NUM_GENERATED_BOOKS);
+    let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+    let mut next_random = |n: u64| -> u64 {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed % n
+    };
+    let mut next_sequence_number: u64 = 0;
+    while books.len() < NUM_GENERATED_BOOKS {
+        let trader: Pubkey = traders[next_random(traders.len() as u64) as usize];
+        let operation: ModelOperation = if next_random(4) == 0 && next_sequence_number > 0 {
+            ModelOperation::CancelOrder {
+                trader,
+                order_sequence_number: next_random(next_sequence_number),
+            }
+        } else {
+            next_sequence_number += 1;
+            ModelOperation::PlaceOrder {
+                trader,
+                num_base_atoms: BaseAtoms::new(1 + next_random(1_000)),
+                price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(
+                    90 + next_random(20) as u32,
+                    -2,
+                )?,
+                is_bid: next_random(2) == 0,
+                order_type: OrderType::Limit,
+            }
+        };
+        if runner.apply(&operation).is_ok() {
+            books.push(market_data(&runner));
+        }
+    }
+    Ok(books)
+}
+
+fn feed(books: Vec<Vec<u8>>, num_updates: usize, rate_hz: u64, sender: Sender<AccountUpdate>) {
+    let market_key: Pubkey = Pubkey::new_unique();
+    let interval: Duration = Duration::from_secs(1) / rate_hz.max(1) as u32;
+    let start: Instant = Instant::now();
+    for update_index in 0..num_updates {
+        let due: Instant = start + interval * update_index as u32;
+        if let Some(wait) = due.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+        let update: AccountUpdate = AccountUpdate {
+            market_key,
+            stamp: StateStamp {
+                slot: update_index as u64,
+                write_version: 0,
+            },
+            data: books[update_index % books.len()].clone(),
+            received_at: Instant::now(),
+        };
+        if sender.send(update).is_err() {
+            return;
+        }
+    }
+}
+
+fn run_quoter(receiver: Receiver<AccountUpdate>, in_atoms: u64) -> Result<Latencies> {
+    let mut markets: HashMap<Pubkey, StampedMarket> = HashMap::new();
+    let mut latencies: Latencies = Latencies::default();
+    for update in receiver {
+        let decode_start: Instant = Instant::now();
+        let market: StampedMarket =
+            StampedMarket::new(update.stamp, load_market_snapshot(&update.data)?);
+        let is_newer: bool = match markets.get_mut(&update.market_key) {
+            Some(stored) => stored.update(market),
+            None => {
+                markets.insert(update.market_key, market);
+                true
+            }
+        };
+        let quote_start: Instant = Instant::now();
+        latencies.decode.push(quote_start - decode_start);
+        if !is_newer {
+            continue;
+        }
+
+        let stored: &StampedMarket = &markets[&update.market_key];
+        let now_slot: u32 = stored.stamp.slot as u32;
+        std::hint::black_box(exact_quote(&stored.value, true, in_atoms, now_slot)?);
+        std::hint::black_box(exact_quote(&stored.value, false, in_atoms, now_slot)?);
+        let done: Instant = Instant::now();
+        latencies.quote.push(done - quote_start);
+        latencies.end_to_end.push(done - update.received_at);
+    }
+    Ok(latencies)
+}
+
+fn print_percentiles(name: &str, samples: &mut [Duration]) {
+    if samples.is_empty() {
+        println!("{name:<12} no samples");
+        return;
+    }
+    samples.sort_unstable();
+    let percentile = |p: f64| -> f64 {
+        let index: usize = ((samples.len() - 1) as f64 * p).round() as usize;
+        samples[index].as_secs_f64() * 1e6
+    };
+    println!(
+        "{name:<12} p50 {:>9.1}  p90 {:>9.1}  p99 {:>9.1}  p99.9 {:>9.1}  max {:>9.1}",
+        percentile(0.5),
+        percentile(0.9),
+        percentile(0.99),
+        percentile(0.999),
+        percentile(1.0),
+    );
+}
+
+fn main() -> Result<()> {
+    let mut num_updates: usize = 10_000;
+    let mut rate_hz: u64 = 1_000;
+    let mut in_atoms: u64 = 1_000;
+    let mut paths: Vec<String> = Vec::new();
+    let mut args_iter = std::env::args().skip(1);
+    while let Some(arg) = args_iter.next() {
+        let mut value = |name: &str| -> Result<u64> {
+            let Some(value) = args_iter.next() else {
+                bail!(USAGE);
+            };
+            value
+                .parse::<u64>()
+                .with_context(|| format!("Invalid {name} {value}"))
+        };
+        match arg.as_str() {
+            "--updates" => num_updates = value("--updates")? as usize,
+            "--rate" => rate_hz = value("--rate")?,
+            "--in-atoms" => in_atoms = value("--in-atoms")?,
+            _ if arg.starts_with("--") => bail!(USAGE),
+            _ => paths.push(arg),
+        }
+    }
+
+    let books: Vec<Vec<u8>> = if paths.is_empty() {
+        generate_books()?
+    } else {
+        paths
+            .iter()
+            .map(|path| std::fs::read(path).with_context(|| format!("Failed to read {path}")))
+            .collect::<Result<Vec<Vec<u8>>>>()?
+    };
+    for (data, path) in books.iter().zip(paths.iter()) {
+        load_market_snapshot(data).with_context(|| format!("Failed to load {path}"))?;
+    }
+
+    let (sender, receiver) = channel::<AccountUpdate>();
+    let feed_handle = thread::spawn(move || feed(books, num_updates, rate_hz, sender));
+    let mut latencies: Latencies = run_quoter(receiver, in_atoms)?;
+    feed_handle.join().expect("Feed thread panicked");
+
+    println!("{num_updates} updates at {rate_hz} Hz, quoting {in_atoms} atoms in, microseconds");
+    print_percentiles("decode", &mut latencies.decode);
+    print_percentiles("quote", &mut latencies.quote);
+    print_percentiles("end to end", &mut latencies.end_to_end);
+    Ok(())
+}
@@ -0,0 +1,1107 @@
+//! Jupiter `Amm` implementation for Manifest markets. `ManifestMarket` keeps
+//! a `MarketValue` and its globals up to date from the accounts Jupiter
+//! fetches and quotes with the impact functions of the program.
+
+use anyhow::{Error, Result};
+use jupiter_amm_interface::{
+    AccountMap, Amm, AmmContext, KeyedAccount, Quote, QuoteParams, Side, Swap, SwapAndAccountMetas,
+    SwapParams,
+};
+
+use hypertree::get_mut_helper;
+use manifest::{
+    program::swap_instruction,
+    quantities::{BaseAtoms, QuoteAtoms, WrapperU64},
+    state::{
+        read_state, DynamicAccount, GlobalFixed, GlobalValue, MarketFixed, MarketValue,
+        GLOBAL_FIXED_SIZE,
+    },
+    validation::{
+        get_global_address, get_global_address_with_bump, get_global_vault_address,
+        get_global_vault_address_with_bump, get_vault_address, get_vault_address_with_bump,
+        loaders::GlobalTradeAccounts, ManifestAccountInfo,
+    },
+};
+use solana_program::{account_info::AccountInfo, system_program};
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+use std::{cell::RefCell, mem::size_of, rc::Rc, sync::Arc};
+
+use crate::{
+    fees::{FeeSchedule, ZeroFees},
+    health::MarketStatus,
+    sync::{QuoteGuard, Stamped, StateStamp},
+};
+
+macro_rules! dynamic_value_opt_to_account_info {
+    ( $name:ident, $value_opt:expr, $fixed_size:expr, $type:ident, $key:expr ) => {
+        let mut data_vec: Vec<u8> = Vec::new();
+        if $value_opt.is_some() {
+            let mut header_bytes: [u8; $fixed_size] = [0; $fixed_size];
+            *get_mut_helper::<$type>(&mut header_bytes, 0_u32) = $value_opt.as_ref().unwrap().fixed;
+            data_vec.extend_from_slice(&header_bytes);
+            data_vec.append(&mut $value_opt.as_ref().unwrap().dynamic.clone());
+        }
+
+        let mut lamports: u64 = 0;
+        let $name: AccountInfo<'_> = AccountInfo {
+            key: &$key,
+            lamports: Rc::new(RefCell::new(&mut lamports)),
+            data: Rc::new(RefCell::new(&mut data_vec[..])),
+            owner: &manifest::ID,
+            rent_epoch: 0,
+            is_signer: false,
+            is_writable: false,
+            executable: false,
+        };
+    };
+}
+
+#[derive(Clone)]
+pub struct ManifestMarket {
+    pub(crate) market: MarketValue,
+    key: Pubkey,
+    label: String,
+    pub(crate) base_global: Option<GlobalValue>,
+    pub(crate) quote_global: Option<GlobalValue>,
+    pub(crate) base_token_program: Pubkey,
+    pub(crate) quote_token_program: Pubkey,
+    stamp: StateStamp,
+    max_age_slots: Option<u64>,
+    // Stamps of the last update that included each global, which can be
+    // older than the market when the globals come from a separate feed.
+    base_global_stamp: Option<StateStamp>,
+    quote_global_stamp: Option<StateStamp>,
+    max_global_lag_slots: Option<u64>,
+    fee_schedule: Arc<dyn FeeSchedule + Send + Sync>,
+}
+
+impl ManifestMarket {
+    pub fn get_base_mint(&self) -> Pubkey {
+        *self.market.get_base_mint()
+    }
+    pub fn get_quote_mint(&self) -> Pubkey {
+        *self.market.get_quote_mint()
+    }
+    pub fn get_base_global_address(&self) -> Pubkey {
+        Self::known_global_address(self.market.get_base_mint(), &self.base_global)
+    }
+    pub fn get_quote_global_address(&self) -> Pubkey {
+        Self::known_global_address(self.market.get_quote_mint(), &self.quote_global)
+    }
+
+    // Addresses below are built from the bumps on the loaded accounts when
+    // possible, since searching for the bump costs a hash per attempt and
+    // builders run on every swap.
+    fn known_global_address(mint: &Pubkey, global_opt: &Option<GlobalValue>) -> Pubkey {
+        global_opt
+            .as_ref()
+            .and_then(|global| {
+                get_global_address_with_bump(mint, global.fixed.get_global_bump()).ok()
+            })
+            .unwrap_or_else(|| get_global_address(mint).0)
+    }
+
+    fn known_global_vault_address(mint: &Pubkey, global_opt: &Option<GlobalValue>) -> Pubkey {
+        global_opt
+            .as_ref()
+            .and_then(|global| {
+                get_global_vault_address_with_bump(mint, global.fixed.get_vault_bump()).ok()
+            })
+            .unwrap_or_else(|| get_global_vault_address(mint).0)
+    }
+
+    fn known_vault_address(&self, is_base: bool) -> Pubkey {
+        let (mint, bump): (&Pubkey, u8) = if is_base {
+            (
+                self.market.get_base_mint(),
+                self.market.fixed.get_base_vault_bump(),
+            )
+        } else {
+            (
+                self.market.get_quote_mint(),
+                self.market.fixed.get_quote_vault_bump(),
+            )
+        };
+        get_vault_address_with_bump(&self.key, mint, bump)
+            .unwrap_or_else(|_| get_vault_address(&self.key, mint).0)
+    }
+
+    /// Global of `mint`, which has to be the base or quote mint.
+    fn get_global_opt(&self, mint: &Pubkey) -> &Option<GlobalValue> {
+        if mint == self.market.get_base_mint() {
+            &self.base_global
+        } else {
+            &self.quote_global
+        }
+    }
+    pub fn get_stamp(&self) -> StateStamp {
+        self.stamp
+    }
+
+    /// Same as `update` but ignores the accounts when they are older than the
+    /// ones already loaded. Returns whether the update was applied.
+    pub fn update_with_stamp(
+        &mut self,
+        account_map: &AccountMap,
+        stamp: StateStamp,
+    ) -> Result<bool> {
+        if stamp < self.stamp {
+            return Ok(false);
+        }
+        self.update(account_map)?;
+        self.stamp = stamp;
+        if account_map.contains_key(&self.get_base_global_address()) {
+            self.base_global_stamp = Some(stamp);
+        }
+        if account_map.contains_key(&self.get_quote_global_address()) {
+            self.quote_global_stamp = Some(stamp);
+        }
+        Ok(true)
+    }
+
+    /// Quote tagged with the stamp of the state it was computed from.
+    pub fn quote_with_stamp(&self, quote_params: &QuoteParams) -> Result<Stamped<Quote>> {
+        Ok(Stamped::new(self.stamp, self.quote(quote_params)?))
+    }
+
+    /// Makes `quote_at_slot` refuse to quote from state more than
+    /// `max_age_slots` behind. Only meaningful with `update_with_stamp`, since
+    /// plain `update` leaves the stamp at slot zero.
+    pub fn set_max_age_slots(&mut self, max_age_slots: Option<u64>) {
+        self.max_age_slots = max_age_slots;
+    }
+
+    /// Makes `quote_at_slot` refuse to quote when a loaded global is more
+    /// than `max_global_lag_slots` behind the market. Global orders on a
+    /// fresh market backed by a stale global can quote funds that were
+    /// already withdrawn or used by another market.
+    pub fn set_max_global_lag_slots(&mut self, max_global_lag_slots: Option<u64>) {
+        self.max_global_lag_slots = max_global_lag_slots;
+    }
+
+    /// Taker fees to take out of quotes. Defaults to `ZeroFees`, which is
+    /// what the program charges.
+    pub fn set_fee_schedule(&mut self, fee_schedule: Arc<dyn FeeSchedule + Send + Sync>) {
+        self.fee_schedule = fee_schedule;
+    }
+
+    /// Address and lag of every loaded global that is behind the market by
+    /// more than the limit from `set_max_global_lag_slots`. Empty when no
+    /// limit is set.
+    pub fn get_stale_globals(&self) -> Vec<(Pubkey, u64)> {
+        let Some(max_global_lag_slots) = self.max_global_lag_slots else {
+            return Vec::new();
+        };
+        [
+            (
+                self.get_base_global_address(),
+                self.base_global.as_ref().map(|_| self.base_global_stamp),
+            ),
+            (
+                self.get_quote_global_address(),
+                self.quote_global.as_ref().map(|_| self.quote_global_stamp),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(global_key, global_stamp)| {
+            // A global loaded by plain `update` has no stamp and counts as
+            // being from slot zero.
+            let lag_slots: u64 =
+                QuoteGuard::global_lag_slots(self.stamp, global_stamp?.unwrap_or_default());
+            (lag_slots > max_global_lag_slots).then_some((global_key, lag_slots))
+        })
+        .collect()
+    }
+
+    /// Same as `quote_with_stamp`, failing when the state is older than the
+    /// limit from `set_max_age_slots` or a global lags the market by more
+    /// than the limit from `set_max_global_lag_slots`.
+    pub fn quote_at_slot(
+        &self,
+        quote_params: &QuoteParams,
+        current_slot: u64,
+    ) -> Result<Stamped<Quote>> {
+        if let Some(max_age_slots) = self.max_age_slots {
+            QuoteGuard::check(self.stamp, current_slot, max_age_slots)?;
+        }
+        if let Some((global_key, lag_slots)) = self.get_stale_globals().first() {
+            return Err(Error::msg(format!(
+                "Global {global_key} is {lag_slots} slots behind the market"
+            )));
+        }
+        self.quote_with_stamp(quote_params)
+    }
+
+    /// Whether the market should be quoted at `current_slot`. Staleness uses
+    /// the limits from `set_max_age_slots` and `set_max_global_lag_slots`.
+    pub fn get_status(&self, current_slot: u64) -> MarketStatus {
+        let status: MarketStatus =
+            health::market_status(&self.market, self.stamp, current_slot, self.max_age_slots);
+        if let MarketStatus::Stale { .. } = status {
+            return status;
+        }
+        if let Some((_, lag_slots)) = self.get_stale_globals().first() {
+            return MarketStatus::StaleGlobal {
+                lag_slots: *lag_slots,
+            };
+        }
+        status
+    }
+
+    /// Swap of exactly `in_atoms` that fails rather than fills partially, see
+    /// `quoting::fok_min_out_atoms`. Global orders are left out of the quote,
+    /// so the swap does not use global accounts either.
+    pub fn fok_swap_instruction(
+        &self,
+        payer: &Pubkey,
+        trader_base_account: &Pubkey,
+        trader_quote_account: &Pubkey,
+        is_base_in: bool,
+        in_atoms: u64,
+        slippage_bps: u16,
+    ) -> Result<Instruction> {
+        let min_out_atoms: u64 =
+            quoting::fok_min_out_atoms(&self.market, is_base_in, in_atoms, u32::MAX, slippage_bps)?;
+        Ok(swap_instruction(
+            &self.key,
+            payer,
+            &self.get_base_mint(),
+            &self.get_quote_mint(),
+            trader_base_account,
+            trader_quote_account,
+            in_atoms,
+            min_out_atoms,
+            is_base_in,
+            true,
+            self.base_token_program,
+            self.quote_token_program,
+            false,
+        ))
+    }
+}
+
+impl Amm for ManifestMarket {
+    fn label(&self) -> String {
+        self.label.clone()
+    }
+
+    fn key(&self) -> Pubkey {
+        self.key
+    }
+
+    fn program_id(&self) -> Pubkey {
+        manifest::id()
+    }
+
+    fn get_reserve_mints(&self) -> Vec<Pubkey> {
+        vec![self.get_base_mint(), self.get_quote_mint()]
+    }
+
+    fn get_accounts_to_update(&self) -> Vec<Pubkey> {
+        vec![
+            self.key,
+            self.get_base_mint(),
+            self.get_quote_mint(),
+            self.get_base_global_address(),
+            self.get_quote_global_address(),
+        ]
+    }
+
+    fn from_keyed_account(keyed_account: &KeyedAccount, _amm_context: &AmmContext) -> Result<Self> {
+        let mut_data: &mut &[u8] = &mut keyed_account.account.data.as_slice();
+
+        let (header_bytes, dynamic_data) = mut_data.split_at(size_of::<MarketFixed>());
+        let market_fixed: MarketFixed = read_state::<MarketFixed>(header_bytes)?;
+
+        Ok(ManifestMarket {
+            market: DynamicAccount::<MarketFixed, Vec<u8>> {
+                fixed: market_fixed,
+                dynamic: dynamic_data.to_vec(),
+            },
+            key: keyed_account.key,
+            label: "Manifest".into(),
+            // Gets updated on the first iter
+            base_token_program: spl_token::id(),
+            quote_token_program: spl_token::id(),
+            base_global: None,
+            quote_global: None,
+            stamp: StateStamp::default(),
+            max_age_slots: None,
+            base_global_stamp: None,
+            quote_global_stamp: None,
+            max_global_lag_slots: None,
+            fee_schedule: Arc::new(ZeroFees),
+        })
+    }
+
+    fn update(&mut self, account_map: &AccountMap) -> Result<()> {
+        if let Some(mint) = account_map.get(&self.get_base_mint()) {
+            self.base_token_program = mint.owner;
+        };
+        if let Some(mint) = account_map.get(&self.get_quote_mint()) {
+            self.quote_token_program = mint.owner;
+        };
+        if let Some(global) = account_map.get(&self.get_quote_global_address()) {
+            let (header_bytes, dynamic_data) = global.data.split_at(size_of::<GlobalFixed>());
+            let global_fixed: GlobalFixed = read_state::<GlobalFixed>(header_bytes)?;
+            self.quote_global = Some(DynamicAccount::<GlobalFixed, Vec<u8>> {
+                fixed: global_fixed,
+                dynamic: dynamic_data.to_vec(),
+            });
+        };
+        if let Some(global) = account_map.get(&self.get_base_global_address()) {
+            let (header_bytes, dynamic_data) = global.data.split_at(size_of::<GlobalFixed>());
+            let global_fixed: GlobalFixed = read_state::<GlobalFixed>(header_bytes)?;
+            self.base_global = Some(DynamicAccount::<GlobalFixed, Vec<u8>> {
+                fixed: global_fixed,
+                dynamic: dynamic_data.to_vec(),
+            });
+        };
+
+        let market_account: &solana_sdk::account::Account = account_map.get(&self.key).unwrap();
+
+        let (header_bytes, dynamic_data) = market_account.data.split_at(size_of::<MarketFixed>());
+        let market_fixed: MarketFixed = read_state::<MarketFixed>(header_bytes)?;
+        self.market = DynamicAccount::<MarketFixed, Vec<u8>> {
+            fixed: market_fixed,
+            dynamic: dynamic_data.to_vec(),
+        };
+        Ok(())
+    }
+
+    fn quote(&self, quote_params: &QuoteParams) -> Result<Quote> {
+        self.quote_with_global(quote_params, true)
+    }
+
+    fn get_swap_and_account_metas(&self, swap_params: &SwapParams) -> Result<SwapAndAccountMetas> {
+        let (side, base_account, quote_account) = self.get_swap_side_and_accounts(swap_params)?;
+        let account_metas: Vec<AccountMeta> = self.get_swap_account_metas(
+            &side,
+            &base_account,
+            &quote_account,
+            &swap_params.token_transfer_authority,
+        );
+        Ok(SwapAndAccountMetas {
+            swap: Swap::Openbook { side },
+            account_metas,
+        })
+    }
+
+    fn clone_amm(&self) -> Box<dyn Amm + Send + Sync> {
+        Box::new(self.clone())
+    }
+
+    fn has_dynamic_accounts(&self) -> bool {
+        false
+    }
+
+    fn unidirectional(&self) -> bool {
+        false
+    }
+
+    fn program_dependencies(&self) -> Vec<(Pubkey, String)> {
+        std::vec![]
+    }
+
+    fn get_accounts_len(&self) -> usize {
+        // 1   Program
+        // 2   Payer
+        // 3   Owner
+        // 4   Market
+        // 5   System Program
+        // 6   User Base
+        // 7   User Quote
+        // 8   Vault Base
+        // 9   Vault Quote
+        // 10  Base Token Program
+        // 11  Base Mint
+        // 12  Quote Token Program
+        // 13  Quote Mint
+        // 14  Global
+        // 15  Global Vault
+        15
+    }
+}
+
+impl ManifestMarket {
+    /// Same as `get_swap_and_account_metas`, but only passes the optional
+    /// accounts that `get_additional_accounts` selects, with the global
+    /// accounts only included with `include_global`. Use the
+    /// `includes_global` of an `AccountBudgetQuote` so the swap matches the
+    /// quote.
+    pub fn get_swap_and_account_metas_with_global(
+        &self,
+        swap_params: &SwapParams,
+        include_global: bool,
+    ) -> Result<SwapAndAccountMetas> {
+        let (side, base_account, quote_account) = self.get_swap_side_and_accounts(swap_params)?;
+        let account_metas: Vec<AccountMeta> = self.get_budgeted_swap_account_metas(
+            &side,
+            &base_account,
+            &quote_account,
+            &swap_params.token_transfer_authority,
+            include_global,
+        );
+        Ok(SwapAndAccountMetas {
+            swap: Swap::Openbook { side },
+            account_metas,
+        })
+    }
+
+    fn get_swap_side_and_accounts(
+        &self,
+        swap_params: &SwapParams,
+    ) -> Result<(Side, Pubkey, Pubkey)> {
+        let SwapParams {
+            destination_mint,
+            source_mint,
+            source_token_account,
+            destination_token_account,
+            ..
+        } = swap_params;
+
+        if source_mint == &self.get_base_mint() {
+            if destination_mint != &self.get_quote_mint() {
+                return Err(Error::msg("Invalid quote mint"));
+            }
+            Ok((Side::Ask, *source_token_account, *destination_token_account))
+        } else {
+            if destination_mint != &self.get_base_mint() {
+                return Err(Error::msg("Invalid base mint"));
+            }
+            Ok((Side::Bid, *destination_token_account, *source_token_account))
+        }
+    }
+
+    /// Accounts of a Manifest swap, in the order the program expects.
+    /// `token_transfer_authority` pays for expansion and owns the token
+    /// accounts.
+    pub(crate) fn get_swap_account_metas(
+        &self,
+        side: &Side,
+        base_account: &Pubkey,
+        quote_account: &Pubkey,
+        token_transfer_authority: &Pubkey,
+    ) -> Vec<AccountMeta> {
+        let destination_mint: Pubkey = match side {
+            Side::Ask => self.get_quote_mint(),
+            Side::Bid => self.get_base_mint(),
+        };
+        let global_opt: &Option<GlobalValue> = self.get_global_opt(&destination_mint);
+        let global: Pubkey = Self::known_global_address(&destination_mint, global_opt);
+        let global_vault: Pubkey = Self::known_global_vault_address(&destination_mint, global_opt);
+
+        let mut account_metas: Vec<AccountMeta> =
+            self.get_core_swap_account_metas(base_account, quote_account, token_transfer_authority);
+        account_metas.extend([
+            AccountMeta::new_readonly(self.get_base_mint(), false),
+            AccountMeta::new_readonly(self.quote_token_program, false),
+            AccountMeta::new_readonly(self.get_quote_mint(), false),
+            AccountMeta::new(global, false),
+            AccountMeta::new(global_vault, false),
+        ]);
+        account_metas
+    }
+
+    /// Same as `get_swap_account_metas`, but optional accounts are the ones
+    /// `get_additional_accounts` selects.
+    pub(crate) fn get_budgeted_swap_account_metas(
+        &self,
+        side: &Side,
+        base_account: &Pubkey,
+        quote_account: &Pubkey,
+        token_transfer_authority: &Pubkey,
+        include_global: bool,
+    ) -> Vec<AccountMeta> {
+        let destination_mint: Pubkey = match side {
+            Side::Ask => self.get_quote_mint(),
+            Side::Bid => self.get_base_mint(),
+        };
+        let mut account_metas: Vec<AccountMeta> =
+            self.get_core_swap_account_metas(base_account, quote_account, token_transfer_authority);
+        account_metas.extend(self.get_additional_account_metas(&destination_mint, include_global));
+        account_metas
+    }
+
+    fn get_core_swap_account_metas(
+        &self,
+        base_account: &Pubkey,
+        quote_account: &Pubkey,
+        token_transfer_authority: &Pubkey,
+    ) -> Vec<AccountMeta> {
+        let base_vault: Pubkey = self.known_vault_address(true);
+        let quote_vault: Pubkey = self.known_vault_address(false);
+
+        vec![
+            AccountMeta::new_readonly(manifest::id(), false),
+            // This account is intended to be the payer of rent for the tx in
+            // the case of a swap that partially fills a global order and needs
+            // to expand the market. It is not checked to be a signer until it
+            // is required to expand the market.
+            AccountMeta::new(*token_transfer_authority, true),
+            AccountMeta::new(*token_transfer_authority, true),
+            AccountMeta::new(self.key, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(*base_account, false),
+            AccountMeta::new(*quote_account, false),
+            AccountMeta::new(base_vault, false),
+            AccountMeta::new(quote_vault, false),
+            AccountMeta::new_readonly(self.base_token_program, false),
+        ]
+    }
+}
+
+/// Quote for a route leg that has to fit within an account budget.
+#[derive(Debug, Clone, Default)]
+pub struct AccountBudgetQuote {
+    pub quote: Quote,
+    /// Accounts needed on top of the ones every swap uses.
+    pub additional_accounts: Vec<Pubkey>,
+    pub accounts_len: usize,
+    pub includes_global: bool,
+}
+
+impl ManifestMarket {
+    // Program, payer, owner, market, system program, trader base, trader
+    // quote, base vault, quote vault and base token program.
+    const CORE_SWAP_ACCOUNTS_LEN: usize = 10;
+
+    /// Mints, extra token program and global accounts that a swap from
+    /// `input_mint` needs beyond the core accounts.
+    pub fn get_additional_accounts(
+        &self,
+        input_mint: &Pubkey,
+        include_global: bool,
+    ) -> Vec<Pubkey> {
+        let output_mint: Pubkey = if *input_mint == self.get_base_mint() {
+            self.get_quote_mint()
+        } else {
+            self.get_base_mint()
+        };
+        self.get_additional_account_metas(&output_mint, include_global)
+            .into_iter()
+            .map(|account_meta| account_meta.pubkey)
+            .collect()
+    }
+
+    // The program only looks for a mint after a token program when the
+    // account there is owned by one, and takes the quote token program to be
+    // the base one when it is not passed, so those can be left out.
+    fn get_additional_account_metas(
+        &self,
+        output_mint: &Pubkey,
+        include_global: bool,
+    ) -> Vec<AccountMeta> {
+        let mut account_metas: Vec<AccountMeta> = Vec::new();
+        if self.base_token_program == spl_token_2022::id() {
+            account_metas.push(AccountMeta::new_readonly(self.get_base_mint(), false));
+        }
+        if self.quote_token_program != self.base_token_program {
+            account_metas.push(AccountMeta::new_readonly(self.quote_token_program, false));
+        }
+        if self.quote_token_program == spl_token_2022::id() {
+            account_metas.push(AccountMeta::new_readonly(self.get_quote_mint(), false));
+        }
+        if include_global {
+            let global_opt: &Option<GlobalValue> = self.get_global_opt(output_mint);
+            account_metas.push(AccountMeta::new(
+                Self::known_global_address(output_mint, global_opt),
+                false,
+            ));
+            account_metas.push(AccountMeta::new(
+                Self::known_global_vault_address(output_mint, global_opt),
+                false,
+            ));
+        }
+        account_metas
+    }
+
+    /// Quote using at most `max_accounts` accounts. When the global accounts
+    /// do not fit, the quote is done without them, which stops the walk at the
+    /// first global order on the book.
+    pub fn quote_with_max_accounts(
+        &self,
+        quote_params: &QuoteParams,
+        max_accounts: usize,
+    ) -> Result<AccountBudgetQuote> {
+        for include_global in [true, false] {
+            let additional_accounts: Vec<Pubkey> =
+                self.get_additional_accounts(&quote_params.input_mint, include_global);
+            let accounts_len: usize = Self::CORE_SWAP_ACCOUNTS_LEN + additional_accounts.len();
+            if accounts_len > max_accounts {
+                continue;
+            }
+            return Ok(AccountBudgetQuote {
+                quote: self.quote_with_global(quote_params, include_global)?,
+                additional_accounts,
+                accounts_len,
+                includes_global: include_global,
+            });
+        }
+        Err(Error::msg(format!(
+            "Swap needs more than {max_accounts} accounts"
+        )))
+    }
+
+    fn quote_with_global(&self, quote_params: &QuoteParams, include_global: bool) -> Result<Quote> {
+        let market: DynamicAccount<MarketFixed, Vec<u8>> = self.market.clone();
+
+        // The reason for checking can_expand is that jup does not want the
+        // payer to be responsible for gas for the market expansion on a partial
+        // fill against a reversible order. This solution is more restrictive
+        // than it really needs to be because there are many cases where trading
+        // against a reversible will work without the ability to expand, like
+        // filling an order completely, or reversing into a coalescing order. Or
+        // there just might not be reversible orders on the book.
+        let can_expand = market.has_two_free_blocks();
+        if !can_expand {
+            return Ok(Quote {
+                out_amount: 0,
+                ..Quote::default()
+            });
+        }
+
+        dynamic_value_opt_to_account_info!(
+            quote_global_account_info,
+            self.quote_global,
+            GLOBAL_FIXED_SIZE,
+            GlobalFixed,
+            self.get_quote_global_address()
+        );
+
+        let quote_global_trade_accounts_opt: Option<GlobalTradeAccounts> =
+            if self.quote_global.is_some() {
+                Some(GlobalTradeAccounts {
+                    mint_opt: None,
+                    global: ManifestAccountInfo::new(&quote_global_account_info).unwrap(),
+                    global_vault_opt: None,
+                    market_vault_opt: None,
+                    token_program_opt: None,
+                    system_program: None,
+                    gas_payer_opt: None,
+                    gas_receiver_opt: None,
+                    market: self.key.clone(),
+                })
+            } else {
+                None
+            };
+
+        dynamic_value_opt_to_account_info!(
+            base_global_account_info,
+            self.base_global,
+            GLOBAL_FIXED_SIZE,
+            GlobalFixed,
+            self.get_base_global_address()
+        );
+
+        let base_global_trade_accounts_opt: Option<GlobalTradeAccounts> =
+            if self.base_global.is_some() {
+                Some(GlobalTradeAccounts {
+                    mint_opt: None,
+                    global: ManifestAccountInfo::new(&base_global_account_info).unwrap(),
+                    global_vault_opt: None,
+                    market_vault_opt: None,
+                    token_program_opt: None,
+                    system_program: None,
+                    gas_payer_opt: None,
+                    gas_receiver_opt: None,
+                    market: self.key.clone(),
+                })
+            } else {
+                None
+            };
+
+        let global_trade_accounts: &[Option<GlobalTradeAccounts>; 2] = &if include_global {
+            [
+                base_global_trade_accounts_opt,
+                quote_global_trade_accounts_opt,
+            ]
+        } else {
+            [None, None]
+        };
+
+        let is_base_in: bool = quote_params.input_mint == self.get_base_mint();
+        let out_amount: u64 = if is_base_in {
+            let in_atoms: BaseAtoms = BaseAtoms::new(quote_params.amount);
+            market
+                .impact_quote_atoms_with_slot(false, in_atoms, global_trade_accounts, u32::MAX)?
+                .as_u64()
+        } else {
+            let in_atoms: QuoteAtoms = QuoteAtoms::new(quote_params.amount);
+            market
+                .impact_base_atoms_with_slot(true, in_atoms, global_trade_accounts, u32::MAX)?
+                .as_u64()
+        };
+        let fee_amount: u64 = self
+            .fee_schedule
+            .taker_fee_atoms(is_base_in, out_amount)
+            .min(out_amount);
+        let out_amount: u64 = out_amount - fee_amount;
+        Ok(Quote {
+            // Artificially penalize by 1 atom to be worse than the non-global version.
+            // This ensures that routes that can be filled without global accounts cause less
+            // lock contention on the global accounts, which will allow them to be included
+            // the block earlier. The UX improvement should be worth at least 1 atom.
+            out_amount: if include_global {
+                out_amount.saturating_sub(1)
+            } else {
+                out_amount
+            },
+            fee_amount,
+            fee_mint: if fee_amount > 0 {
+                quote_params.output_mint
+            } else {
+                Pubkey::default()
+            },
+            ..Quote::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hypertree::{get_mut_helper, DataIndex};
+    use jupiter_amm_interface::{ClockRef, SwapMode};
+    use manifest::{
+        quantities::{BaseAtoms, GlobalAtoms},
+        state::{
+            constants::NO_EXPIRATION_LAST_VALID_SLOT, AddOrderToMarketArgs, OrderType,
+            GLOBAL_BLOCK_SIZE, MARKET_BLOCK_SIZE, MARKET_FIXED_SIZE,
+        },
+        validation::{MintAccountInfo, Signer},
+    };
+    use solana_sdk::{account::Account, account_info::AccountInfo, pubkey};
+    use spl_token_2022::state::Mint;
+    use std::{cell::RefCell, rc::Rc};
+
+    const BASE_MINT_KEY: Pubkey = pubkey!("So11111111111111111111111111111111111111112");
+    const QUOTE_MINT_KEY: Pubkey = pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+    const MARKET_KEY: Pubkey = pubkey!("GPPda3ZQZannxp3AK8bSishVqvhHAxogiWdhw1mvmoZr");
+    const TRADER_KEY: Pubkey = pubkey!("GCtjtH2ehL6BZTjismuZ8JhQnuM6U3bmtxVoFyiHMHGc");
+
+    macro_rules! mint_account_info {
+        ($name:ident, $decimals:expr) => {
+            let mut lamports: u64 = 0;
+            let $name: MintAccountInfo = MintAccountInfo {
+                mint: Mint {
+                    mint_authority: None.into(),
+                    supply: 0,
+                    decimals: $decimals,
+                    is_initialized: true,
+                    freeze_authority: None.into(),
+                },
+                info: &AccountInfo {
+                    key: if $decimals == 9 {
+                        &BASE_MINT_KEY
+                    } else {
+                        &QUOTE_MINT_KEY
+                    },
+                    lamports: Rc::new(RefCell::new(&mut lamports)),
+                    data: Rc::new(RefCell::new(&mut [])),
+                    owner: &Pubkey::new_unique(),
+                    rent_epoch: 0,
+                    is_signer: false,
+                    is_writable: false,
+                    executable: false,
+                },
+            };
+        };
+    }
+
+    macro_rules! dynamic_value_to_account {
+        ( $name:ident, $value:expr, $fixed_size:expr, $type:ident ) => {
+            let mut header_bytes: [u8; $fixed_size] = [0; $fixed_size];
+            *get_mut_helper::<$type>(&mut header_bytes, 0_u32) = $value.fixed;
+
+            let mut data_vec: Vec<u8> = Vec::new();
+            data_vec.extend_from_slice(&header_bytes);
+            data_vec.append(&mut $value.dynamic);
+
+            let $name: Account = Account {
+                lamports: 0,
+                data: data_vec,
+                owner: manifest::id(),
+                executable: false,
+                rent_epoch: 0,
+            };
+        };
+    }
+
+    macro_rules! signer {
+        ( $name:ident) => {
+            let mut lamports: u64 = 1_000_000_000;
+            let account_info: AccountInfo<'_> = AccountInfo {
+                key: &TRADER_KEY,
+                lamports: Rc::new(RefCell::new(&mut lamports)),
+                data: Rc::new(RefCell::new(&mut [])),
+                owner: &manifest::ID,
+                rent_epoch: 0,
+                is_signer: true,
+                is_writable: false,
+                executable: false,
+            };
+            let $name = Signer::new(&account_info).expect("valid signer");
+        };
+    }
+
+    #[test]
+    fn test_jupiter_global_with_global_orders() {
+        mint_account_info!(base_mint, 9);
+        mint_account_info!(quote_mint, 6);
+        let quote_global_key: Pubkey = get_global_address(&QUOTE_MINT_KEY).0;
+
+        let mut quote_global_value: DynamicAccount<GlobalFixed, Vec<u8>> = GlobalValue {
+            fixed: GlobalFixed::new_empty(&QUOTE_MINT_KEY),
+            // 2 because 1 deposit, 1 seat
+            dynamic: vec![0; GLOBAL_BLOCK_SIZE * 2],
+        };
+        // Claim a seat and deposit plenty of quote atoms.
+        quote_global_value.global_expand().expect("global expand");
+        quote_global_value
+            .add_trader(&TRADER_KEY)
+            .expect("claim global seat");
+        quote_global_value
+            .deposit_global(&TRADER_KEY, GlobalAtoms::new(1_000_000_000))
+            .expect("deposit quote global");
+
+        // Clone so the consumed bytes are available for the global trade
+        // accounts later when quoting.
+        dynamic_value_opt_to_account_info!(
+            quote_global_account_info,
+            Some(quote_global_value.clone()),
+            GLOBAL_FIXED_SIZE,
+            GlobalFixed,
+            quote_global_key
+        );
+        signer!(gas_payer_account_info);
+
+        let quote_global_trade_accounts: Option<GlobalTradeAccounts<'_, '_>> =
+            Some(GlobalTradeAccounts {
+                mint_opt: None,
+                global: ManifestAccountInfo::new(&quote_global_account_info).unwrap(),
+                global_vault_opt: None,
+                market_vault_opt: None,
+                token_program_opt: None,
+                system_program: None,
+                gas_payer_opt: Some(gas_payer_account_info),
+                gas_receiver_opt: None,
+                market: MARKET_KEY,
+            });
+
+        dynamic_value_to_account!(
+            quote_global_account,
+            quote_global_value,
+            GLOBAL_FIXED_SIZE,
+            GlobalFixed
+        );
+
+        let mut market_value: DynamicAccount<MarketFixed, Vec<u8>> = MarketValue {
+            fixed: MarketFixed::new_empty(&base_mint, &quote_mint, &MARKET_KEY),
+            // 5 because 2 extra, 1 seat, 2 orders.
+            dynamic: vec![0; MARKET_BLOCK_SIZE * 5],
+        };
+        // Claim a seat and deposit plenty on both sides.
+        market_value.market_expand().unwrap();
+        market_value.claim_seat(&TRADER_KEY).unwrap();
+        let trader_index: DataIndex = market_value.get_trader_index(&TRADER_KEY);
+        market_value
+            .deposit(trader_index, 1_000_000_000_000, true)
+            .unwrap();
+        market_value
+            .deposit(trader_index, 1_000_000_000_000, false)
+            .unwrap();
+
+        // Bid for 10 SOL@ 150USDC/SOL global
+        market_value.market_expand().unwrap();
+        market_value
+            .place_order(AddOrderToMarketArgs {
+                market: MARKET_KEY,
+                trader_index,
+                num_base_atoms: BaseAtoms::new(10_000),
+                price: 0.150.try_into().unwrap(),
+                is_bid: true,
+                last_valid_slot: NO_EXPIRATION_LAST_VALID_SLOT,
+                order_type: OrderType::Global,
+                global_trade_accounts_opts: &[None, quote_global_trade_accounts],
+                current_slot: None,
+            })
+            .unwrap();
+
+        // Ask 10 SOL @ 180USDC/SOL
+        market_value.market_expand().unwrap();
+        market_value
+            .place_order(AddOrderToMarketArgs {
+                market: MARKET_KEY,
+                trader_index,
+                num_base_atoms: BaseAtoms::new(10_000),
+                price: 0.180.try_into().unwrap(),
+                is_bid: false,
+                last_valid_slot: NO_EXPIRATION_LAST_VALID_SLOT,
+                order_type: OrderType::Limit,
+                global_trade_accounts_opts: &[None, None],
+                current_slot: None,
+            })
+            .unwrap();
+
+        // Expand for the second extra for the case of reverse orders.
+        market_value.market_expand().unwrap();
+        market_value.market_expand().unwrap();
+
+        dynamic_value_to_account!(market_account, market_value, MARKET_FIXED_SIZE, MarketFixed);
+
+        let market_keyed_account: KeyedAccount = KeyedAccount {
+            key: MARKET_KEY,
+            account: market_account.clone(),
+            params: None,
+        };
+
+        let amm_context: AmmContext = AmmContext {
+            clock_ref: ClockRef::default(),
+        };
+
+        let mut manifest_market: ManifestMarket =
+            ManifestMarket::from_keyed_account(&market_keyed_account, &amm_context).unwrap();
+
+        let accounts_map = AccountMap::from_iter([
+            (MARKET_KEY, market_account),
+            (quote_global_key, quote_global_account),
+            (
+                BASE_MINT_KEY,
+                Account {
+                    lamports: 0,
+                    data: Vec::new(),
+                    owner: spl_token::id(),
+                    executable: false,
+                    rent_epoch: 0,
+                },
+            ),
+            (
+                QUOTE_MINT_KEY,
+                Account {
+                    lamports: 0,
+                    data: Vec::new(),
+                    owner: spl_token::id(),
+                    executable: false,
+                    rent_epoch: 0,
+                },
+            ),
+        ]);
+        manifest_market.update(&accounts_map).unwrap();
+
+        let (base_mint, quote_mint) = {
+            let reserves: Vec<Pubkey> = manifest_market.get_reserve_mints();
+            (reserves[0], reserves[1])
+        };
+
+        // Ask for 1 SOL, Bid for 180 USDC
+        for (side, in_amount) in [(Side::Ask, 1_000_000_000), (Side::Bid, 180_000_000)] {
+            let (input_mint, output_mint) = match side {
+                Side::Ask => (base_mint, quote_mint),
+                Side::Bid => (quote_mint, base_mint),
+            };
+
+            let quote_params: QuoteParams = QuoteParams {
+                amount: in_amount,
+                swap_mode: SwapMode::ExactIn,
+                input_mint,
+                output_mint,
+            };
+
+            let quote: Quote = manifest_market.quote(&quote_params).unwrap();
+
+            match side {
+                Side::Ask => {
+                    assert_eq!(quote.out_amount, 1_499);
+                }
+                Side::Bid => {
+                    assert_eq!(quote.out_amount, 9_999);
+                }
+            };
+        }
+
+        // The global bid is only reachable when the global accounts fit.
+        let quote_params: QuoteParams = QuoteParams {
+            amount: 1_000_000_000,
+            swap_mode: SwapMode::ExactIn,
+            input_mint: base_mint,
+            output_mint: quote_mint,
+        };
+        let budget_quote: AccountBudgetQuote = manifest_market
+            .quote_with_max_accounts(&quote_params, 12)
+            .unwrap();
+        assert!(budget_quote.includes_global);
+        assert_eq!(budget_quote.accounts_len, 12);
+        assert_eq!(budget_quote.quote.out_amount, 1_499);
+        let account_metas: Vec<AccountMeta> = manifest_market.get_budgeted_swap_account_metas(
+            &Side::Ask,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            budget_quote.includes_global,
+        );
+        assert_eq!(account_metas.len(), budget_quote.accounts_len);
+        let budget_quote: AccountBudgetQuote = manifest_market
+            .quote_with_max_accounts(&quote_params, 11)
+            .unwrap();
+        assert!(!budget_quote.includes_global);
+        assert_eq!(budget_quote.quote.out_amount, 0);
+        let account_metas: Vec<AccountMeta> = manifest_market.get_budgeted_swap_account_metas(
+            &Side::Ask,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            budget_quote.includes_global,
+        );
+        assert_eq!(account_metas.len(), budget_quote.accounts_len);
+        assert!(manifest_market
+            .quote_with_max_accounts(&quote_params, 9)
+            .is_err());
+
+        // The budget does not change the default account list.
+        let account_metas: Vec<AccountMeta> = manifest_market.get_swap_account_metas(
+            &Side::Ask,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        );
+        assert_eq!(account_metas.len(), manifest_market.get_accounts_len());
+        assert_eq!(account_metas.len(), 15);
+        assert_eq!(account_metas[10].pubkey, base_mint);
+        assert_eq!(account_metas[11].pubkey, spl_token::id());
+        assert_eq!(account_metas[12].pubkey, quote_mint);
+        assert_eq!(account_metas[13].pubkey, quote_global_key);
+
+        // The global stops updating while the market keeps going.
+        let stamp = |slot: u64| StateStamp {
+            slot,
+            write_version: 0,
+        };
+        manifest_market.set_max_global_lag_slots(Some(20));
+        assert!(manifest_market
+            .update_with_stamp(&accounts_map, stamp(100))
+            .unwrap());
+        let mut market_only_map: AccountMap = accounts_map.clone();
+        market_only_map.remove(&quote_global_key);
+        assert!(manifest_market
+            .update_with_stamp(&market_only_map, stamp(120))
+            .unwrap());
+        assert!(manifest_market.get_stale_globals().is_empty());
+        assert!(manifest_market.quote_at_slot(&quote_params, 120).is_ok());
+        assert!(manifest_market
+            .update_with_stamp(&market_only_map, stamp(121))
+            .unwrap());
+        assert_eq!(
+            manifest_market.get_stale_globals(),
+            vec![(quote_global_key, 21)]
+        );
+        assert!(manifest_market.quote_at_slot(&quote_params, 121).is_err());
+        assert_eq!(
+            manifest_market.get_status(121),
+            MarketStatus::StaleGlobal { lag_slots: 21 }
+        );
+    }
+}
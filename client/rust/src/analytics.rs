@@ -0,0 +1,1453 @@
+//! Pre-trade portfolio checks on a market snapshot.
+
+use anyhow::{Error, Result};
+use hypertree::{DataIndex, NIL};
+use manifest::{
+    logs::{CancelOrderLog, FillLog, PlaceOrderLog},
+    quantities::{BaseAtoms, QuoteAtoms, QuoteAtomsPerBaseAtom, WrapperU64},
+    state::{MarketRef, MarketValue, OrderType, RestingOrder},
+};
+use solana_program::pubkey::Pubkey;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    book::OrderSource,
+    decoder::CancelReason,
+    execution::{estimate_queue_position, QueuePosition},
+    sync::StampedMarket,
+};
+
+/// A fill that has not happened yet. When `maker_order_sequence_number` is
+/// set, the fill is against one of the trader's own resting orders and is
+/// paid for by the funds locked in that order. Otherwise the trader is the
+/// taker and pays from the withdrawable balance.
+#[derive(Debug, Clone, Copy)]
+pub struct HypotheticalFill {
+    pub base_atoms: BaseAtoms,
+    pub price: QuoteAtomsPerBaseAtom,
+    pub is_bid: bool,
+    pub maker_order_sequence_number: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct WithdrawalIntent {
+    pub amount_atoms: u64,
+    pub is_base: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceProjection {
+    pub base_withdrawable: BaseAtoms,
+    pub quote_withdrawable: QuoteAtoms,
+    /// Atoms still backing the trader's non-global resting orders.
+    pub base_locked: BaseAtoms,
+    pub quote_locked: QuoteAtoms,
+    /// Indices into the withdrawal intents that could not be satisfied after
+    /// the fills. Intents are applied in order, so an earlier withdrawal can
+    /// make a later one infeasible.
+    pub infeasible_withdrawals: Vec<usize>,
+}
+
+/// Apply hypothetical fills to the trader's seat and open orders. Rounding is
+/// always taken against the trader so the projection is a lower bound on
+/// what would be withdrawable.
+pub fn project_balances(
+    market: &MarketValue,
+    trader: &Pubkey,
+    fills: &[HypotheticalFill],
+    withdrawal_intents: &[WithdrawalIntent],
+) -> Result<BalanceProjection> {
+    let trader_index: DataIndex = market.get_trader_index(trader);
+    if trader_index == NIL {
+        return Err(Error::msg(format!("No seat for {trader}")));
+    }
+    let (base_withdrawable, quote_withdrawable) = market.get_trader_balance(trader);
+    project_seat_balances(
+        trader_index,
+        base_withdrawable,
+        quote_withdrawable,
+        &market.get_bids(),
+        &market.get_asks(),
+        fills,
+        withdrawal_intents,
+    )
+}
+
+/// Same as `project_balances` for a seat whose balances and books were read
+/// separately, for example from `BooksideSnapshot`s.
+pub fn project_seat_balances<S: OrderSource>(
+    trader_index: DataIndex,
+    mut base_withdrawable: BaseAtoms,
+    mut quote_withdrawable: QuoteAtoms,
+    bids: &S,
+    asks: &S,
+    fills: &[HypotheticalFill],
+    withdrawal_intents: &[WithdrawalIntent],
+) -> Result<BalanceProjection> {
+    // Remaining size of every open order keyed by sequence number.
+    let mut open_orders: BTreeMap<u64, RestingOrder> = BTreeMap::new();
+    for order in bids.resting_orders().chain(asks.resting_orders()) {
+        if order.get_trader_index() == trader_index {
+            open_orders.insert(order.get_sequence_number(), order);
+        }
+    }
+
+    for fill in fills {
+        let HypotheticalFill {
+            base_atoms,
+            price,
+            is_bid,
+            maker_order_sequence_number,
+        } = *fill;
+        match maker_order_sequence_number {
+            Some(sequence_number) => {
+                let order: &mut RestingOrder = open_orders
+                    .get_mut(&sequence_number)
+                    .ok_or_else(|| Error::msg(format!("No open order {sequence_number}")))?;
+                if order.get_num_base_atoms() < base_atoms {
+                    return Err(Error::msg(format!(
+                        "Fill of {base_atoms} exceeds order {sequence_number}"
+                    )));
+                }
+                let order_price: QuoteAtomsPerBaseAtom = order.get_price();
+                if order.get_is_bid() {
+                    base_withdrawable = base_withdrawable.checked_add(base_atoms)?;
+                    if !order.is_global() {
+                        // Whatever the fill does not use of the rounded up
+                        // allocation comes back to the seat.
+                        let previous_locked: QuoteAtoms =
+                            order_price.checked_quote_for_base(order.get_num_base_atoms(), true)?;
+                        let new_locked: QuoteAtoms = order_price.checked_quote_for_base(
+                            order.get_num_base_atoms().checked_sub(base_atoms)?,
+                            true,
+                        )?;
+                        let quote_paid: QuoteAtoms =
+                            order_price.checked_quote_for_base(base_atoms, true)?;
+                        quote_withdrawable = quote_withdrawable.checked_add(
+                            previous_locked
+                                .checked_sub(new_locked)?
+                                .saturating_sub(quote_paid),
+                        )?;
+                    }
+                } else {
+                    quote_withdrawable = quote_withdrawable
+                        .checked_add(order_price.checked_quote_for_base(base_atoms, false)?)?;
+                }
+                order.reduce(base_atoms)?;
+            }
+            None => {
+                if is_bid {
+                    let quote_paid: QuoteAtoms = price.checked_quote_for_base(base_atoms, true)?;
+                    if quote_withdrawable < quote_paid {
+                        return Err(Error::msg(format!(
+                            "Insufficient quote for fill. Has {quote_withdrawable}, needs {quote_paid}"
+                        )));
+                    }
+                    quote_withdrawable = quote_withdrawable.checked_sub(quote_paid)?;
+                    base_withdrawable = base_withdrawable.checked_add(base_atoms)?;
+                } else {
+                    if base_withdrawable < base_atoms {
+                        return Err(Error::msg(format!(
+                            "Insufficient base for fill. Has {base_withdrawable}, needs {base_atoms}"
+                        )));
+                    }
+                    base_withdrawable = base_withdrawable.checked_sub(base_atoms)?;
+                    quote_withdrawable = quote_withdrawable
+                        .checked_add(price.checked_quote_for_base(base_atoms, false)?)?;
+                }
+            }
+        }
+    }
+
+    let mut base_locked: BaseAtoms = BaseAtoms::ZERO;
+    let mut quote_locked: QuoteAtoms = QuoteAtoms::ZERO;
+    for order in open_orders.values() {
+        if order.is_global() {
+            continue;
+        }
+        if order.get_is_bid() {
+            quote_locked = quote_locked.checked_add(
+                order
+                    .get_price()
+                    .checked_quote_for_base(order.get_num_base_atoms(), true)?,
+            )?;
+        } else {
+            base_locked = base_locked.checked_add(order.get_num_base_atoms())?;
+        }
+    }
+
+    let mut infeasible_withdrawals: Vec<usize> = Vec::new();
+    for (index, intent) in withdrawal_intents.iter().enumerate() {
+        if intent.is_base {
+            if base_withdrawable.as_u64() < intent.amount_atoms {
+                infeasible_withdrawals.push(index);
+            } else {
+                base_withdrawable =
+                    base_withdrawable.checked_sub(BaseAtoms::new(intent.amount_atoms))?;
+            }
+        } else if quote_withdrawable.as_u64() < intent.amount_atoms {
+            infeasible_withdrawals.push(index);
+        } else {
+            quote_withdrawable =
+                quote_withdrawable.checked_sub(QuoteAtoms::new(intent.amount_atoms))?;
+        }
+    }
+
+    Ok(BalanceProjection {
+        base_withdrawable,
+        quote_withdrawable,
+        base_locked,
+        quote_locked,
+        infeasible_withdrawals,
+    })
+}
+
+/// Order lifecycle log with the slot of the transaction that emitted it.
+#[derive(Clone, Copy)]
+pub enum OrderEvent {
+    Place {
+        slot: u64,
+        log: PlaceOrderLog,
+    },
+    Fill {
+        slot: u64,
+        log: FillLog,
+    },
+    Cancel {
+        slot: u64,
+        log: CancelOrderLog,
+        reason: CancelReason,
+    },
+}
+
+impl OrderEvent {
+    pub fn get_slot(&self) -> u64 {
+        match self {
+            OrderEvent::Place { slot, .. }
+            | OrderEvent::Fill { slot, .. }
+            | OrderEvent::Cancel { slot, .. } => *slot,
+        }
+    }
+}
+
+/// Slots from `start_slot` up to but not including `end_slot`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotWindow {
+    pub start_slot: u64,
+    pub end_slot: u64,
+}
+
+impl SlotWindow {
+    pub fn contains(&self, slot: u64) -> bool {
+        self.start_slot <= slot && slot < self.end_slot
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct MakerFillStats {
+    pub fill_count: u64,
+    pub filled_base_atoms: BaseAtoms,
+    /// Cancels by the maker.
+    pub cancel_count: u64,
+    /// Orders removed by someone else, see `CancelReason`.
+    pub forced_cancel_count: u64,
+    /// Average slots between placement and fill. Only fills whose placement
+    /// is in the events count towards it.
+    pub average_resting_slots: Option<f64>,
+    /// None when the maker did not cancel anything in the window.
+    pub fill_to_cancel_ratio: Option<f64>,
+}
+
+/// Per maker fill statistics over a window. Events before the window are
+/// only used to find when filled orders were placed, so pass some history
+/// before `window.start_slot` for accurate resting times.
+pub fn maker_fill_stats(
+    events: &[OrderEvent],
+    window: SlotWindow,
+) -> BTreeMap<Pubkey, MakerFillStats> {
+    let mut placed_slots: HashMap<u64, u64> = HashMap::new();
+    let mut stats: BTreeMap<Pubkey, MakerFillStats> = BTreeMap::new();
+    let mut resting_slots: BTreeMap<Pubkey, (u64, u64)> = BTreeMap::new();
+
+    for event in events {
+        match event {
+            OrderEvent::Place { slot, log } => {
+                placed_slots.insert(log.order_sequence_number, *slot);
+            }
+            OrderEvent::Fill { slot, log } if window.contains(*slot) => {
+                let maker_stats: &mut MakerFillStats = stats.entry(log.maker).or_default();
+                maker_stats.fill_count += 1;
+                maker_stats.filled_base_atoms =
+                    maker_stats.filled_base_atoms.saturating_add(log.base_atoms);
+                if let Some(placed_slot) = placed_slots.get(&log.maker_sequence_number) {
+                    let (total_slots, count) = resting_slots.entry(log.maker).or_default();
+                    *total_slots += slot.saturating_sub(*placed_slot);
+                    *count += 1;
+                }
+            }
+            OrderEvent::Cancel { slot, log, reason } if window.contains(*slot) => {
+                let maker_stats: &mut MakerFillStats = stats.entry(log.trader).or_default();
+                if reason.is_forced() {
+                    maker_stats.forced_cancel_count += 1;
+                } else {
+                    maker_stats.cancel_count += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for (maker, maker_stats) in stats.iter_mut() {
+        maker_stats.average_resting_slots = resting_slots
+            .get(maker)
+            .map(|(total_slots, count)| *total_slots as f64 / *count as f64);
+        if maker_stats.cancel_count > 0 {
+            maker_stats.fill_to_cancel_ratio =
+                Some(maker_stats.fill_count as f64 / maker_stats.cancel_count as f64);
+        }
+    }
+    stats
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ReverseFeeStats {
+    pub fill_count: u64,
+    pub filled_base_atoms: BaseAtoms,
+    /// Quote atoms between the fill and the order it flipped into. This is
+    /// income once the flipped order trades too, so it is an upper bound on
+    /// realized fees for flips that are still resting.
+    pub spread_quote_atoms: QuoteAtoms,
+}
+
+/// Spread captured per maker by fills of reverse orders in a window. `orders`
+/// are the reverse orders as they rested before filling, from snapshots of
+/// the book, and are matched to fills by sequence number. Fills of orders
+/// not in `orders` are skipped.
+pub fn reverse_fee_report(
+    fills: &[OrderEvent],
+    orders: &[RestingOrder],
+    window: SlotWindow,
+) -> Result<BTreeMap<Pubkey, ReverseFeeStats>> {
+    let orders_by_sequence_number: HashMap<u64, &RestingOrder> = orders
+        .iter()
+        .filter(|order| order.is_reversible())
+        .map(|order| (order.get_sequence_number(), order))
+        .collect();
+    let mut report: BTreeMap<Pubkey, ReverseFeeStats> = BTreeMap::new();
+
+    for event in fills {
+        let OrderEvent::Fill { slot, log } = event else {
+            continue;
+        };
+        if !window.contains(*slot) {
+            continue;
+        }
+        let Some(order) = orders_by_sequence_number.get(&log.maker_sequence_number) else {
+            continue;
+        };
+        // An ask flips into a bid that costs less than the fill paid out and
+        // a bid into an ask that brings in more than the fill cost. Round
+        // against the maker in both cases.
+        let flip_quote_atoms: QuoteAtoms = order
+            .reverse_price()?
+            .checked_quote_for_base(log.base_atoms, !order.get_is_bid())?;
+        let spread_quote_atoms: QuoteAtoms = if order.get_is_bid() {
+            flip_quote_atoms.saturating_sub(log.quote_atoms)
+        } else {
+            log.quote_atoms.saturating_sub(flip_quote_atoms)
+        };
+
+        let stats: &mut ReverseFeeStats = report.entry(log.maker).or_default();
+        stats.fill_count += 1;
+        stats.filled_base_atoms = stats.filled_base_atoms.checked_add(log.base_atoms)?;
+        stats.spread_quote_atoms = stats.spread_quote_atoms.checked_add(spread_quote_atoms)?;
+    }
+    Ok(report)
+}
+
+/// Inventory from one kind of order with its cost at the average price it
+/// was built at.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PositionPnl {
+    /// Base atoms bought minus sold.
+    pub position_base_atoms: i128,
+    /// Quote atoms paid for the position, negative when it is short.
+    pub cost_quote_atoms: i128,
+    pub realized_quote_atoms: i128,
+}
+
+impl PositionPnl {
+    /// Applies a fill that changes the base atoms by `base_delta` and the
+    /// quote atoms by `quote_delta`, which have opposite signs. The part that
+    /// reduces the position realizes against the average cost.
+    fn apply(&mut self, mut base_delta: i128, mut quote_delta: i128) {
+        if self.position_base_atoms != 0
+            && base_delta.signum() == -self.position_base_atoms.signum()
+        {
+            let closing: i128 = base_delta.abs().min(self.position_base_atoms.abs());
+            let closed_cost: i128 =
+                self.cost_quote_atoms * closing / self.position_base_atoms.abs();
+            let closing_quote: i128 = quote_delta * closing / base_delta.abs();
+            self.realized_quote_atoms += closing_quote - closed_cost;
+            self.cost_quote_atoms -= closed_cost;
+            self.position_base_atoms += closing * base_delta.signum();
+            base_delta -= closing * base_delta.signum();
+            quote_delta -= closing_quote;
+        }
+        self.position_base_atoms += base_delta;
+        self.cost_quote_atoms -= quote_delta;
+    }
+}
+
+/// Maker PnL split by the kind of order that was filled. Post only orders
+/// count as limit orders since they rest the same way.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PnlByOrderType {
+    pub limit: PositionPnl,
+    pub global: PositionPnl,
+    pub reverse: PositionPnl,
+    pub reverse_tight: PositionPnl,
+    /// Fills of orders whose type is not known, see `PositionTracker`.
+    pub unattributed: PositionPnl,
+}
+
+impl PnlByOrderType {
+    pub fn get_total_realized_quote_atoms(&self) -> i128 {
+        [
+            self.limit,
+            self.global,
+            self.reverse,
+            self.reverse_tight,
+            self.unattributed,
+        ]
+        .iter()
+        .map(|pnl| pnl.realized_quote_atoms)
+        .sum()
+    }
+
+    fn get_mut(&mut self, order_type: Option<OrderType>) -> &mut PositionPnl {
+        match order_type {
+            Some(OrderType::Limit | OrderType::PostOnly | OrderType::ImmediateOrCancel) => {
+                &mut self.limit
+            }
+            Some(OrderType::Global) => &mut self.global,
+            Some(OrderType::Reverse) => &mut self.reverse,
+            Some(OrderType::ReverseTight) => &mut self.reverse_tight,
+            None => &mut self.unattributed,
+        }
+    }
+}
+
+/// Realized PnL of a maker's fills, attributed to the type of each filled
+/// order so liquidity styles can be compared on a market. Each type keeps
+/// its own inventory, as if run as a separate strategy.
+///
+/// Types come from place events. The orders reverse orders flip into are not
+/// logged, so register them from book snapshots with `register_order`.
+/// Fills of other unknown orders use the global flag of the fill and are
+/// otherwise unattributed.
+#[derive(Debug, Clone)]
+pub struct PositionTracker {
+    maker: Pubkey,
+    order_types: HashMap<u64, OrderType>,
+    pnl: PnlByOrderType,
+}
+
+impl PositionTracker {
+    pub fn new(maker: &Pubkey) -> Self {
+        PositionTracker {
+            maker: *maker,
+            order_types: HashMap::new(),
+            pnl: PnlByOrderType::default(),
+        }
+    }
+
+    pub fn register_order(&mut self, sequence_number: u64, order_type: OrderType) {
+        self.order_types.insert(sequence_number, order_type);
+    }
+
+    pub fn apply(&mut self, event: &OrderEvent) {
+        match event {
+            OrderEvent::Place { log, .. } if log.trader == self.maker => {
+                self.register_order(log.order_sequence_number, log.order_type);
+            }
+            OrderEvent::Fill { log, .. } if log.maker == self.maker => {
+                let order_type: Option<OrderType> = self
+                    .order_types
+                    .get(&log.maker_sequence_number)
+                    .copied()
+                    .or((log.is_maker_global.0 != 0).then_some(OrderType::Global));
+                let base_atoms: i128 = log.base_atoms.as_u64() as i128;
+                let quote_atoms: i128 = log.quote_atoms.as_u64() as i128;
+                // The maker is on the other side of the taker.
+                if log.taker_is_buy.0 != 0 {
+                    self.pnl.get_mut(order_type).apply(-base_atoms, quote_atoms);
+                } else {
+                    self.pnl.get_mut(order_type).apply(base_atoms, -quote_atoms);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn get_pnl(&self) -> &PnlByOrderType {
+        &self.pnl
+    }
+}
+
+/// Resting base atoms per price bucket at the end of one interval.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeatmapFrame {
+    pub start_slot: u64,
+    /// Indexed by `price_index` of the heatmap, lowest price first.
+    pub bid_base_atoms: Vec<u64>,
+    pub ask_base_atoms: Vec<u64>,
+}
+
+/// Time by price liquidity matrix. Every frame has a row for every bucket
+/// from the lowest to the highest price seen in any frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heatmap {
+    pub price_bucket: f64,
+    pub interval_slots: u64,
+    /// Bucket of the first row. Row `i` covers prices from
+    /// `(min_bucket + i) * price_bucket` up to the next bucket.
+    pub min_bucket: i64,
+    pub frames: Vec<HeatmapFrame>,
+}
+
+impl Heatmap {
+    pub fn get_bucket_price(&self, price_index: usize) -> f64 {
+        (self.min_bucket + price_index as i64) as f64 * self.price_bucket
+    }
+}
+
+/// Bucket `recorded_updates` into frames of `interval_slots` starting at the
+/// first update. Each frame shows the last update before it ends, so frames
+/// without updates repeat the previous one. Updates need to be in slot
+/// order. Prices are in quote atoms per base atom.
+pub fn heatmap_frames(
+    recorded_updates: &[StampedMarket],
+    price_bucket: f64,
+    interval_slots: u64,
+) -> Result<Heatmap> {
+    if price_bucket.is_nan() || price_bucket <= 0.0 || interval_slots == 0 {
+        return Err(Error::msg("Price bucket and interval need to be positive"));
+    }
+    if recorded_updates
+        .windows(2)
+        .any(|pair| pair[1].stamp < pair[0].stamp)
+    {
+        return Err(Error::msg("Recorded updates are not in slot order"));
+    }
+    let Some(first) = recorded_updates.first() else {
+        return Ok(Heatmap {
+            price_bucket,
+            interval_slots,
+            min_bucket: 0,
+            frames: Vec::new(),
+        });
+    };
+    let start_slot: u64 = first.stamp.slot;
+    let last_slot: u64 = recorded_updates.last().unwrap().stamp.slot;
+    let num_frames: u64 = (last_slot - start_slot) / interval_slots + 1;
+
+    // Liquidity by bucket for each frame before the rows are known.
+    let mut sparse_frames: Vec<(BTreeMap<i64, u64>, BTreeMap<i64, u64>)> = Vec::new();
+    let mut next_update: usize = 0;
+    for frame_index in 0..num_frames {
+        let end_slot: u64 = start_slot + (frame_index + 1) * interval_slots;
+        while next_update + 1 < recorded_updates.len()
+            && recorded_updates[next_update + 1].stamp.slot < end_slot
+        {
+            next_update += 1;
+        }
+        let market: &MarketValue = &recorded_updates[next_update].value;
+        let now_slot: u32 = (end_slot - 1).min(u32::MAX as u64) as u32;
+        let mut sides: [BTreeMap<i64, u64>; 2] = [BTreeMap::new(), BTreeMap::new()];
+        for (side, book) in sides.iter_mut().zip([market.get_bids(), market.get_asks()]) {
+            for order in book.resting_orders() {
+                if order.is_expired(now_slot) {
+                    continue;
+                }
+                let bucket: i64 = (order.get_price().as_f64() / price_bucket).floor() as i64;
+                *side.entry(bucket).or_default() += order.get_num_base_atoms().as_u64();
+            }
+        }
+        let [bids, asks] = sides;
+        sparse_frames.push((bids, asks));
+    }
+
+    let buckets = || {
+        sparse_frames
+            .iter()
+            .flat_map(|(bids, asks)| bids.keys().chain(asks.keys()))
+            .copied()
+    };
+    let min_bucket: i64 = buckets().min().unwrap_or(0);
+    let num_rows: usize = buckets()
+        .max()
+        .map_or(0, |max_bucket| (max_bucket - min_bucket) as usize + 1);
+    let dense = |side: &BTreeMap<i64, u64>| {
+        let mut row: Vec<u64> = vec![0; num_rows];
+        for (bucket, base_atoms) in side {
+            row[(bucket - min_bucket) as usize] = *base_atoms;
+        }
+        row
+    };
+    let frames: Vec<HeatmapFrame> = sparse_frames
+        .iter()
+        .enumerate()
+        .map(|(frame_index, (bids, asks))| HeatmapFrame {
+            start_slot: start_slot + frame_index as u64 * interval_slots,
+            bid_base_atoms: dense(bids),
+            ask_base_atoms: dense(asks),
+        })
+        .collect();
+    Ok(Heatmap {
+        price_bucket,
+        interval_slots,
+        min_bucket,
+        frames,
+    })
+}
+
+/// Resting base atoms at one price across markets of the same pair.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergedLevel {
+    pub price: QuoteAtomsPerBaseAtom,
+    pub base_atoms: BaseAtoms,
+    /// Base atoms per market at this price, by index into the markets passed
+    /// to `merge_books`, lowest index first.
+    pub sources: Vec<(usize, BaseAtoms)>,
+}
+
+/// Combined book of several markets, best level first on each side.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergedBook {
+    pub bids: Vec<MergedLevel>,
+    pub asks: Vec<MergedLevel>,
+    pub bid_base_atoms: BaseAtoms,
+    pub ask_base_atoms: BaseAtoms,
+}
+
+impl MergedBook {
+    pub fn best_bid(&self) -> Option<&MergedLevel> {
+        self.bids.first()
+    }
+
+    pub fn best_ask(&self) -> Option<&MergedLevel> {
+        self.asks.first()
+    }
+}
+
+/// Merge the books of markets that list the same pair. Expired orders are
+/// left out. Global orders are included whether or not their makers can
+/// back them, since that needs the global accounts.
+pub fn merge_books(markets: &[&MarketRef], now_slot: u32) -> Result<MergedBook> {
+    if let Some(first) = markets.first() {
+        if markets.iter().any(|market| {
+            market.get_base_mint() != first.get_base_mint()
+                || market.get_quote_mint() != first.get_quote_mint()
+        }) {
+            return Err(Error::msg("Markets do not all list the same pair"));
+        }
+    }
+
+    let mut sides: [BTreeMap<QuoteAtomsPerBaseAtom, MergedLevel>; 2] =
+        [BTreeMap::new(), BTreeMap::new()];
+    let mut totals: [BaseAtoms; 2] = [BaseAtoms::ZERO; 2];
+    for (market_index, market) in markets.iter().enumerate() {
+        for ((levels, total), book) in sides
+            .iter_mut()
+            .zip(totals.iter_mut())
+            .zip([market.get_bids(), market.get_asks()])
+        {
+            for order in book.resting_orders() {
+                if order.is_expired(now_slot) {
+                    continue;
+                }
+                let base_atoms: BaseAtoms = order.get_num_base_atoms();
+                let level: &mut MergedLevel =
+                    levels
+                        .entry(order.get_price())
+                        .or_insert_with(|| MergedLevel {
+                            price: order.get_price(),
+                            base_atoms: BaseAtoms::ZERO,
+                            sources: Vec::new(),
+                        });
+                level.base_atoms = level.base_atoms.checked_add(base_atoms)?;
+                match level.sources.last_mut() {
+                    Some((index, source_atoms)) if *index == market_index => {
+                        *source_atoms = source_atoms.checked_add(base_atoms)?;
+                    }
+                    _ => level.sources.push((market_index, base_atoms)),
+                }
+                *total = total.checked_add(base_atoms)?;
+            }
+        }
+    }
+
+    let [bids, asks] = sides;
+    Ok(MergedBook {
+        bids: bids.into_values().rev().collect(),
+        asks: asks.into_values().collect(),
+        bid_base_atoms: totals[0],
+        ask_base_atoms: totals[1],
+    })
+}
+
+/// Taker volume over a window of fills, see `flow_stats`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FlowStats {
+    pub num_slots: u64,
+    /// Base atoms bought by takers, which fill asks.
+    pub taker_buy_base_atoms: u64,
+    /// Base atoms sold by takers, which fill bids.
+    pub taker_sell_base_atoms: u64,
+}
+
+impl FlowStats {
+    /// Base atoms per slot that reach resting orders on the side of `is_bid`.
+    pub fn get_rate(&self, is_bid: bool) -> f64 {
+        if self.num_slots == 0 {
+            return 0.0;
+        }
+        let base_atoms: u64 = if is_bid {
+            self.taker_sell_base_atoms
+        } else {
+            self.taker_buy_base_atoms
+        };
+        base_atoms as f64 / self.num_slots as f64
+    }
+}
+
+pub fn flow_stats(events: &[OrderEvent], window: SlotWindow) -> FlowStats {
+    let mut stats: FlowStats = FlowStats {
+        num_slots: window.end_slot.saturating_sub(window.start_slot),
+        ..FlowStats::default()
+    };
+    for event in events {
+        if let OrderEvent::Fill { slot, log } = event {
+            if !window.contains(*slot) {
+                continue;
+            }
+            if log.taker_is_buy.0 != 0 {
+                stats.taker_buy_base_atoms += log.base_atoms.as_u64();
+            } else {
+                stats.taker_sell_base_atoms += log.base_atoms.as_u64();
+            }
+        }
+    }
+    stats
+}
+
+/// About four minutes of slots.
+pub const LOW_URGENCY_HORIZON_SLOTS: u64 = 600;
+/// About a minute of slots.
+pub const URGENCY_HORIZON_SLOTS: u64 = 150;
+
+/// How soon a limit order should fill, see `suggest_limit_price`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    /// Even odds of filling within `LOW_URGENCY_HORIZON_SLOTS`.
+    Low,
+    /// Even odds of filling within `URGENCY_HORIZON_SLOTS`.
+    Medium,
+    /// Nine in ten within `URGENCY_HORIZON_SLOTS`.
+    High,
+    /// Fill now by crossing the spread.
+    Immediate,
+}
+
+impl Urgency {
+    /// Fill probability to reach and the slots to reach it in. None for
+    /// `Immediate`.
+    pub fn get_target(&self) -> Option<(f64, u64)> {
+        match self {
+            Urgency::Low => Some((0.5, LOW_URGENCY_HORIZON_SLOTS)),
+            Urgency::Medium => Some((0.5, URGENCY_HORIZON_SLOTS)),
+            Urgency::High => Some((0.9, URGENCY_HORIZON_SLOTS)),
+            Urgency::Immediate => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LimitPriceSuggestion {
+    pub price: QuoteAtomsPerBaseAtom,
+    /// Estimated chance of a full fill within the horizon of the urgency.
+    /// One for a price that crosses, which fills on placement as far as the
+    /// book allows.
+    pub fill_probability: f64,
+    /// Where the order would rest. Default for a price that crosses.
+    pub queue_position: QueuePosition,
+    pub crosses: bool,
+}
+
+/// Chance that taker flow averaging `rate` base atoms per slot gets through
+/// `base_atoms` within `horizon_slots`, with the flow arriving at random at
+/// that rate.
+fn estimate_fill_probability(base_atoms: u64, rate: f64, horizon_slots: u64) -> f64 {
+    if base_atoms == 0 {
+        return 1.0;
+    }
+    1.0 - (-(rate * horizon_slots as f64) / base_atoms as f64).exp()
+}
+
+/// Limit price for `size` base atoms that is as passive as `urgency` allows.
+/// Each price already on the side of the order is a candidate, joining the
+/// back of its queue, and the first from the back of the book whose estimated
+/// fill probability meets the target wins. The estimate is the chance that
+/// taker flow at the rate of `flow` trades through the queue ahead and the
+/// order itself. When no resting price is enough, or for
+/// `Urgency::Immediate`, the suggestion is the worst price the size matches
+/// at as a taker. None when both sides are empty.
+pub fn suggest_limit_price(
+    is_bid: bool,
+    size: BaseAtoms,
+    urgency: Urgency,
+    flow: &FlowStats,
+    market: &MarketValue,
+    now_slot: u32,
+) -> Result<Option<LimitPriceSuggestion>> {
+    let (bookside, opposite) = if is_bid {
+        (market.get_bids(), market.get_asks())
+    } else {
+        (market.get_asks(), market.get_bids())
+    };
+
+    // Base atoms ahead of a new order at each resting price, best first.
+    let mut levels: Vec<(QuoteAtomsPerBaseAtom, BaseAtoms)> = Vec::new();
+    let mut base_atoms_ahead: BaseAtoms = BaseAtoms::ZERO;
+    for order in bookside.resting_orders() {
+        if order.is_expired(now_slot) {
+            continue;
+        }
+        base_atoms_ahead = base_atoms_ahead.checked_add(order.get_num_base_atoms())?;
+        match levels.last_mut() {
+            Some((price, level_atoms)) if *price == order.get_price() => {
+                *level_atoms = base_atoms_ahead;
+            }
+            _ => levels.push((order.get_price(), base_atoms_ahead)),
+        }
+    }
+    let rate: f64 = flow.get_rate(is_bid);
+    let passive_suggestion = |price: QuoteAtomsPerBaseAtom,
+                              base_atoms_ahead: BaseAtoms,
+                              horizon_slots: u64|
+     -> Result<LimitPriceSuggestion> {
+        Ok(LimitPriceSuggestion {
+            price,
+            fill_probability: estimate_fill_probability(
+                base_atoms_ahead.as_u64().saturating_add(size.as_u64()),
+                rate,
+                horizon_slots,
+            ),
+            queue_position: estimate_queue_position(market, is_bid, price, None, now_slot)?,
+            crosses: false,
+        })
+    };
+
+    if let Some((target_probability, horizon_slots)) = urgency.get_target() {
+        for (price, base_atoms_ahead) in levels.iter().rev() {
+            let suggestion: LimitPriceSuggestion =
+                passive_suggestion(*price, *base_atoms_ahead, horizon_slots)?;
+            if suggestion.fill_probability >= target_probability {
+                return Ok(Some(suggestion));
+            }
+        }
+    }
+
+    let mut crossing_price_opt: Option<QuoteAtomsPerBaseAtom> = None;
+    let mut remaining_base_atoms: BaseAtoms = size;
+    for order in opposite.resting_orders() {
+        if order.is_expired(now_slot) {
+            continue;
+        }
+        crossing_price_opt = Some(order.get_price());
+        if order.get_num_base_atoms() >= remaining_base_atoms {
+            break;
+        }
+        remaining_base_atoms = remaining_base_atoms.checked_sub(order.get_num_base_atoms())?;
+    }
+    if let Some(price) = crossing_price_opt {
+        return Ok(Some(LimitPriceSuggestion {
+            price,
+            fill_probability: 1.0,
+            queue_position: QueuePosition::default(),
+            crosses: true,
+        }));
+    }
+
+    // Nothing to take, so the best that can be done is the front of the book.
+    match levels.first() {
+        Some((price, base_atoms_ahead)) => {
+            let horizon_slots: u64 = urgency
+                .get_target()
+                .map_or(URGENCY_HORIZON_SLOTS, |(_, horizon_slots)| horizon_slots);
+            Ok(Some(passive_suggestion(
+                *price,
+                *base_atoms_ahead,
+                horizon_slots,
+            )?))
+        }
+        None => Ok(None),
+    }
+}
+
+#[cfg(all(test, feature = "model"))]
+mod test {
+    use super::*;
+    use crate::{
+        model::{ModelOperation, ModelRunner},
+        sync::{Stamped, StateStamp},
+    };
+    #[test]
+    fn test_project_balances() {
+        let trader: Pubkey = Pubkey::new_unique();
+        let mut runner: ModelRunner = ModelRunner::new(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        );
+        runner.claim_seat(&trader).unwrap();
+        runner
+            .apply(&ModelOperation::Deposit {
+                trader,
+                amount_atoms: 1_000,
+                is_base: false,
+            })
+            .unwrap();
+        // Bid for 100 base at 2 quote each. Locks 200 quote.
+        let price: QuoteAtomsPerBaseAtom =
+            QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(2, 0).unwrap();
+        runner
+            .apply(&ModelOperation::PlaceOrder {
+                trader,
+                num_base_atoms: BaseAtoms::new(100),
+                price,
+                is_bid: true,
+                order_type: OrderType::Limit,
+            })
+            .unwrap();
+
+        let projection: BalanceProjection = project_balances(
+            &runner.market,
+            &trader,
+            &[
+                HypotheticalFill {
+                    base_atoms: BaseAtoms::new(40),
+                    price,
+                    is_bid: true,
+                    maker_order_sequence_number: Some(0),
+                },
+                HypotheticalFill {
+                    base_atoms: BaseAtoms::new(100),
+                    price,
+                    is_bid: true,
+                    maker_order_sequence_number: None,
+                },
+            ],
+            &[
+                WithdrawalIntent {
+                    amount_atoms: 600,
+                    is_base: false,
+                },
+                WithdrawalIntent {
+                    amount_atoms: 140,
+                    is_base: true,
+                },
+                WithdrawalIntent {
+                    amount_atoms: 1,
+                    is_base: false,
+                },
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(projection.base_locked, BaseAtoms::new(0));
+        assert_eq!(projection.quote_locked, QuoteAtoms::new(120));
+        assert_eq!(projection.base_withdrawable, BaseAtoms::ZERO);
+        assert_eq!(projection.quote_withdrawable, QuoteAtoms::ZERO);
+        assert_eq!(projection.infeasible_withdrawals, vec![2]);
+    }
+
+    #[test]
+    fn test_maker_fill_stats() {
+        let market: Pubkey = Pubkey::new_unique();
+        let maker: Pubkey = Pubkey::new_unique();
+        let place = |slot: u64, order_sequence_number: u64| OrderEvent::Place {
+            slot,
+            log: PlaceOrderLog {
+                market,
+                trader: maker,
+                price: QuoteAtomsPerBaseAtom::ZERO,
+                base_atoms: BaseAtoms::new(10),
+                order_sequence_number,
+                order_index: 0,
+                last_valid_slot: 0,
+                order_type: OrderType::Limit,
+                is_bid: false.into(),
+                _padding: [0; 6],
+            },
+        };
+        let fill = |slot: u64, maker_sequence_number: u64| OrderEvent::Fill {
+            slot,
+            log: FillLog {
+                market,
+                maker,
+                taker: Pubkey::new_unique(),
+                base_mint: Pubkey::new_unique(),
+                quote_mint: Pubkey::new_unique(),
+                price: QuoteAtomsPerBaseAtom::ZERO,
+                base_atoms: BaseAtoms::new(5),
+                quote_atoms: QuoteAtoms::ZERO,
+                maker_sequence_number,
+                taker_sequence_number: 100,
+                taker_is_buy: true.into(),
+                is_maker_global: false.into(),
+                _padding: [0; 14],
+            },
+        };
+        let cancel =
+            |slot: u64, order_sequence_number: u64, reason: CancelReason| OrderEvent::Cancel {
+                slot,
+                log: CancelOrderLog {
+                    market,
+                    trader: maker,
+                    order_sequence_number,
+                },
+                reason,
+            };
+
+        let events: Vec<OrderEvent> = vec![
+            place(1, 0),
+            place(2, 1),
+            place(3, 2),
+            fill(11, 0),
+            fill(22, 1),
+            place(4, 3),
+            cancel(23, 2, CancelReason::User),
+            cancel(24, 3, CancelReason::Expired),
+            // Outside of the window.
+            fill(40, 1),
+        ];
+        let stats: BTreeMap<Pubkey, MakerFillStats> = maker_fill_stats(
+            &events,
+            SlotWindow {
+                start_slot: 10,
+                end_slot: 30,
+            },
+        );
+        let maker_stats: &MakerFillStats = stats.get(&maker).unwrap();
+        assert_eq!(maker_stats.fill_count, 2);
+        assert_eq!(maker_stats.filled_base_atoms, BaseAtoms::new(10));
+        assert_eq!(maker_stats.cancel_count, 1);
+        assert_eq!(maker_stats.forced_cancel_count, 1);
+        assert_eq!(maker_stats.average_resting_slots, Some(15.0));
+        assert_eq!(maker_stats.fill_to_cancel_ratio, Some(2.0));
+    }
+
+    #[test]
+    fn test_reverse_fee_report() {
+        let maker: Pubkey = Pubkey::new_unique();
+        let price: QuoteAtomsPerBaseAtom =
+            QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(2, 0).unwrap();
+        let mut reverse_ask: RestingOrder = RestingOrder::new(
+            0,
+            BaseAtoms::new(100),
+            price,
+            0,
+            manifest::state::NO_EXPIRATION_LAST_VALID_SLOT,
+            false,
+            OrderType::Reverse,
+        )
+        .unwrap();
+        // 10%
+        reverse_ask.set_reverse_spread(10_000);
+        let limit_ask: RestingOrder = RestingOrder::new(
+            0,
+            BaseAtoms::new(100),
+            price,
+            1,
+            manifest::state::NO_EXPIRATION_LAST_VALID_SLOT,
+            false,
+            OrderType::Limit,
+        )
+        .unwrap();
+        let fill = |slot: u64, maker_sequence_number: u64| OrderEvent::Fill {
+            slot,
+            log: FillLog {
+                market: Pubkey::new_unique(),
+                maker,
+                taker: Pubkey::new_unique(),
+                base_mint: Pubkey::new_unique(),
+                quote_mint: Pubkey::new_unique(),
+                price,
+                base_atoms: BaseAtoms::new(100),
+                quote_atoms: QuoteAtoms::new(200),
+                maker_sequence_number,
+                taker_sequence_number: 100,
+                taker_is_buy: true.into(),
+                is_maker_global: false.into(),
+                _padding: [0; 14],
+            },
+        };
+
+        let report: BTreeMap<Pubkey, ReverseFeeStats> = reverse_fee_report(
+            &[fill(5, 0), fill(6, 1), fill(50, 0)],
+            &[reverse_ask, limit_ask],
+            SlotWindow {
+                start_slot: 0,
+                end_slot: 10,
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            report.get(&maker).unwrap(),
+            &ReverseFeeStats {
+                fill_count: 1,
+                filled_base_atoms: BaseAtoms::new(100),
+                // Flips into a bid at 1.8.
+                spread_quote_atoms: QuoteAtoms::new(20),
+            }
+        );
+    }
+
+    #[test]
+    fn test_position_tracker() {
+        let maker: Pubkey = Pubkey::new_unique();
+        let price: QuoteAtomsPerBaseAtom =
+            QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(2, 0).unwrap();
+        let place = |order_sequence_number: u64, order_type: OrderType| OrderEvent::Place {
+            slot: 1,
+            log: PlaceOrderLog {
+                market: Pubkey::new_unique(),
+                trader: maker,
+                price,
+                base_atoms: BaseAtoms::new(100),
+                order_sequence_number,
+                order_index: 0,
+                last_valid_slot: 0,
+                order_type,
+                is_bid: true.into(),
+                _padding: [0; 6],
+            },
+        };
+        let fill = |maker_sequence_number: u64,
+                    base_atoms: u64,
+                    quote_atoms: u64,
+                    taker_is_buy: bool,
+                    is_maker_global: bool| OrderEvent::Fill {
+            slot: 2,
+            log: FillLog {
+                market: Pubkey::new_unique(),
+                maker,
+                taker: Pubkey::new_unique(),
+                base_mint: Pubkey::new_unique(),
+                quote_mint: Pubkey::new_unique(),
+                price,
+                base_atoms: BaseAtoms::new(base_atoms),
+                quote_atoms: QuoteAtoms::new(quote_atoms),
+                maker_sequence_number,
+                taker_sequence_number: 100,
+                taker_is_buy: taker_is_buy.into(),
+                is_maker_global: is_maker_global.into(),
+                _padding: [0; 14],
+            },
+        };
+
+        let mut tracker: PositionTracker = PositionTracker::new(&maker);
+        tracker.register_order(3, OrderType::ReverseTight);
+        for event in [
+            place(1, OrderType::Limit),
+            place(2, OrderType::Reverse),
+            // Limit buys 10 for 20 and sells 4 for 12, then 8 for 16.
+            fill(1, 10, 20, false, false),
+            fill(1, 4, 12, true, false),
+            fill(1, 8, 16, true, false),
+            fill(2, 5, 10, true, false),
+            fill(3, 5, 10, false, false),
+            fill(4, 5, 10, true, true),
+            fill(5, 5, 10, true, false),
+        ] {
+            tracker.apply(&event);
+        }
+
+        let pnl: &PnlByOrderType = tracker.get_pnl();
+        assert_eq!(
+            pnl.limit,
+            PositionPnl {
+                position_base_atoms: -2,
+                cost_quote_atoms: -4,
+                realized_quote_atoms: 4,
+            }
+        );
+        assert_eq!(pnl.reverse.position_base_atoms, -5);
+        assert_eq!(pnl.reverse_tight.position_base_atoms, 5);
+        assert_eq!(pnl.global.position_base_atoms, -5);
+        assert_eq!(pnl.unattributed.position_base_atoms, -5);
+        assert_eq!(pnl.get_total_realized_quote_atoms(), 4);
+    }
+
+    #[test]
+    fn test_heatmap_frames() {
+        let trader: Pubkey = Pubkey::new_unique();
+        let mut runner: ModelRunner = ModelRunner::new(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        );
+        runner.claim_seat(&trader).unwrap();
+        for is_base in [true, false] {
+            runner
+                .apply(&ModelOperation::Deposit {
+                    trader,
+                    amount_atoms: 10_000,
+                    is_base,
+                })
+                .unwrap();
+        }
+        let stamped = |runner: &ModelRunner, slot: u64| {
+            Stamped::new(
+                StateStamp {
+                    slot,
+                    write_version: 0,
+                },
+                runner.market.clone(),
+            )
+        };
+        let place = |runner: &mut ModelRunner, mantissa: u32, is_bid: bool| {
+            runner
+                .apply(&ModelOperation::PlaceOrder {
+                    trader,
+                    num_base_atoms: BaseAtoms::new(100),
+                    price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(mantissa, 0)
+                        .unwrap(),
+                    is_bid,
+                    order_type: OrderType::Limit,
+                })
+                .unwrap();
+        };
+        place(&mut runner, 10, true);
+        let mut updates: Vec<StampedMarket> = vec![stamped(&runner, 100)];
+        place(&mut runner, 12, false);
+        place(&mut runner, 13, false);
+        updates.push(stamped(&runner, 125));
+
+        let heatmap: Heatmap = heatmap_frames(&updates, 2.0, 10).unwrap();
+        // Buckets 5 and 6, covering prices 10 up to 14.
+        assert_eq!(heatmap.min_bucket, 5);
+        assert_eq!(heatmap.get_bucket_price(1), 12.0);
+        assert_eq!(heatmap.frames.len(), 3);
+        assert_eq!(
+            heatmap.frames[1],
+            HeatmapFrame {
+                start_slot: 110,
+                bid_base_atoms: vec![100, 0],
+                ask_base_atoms: vec![0, 0],
+            }
+        );
+        assert_eq!(heatmap.frames[2].ask_base_atoms, vec![0, 200]);
+
+        updates.swap(0, 1);
+        assert!(heatmap_frames(&updates, 2.0, 10).is_err());
+    }
+
+    #[test]
+    fn test_merge_books() {
+        let trader: Pubkey = Pubkey::new_unique();
+        let base_mint: Pubkey = Pubkey::new_unique();
+        let quote_mint: Pubkey = Pubkey::new_unique();
+        let new_runner = || {
+            let mut runner: ModelRunner =
+                ModelRunner::new(&Pubkey::new_unique(), &base_mint, &quote_mint);
+            runner.claim_seat(&trader).unwrap();
+            for is_base in [true, false] {
+                runner
+                    .apply(&ModelOperation::Deposit {
+                        trader,
+                        amount_atoms: 10_000,
+                        is_base,
+                    })
+                    .unwrap();
+            }
+            runner
+        };
+        let place = |runner: &mut ModelRunner, mantissa: u32, base_atoms: u64, is_bid: bool| {
+            runner
+                .apply(&ModelOperation::PlaceOrder {
+                    trader,
+                    num_base_atoms: BaseAtoms::new(base_atoms),
+                    price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(mantissa, 0)
+                        .unwrap(),
+                    is_bid,
+                    order_type: OrderType::Limit,
+                })
+                .unwrap();
+        };
+        let mut first: ModelRunner = new_runner();
+        place(&mut first, 10, 100, true);
+        place(&mut first, 12, 100, false);
+        place(&mut first, 12, 50, false);
+        let mut second: ModelRunner = new_runner();
+        place(&mut second, 11, 30, true);
+        place(&mut second, 12, 20, false);
+        let market_ref = |runner: &ModelRunner| MarketRef {
+            fixed: &runner.market.fixed,
+            dynamic: &runner.market.dynamic,
+        };
+
+        let merged: MergedBook =
+            merge_books(&[&market_ref(&first), &market_ref(&second)], 0).unwrap();
+        let price = |mantissa: u32| {
+            QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(mantissa, 0).unwrap()
+        };
+        assert_eq!(
+            merged.bids,
+            vec![
+                MergedLevel {
+                    price: price(11),
+                    base_atoms: BaseAtoms::new(30),
+                    sources: vec![(1, BaseAtoms::new(30))],
+                },
+                MergedLevel {
+                    price: price(10),
+                    base_atoms: BaseAtoms::new(100),
+                    sources: vec![(0, BaseAtoms::new(100))],
+                },
+            ]
+        );
+        assert_eq!(
+            merged.best_ask(),
+            Some(&MergedLevel {
+                price: price(12),
+                base_atoms: BaseAtoms::new(170),
+                sources: vec![(0, BaseAtoms::new(150)), (1, BaseAtoms::new(20))],
+            })
+        );
+        assert_eq!(merged.bid_base_atoms, BaseAtoms::new(130));
+        assert_eq!(merged.ask_base_atoms, BaseAtoms::new(170));
+
+        let other_pair: ModelRunner =
+            ModelRunner::new(&Pubkey::new_unique(), &base_mint, &Pubkey::new_unique());
+        assert!(merge_books(&[&market_ref(&first), &market_ref(&other_pair)], 0).is_err());
+    }
+
+    #[test]
+    fn test_suggest_limit_price() {
+        let maker: Pubkey = Pubkey::new_unique();
+        let mut runner: ModelRunner = ModelRunner::new(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        );
+        runner.claim_seat(&maker).unwrap();
+        for is_base in [true, false] {
+            runner
+                .apply(&ModelOperation::Deposit {
+                    trader: maker,
+                    amount_atoms: 10_000,
+                    is_base,
+                })
+                .unwrap();
+        }
+        let price = |mantissa: u32| -> QuoteAtomsPerBaseAtom {
+            QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(mantissa, -1).unwrap()
+        };
+        // Bids of 100 at 1 and 1_000 at 0.5, an ask of 100 at 2.
+        for (num_base_atoms, mantissa, is_bid) in
+            [(100, 10, true), (1_000, 5, true), (100, 20, false)]
+        {
+            runner
+                .apply(&ModelOperation::PlaceOrder {
+                    trader: maker,
+                    num_base_atoms: BaseAtoms::new(num_base_atoms),
+                    price: price(mantissa),
+                    is_bid,
+                    order_type: OrderType::Limit,
+                })
+                .unwrap();
+        }
+
+        let fill = |slot: u64, taker_is_buy: bool| OrderEvent::Fill {
+            slot,
+            log: FillLog {
+                market: *runner.get_market_key(),
+                maker,
+                taker: Pubkey::new_unique(),
+                base_mint: Pubkey::new_unique(),
+                quote_mint: Pubkey::new_unique(),
+                price: QuoteAtomsPerBaseAtom::ZERO,
+                base_atoms: BaseAtoms::new(500),
+                quote_atoms: QuoteAtoms::ZERO,
+                maker_sequence_number: 0,
+                taker_sequence_number: 100,
+                taker_is_buy: taker_is_buy.into(),
+                is_maker_global: false.into(),
+                _padding: [0; 14],
+            },
+        };
+        let flow: FlowStats = flow_stats(
+            &[
+                fill(10, false),
+                fill(20, false),
+                fill(30, true),
+                fill(200, false),
+            ],
+            SlotWindow {
+                start_slot: 0,
+                end_slot: 100,
+            },
+        );
+        assert_eq!(
+            flow,
+            FlowStats {
+                num_slots: 100,
+                taker_buy_base_atoms: 500,
+                taker_sell_base_atoms: 1_000,
+            }
+        );
+        assert_eq!(flow.get_rate(true), 10.0);
+
+        // 1_500 base of sell flow expected over the horizon against 1_200
+        // ahead of and including the order at 0.5.
+        let suggest = |is_bid: bool, size: u64, urgency: Urgency, flow: &FlowStats| {
+            suggest_limit_price(
+                is_bid,
+                BaseAtoms::new(size),
+                urgency,
+                flow,
+                &runner.market,
+                0,
+            )
+            .unwrap()
+            .unwrap()
+        };
+        let suggestion: LimitPriceSuggestion = suggest(true, 100, Urgency::Medium, &flow);
+        assert_eq!(suggestion.price, price(5));
+        assert!(!suggestion.crosses);
+        assert!((suggestion.fill_probability - (1.0 - (-1.25_f64).exp())).abs() < 1e-9);
+        assert_eq!(suggestion.queue_position.orders_ahead, 2);
+        assert_eq!(
+            suggestion.queue_position.base_atoms_ahead,
+            BaseAtoms::new(1_100)
+        );
+
+        let suggestion: LimitPriceSuggestion = suggest(true, 100, Urgency::High, &flow);
+        assert_eq!(suggestion.price, price(10));
+        assert!(suggestion.fill_probability >= 0.9);
+
+        let suggestion: LimitPriceSuggestion = suggest(true, 100, Urgency::Immediate, &flow);
+        assert_eq!(suggestion.price, price(20));
+        assert!(suggestion.crosses);
+        // No flow to fill a resting order, so cross.
+        assert!(suggest(true, 100, Urgency::Low, &FlowStats::default()).crosses);
+        // Selling 150 takes both bids.
+        assert_eq!(
+            suggest(false, 150, Urgency::Immediate, &flow).price,
+            price(5)
+        );
+    }
+}
@@ -0,0 +1,186 @@
+//! Lamport accounting across Manifest accounts for treasury reviews.
+
+use hypertree::get_helper;
+use manifest::state::{
+    GlobalFixed, GlobalValue, MarketFixed, GLOBAL_FIXED_DISCRIMINANT, MARKET_FIXED_DISCRIMINANT,
+};
+use solana_sdk::{account::Account, pubkey::Pubkey, rent::Rent};
+use std::{collections::HashMap, mem::size_of};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditedAccountKind {
+    Market,
+    Global,
+    MarketVault,
+    GlobalVault,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LamportEntry {
+    pub key: Pubkey,
+    pub kind: AuditedAccountKind,
+    pub lamports: u64,
+    pub rent_exempt_minimum: u64,
+    /// Part of the excess that is gas prepayments for global orders. These
+    /// belong to whoever removes the orders, so they are not reclaimable.
+    pub gas_escrowed: u64,
+}
+
+impl LamportEntry {
+    pub fn get_excess(&self) -> u64 {
+        self.lamports.saturating_sub(self.rent_exempt_minimum)
+    }
+
+    /// Excess that is not owed to anyone.
+    pub fn get_reclaimable(&self) -> u64 {
+        self.get_excess().saturating_sub(self.gas_escrowed)
+    }
+
+    pub fn is_over_funded(&self) -> bool {
+        self.get_reclaimable() > 0
+    }
+
+    pub fn is_under_funded(&self) -> bool {
+        self.lamports < self.rent_exempt_minimum
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LamportReport {
+    pub entries: Vec<LamportEntry>,
+    pub total_lamports: u64,
+    pub total_rent_exempt_minimum: u64,
+    pub total_gas_escrowed: u64,
+    pub total_reclaimable: u64,
+}
+
+impl LamportReport {
+    pub fn over_funded(&self) -> impl Iterator<Item = &LamportEntry> {
+        self.entries.iter().filter(|entry| entry.is_over_funded())
+    }
+
+    pub fn under_funded(&self) -> impl Iterator<Item = &LamportEntry> {
+        self.entries.iter().filter(|entry| entry.is_under_funded())
+    }
+}
+
+fn get_discriminant(data: &[u8]) -> Option<u64> {
+    Some(u64::from_le_bytes(data.get(..8)?.try_into().ok()?))
+}
+
+/// Classify the given accounts and compare their lamports against rent
+/// exemption. Markets and globals are recognized by owner and discriminant.
+/// Vaults are only recognized when the market or global they belong to is in
+/// `accounts` too. Anything else is left out of the report. Uses the default
+/// rent, which is what mainnet charges.
+pub fn lamport_report(accounts: &[(Pubkey, Account)]) -> LamportReport {
+    let rent: Rent = Rent::default();
+    let mut kinds: HashMap<Pubkey, AuditedAccountKind> = HashMap::new();
+    let mut gas_escrowed: HashMap<Pubkey, u64> = HashMap::new();
+
+    for (key, account) in accounts.iter() {
+        if account.owner != manifest::id() {
+            continue;
+        }
+        match get_discriminant(&account.data) {
+            Some(MARKET_FIXED_DISCRIMINANT) if account.data.len() >= size_of::<MarketFixed>() => {
+                let market_fixed: &MarketFixed = get_helper::<MarketFixed>(&account.data, 0_u32);
+                kinds.insert(*key, AuditedAccountKind::Market);
+                kinds.insert(
+                    *market_fixed.get_base_vault(),
+                    AuditedAccountKind::MarketVault,
+                );
+                kinds.insert(
+                    *market_fixed.get_quote_vault(),
+                    AuditedAccountKind::MarketVault,
+                );
+            }
+            Some(GLOBAL_FIXED_DISCRIMINANT) if account.data.len() >= size_of::<GlobalFixed>() => {
+                let (header_bytes, dynamic_data) = account.data.split_at(size_of::<GlobalFixed>());
+                let global: GlobalValue = GlobalValue {
+                    fixed: *get_helper::<GlobalFixed>(header_bytes, 0_u32),
+                    dynamic: dynamic_data.to_vec(),
+                };
+                kinds.insert(*key, AuditedAccountKind::Global);
+                kinds.insert(*global.fixed.get_vault(), AuditedAccountKind::GlobalVault);
+                gas_escrowed.insert(*key, global.total_gas_escrowed(account.lamports, &rent));
+            }
+            _ => {}
+        }
+    }
+
+    let mut report: LamportReport = LamportReport::default();
+    for (key, account) in accounts.iter() {
+        let Some(kind) = kinds.get(key) else {
+            continue;
+        };
+        let entry: LamportEntry = LamportEntry {
+            key: *key,
+            kind: *kind,
+            lamports: account.lamports,
+            rent_exempt_minimum: rent.minimum_balance(account.data.len()),
+            gas_escrowed: gas_escrowed.get(key).copied().unwrap_or_default(),
+        };
+        report.total_lamports += entry.lamports;
+        report.total_rent_exempt_minimum += entry.rent_exempt_minimum;
+        report.total_gas_escrowed += entry.gas_escrowed;
+        report.total_reclaimable += entry.get_reclaimable();
+        report.entries.push(entry);
+    }
+    report
+}
+
+#[cfg(all(test, feature = "client", feature = "model"))]
+mod test {
+    use super::*;
+    use crate::{
+        model::ModelRunner,
+        rpc::{AccountFetcher, MockRpc},
+    };
+    use manifest::state::{GAS_DEPOSIT_LAMPORTS, GLOBAL_BLOCK_SIZE};
+
+    #[test]
+    fn test_lamport_report() {
+        let market_key: Pubkey = Pubkey::new_unique();
+        let base_mint: Pubkey = Pubkey::new_unique();
+        let quote_mint: Pubkey = Pubkey::new_unique();
+        let global_key: Pubkey = Pubkey::new_unique();
+        let runner: ModelRunner = ModelRunner::new(&market_key, &base_mint, &quote_mint);
+        let market_fixed: MarketFixed = runner.market.fixed;
+
+        let mut rpc: MockRpc = MockRpc::new();
+        rpc.set_model(&runner);
+        rpc.set_global(
+            &global_key,
+            &GlobalValue {
+                fixed: GlobalFixed::new_empty(&quote_mint),
+                dynamic: vec![0; GLOBAL_BLOCK_SIZE],
+            },
+        );
+        let mut market_account: Account = rpc.get_account(&market_key).unwrap();
+        market_account.lamports += 1_000;
+        let mut global_account: Account = rpc.get_account(&global_key).unwrap();
+        global_account.lamports += 2 * GAS_DEPOSIT_LAMPORTS;
+        let base_vault: Account = Account {
+            lamports: Rent::default().minimum_balance(165),
+            data: vec![0; 165],
+            owner: spl_token::id(),
+            executable: false,
+            rent_epoch: 0,
+        };
+
+        let report: LamportReport = lamport_report(&[
+            (market_key, market_account),
+            (global_key, global_account),
+            (*market_fixed.get_base_vault(), base_vault),
+            (Pubkey::new_unique(), Account::default()),
+        ]);
+        assert_eq!(report.entries.len(), 3);
+        assert_eq!(report.total_gas_escrowed, 2 * GAS_DEPOSIT_LAMPORTS);
+        assert_eq!(report.total_reclaimable, 1_000);
+        let over_funded: Vec<&LamportEntry> = report.over_funded().collect();
+        assert_eq!(over_funded.len(), 1);
+        assert_eq!(over_funded[0].kind, AuditedAccountKind::Market);
+        assert_eq!(report.under_funded().count(), 0);
+    }
+}
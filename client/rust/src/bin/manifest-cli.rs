@@ -0,0 +1,88 @@
+//! Support tooling for Manifest markets.
+//!
+//! manifest-cli diff <snapshot_a> <snapshot_b> [--json] [--labels <file>]
+//! manifest-cli repro <case>
+//!
+//! Snapshots are raw market account data, for example from
+//! `solana account <market> --output-file <path>`. The labels file has a
+//! pubkey and a name per line and is used to name traders in the output.
+//! Cases are `.mfst-case` files recorded by `ModelRunner::new_recording` and
+//! are replayed against the matching engine until the first divergence.
+
+use anyhow::{bail, Context, Result};
+use manifest::state::MarketValue;
+use manifest_jupiter::{
+    display::TraderRegistry,
+    model::ModelRunner,
+    repro::ReproCase,
+    snapshot::{diff_markets, load_market_snapshot, MarketDiff},
+};
+
+const USAGE: &str = "Usage: manifest-cli diff <snapshot_a> <snapshot_b> [--json] [--labels <file>]
+       manifest-cli repro <case>";
+
+fn load(path: &str) -> Result<MarketValue> {
+    let data: Vec<u8> = std::fs::read(path).with_context(|| format!("Failed to read {path}"))?;
+    load_market_snapshot(&data).with_context(|| format!("Failed to load {path}"))
+}
+
+fn diff(args: &[String]) -> Result<()> {
+    let mut json: bool = false;
+    let mut labels: TraderRegistry = TraderRegistry::new();
+    let mut paths: Vec<&String> = Vec::new();
+    let mut args_iter = args.iter();
+    while let Some(arg) = args_iter.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--labels" => {
+                let Some(labels_path) = args_iter.next() else {
+                    bail!(USAGE);
+                };
+                labels = TraderRegistry::load_file(labels_path)
+                    .with_context(|| format!("Failed to load {labels_path}"))?;
+            }
+            _ => paths.push(arg),
+        }
+    }
+    let [path_a, path_b] = paths.as_slice() else {
+        bail!(USAGE);
+    };
+    let market_diff: MarketDiff = diff_markets(&load(path_a)?, &load(path_b)?)?;
+    if json {
+        println!("{}", market_diff.to_json());
+    } else {
+        print!("{}", market_diff.with_labels(&labels));
+    }
+    Ok(())
+}
+
+fn repro(args: &[String]) -> Result<()> {
+    let [path] = args else {
+        bail!(USAGE);
+    };
+    let case: ReproCase =
+        ReproCase::load(path).with_context(|| format!("Failed to load {path}"))?;
+    println!(
+        "Market {} base {} quote {}, {} steps",
+        case.market_key,
+        case.base_mint,
+        case.quote_mint,
+        case.steps.len()
+    );
+    let runner: ModelRunner = case.replay()?;
+    println!(
+        "No divergence, {} bids and {} asks resting",
+        runner.model.bids().len(),
+        runner.model.asks().len()
+    );
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("diff") => diff(&args[1..]),
+        Some("repro") => repro(&args[1..]),
+        _ => bail!(USAGE),
+    }
+}
@@ -0,0 +1,130 @@
+//! Owned copies of book sides. Iterating a `BooksideReadOnly` borrows the
+//! market account data for as long as the iteration runs, so long analytics
+//! runs should capture a `BooksideSnapshot` first and work on that.
+
+use hypertree::{DataIndex, HyperTreeValueIteratorTrait};
+use manifest::state::{BooksideReadOnly, MarketValue, RestingOrder};
+
+#[derive(Debug, Clone, Copy)]
+pub struct OrderView {
+    /// Where the order was in the market account when captured. Can be
+    /// passed as a cancel hint while the order is still resting.
+    pub order_index: DataIndex,
+    pub order: RestingOrder,
+}
+
+/// Orders of one side of the book in matching priority, best first.
+#[derive(Debug, Clone)]
+pub struct BooksideSnapshot {
+    pub slot: u64,
+    pub is_bid: bool,
+    pub orders: Vec<OrderView>,
+}
+
+impl BooksideSnapshot {
+    pub fn capture(bookside: &BooksideReadOnly, is_bid: bool, slot: u64) -> Self {
+        BooksideSnapshot {
+            slot,
+            is_bid,
+            orders: bookside
+                .iter::<RestingOrder>()
+                .map(|(order_index, order)| OrderView {
+                    order_index,
+                    order: *order,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn capture_bids(market: &MarketValue, slot: u64) -> Self {
+        Self::capture(&market.get_bids(), true, slot)
+    }
+
+    pub fn capture_asks(market: &MarketValue, slot: u64) -> Self {
+        Self::capture(&market.get_asks(), false, slot)
+    }
+}
+
+/// Anything that yields the resting orders of a book side best first. Lets
+/// analytics run on either the live book or a snapshot of it.
+pub trait OrderSource {
+    fn resting_orders(&self) -> Box<dyn Iterator<Item = RestingOrder> + '_>;
+}
+
+impl OrderSource for BooksideReadOnly<'_> {
+    fn resting_orders(&self) -> Box<dyn Iterator<Item = RestingOrder> + '_> {
+        Box::new(self.iter::<RestingOrder>().map(|(_, order)| *order))
+    }
+}
+
+impl OrderSource for BooksideSnapshot {
+    fn resting_orders(&self) -> Box<dyn Iterator<Item = RestingOrder> + '_> {
+        Box::new(self.orders.iter().map(|view| view.order))
+    }
+}
+
+#[cfg(all(test, feature = "analytics", feature = "model"))]
+mod test {
+    use super::*;
+    use crate::{
+        analytics::{project_balances, project_seat_balances, BalanceProjection},
+        model::{ModelOperation, ModelRunner},
+    };
+    use manifest::{
+        quantities::{BaseAtoms, QuoteAtomsPerBaseAtom},
+        state::OrderType,
+    };
+    use solana_program::pubkey::Pubkey;
+
+    #[test]
+    fn test_snapshot_matches_live_book() {
+        let trader: Pubkey = Pubkey::new_unique();
+        let mut runner: ModelRunner = ModelRunner::new(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        );
+        runner.claim_seat(&trader).unwrap();
+        runner
+            .apply(&ModelOperation::Deposit {
+                trader,
+                amount_atoms: 1_000,
+                is_base: false,
+            })
+            .unwrap();
+        for mantissa in [1, 3, 2] {
+            runner
+                .apply(&ModelOperation::PlaceOrder {
+                    trader,
+                    num_base_atoms: BaseAtoms::new(10),
+                    price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(mantissa, 0)
+                        .unwrap(),
+                    is_bid: true,
+                    order_type: OrderType::Limit,
+                })
+                .unwrap();
+        }
+
+        let bids: BooksideSnapshot = BooksideSnapshot::capture_bids(&runner.market, 7);
+        let asks: BooksideSnapshot = BooksideSnapshot::capture_asks(&runner.market, 7);
+        assert_eq!(bids.orders.len(), 3);
+        assert!(asks.orders.is_empty());
+        // Best bid first.
+        assert_eq!(bids.orders[0].order.get_sequence_number(), 1);
+        assert_eq!(bids.orders[2].order.get_sequence_number(), 0);
+
+        let live: BalanceProjection = project_balances(&runner.market, &trader, &[], &[]).unwrap();
+        let (base_withdrawable, quote_withdrawable) = runner.market.get_trader_balance(&trader);
+        let from_snapshot: BalanceProjection = project_seat_balances(
+            runner.market.get_trader_index(&trader),
+            base_withdrawable,
+            quote_withdrawable,
+            &bids,
+            &asks,
+            &[],
+            &[],
+        )
+        .unwrap();
+        assert_eq!(live, from_snapshot);
+    }
+}
@@ -0,0 +1,134 @@
+//! Lamports a trader pays to place orders, on top of the funds that back them.
+//! Meant for showing users a single figure before they confirm.
+
+use anyhow::Result;
+use manifest::{
+    program::batch_update::PlaceOrderParams,
+    state::{MarketFixed, MarketValue, OrderType, GAS_DEPOSIT_LAMPORTS, MARKET_BLOCK_SIZE},
+};
+use solana_program::rent::Rent;
+use std::mem::size_of;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PriorityFee {
+    pub compute_unit_limit: u32,
+    pub micro_lamports_per_compute_unit: u64,
+}
+
+impl PriorityFee {
+    pub fn get_lamports(&self) -> u64 {
+        (self.compute_unit_limit as u128 * self.micro_lamports_per_compute_unit as u128)
+            .div_ceil(1_000_000) as u64
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PlacementCost {
+    /// Rent for the blocks the market is expanded by.
+    pub expand_rent_lamports: u64,
+    /// Gas prepayment for global orders, claimable by whoever cleans them up
+    /// if they are later unbacked or expired.
+    pub global_gas_lamports: u64,
+    pub priority_fee_lamports: u64,
+}
+
+impl PlacementCost {
+    pub fn get_total_lamports(&self) -> u64 {
+        self.expand_rent_lamports
+            .saturating_add(self.global_gas_lamports)
+            .saturating_add(self.priority_fee_lamports)
+    }
+}
+
+/// Cost of placing `orders` in one batch update on `market`. Assumes every
+/// order rests, so the expand rent is an upper bound when orders cross. The
+/// program keeps a free block after each order, expanding when needed.
+pub fn placement_cost(
+    orders: &[PlaceOrderParams],
+    market: &MarketValue,
+    rent: &Rent,
+    priority_fee: &PriorityFee,
+) -> Result<PlacementCost> {
+    let resting_orders: u32 = orders
+        .iter()
+        .filter(|order| order.order_type() != OrderType::ImmediateOrCancel)
+        .count() as u32;
+    let num_expand_blocks: u32 = if resting_orders == 0 {
+        0
+    } else {
+        market
+            .free_blocks_short_of_n(resting_orders + 1)
+            .unwrap_or(0)
+    };
+    let account_len: usize = size_of::<MarketFixed>() + market.dynamic.len();
+    let expanded_len: usize = account_len + num_expand_blocks as usize * MARKET_BLOCK_SIZE;
+    let expand_rent_lamports: u64 = rent
+        .minimum_balance(expanded_len)
+        .saturating_sub(rent.minimum_balance(account_len));
+
+    let num_global_orders: u64 = orders
+        .iter()
+        .filter(|order| order.order_type() == OrderType::Global)
+        .count() as u64;
+
+    Ok(PlacementCost {
+        expand_rent_lamports,
+        global_gas_lamports: num_global_orders * GAS_DEPOSIT_LAMPORTS,
+        priority_fee_lamports: priority_fee.get_lamports(),
+    })
+}
+
+#[cfg(all(test, feature = "model"))]
+mod test {
+    use super::*;
+    use crate::model::ModelRunner;
+    use solana_program::pubkey::Pubkey;
+
+    #[test]
+    fn test_placement_cost() {
+        let mut market: MarketValue = ModelRunner::new(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        )
+        .market;
+        while !market.has_two_free_blocks() {
+            market.dynamic.extend_from_slice(&[0; MARKET_BLOCK_SIZE]);
+            market.market_expand().unwrap();
+        }
+        let order = |order_type: OrderType| PlaceOrderParams::new(10, 1, 0, true, order_type, 0);
+        let rent: Rent = Rent::default();
+        let priority_fee: PriorityFee = PriorityFee {
+            compute_unit_limit: 200_000,
+            micro_lamports_per_compute_unit: 5,
+        };
+
+        // Two free blocks cover one resting order and the block kept free.
+        let cost: PlacementCost = placement_cost(
+            &[order(OrderType::Limit), order(OrderType::ImmediateOrCancel)],
+            &market,
+            &rent,
+            &priority_fee,
+        )
+        .unwrap();
+        assert_eq!(cost.expand_rent_lamports, 0);
+        assert_eq!(cost.priority_fee_lamports, 1);
+
+        let cost: PlacementCost = placement_cost(
+            &[order(OrderType::Limit), order(OrderType::Global)],
+            &market,
+            &rent,
+            &priority_fee,
+        )
+        .unwrap();
+        assert_eq!(
+            cost.expand_rent_lamports,
+            rent.minimum_balance(MARKET_BLOCK_SIZE) - rent.minimum_balance(0)
+        );
+        assert_eq!(cost.global_gas_lamports, GAS_DEPOSIT_LAMPORTS);
+        assert_eq!(
+            cost.get_total_lamports(),
+            cost.expand_rent_lamports + GAS_DEPOSIT_LAMPORTS + 1
+        );
+    }
+}
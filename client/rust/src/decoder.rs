@@ -0,0 +1,573 @@
+//! Manifest instructions in confirmed transactions, decoded into their
+//! parameters with `decode_instruction` and into events synthesized from
+//! them with `decode_event`.
+
+use anyhow::{bail, Error, Result};
+use borsh::BorshDeserialize;
+use hypertree::{get_helper, DataIndex, RBNode};
+use manifest::{
+    logs::CancelOrderLog,
+    program::{
+        batch_update::{
+            BatchUpdateParams, CancelOrderParams, MarketDataTreeNodeType, PlaceOrderParams,
+        },
+        deposit::DepositParams,
+        global_clean::GlobalCleanParams,
+        global_deposit::GlobalDepositParams,
+        global_withdraw::GlobalWithdrawParams,
+        withdraw::WithdrawParams,
+        SwapParams,
+    },
+    state::{GlobalValue, MarketValue, RestingOrder, MARKET_BLOCK_SIZE},
+    validation::ProgramIdSet,
+};
+use solana_program::{message::VersionedMessage, pubkey::Pubkey};
+#[cfg(feature = "client")]
+use solana_sdk::transaction::VersionedTransaction;
+
+use crate::instructions::ManifestInstructionTag;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarketCreated {
+    pub market: Pubkey,
+    pub base_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    pub creator: Pubkey,
+    pub slot: u64,
+}
+
+/// A GlobalClean removed the order at `order_index`. The instruction does not
+/// say which order that was or why it was eligible, see `resolve`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderCleaned {
+    pub market: Pubkey,
+    pub global: Pubkey,
+    pub cleaner: Pubkey,
+    pub order_index: DataIndex,
+    pub slot: u64,
+}
+
+/// Why an order left the book without filling. Only `User` cancels emit a
+/// CancelOrderLog, the others are worked out from a GlobalClean and the
+/// accounts it touched. Expired orders that matching walks past are removed
+/// without a trace in the transaction and do not show up at all. Wrappers in
+/// this repository do not cancel on their own, so wrapper cancels are `User`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelReason {
+    User,
+    Expired,
+    /// The maker lost their global seat to an eviction.
+    Evicted,
+    /// The maker still has a global seat but not enough deposited.
+    Unbacked,
+}
+
+impl CancelReason {
+    pub fn is_forced(&self) -> bool {
+        *self != CancelReason::User
+    }
+}
+
+impl OrderCleaned {
+    /// The cancelled order and the reason, given the market and global as
+    /// they were before the clean. None if there was no order at the index.
+    pub fn resolve(
+        &self,
+        market: &MarketValue,
+        global: &GlobalValue,
+    ) -> Option<(CancelOrderLog, CancelReason)> {
+        if self.order_index % MARKET_BLOCK_SIZE as DataIndex != 0
+            || self.order_index as usize + MARKET_BLOCK_SIZE > market.dynamic.len()
+        {
+            return None;
+        }
+        let order_node: &RBNode<RestingOrder> =
+            get_helper::<RBNode<RestingOrder>>(&market.dynamic, self.order_index);
+        if order_node.get_payload_type() != MarketDataTreeNodeType::RestingOrder as u8 {
+            return None;
+        }
+        let order: &RestingOrder = order_node.get_value();
+        let trader: Pubkey = *market.get_trader_key_by_index(order.get_trader_index());
+        let reason: CancelReason = if order.is_expired(slot_to_u32(self.slot)) {
+            CancelReason::Expired
+        } else if !global.has_global_seat(&trader) {
+            CancelReason::Evicted
+        } else {
+            CancelReason::Unbacked
+        };
+        Some((
+            CancelOrderLog {
+                market: self.market,
+                trader,
+                order_sequence_number: order.get_sequence_number(),
+            },
+            reason,
+        ))
+    }
+}
+
+/// Parameters of a Manifest instruction, see `decode_instruction`.
+#[derive(Debug, Clone)]
+pub enum DecodedInstruction {
+    CreateMarket,
+    ClaimSeat,
+    Deposit {
+        amount_atoms: u64,
+        trader_index_hint: Option<DataIndex>,
+    },
+    Withdraw {
+        amount_atoms: u64,
+        trader_index_hint: Option<DataIndex>,
+    },
+    Swap(DecodedSwap),
+    SwapV2(DecodedSwap),
+    Expand {
+        /// None for the original Expand, which only adds a block when fewer
+        /// than two are free.
+        num_free_blocks_opt: Option<u32>,
+    },
+    BatchUpdate {
+        trader_index_hint: Option<DataIndex>,
+        cancels: Vec<CancelOrderParams>,
+        orders: Vec<PlaceOrderParams>,
+    },
+    GlobalCreate,
+    GlobalAddTrader,
+    GlobalDeposit {
+        amount_atoms: u64,
+    },
+    GlobalWithdraw {
+        amount_atoms: u64,
+    },
+    GlobalEvict {
+        amount_atoms: u64,
+    },
+    GlobalClean {
+        order_index: DataIndex,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodedSwap {
+    pub in_atoms: u64,
+    pub out_atoms: u64,
+    pub is_base_in: bool,
+    pub is_exact_in: bool,
+}
+
+impl DecodedInstruction {
+    pub fn get_tag(&self) -> ManifestInstructionTag {
+        match self {
+            DecodedInstruction::CreateMarket => ManifestInstructionTag::CreateMarket,
+            DecodedInstruction::ClaimSeat => ManifestInstructionTag::ClaimSeat,
+            DecodedInstruction::Deposit { .. } => ManifestInstructionTag::Deposit,
+            DecodedInstruction::Withdraw { .. } => ManifestInstructionTag::Withdraw,
+            DecodedInstruction::Swap(_) => ManifestInstructionTag::Swap,
+            DecodedInstruction::SwapV2(_) => ManifestInstructionTag::SwapV2,
+            DecodedInstruction::Expand { .. } => ManifestInstructionTag::Expand,
+            DecodedInstruction::BatchUpdate { .. } => ManifestInstructionTag::BatchUpdate,
+            DecodedInstruction::GlobalCreate => ManifestInstructionTag::GlobalCreate,
+            DecodedInstruction::GlobalAddTrader => ManifestInstructionTag::GlobalAddTrader,
+            DecodedInstruction::GlobalDeposit { .. } => ManifestInstructionTag::GlobalDeposit,
+            DecodedInstruction::GlobalWithdraw { .. } => ManifestInstructionTag::GlobalWithdraw,
+            DecodedInstruction::GlobalEvict { .. } => ManifestInstructionTag::GlobalEvict,
+            DecodedInstruction::GlobalClean { .. } => ManifestInstructionTag::GlobalClean,
+        }
+    }
+}
+
+// Slots past the u32 range saturate instead of wrapping, so an expired order
+// is not decoded as live. Orders with a last valid slot of u32::MAX stay live
+// at that slot since expiry is strictly after it.
+fn slot_to_u32(slot: u64) -> u32 {
+    u32::try_from(slot).unwrap_or(u32::MAX)
+}
+
+fn decode_params<T: BorshDeserialize>(tag: ManifestInstructionTag, params: &[u8]) -> Result<T> {
+    T::try_from_slice(params)
+        .map_err(|err| Error::msg(format!("Malformed {tag:?} instruction data: {err}")))
+}
+
+/// Decode the data of a Manifest instruction, the same way the program
+/// does. Fails for unknown tags and for data the program would reject as
+/// malformed, including trailing bytes.
+pub fn decode_instruction(data: &[u8]) -> Result<DecodedInstruction> {
+    let Some((&tag_byte, params)) = data.split_first() else {
+        bail!("Empty instruction data");
+    };
+    let Ok(tag) = ManifestInstructionTag::try_from(tag_byte) else {
+        bail!("Unknown instruction tag {tag_byte}");
+    };
+    Ok(match tag {
+        ManifestInstructionTag::CreateMarket => DecodedInstruction::CreateMarket,
+        ManifestInstructionTag::ClaimSeat => DecodedInstruction::ClaimSeat,
+        ManifestInstructionTag::Deposit => {
+            let DepositParams {
+                amount_atoms,
+                trader_index_hint,
+            } = decode_params(tag, params)?;
+            DecodedInstruction::Deposit {
+                amount_atoms,
+                trader_index_hint,
+            }
+        }
+        ManifestInstructionTag::Withdraw => {
+            let WithdrawParams {
+                amount_atoms,
+                trader_index_hint,
+            } = decode_params(tag, params)?;
+            DecodedInstruction::Withdraw {
+                amount_atoms,
+                trader_index_hint,
+            }
+        }
+        ManifestInstructionTag::Swap | ManifestInstructionTag::SwapV2 => {
+            let SwapParams {
+                in_atoms,
+                out_atoms,
+                is_base_in,
+                is_exact_in,
+            } = decode_params(tag, params)?;
+            let swap: DecodedSwap = DecodedSwap {
+                in_atoms,
+                out_atoms,
+                is_base_in,
+                is_exact_in,
+            };
+            if tag == ManifestInstructionTag::Swap {
+                DecodedInstruction::Swap(swap)
+            } else {
+                DecodedInstruction::SwapV2(swap)
+            }
+        }
+        // Extra bytes past the block count are ignored by the program.
+        ManifestInstructionTag::Expand => DecodedInstruction::Expand {
+            num_free_blocks_opt: params
+                .first_chunk::<4>()
+                .map(|bytes| u32::from_le_bytes(*bytes)),
+        },
+        ManifestInstructionTag::BatchUpdate => {
+            let batch_update: BatchUpdateParams = decode_params(tag, params)?;
+            DecodedInstruction::BatchUpdate {
+                trader_index_hint: batch_update.trader_index_hint,
+                cancels: batch_update.cancels,
+                orders: batch_update.orders,
+            }
+        }
+        ManifestInstructionTag::GlobalCreate => DecodedInstruction::GlobalCreate,
+        ManifestInstructionTag::GlobalAddTrader => DecodedInstruction::GlobalAddTrader,
+        ManifestInstructionTag::GlobalDeposit => {
+            let GlobalDepositParams { amount_atoms } = decode_params(tag, params)?;
+            DecodedInstruction::GlobalDeposit { amount_atoms }
+        }
+        ManifestInstructionTag::GlobalWithdraw => {
+            let GlobalWithdrawParams { amount_atoms } = decode_params(tag, params)?;
+            DecodedInstruction::GlobalWithdraw { amount_atoms }
+        }
+        // GlobalEvictParams keeps its amount private, it is a lone u64.
+        ManifestInstructionTag::GlobalEvict => DecodedInstruction::GlobalEvict {
+            amount_atoms: decode_params(tag, params)?,
+        },
+        ManifestInstructionTag::GlobalClean => {
+            let GlobalCleanParams { order_index } = decode_params(tag, params)?;
+            DecodedInstruction::GlobalClean { order_index }
+        }
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestEvent {
+    MarketCreated(MarketCreated),
+    OrderCleaned(OrderCleaned),
+}
+
+/// Decode a single instruction. `account_keys` are the keys of the
+/// instruction accounts in order. Returns None for instructions that do not
+/// map to an event.
+pub fn decode_event(data: &[u8], account_keys: &[Pubkey], slot: u64) -> Option<ManifestEvent> {
+    let tag: ManifestInstructionTag = ManifestInstructionTag::from_instruction_data(data)?;
+    match tag {
+        ManifestInstructionTag::CreateMarket => Some(ManifestEvent::MarketCreated(MarketCreated {
+            creator: *account_keys.first()?,
+            market: *account_keys.get(1)?,
+            base_mint: *account_keys.get(3)?,
+            quote_mint: *account_keys.get(4)?,
+            slot,
+        })),
+        ManifestInstructionTag::GlobalClean => Some(ManifestEvent::OrderCleaned(OrderCleaned {
+            cleaner: *account_keys.first()?,
+            market: *account_keys.get(1)?,
+            global: *account_keys.get(3)?,
+            order_index: DataIndex::from_le_bytes(data.get(1..5)?.try_into().ok()?),
+            slot,
+        })),
+        _ => None,
+    }
+}
+
+/// Events from the top level instructions of a message to any program in
+/// `program_ids`. Accounts loaded through lookup tables are not resolved, so
+/// instructions that use them are skipped. The caller is responsible for only
+/// passing messages of successful transactions.
+pub fn decode_message_events(
+    message: &VersionedMessage,
+    slot: u64,
+    program_ids: &ProgramIdSet,
+) -> Vec<ManifestEvent> {
+    let static_keys: &[Pubkey] = message.static_account_keys();
+    message
+        .instructions()
+        .iter()
+        .filter_map(|instruction| {
+            let program_id: &Pubkey = static_keys.get(instruction.program_id_index as usize)?;
+            if !program_ids.contains(program_id) {
+                return None;
+            }
+            let account_keys: Vec<Pubkey> = instruction
+                .accounts
+                .iter()
+                .map(|index| static_keys.get(*index as usize).copied())
+                .collect::<Option<Vec<Pubkey>>>()?;
+            decode_event(&instruction.data, &account_keys, slot)
+        })
+        .collect()
+}
+
+/// Turn a stream of confirmed transactions with their slots into a stream of
+/// newly created markets. Works with anything iterable, for example the
+/// receiving end of a channel fed by a websocket or geyser subscription.
+#[cfg(feature = "client")]
+pub fn watch_new_markets<I>(
+    transactions: I,
+    program_ids: ProgramIdSet,
+) -> impl Iterator<Item = MarketCreated>
+where
+    I: IntoIterator<Item = (u64, VersionedTransaction)>,
+{
+    transactions
+        .into_iter()
+        .flat_map(move |(slot, transaction)| {
+            decode_message_events(&transaction.message, slot, &program_ids)
+        })
+        .filter_map(|event| match event {
+            ManifestEvent::MarketCreated(market_created) => Some(market_created),
+            _ => None,
+        })
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod test {
+    use super::*;
+    use crate::{
+        model::{ModelOperation, ModelRunner},
+        testing::GlobalFixture,
+    };
+    use hypertree::HyperTreeValueIteratorTrait;
+    use manifest::{
+        program::{
+            batch_update_instruction, create_market_instructions, deposit_instruction,
+            global_clean_instruction,
+        },
+        quantities::{BaseAtoms, QuoteAtomsPerBaseAtom},
+        state::{OrderType, NO_EXPIRATION_LAST_VALID_SLOT},
+    };
+    use solana_program::instruction::Instruction;
+    #[cfg(feature = "client")]
+    use {
+        solana_program::message::Message,
+        std::sync::mpsc::{channel, Receiver, Sender},
+    };
+
+    #[test]
+    #[cfg(feature = "client")]
+    fn test_watch_new_markets() {
+        let market: Pubkey = Pubkey::new_unique();
+        let base_mint: Pubkey = Pubkey::new_unique();
+        let quote_mint: Pubkey = Pubkey::new_unique();
+        let creator: Pubkey = Pubkey::new_unique();
+        let message: Message = Message::new(
+            &create_market_instructions(&market, &base_mint, &quote_mint, &creator).unwrap(),
+            Some(&creator),
+        );
+
+        let (sender, receiver): (
+            Sender<(u64, VersionedTransaction)>,
+            Receiver<(u64, VersionedTransaction)>,
+        ) = channel();
+        sender
+            .send((
+                10,
+                VersionedTransaction {
+                    signatures: vec![],
+                    message: VersionedMessage::Legacy(message),
+                },
+            ))
+            .unwrap();
+        drop(sender);
+
+        let markets: Vec<MarketCreated> =
+            watch_new_markets(receiver, ProgramIdSet::default()).collect();
+        assert_eq!(
+            markets,
+            vec![MarketCreated {
+                market,
+                base_mint,
+                quote_mint,
+                creator,
+                slot: 10,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_order_cleaned() {
+        let market_key: Pubkey = Pubkey::new_unique();
+        let base_mint: Pubkey = Pubkey::new_unique();
+        let quote_mint: Pubkey = Pubkey::new_unique();
+        let maker: Pubkey = Pubkey::new_unique();
+        let cleaner: Pubkey = Pubkey::new_unique();
+        let mut runner: ModelRunner = ModelRunner::new(&market_key, &base_mint, &quote_mint);
+        runner.claim_seat(&maker).unwrap();
+        runner
+            .apply(&ModelOperation::Deposit {
+                trader: maker,
+                amount_atoms: 1_000,
+                is_base: true,
+            })
+            .unwrap();
+        runner
+            .apply(&ModelOperation::PlaceOrder {
+                trader: maker,
+                num_base_atoms: BaseAtoms::new(1_000),
+                price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(1, 0).unwrap(),
+                is_bid: false,
+                // The model only places orders backed by the market, which
+                // makes no difference to how a clean is resolved.
+                order_type: OrderType::Limit,
+            })
+            .unwrap();
+        let (order_index, order): (DataIndex, &RestingOrder) = runner
+            .market
+            .get_asks()
+            .iter::<RestingOrder>()
+            .next()
+            .unwrap();
+        let order_sequence_number: u64 = order.get_sequence_number();
+
+        let mut global: GlobalFixture = GlobalFixture::new(&base_mint);
+        let instruction: Instruction =
+            global_clean_instruction(global.get_key(), &cleaner, &market_key, order_index);
+        let keys: Vec<Pubkey> = instruction
+            .accounts
+            .iter()
+            .map(|account_meta| account_meta.pubkey)
+            .collect();
+        let Some(ManifestEvent::OrderCleaned(order_cleaned)) =
+            decode_event(&instruction.data, &keys, 10)
+        else {
+            panic!("GlobalClean should decode");
+        };
+        assert_eq!(order_cleaned.order_index, order_index);
+        assert_eq!(order_cleaned.cleaner, cleaner);
+
+        let (log, reason): (CancelOrderLog, CancelReason) = order_cleaned
+            .resolve(&runner.market, global.get_global())
+            .unwrap();
+        assert_eq!(log.market, market_key);
+        assert_eq!(log.trader, maker);
+        assert_eq!(log.order_sequence_number, order_sequence_number);
+        assert_eq!(reason, CancelReason::Evicted);
+        assert!(reason.is_forced());
+
+        global.add_trader(&maker).unwrap();
+        let (_, reason): (CancelOrderLog, CancelReason) = order_cleaned
+            .resolve(&runner.market, global.get_global())
+            .unwrap();
+        assert_eq!(reason, CancelReason::Unbacked);
+
+        // Nothing to resolve at an index that does not hold an order.
+        let mut misaligned: OrderCleaned = order_cleaned;
+        misaligned.order_index += 1;
+        assert_eq!(
+            misaligned.resolve(&runner.market, global.get_global()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_decode_instruction() {
+        let market: Pubkey = Pubkey::new_unique();
+        let trader: Pubkey = Pubkey::new_unique();
+        let mint: Pubkey = Pubkey::new_unique();
+
+        let instruction: Instruction = deposit_instruction(
+            &market,
+            &trader,
+            &mint,
+            1_000,
+            &Pubkey::new_unique(),
+            spl_token::id(),
+            Some(80),
+        );
+        assert!(matches!(
+            decode_instruction(&instruction.data).unwrap(),
+            DecodedInstruction::Deposit {
+                amount_atoms: 1_000,
+                trader_index_hint: Some(80),
+            }
+        ));
+
+        let instruction: Instruction = batch_update_instruction(
+            &market,
+            &trader,
+            None,
+            vec![CancelOrderParams::new_with_hint(7, Some(160))],
+            vec![PlaceOrderParams::new(
+                500,
+                95,
+                -2,
+                true,
+                OrderType::PostOnly,
+                NO_EXPIRATION_LAST_VALID_SLOT,
+            )],
+            None,
+            None,
+            None,
+            None,
+        );
+        let decoded: DecodedInstruction = decode_instruction(&instruction.data).unwrap();
+        assert_eq!(decoded.get_tag(), ManifestInstructionTag::BatchUpdate);
+        let DecodedInstruction::BatchUpdate {
+            trader_index_hint,
+            cancels,
+            orders,
+        } = decoded
+        else {
+            panic!("Decoded {decoded:?}");
+        };
+        assert_eq!(trader_index_hint, None);
+        assert_eq!(cancels[0].order_sequence_number(), 7);
+        assert_eq!(cancels[0].order_index_hint(), Some(160));
+        assert_eq!(orders[0].base_atoms(), 500);
+        assert_eq!(orders[0].order_type(), OrderType::PostOnly);
+
+        let instruction: Instruction = global_clean_instruction(&mint, &trader, &market, 240);
+        assert!(matches!(
+            decode_instruction(&instruction.data).unwrap(),
+            DecodedInstruction::GlobalClean { order_index: 240 }
+        ));
+        assert!(matches!(
+            decode_instruction(&[ManifestInstructionTag::Expand as u8]).unwrap(),
+            DecodedInstruction::Expand {
+                num_free_blocks_opt: None
+            }
+        ));
+
+        assert!(decode_instruction(&[]).is_err());
+        assert!(decode_instruction(&[99]).is_err());
+        let mut data: Vec<u8> = instruction.data.clone();
+        data.push(0);
+        assert!(decode_instruction(&data).is_err());
+        assert!(decode_instruction(&data[..3]).is_err());
+    }
+}
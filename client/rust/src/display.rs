@@ -0,0 +1,152 @@
+//! Human readable names for traders in reports. Books are often dominated by
+//! a handful of known makers, and their labels are much easier to scan than
+//! pubkeys.
+
+use anyhow::{anyhow, bail, Result};
+use solana_program::pubkey::Pubkey;
+use std::{collections::HashMap, fmt, str::FromStr};
+
+/// Source of labels other than a file, for example an on chain name service.
+pub trait NameService {
+    /// Same length and order as `traders`, None for names that are not known.
+    fn lookup(&self, traders: &[Pubkey]) -> Result<Vec<Option<String>>>;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TraderRegistry {
+    labels: HashMap<Pubkey, String>,
+}
+
+impl TraderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// One trader per line, the pubkey followed by whitespace and the label.
+    /// Empty lines and lines starting with `#` are skipped.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut registry: TraderRegistry = TraderRegistry::new();
+        for (line_index, line) in contents.lines().enumerate() {
+            let line: &str = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, label)) = line.split_once(char::is_whitespace) else {
+                bail!("Line {} has no label", line_index + 1);
+            };
+            let trader: Pubkey =
+                Pubkey::from_str(key).map_err(|err| anyhow!("Line {}: {err}", line_index + 1))?;
+            registry.insert(&trader, label.trim());
+        }
+        Ok(registry)
+    }
+
+    pub fn load_file(path: &str) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Look up `traders` that do not have a label yet. Labels that are
+    /// already known, like ones from a file, take precedence.
+    pub fn add_from_name_service<N: NameService>(
+        &mut self,
+        name_service: &N,
+        traders: &[Pubkey],
+    ) -> Result<()> {
+        let unknown: Vec<Pubkey> = traders
+            .iter()
+            .filter(|trader| !self.labels.contains_key(trader))
+            .copied()
+            .collect();
+        for (trader, label) in unknown.iter().zip(name_service.lookup(&unknown)?) {
+            if let Some(label) = label {
+                self.insert(trader, &label);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn insert(&mut self, trader: &Pubkey, label: &str) {
+        self.labels.insert(*trader, label.to_string());
+    }
+
+    pub fn get_label(&self, trader: &Pubkey) -> Option<&str> {
+        self.labels.get(trader).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// Displays as the label followed by the pubkey when the trader is known
+    /// and as just the pubkey otherwise.
+    pub fn annotate<'a>(&'a self, trader: &'a Pubkey) -> AnnotatedTrader<'a> {
+        AnnotatedTrader {
+            trader,
+            label: self.get_label(trader),
+        }
+    }
+}
+
+pub struct AnnotatedTrader<'a> {
+    trader: &'a Pubkey,
+    label: Option<&'a str>,
+}
+
+impl fmt::Display for AnnotatedTrader<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.label {
+            Some(label) => write!(f, "{} ({})", label, self.trader),
+            None => write!(f, "{}", self.trader),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct FixedNames(HashMap<Pubkey, String>);
+
+    impl NameService for FixedNames {
+        fn lookup(&self, traders: &[Pubkey]) -> Result<Vec<Option<String>>> {
+            Ok(traders
+                .iter()
+                .map(|trader| self.0.get(trader).cloned())
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_trader_registry() {
+        let market_maker: Pubkey = Pubkey::new_unique();
+        let other: Pubkey = Pubkey::new_unique();
+        let unknown: Pubkey = Pubkey::new_unique();
+        let mut registry: TraderRegistry = TraderRegistry::parse(&format!(
+            "# Known makers\n\n{market_maker}  Market Maker A\n"
+        ))
+        .unwrap();
+        assert!(TraderRegistry::parse("not-a-pubkey label").is_err());
+
+        let name_service: FixedNames = FixedNames(HashMap::from([
+            (market_maker, "ignored".to_string()),
+            (other, "other.sol".to_string()),
+        ]));
+        registry
+            .add_from_name_service(&name_service, &[market_maker, other, unknown])
+            .unwrap();
+        assert_eq!(registry.len(), 2);
+        assert_eq!(
+            registry.annotate(&market_maker).to_string(),
+            format!("Market Maker A ({market_maker})")
+        );
+        assert_eq!(
+            registry.annotate(&other).to_string(),
+            format!("other.sol ({other})")
+        );
+        assert_eq!(registry.annotate(&unknown).to_string(), unknown.to_string());
+    }
+}
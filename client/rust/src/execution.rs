@@ -0,0 +1,887 @@
+//! Helpers for strategies that manage their own resting orders, and
+//! `DcaScheduler` for ones that only take.
+
+use anyhow::{bail, Result};
+use hypertree::{DataIndex, HyperTreeValueIteratorTrait, NIL};
+use manifest::{
+    program::{
+        batch_update::{CancelOrderParams, PlaceOrderParams},
+        batch_update_instruction,
+    },
+    quantities::{BaseAtoms, QuoteAtomsPerBaseAtom},
+    state::{MarketValue, RestingOrder, NO_EXPIRATION_LAST_VALID_SLOT},
+};
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+#[cfg(feature = "client")]
+use crate::{
+    instruction_builders::{swap, SwapArgs},
+    quoting::exact_quote,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExpiryNotification {
+    pub order_sequence_number: u64,
+    pub last_valid_slot: u32,
+    /// Slots left in which the order can still be filled.
+    pub slots_remaining: u32,
+}
+
+/// Orders of one trader indexed by `last_valid_slot`. Each order is reported
+/// once when it comes within `lead_slots` of expiring, so the strategy has
+/// time to replace it. Orders without expiration are never tracked.
+#[derive(Debug, Clone, Default)]
+pub struct ExpiryQueue {
+    lead_slots: u32,
+    // (last_valid_slot, sequence number) -> already notified
+    by_slot: BTreeMap<(u32, u64), bool>,
+    slot_by_sequence_number: HashMap<u64, u32>,
+}
+
+impl ExpiryQueue {
+    pub fn new(lead_slots: u32) -> Self {
+        ExpiryQueue {
+            lead_slots,
+            ..Default::default()
+        }
+    }
+
+    pub fn insert(&mut self, order: &RestingOrder) {
+        let last_valid_slot: u32 = order.get_last_valid_slot();
+        if last_valid_slot == NO_EXPIRATION_LAST_VALID_SLOT {
+            return;
+        }
+        let sequence_number: u64 = order.get_sequence_number();
+        if self
+            .slot_by_sequence_number
+            .insert(sequence_number, last_valid_slot)
+            .is_none()
+        {
+            self.by_slot
+                .insert((last_valid_slot, sequence_number), false);
+        }
+    }
+
+    pub fn remove(&mut self, order_sequence_number: u64) {
+        if let Some(last_valid_slot) = self.slot_by_sequence_number.remove(&order_sequence_number) {
+            self.by_slot
+                .remove(&(last_valid_slot, order_sequence_number));
+        }
+    }
+
+    /// Replace the tracked orders with the trader's orders on the book. Meant
+    /// to be called on every market account update from a subscription.
+    /// Orders that were already reported stay reported.
+    pub fn sync_with_market(&mut self, market: &MarketValue, trader: &Pubkey) {
+        let trader_index: DataIndex = market.get_trader_index(trader);
+        let mut by_slot: BTreeMap<(u32, u64), bool> = BTreeMap::new();
+        if trader_index != NIL {
+            for bookside in [market.get_bids(), market.get_asks()] {
+                for (_, order) in bookside.iter::<RestingOrder>() {
+                    let last_valid_slot: u32 = order.get_last_valid_slot();
+                    if order.get_trader_index() != trader_index
+                        || last_valid_slot == NO_EXPIRATION_LAST_VALID_SLOT
+                    {
+                        continue;
+                    }
+                    let key: (u32, u64) = (last_valid_slot, order.get_sequence_number());
+                    let notified: bool = self.by_slot.get(&key).copied().unwrap_or(false);
+                    by_slot.insert(key, notified);
+                }
+            }
+        }
+        self.slot_by_sequence_number = by_slot
+            .keys()
+            .map(|(last_valid_slot, sequence_number)| (*sequence_number, *last_valid_slot))
+            .collect();
+        self.by_slot = by_slot;
+    }
+
+    /// Notifications for orders that entered the lead window since the last
+    /// poll. Orders that already expired are dropped.
+    pub fn poll(&mut self, now_slot: u32) -> Vec<ExpiryNotification> {
+        let expired: Vec<(u32, u64)> = self
+            .by_slot
+            .range(..(now_slot, 0))
+            .map(|(key, _)| *key)
+            .collect();
+        for (last_valid_slot, sequence_number) in expired {
+            self.by_slot.remove(&(last_valid_slot, sequence_number));
+            self.slot_by_sequence_number.remove(&sequence_number);
+        }
+
+        let mut notifications: Vec<ExpiryNotification> = Vec::new();
+        let horizon: u32 = now_slot.saturating_add(self.lead_slots);
+        for ((last_valid_slot, sequence_number), notified) in
+            self.by_slot.range_mut(..=(horizon, u64::MAX))
+        {
+            if *notified {
+                continue;
+            }
+            *notified = true;
+            notifications.push(ExpiryNotification {
+                order_sequence_number: *sequence_number,
+                last_valid_slot: *last_valid_slot,
+                slots_remaining: last_valid_slot - now_slot,
+            });
+        }
+        notifications
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_slot.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_slot.is_empty()
+    }
+}
+
+/// Where an order sits on its side of the book. Only orders that have not
+/// expired count.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueuePosition {
+    /// Orders that fill first, at better prices or earlier at the same price.
+    pub orders_ahead: u64,
+    pub base_atoms_ahead: BaseAtoms,
+    /// The part of `base_atoms_ahead` at the same price.
+    pub base_atoms_ahead_at_price: BaseAtoms,
+}
+
+/// Position of a resting order. None if it is not on the book.
+pub fn get_queue_position(
+    market: &MarketValue,
+    order_sequence_number: u64,
+    now_slot: u32,
+) -> Result<Option<QueuePosition>> {
+    for bookside in [market.get_bids(), market.get_asks()] {
+        let mut position: QueuePosition = QueuePosition::default();
+        let mut price: Option<QuoteAtomsPerBaseAtom> = None;
+        for (_, order) in bookside.iter::<RestingOrder>() {
+            if order.get_sequence_number() == order_sequence_number {
+                return Ok(Some(position));
+            }
+            if order.is_expired(now_slot) {
+                continue;
+            }
+            if price != Some(order.get_price()) {
+                price = Some(order.get_price());
+                position.base_atoms_ahead_at_price = BaseAtoms::ZERO;
+            }
+            position.orders_ahead += 1;
+            position.base_atoms_ahead = position
+                .base_atoms_ahead
+                .checked_add(order.get_num_base_atoms())?;
+            position.base_atoms_ahead_at_price = position
+                .base_atoms_ahead_at_price
+                .checked_add(order.get_num_base_atoms())?;
+        }
+    }
+    Ok(None)
+}
+
+/// Position a new order at `price` would get if nothing trades first. New
+/// orders go behind everything at their price. The order with
+/// `replaced_sequence_number` is left out, for an order cancelled in the same
+/// transaction.
+pub fn estimate_queue_position(
+    market: &MarketValue,
+    is_bid: bool,
+    price: QuoteAtomsPerBaseAtom,
+    replaced_sequence_number: Option<u64>,
+    now_slot: u32,
+) -> Result<QueuePosition> {
+    let bookside = if is_bid {
+        market.get_bids()
+    } else {
+        market.get_asks()
+    };
+    let mut position: QueuePosition = QueuePosition::default();
+    for (_, order) in bookside.iter::<RestingOrder>() {
+        let is_ahead: bool = if is_bid {
+            order.get_price() >= price
+        } else {
+            order.get_price() <= price
+        };
+        if !is_ahead {
+            break;
+        }
+        if order.is_expired(now_slot)
+            || Some(order.get_sequence_number()) == replaced_sequence_number
+        {
+            continue;
+        }
+        position.orders_ahead += 1;
+        position.base_atoms_ahead = position
+            .base_atoms_ahead
+            .checked_add(order.get_num_base_atoms())?;
+        if order.get_price() == price {
+            position.base_atoms_ahead_at_price = position
+                .base_atoms_ahead_at_price
+                .checked_add(order.get_num_base_atoms())?;
+        }
+    }
+    Ok(position)
+}
+
+#[derive(Debug, Clone)]
+pub struct Amendment {
+    /// BatchUpdate that cancels the old order and places the new one.
+    pub instruction: Instruction,
+    pub old_position: QueuePosition,
+    pub new_position: QueuePosition,
+    /// The new price reaches the other side of the book, so the order trades
+    /// before anything rests and `new_position` is only for the remainder.
+    pub crosses: bool,
+}
+
+impl Amendment {
+    /// Manifest has no in place amend, the new order always goes to the back
+    /// of its price. That costs priority whenever other orders rest there.
+    pub fn loses_time_priority(&self) -> bool {
+        self.new_position.base_atoms_ahead_at_price > BaseAtoms::ZERO
+    }
+}
+
+/// Replace a resting order of `trader` with one at a new price and size,
+/// keeping its side, order type and expiration, and report what that does to
+/// its place in the queue. Global orders are placed with the global accounts
+/// of the regular token program.
+#[allow(clippy::too_many_arguments)]
+pub fn amend_order(
+    market_key: &Pubkey,
+    market: &MarketValue,
+    trader: &Pubkey,
+    old_sequence_number: u64,
+    new_price_mantissa: u32,
+    new_price_exponent: i8,
+    new_base_atoms: u64,
+    now_slot: u32,
+) -> Result<Amendment> {
+    let trader_index: DataIndex = market.get_trader_index(trader);
+    if trader_index == NIL {
+        bail!("Trader {trader} has no seat on {market_key}");
+    }
+    let Some((order_index, order)) = market
+        .get_bids()
+        .iter::<RestingOrder>()
+        .chain(market.get_asks().iter::<RestingOrder>())
+        .find(|(_, order)| order.get_sequence_number() == old_sequence_number)
+        .map(|(order_index, order)| (order_index, *order))
+    else {
+        bail!("Order {old_sequence_number} is not on the book");
+    };
+    if order.get_trader_index() != trader_index {
+        bail!("Order {old_sequence_number} does not belong to {trader}");
+    }
+
+    let is_bid: bool = order.get_is_bid();
+    let new_price: QuoteAtomsPerBaseAtom = QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(
+        new_price_mantissa,
+        new_price_exponent,
+    )?;
+    let old_position: QueuePosition =
+        get_queue_position(market, old_sequence_number, now_slot)?.unwrap_or_default();
+    let new_position: QueuePosition = estimate_queue_position(
+        market,
+        is_bid,
+        new_price,
+        Some(old_sequence_number),
+        now_slot,
+    )?;
+    let other_side = if is_bid {
+        market.get_asks()
+    } else {
+        market.get_bids()
+    };
+    let crosses: bool = other_side
+        .iter::<RestingOrder>()
+        .find(|(_, other)| !other.is_expired(now_slot))
+        .is_some_and(|(_, best)| {
+            if is_bid {
+                best.get_price() <= new_price
+            } else {
+                best.get_price() >= new_price
+            }
+        });
+
+    // Reverse orders take their spread in place of an expiration.
+    let last_valid_slot: u32 = if order.is_reversible() {
+        order.get_reverse_spread() as u32
+    } else {
+        order.get_last_valid_slot()
+    };
+    let global_mint_opt: Option<Pubkey> = if !order.is_global() {
+        None
+    } else if is_bid {
+        Some(*market.get_quote_mint())
+    } else {
+        Some(*market.get_base_mint())
+    };
+    let (base_mint_opt, quote_mint_opt): (Option<Pubkey>, Option<Pubkey>) = if is_bid {
+        (None, global_mint_opt)
+    } else {
+        (global_mint_opt, None)
+    };
+    let instruction: Instruction = batch_update_instruction(
+        market_key,
+        trader,
+        Some(trader_index),
+        vec![CancelOrderParams::new_with_hint(
+            old_sequence_number,
+            Some(order_index),
+        )],
+        vec![PlaceOrderParams::new(
+            new_base_atoms,
+            new_price_mantissa,
+            new_price_exponent,
+            is_bid,
+            order.get_order_type(),
+            last_valid_slot,
+        )],
+        base_mint_opt,
+        None,
+        quote_mint_opt,
+        None,
+    );
+    Ok(Amendment {
+        instruction,
+        old_position,
+        new_position,
+        crosses,
+    })
+}
+
+/// Swaps of `in_atoms_per_execution` every `interval_slots` until
+/// `total_in_atoms` went through, see `DcaScheduler`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DcaSchedule {
+    pub trader: Pubkey,
+    pub is_base_in: bool,
+    pub total_in_atoms: u64,
+    pub in_atoms_per_execution: u64,
+    pub start_slot: u32,
+    pub interval_slots: u32,
+    /// The min out of each swap is its quote less this.
+    pub max_slippage_bps: u16,
+    /// An execution is skipped until the next interval when its quote is
+    /// this far below trading all of it at the best price.
+    pub max_impact_bps: u16,
+    /// Token programs of the mints, None for the spl token program.
+    pub base_token_program_opt: Option<Pubkey>,
+    pub quote_token_program_opt: Option<Pubkey>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DcaStatus {
+    /// Handed out by `DcaScheduler::poll` and not confirmed yet.
+    Pending,
+    Filled {
+        out_atoms: u64,
+    },
+    /// Did not land. Its atoms go back into what is left to swap.
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DcaExecution {
+    pub slot: u32,
+    pub in_atoms: u64,
+    pub quoted_out_atoms: u64,
+    pub min_out_atoms: u64,
+    pub status: DcaStatus,
+}
+
+const DCA_LEDGER_MAGIC: &[u8; 8] = b"MFSTDCAL";
+const DCA_LEDGER_VERSION: u8 = 1;
+const DCA_LEDGER_HEADER_LEN: usize = 21;
+const DCA_EXECUTION_LEN: usize = 37;
+
+/// Progress of a schedule. Saved after every change so a restarted
+/// scheduler neither repeats nor drops an execution.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DcaLedger {
+    pub next_slot: u32,
+    pub num_skipped: u32,
+    pub executions: Vec<DcaExecution>,
+}
+
+impl DcaLedger {
+    /// In atoms of executions that are pending or filled.
+    pub fn get_committed_in_atoms(&self) -> u64 {
+        self.executions
+            .iter()
+            .filter(|execution| execution.status != DcaStatus::Failed)
+            .map(|execution| execution.in_atoms)
+            .sum()
+    }
+
+    pub fn get_filled_out_atoms(&self) -> u64 {
+        self.executions
+            .iter()
+            .map(|execution| match execution.status {
+                DcaStatus::Filled { out_atoms } => out_atoms,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Layout is the magic and version, `next_slot`, `num_skipped` and a u32
+    /// execution count, then the executions with their fields in little
+    /// endian and the status as a tag and out atoms.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> =
+            Vec::with_capacity(DCA_LEDGER_HEADER_LEN + self.executions.len() * DCA_EXECUTION_LEN);
+        bytes.extend_from_slice(DCA_LEDGER_MAGIC);
+        bytes.push(DCA_LEDGER_VERSION);
+        bytes.extend_from_slice(&self.next_slot.to_le_bytes());
+        bytes.extend_from_slice(&self.num_skipped.to_le_bytes());
+        bytes.extend_from_slice(&(self.executions.len() as u32).to_le_bytes());
+        for execution in self.executions.iter() {
+            let (tag, out_atoms): (u8, u64) = match execution.status {
+                DcaStatus::Pending => (0, 0),
+                DcaStatus::Filled { out_atoms } => (1, out_atoms),
+                DcaStatus::Failed => (2, 0),
+            };
+            bytes.extend_from_slice(&execution.slot.to_le_bytes());
+            bytes.extend_from_slice(&execution.in_atoms.to_le_bytes());
+            bytes.extend_from_slice(&execution.quoted_out_atoms.to_le_bytes());
+            bytes.extend_from_slice(&execution.min_out_atoms.to_le_bytes());
+            bytes.push(tag);
+            bytes.extend_from_slice(&out_atoms.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < DCA_LEDGER_HEADER_LEN || &bytes[..8] != DCA_LEDGER_MAGIC {
+            bail!("Not a DCA ledger");
+        }
+        if bytes[8] != DCA_LEDGER_VERSION {
+            bail!("Unsupported DCA ledger version {}", bytes[8]);
+        }
+        let read_u32 = |offset: usize| -> u32 {
+            u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+        };
+        let read_u64 = |offset: usize| -> u64 {
+            u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+        };
+        let num_executions: usize = read_u32(17) as usize;
+        if bytes.len() != DCA_LEDGER_HEADER_LEN + num_executions * DCA_EXECUTION_LEN {
+            bail!("DCA ledger has the wrong length for {num_executions} executions");
+        }
+        let mut executions: Vec<DcaExecution> = Vec::with_capacity(num_executions);
+        for index in 0..num_executions {
+            let offset: usize = DCA_LEDGER_HEADER_LEN + index * DCA_EXECUTION_LEN;
+            let status: DcaStatus = match bytes[offset + 28] {
+                0 => DcaStatus::Pending,
+                1 => DcaStatus::Filled {
+                    out_atoms: read_u64(offset + 29),
+                },
+                2 => DcaStatus::Failed,
+                tag => bail!("Invalid DCA status {tag}"),
+            };
+            executions.push(DcaExecution {
+                slot: read_u32(offset),
+                in_atoms: read_u64(offset + 4),
+                quoted_out_atoms: read_u64(offset + 12),
+                min_out_atoms: read_u64(offset + 20),
+                status,
+            });
+        }
+        Ok(DcaLedger {
+            next_slot: read_u32(9),
+            num_skipped: read_u32(13),
+            executions,
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, self.encode())?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::decode(&std::fs::read(path)?)
+    }
+}
+
+/// A swap that `DcaScheduler::poll` wants sent.
+#[derive(Debug, Clone)]
+pub struct DcaOrder {
+    pub instruction: Instruction,
+    /// Index in the ledger to confirm or fail once the swap is known.
+    pub execution_index: usize,
+    pub execution: DcaExecution,
+}
+
+/// Splits a large swap into exact in swaps over time. Each `poll` that is
+/// due quotes the next slice, skips it if the book is too thin for it, and
+/// otherwise returns the swap with a min out that holds it to the quote.
+/// The caller reports how each swap went, and failed slices are retried.
+#[cfg(feature = "client")]
+#[derive(Debug, Clone)]
+pub struct DcaScheduler {
+    schedule: DcaSchedule,
+    ledger: DcaLedger,
+}
+
+#[cfg(feature = "client")]
+impl DcaScheduler {
+    pub fn new(schedule: DcaSchedule) -> Result<Self> {
+        if schedule.in_atoms_per_execution == 0 {
+            bail!("Executions need a nonzero size");
+        }
+        let ledger: DcaLedger = DcaLedger {
+            next_slot: schedule.start_slot,
+            ..DcaLedger::default()
+        };
+        Ok(DcaScheduler { schedule, ledger })
+    }
+
+    /// Continue from a ledger saved by an earlier run of `schedule`.
+    pub fn resume(schedule: DcaSchedule, ledger: DcaLedger) -> Result<Self> {
+        let mut scheduler: DcaScheduler = DcaScheduler::new(schedule)?;
+        if ledger.get_committed_in_atoms() > schedule.total_in_atoms {
+            bail!("Ledger swapped more than the schedule allows");
+        }
+        scheduler.ledger = ledger;
+        Ok(scheduler)
+    }
+
+    pub fn get_schedule(&self) -> &DcaSchedule {
+        &self.schedule
+    }
+
+    pub fn get_ledger(&self) -> &DcaLedger {
+        &self.ledger
+    }
+
+    pub fn get_remaining_in_atoms(&self) -> u64 {
+        self.schedule
+            .total_in_atoms
+            .saturating_sub(self.ledger.get_committed_in_atoms())
+    }
+
+    /// Nothing left to swap and nothing pending.
+    pub fn is_done(&self) -> bool {
+        self.get_remaining_in_atoms() == 0
+            && self
+                .ledger
+                .executions
+                .iter()
+                .all(|execution| execution.status != DcaStatus::Pending)
+    }
+
+    /// The next swap if one is due at `now_slot`.
+    pub fn poll(
+        &mut self,
+        market_key: &Pubkey,
+        market: &MarketValue,
+        now_slot: u32,
+    ) -> Result<Option<DcaOrder>> {
+        let in_atoms: u64 = self
+            .get_remaining_in_atoms()
+            .min(self.schedule.in_atoms_per_execution);
+        if in_atoms == 0 || now_slot < self.ledger.next_slot {
+            return Ok(None);
+        }
+        self.ledger.next_slot = now_slot.saturating_add(self.schedule.interval_slots);
+
+        let is_base_in: bool = self.schedule.is_base_in;
+        let quoted_out_atoms: u64 = exact_quote(market, is_base_in, in_atoms, now_slot)?;
+        // Same orders as the quote, which does not use globals.
+        let best_price_opt: Option<QuoteAtomsPerBaseAtom> = if is_base_in {
+            market.best_bid(&[None, None], now_slot)?
+        } else {
+            market.best_ask(&[None, None], now_slot)?
+        };
+        let best_out_atoms: f64 = match best_price_opt {
+            Some(best_price) if is_base_in => in_atoms as f64 * best_price.as_f64(),
+            Some(best_price) => in_atoms as f64 / best_price.as_f64(),
+            None => 0.0,
+        };
+        let min_allowed_out_atoms: f64 =
+            best_out_atoms * (10_000.0 - self.schedule.max_impact_bps as f64) / 10_000.0;
+        if quoted_out_atoms == 0 || (quoted_out_atoms as f64) < min_allowed_out_atoms.floor() {
+            self.ledger.num_skipped += 1;
+            return Ok(None);
+        }
+
+        let min_out_atoms: u64 = (quoted_out_atoms as u128
+            * 10_000_u128.saturating_sub(self.schedule.max_slippage_bps as u128)
+            / 10_000) as u64;
+        let execution: DcaExecution = DcaExecution {
+            slot: now_slot,
+            in_atoms,
+            quoted_out_atoms,
+            min_out_atoms,
+            status: DcaStatus::Pending,
+        };
+        let instruction: Instruction = swap(
+            market_key,
+            market,
+            &SwapArgs {
+                trader: self.schedule.trader,
+                in_atoms,
+                out_atoms: min_out_atoms,
+                is_base_in,
+                is_exact_in: true,
+                base_token_program_opt: self.schedule.base_token_program_opt,
+                quote_token_program_opt: self.schedule.quote_token_program_opt,
+            },
+            now_slot,
+        );
+        self.ledger.executions.push(execution);
+        Ok(Some(DcaOrder {
+            instruction,
+            execution_index: self.ledger.executions.len() - 1,
+            execution,
+        }))
+    }
+
+    pub fn confirm(&mut self, execution_index: usize, out_atoms: u64) -> Result<()> {
+        self.settle(execution_index, DcaStatus::Filled { out_atoms })
+    }
+
+    pub fn fail(&mut self, execution_index: usize) -> Result<()> {
+        self.settle(execution_index, DcaStatus::Failed)
+    }
+
+    fn settle(&mut self, execution_index: usize, status: DcaStatus) -> Result<()> {
+        let Some(execution) = self.ledger.executions.get_mut(execution_index) else {
+            bail!("No execution {execution_index}");
+        };
+        if execution.status != DcaStatus::Pending {
+            bail!("Execution {execution_index} is already settled");
+        }
+        execution.status = status;
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "model"))]
+mod test {
+    use super::*;
+    use crate::model::{ModelOperation, ModelRunner};
+    use manifest::{quantities::WrapperU64, state::OrderType};
+
+    fn order(sequence_number: u64, last_valid_slot: u32) -> RestingOrder {
+        RestingOrder::new(
+            0,
+            BaseAtoms::new(1),
+            QuoteAtomsPerBaseAtom::ZERO,
+            sequence_number,
+            last_valid_slot,
+            true,
+            OrderType::Limit,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_expiry_queue() {
+        let mut queue: ExpiryQueue = ExpiryQueue::new(10);
+        queue.insert(&order(0, 100));
+        queue.insert(&order(1, 150));
+        queue.insert(&order(2, NO_EXPIRATION_LAST_VALID_SLOT));
+        queue.insert(&order(3, 105));
+        queue.remove(3);
+        assert_eq!(queue.len(), 2);
+
+        assert!(queue.poll(80).is_empty());
+        assert_eq!(
+            queue.poll(95),
+            vec![ExpiryNotification {
+                order_sequence_number: 0,
+                last_valid_slot: 100,
+                slots_remaining: 5,
+            }]
+        );
+        // Only reported once.
+        assert!(queue.poll(96).is_empty());
+        // Order 0 expired, order 1 is in the window.
+        assert_eq!(queue.poll(141)[0].order_sequence_number, 1);
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[test]
+    fn test_amend_order() {
+        let market_key: Pubkey = Pubkey::new_unique();
+        let mut runner: ModelRunner =
+            ModelRunner::new(&market_key, &Pubkey::new_unique(), &Pubkey::new_unique());
+        let first: Pubkey = Pubkey::new_unique();
+        let second: Pubkey = Pubkey::new_unique();
+        let seller: Pubkey = Pubkey::new_unique();
+        for (trader, is_base) in [(first, false), (second, false), (seller, true)] {
+            runner.claim_seat(&trader).unwrap();
+            runner
+                .apply(&ModelOperation::Deposit {
+                    trader,
+                    amount_atoms: 10_000,
+                    is_base,
+                })
+                .unwrap();
+        }
+        // Sequence numbers 0 to 3.
+        for (trader, num_base_atoms, mantissa, is_bid) in [
+            (first, 100, 2, true),
+            (second, 50, 2, true),
+            (second, 30, 3, true),
+            (seller, 20, 5, false),
+        ] {
+            runner
+                .apply(&ModelOperation::PlaceOrder {
+                    trader,
+                    num_base_atoms: BaseAtoms::new(num_base_atoms),
+                    price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(mantissa, 0)
+                        .unwrap(),
+                    is_bid,
+                    order_type: OrderType::Limit,
+                })
+                .unwrap();
+        }
+        let market: &MarketValue = &runner.market;
+        assert_eq!(
+            get_queue_position(market, 1, 0).unwrap(),
+            Some(QueuePosition {
+                orders_ahead: 2,
+                base_atoms_ahead: BaseAtoms::new(130),
+                base_atoms_ahead_at_price: BaseAtoms::new(100),
+            })
+        );
+        assert_eq!(get_queue_position(market, 99, 0).unwrap(), None);
+
+        // Resizing in place still goes behind the other order at 2.
+        let amendment: Amendment =
+            amend_order(&market_key, market, &first, 0, 2, 0, 80, 0).unwrap();
+        assert_eq!(amendment.instruction.program_id, manifest::id());
+        assert_eq!(
+            amendment.old_position,
+            QueuePosition {
+                orders_ahead: 1,
+                base_atoms_ahead: BaseAtoms::new(30),
+                base_atoms_ahead_at_price: BaseAtoms::ZERO,
+            }
+        );
+        assert_eq!(
+            amendment.new_position,
+            QueuePosition {
+                orders_ahead: 2,
+                base_atoms_ahead: BaseAtoms::new(80),
+                base_atoms_ahead_at_price: BaseAtoms::new(50),
+            }
+        );
+        assert!(amendment.loses_time_priority());
+        assert!(!amendment.crosses);
+
+        // A new best price keeps nothing ahead.
+        let amendment: Amendment =
+            amend_order(&market_key, market, &first, 0, 4, 0, 80, 0).unwrap();
+        assert_eq!(amendment.new_position, QueuePosition::default());
+        assert!(!amendment.loses_time_priority());
+        assert!(!amendment.crosses);
+        assert!(
+            amend_order(&market_key, market, &first, 0, 5, 0, 80, 0)
+                .unwrap()
+                .crosses
+        );
+
+        assert!(amend_order(&market_key, market, &second, 0, 2, 0, 80, 0).is_err());
+        assert!(amend_order(&market_key, market, &first, 99, 2, 0, 80, 0).is_err());
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn test_dca_scheduler() {
+        let market_key: Pubkey = Pubkey::new_unique();
+        let maker: Pubkey = Pubkey::new_unique();
+        let mut runner: ModelRunner =
+            ModelRunner::new(&market_key, &Pubkey::new_unique(), &Pubkey::new_unique());
+        runner.claim_seat(&maker).unwrap();
+        runner
+            .apply(&ModelOperation::Deposit {
+                trader: maker,
+                amount_atoms: 10_000,
+                is_base: true,
+            })
+            .unwrap();
+        // 100 base at 2 and 1_000 more at 4.
+        for (num_base_atoms, mantissa) in [(100, 2), (1_000, 4)] {
+            runner
+                .apply(&ModelOperation::PlaceOrder {
+                    trader: maker,
+                    num_base_atoms: BaseAtoms::new(num_base_atoms),
+                    price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(mantissa, 0)
+                        .unwrap(),
+                    is_bid: false,
+                    order_type: OrderType::Limit,
+                })
+                .unwrap();
+        }
+        let market: &MarketValue = &runner.market;
+        let schedule: DcaSchedule = DcaSchedule {
+            trader: Pubkey::new_unique(),
+            is_base_in: false,
+            total_in_atoms: 50,
+            in_atoms_per_execution: 20,
+            start_slot: 100,
+            interval_slots: 10,
+            max_slippage_bps: 100,
+            max_impact_bps: 50,
+            base_token_program_opt: None,
+            quote_token_program_opt: None,
+        };
+        let mut scheduler: DcaScheduler = DcaScheduler::new(schedule).unwrap();
+        assert!(scheduler.poll(&market_key, market, 99).unwrap().is_none());
+
+        let first: DcaOrder = scheduler.poll(&market_key, market, 100).unwrap().unwrap();
+        assert_eq!(first.instruction.accounts[0].pubkey, schedule.trader);
+        assert_eq!(
+            first.execution,
+            DcaExecution {
+                slot: 100,
+                in_atoms: 20,
+                quoted_out_atoms: 10,
+                min_out_atoms: 9,
+                status: DcaStatus::Pending,
+            }
+        );
+        assert!(scheduler.poll(&market_key, market, 105).unwrap().is_none());
+        scheduler.confirm(first.execution_index, 10).unwrap();
+        assert!(scheduler.confirm(first.execution_index, 10).is_err());
+
+        let second: DcaOrder = scheduler.poll(&market_key, market, 110).unwrap().unwrap();
+        scheduler.fail(second.execution_index).unwrap();
+        assert_eq!(scheduler.get_remaining_in_atoms(), 30);
+
+        // The ledger survives a restart.
+        let ledger: DcaLedger = DcaLedger::decode(&scheduler.get_ledger().encode()).unwrap();
+        assert_eq!(&ledger, scheduler.get_ledger());
+        let mut scheduler: DcaScheduler = DcaScheduler::resume(schedule, ledger).unwrap();
+        let third: DcaOrder = scheduler.poll(&market_key, market, 120).unwrap().unwrap();
+        scheduler.confirm(third.execution_index, 10).unwrap();
+        // The last 10 are what is left.
+        let last: DcaOrder = scheduler.poll(&market_key, market, 130).unwrap().unwrap();
+        assert_eq!(last.execution.in_atoms, 10);
+        scheduler.confirm(last.execution_index, 5).unwrap();
+        assert!(scheduler.is_done());
+        assert_eq!(scheduler.get_ledger().get_filled_out_atoms(), 25);
+        assert!(scheduler.poll(&market_key, market, 140).unwrap().is_none());
+
+        // 400 quote walks into the asks at 4, far from the best price of 2.
+        let mut scheduler: DcaScheduler = DcaScheduler::new(DcaSchedule {
+            total_in_atoms: 400,
+            in_atoms_per_execution: 400,
+            ..schedule
+        })
+        .unwrap();
+        assert!(scheduler.poll(&market_key, market, 100).unwrap().is_none());
+        assert_eq!(scheduler.get_ledger().num_skipped, 1);
+        assert!(scheduler.get_ledger().executions.is_empty());
+    }
+}
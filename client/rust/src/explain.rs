@@ -0,0 +1,441 @@
+//! Short summaries of what a transaction did on Manifest, for explorers and
+//! support tooling. Combines the instructions, the logged events and the
+//! token balance changes of a confirmed transaction.
+
+use manifest::{
+    logs::{decode_log, CancelOrderLog, DepositLog, FillLog, PlaceOrderLog, WithdrawLog},
+    quantities::{BaseAtoms, QuoteAtomsPerBaseAtom, WrapperU64},
+    state::OrderType,
+    validation::ProgramIdSet,
+};
+use solana_sdk::{pubkey::Pubkey, transaction::VersionedTransaction};
+use std::collections::HashMap;
+
+use crate::{
+    decoder::{decode_event, ManifestEvent, MarketCreated},
+    display::TraderRegistry,
+    instructions::ManifestInstructionTag,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenBalanceChange {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    /// Post balance minus pre balance.
+    pub delta_atoms: i128,
+}
+
+/// Parts of the transaction status a summary needs.
+#[derive(Debug, Clone, Default)]
+pub struct TransactionMeta {
+    /// Decoded payloads of the `Program data:` log lines, in order.
+    pub log_data: Vec<Vec<u8>>,
+    pub token_balance_changes: Vec<TokenBalanceChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MintLabel {
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    CreateMarket(MarketCreated),
+    Deposit {
+        trader: Pubkey,
+        market: Pubkey,
+        mint: Pubkey,
+        amount_atoms: u64,
+    },
+    Withdraw {
+        trader: Pubkey,
+        market: Pubkey,
+        mint: Pubkey,
+        amount_atoms: u64,
+    },
+    Swap {
+        trader: Pubkey,
+        market: Pubkey,
+        in_mint: Pubkey,
+        in_atoms: u64,
+        out_mint: Pubkey,
+        out_atoms: u64,
+        orders_crossed: usize,
+    },
+    PlaceOrder {
+        trader: Pubkey,
+        market: Pubkey,
+        is_bid: bool,
+        base_atoms: BaseAtoms,
+        price: QuoteAtomsPerBaseAtom,
+        order_type: OrderType,
+    },
+    CancelOrder {
+        trader: Pubkey,
+        market: Pubkey,
+        order_sequence_number: u64,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TransactionSummary {
+    pub actions: Vec<Action>,
+}
+
+/// Top level Manifest instructions with their account keys. Instructions
+/// that use lookup table accounts are skipped, like in the decoder.
+fn manifest_instructions(
+    transaction: &VersionedTransaction,
+    program_ids: &ProgramIdSet,
+) -> Vec<(ManifestInstructionTag, Vec<Pubkey>)> {
+    let static_keys: &[Pubkey] = transaction.message.static_account_keys();
+    transaction
+        .message
+        .instructions()
+        .iter()
+        .filter_map(|instruction| {
+            let program_id: &Pubkey = static_keys.get(instruction.program_id_index as usize)?;
+            if !program_ids.contains(program_id) {
+                return None;
+            }
+            let tag: ManifestInstructionTag =
+                ManifestInstructionTag::from_instruction_data(&instruction.data)?;
+            let account_keys: Vec<Pubkey> = instruction
+                .accounts
+                .iter()
+                .map(|index| static_keys.get(*index as usize).copied())
+                .collect::<Option<Vec<Pubkey>>>()?;
+            Some((tag, account_keys))
+        })
+        .collect()
+}
+
+/// What `transaction` did, which should have succeeded. Only instructions to
+/// a program in `program_ids` are summarized. Swap amounts come from the
+/// trader's token balance changes when they are in `meta` and from the fills
+/// otherwise.
+pub fn summarize_transaction(
+    transaction: &VersionedTransaction,
+    meta: &TransactionMeta,
+    program_ids: &ProgramIdSet,
+) -> TransactionSummary {
+    let mut actions: Vec<Action> = Vec::new();
+    let instructions: Vec<(ManifestInstructionTag, Vec<Pubkey>)> =
+        manifest_instructions(transaction, program_ids);
+    for (tag, account_keys) in &instructions {
+        if let Some(ManifestEvent::MarketCreated(market_created)) =
+            decode_event(&[u8::from(*tag)], account_keys, 0)
+        {
+            actions.push(Action::CreateMarket(market_created));
+        }
+    }
+
+    let mut fills: Vec<FillLog> = Vec::new();
+    for data in &meta.log_data {
+        if let Some(log) = decode_log::<DepositLog>(data) {
+            actions.push(Action::Deposit {
+                trader: log.trader,
+                market: log.market,
+                mint: log.mint,
+                amount_atoms: log.amount_atoms,
+            });
+        } else if let Some(log) = decode_log::<WithdrawLog>(data) {
+            actions.push(Action::Withdraw {
+                trader: log.trader,
+                market: log.market,
+                mint: log.mint,
+                amount_atoms: log.amount_atoms,
+            });
+        } else if let Some(log) = decode_log::<PlaceOrderLog>(data) {
+            actions.push(Action::PlaceOrder {
+                trader: log.trader,
+                market: log.market,
+                is_bid: log.is_bid.0 != 0,
+                base_atoms: log.base_atoms,
+                price: log.price,
+                order_type: log.order_type,
+            });
+        } else if let Some(log) = decode_log::<CancelOrderLog>(data) {
+            actions.push(Action::CancelOrder {
+                trader: log.trader,
+                market: log.market,
+                order_sequence_number: log.order_sequence_number,
+            });
+        } else if let Some(log) = decode_log::<FillLog>(data) {
+            fills.push(log);
+        }
+    }
+
+    for (tag, account_keys) in &instructions {
+        // Swap v2 separates the payer from the owner of the token accounts.
+        let (trader, market): (Pubkey, Pubkey) = match (tag, account_keys.as_slice()) {
+            (ManifestInstructionTag::Swap, [payer, market, ..]) => (*payer, *market),
+            (ManifestInstructionTag::SwapV2, [_, owner, market, ..]) => (*owner, *market),
+            _ => continue,
+        };
+        let swap_fills: Vec<&FillLog> = fills
+            .iter()
+            .filter(|fill| fill.market == market && fill.taker == trader)
+            .collect();
+        let Some(first_fill) = swap_fills.first() else {
+            continue;
+        };
+        let taker_is_buy: bool = first_fill.taker_is_buy.0 != 0;
+        let (in_mint, out_mint): (Pubkey, Pubkey) = if taker_is_buy {
+            (first_fill.quote_mint, first_fill.base_mint)
+        } else {
+            (first_fill.base_mint, first_fill.quote_mint)
+        };
+        let base_atoms: u64 = swap_fills.iter().map(|fill| fill.base_atoms.as_u64()).sum();
+        let quote_atoms: u64 = swap_fills
+            .iter()
+            .map(|fill| fill.quote_atoms.as_u64())
+            .sum();
+        let (filled_in_atoms, filled_out_atoms): (u64, u64) = if taker_is_buy {
+            (quote_atoms, base_atoms)
+        } else {
+            (base_atoms, quote_atoms)
+        };
+        let balance_change = |mint: &Pubkey| -> Option<i128> {
+            meta.token_balance_changes
+                .iter()
+                .find(|change| change.owner == trader && change.mint == *mint)
+                .map(|change| change.delta_atoms)
+        };
+        let (in_atoms, out_atoms): (u64, u64) =
+            match (balance_change(&in_mint), balance_change(&out_mint)) {
+                (Some(in_delta), Some(out_delta)) if in_delta < 0 && out_delta > 0 => {
+                    (in_delta.unsigned_abs() as u64, out_delta as u64)
+                }
+                _ => (filled_in_atoms, filled_out_atoms),
+            };
+        actions.push(Action::Swap {
+            trader,
+            market,
+            in_mint,
+            in_atoms,
+            out_mint,
+            out_atoms,
+            orders_crossed: swap_fills.len(),
+        });
+    }
+    TransactionSummary { actions }
+}
+
+fn format_mint(mint: &Pubkey, mints: &HashMap<Pubkey, MintLabel>) -> String {
+    match mints.get(mint) {
+        Some(label) => label.symbol.clone(),
+        None => mint.to_string(),
+    }
+}
+
+/// Atoms in whole tokens with the symbol when the mint is known.
+fn format_amount(atoms: u64, mint: &Pubkey, mints: &HashMap<Pubkey, MintLabel>) -> String {
+    match mints.get(mint) {
+        Some(label) => format!(
+            "{} {}",
+            atoms as f64 / 10_f64.powi(label.decimals as i32),
+            label.symbol
+        ),
+        None => format!("{atoms} atoms of {mint}"),
+    }
+}
+
+impl TransactionSummary {
+    /// One sentence per action, for example "Trader X swapped 1.2 SOL for
+    /// 180 USDC on market M crossing 3 orders."
+    pub fn describe(&self, mints: &HashMap<Pubkey, MintLabel>, traders: &TraderRegistry) -> String {
+        self.actions
+            .iter()
+            .map(|action| match action {
+                Action::CreateMarket(market_created) => format!(
+                    "Trader {} created market {} for {}/{}.",
+                    traders.annotate(&market_created.creator),
+                    market_created.market,
+                    format_mint(&market_created.base_mint, mints),
+                    format_mint(&market_created.quote_mint, mints),
+                ),
+                Action::Deposit {
+                    trader,
+                    market,
+                    mint,
+                    amount_atoms,
+                } => format!(
+                    "Trader {} deposited {} on market {market}.",
+                    traders.annotate(trader),
+                    format_amount(*amount_atoms, mint, mints)
+                ),
+                Action::Withdraw {
+                    trader,
+                    market,
+                    mint,
+                    amount_atoms,
+                } => format!(
+                    "Trader {} withdrew {} from market {market}.",
+                    traders.annotate(trader),
+                    format_amount(*amount_atoms, mint, mints)
+                ),
+                Action::Swap {
+                    trader,
+                    market,
+                    in_mint,
+                    in_atoms,
+                    out_mint,
+                    out_atoms,
+                    orders_crossed,
+                } => format!(
+                    "Trader {} swapped {} for {} on market {market} crossing {orders_crossed} {}.",
+                    traders.annotate(trader),
+                    format_amount(*in_atoms, in_mint, mints),
+                    format_amount(*out_atoms, out_mint, mints),
+                    if *orders_crossed == 1 {
+                        "order"
+                    } else {
+                        "orders"
+                    }
+                ),
+                Action::PlaceOrder {
+                    trader,
+                    market,
+                    is_bid,
+                    base_atoms,
+                    price,
+                    order_type,
+                } => format!(
+                    "Trader {} placed a {:?} {} for {} base atoms at {price} quote atoms per base \
+                     atom on market {market}.",
+                    traders.annotate(trader),
+                    order_type,
+                    if *is_bid { "bid" } else { "ask" },
+                    base_atoms.as_u64(),
+                ),
+                Action::CancelOrder {
+                    trader,
+                    market,
+                    order_sequence_number,
+                } => format!(
+                    "Trader {} cancelled order {order_sequence_number} on market {market}.",
+                    traders.annotate(trader),
+                ),
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bytemuck::bytes_of;
+    use manifest::{
+        logs::Discriminant,
+        program::swap_instruction,
+        quantities::{QuoteAtoms, QuoteAtomsPerBaseAtom},
+    };
+    use solana_sdk::message::{Message, VersionedMessage};
+
+    #[test]
+    fn test_summarize_swap() {
+        let trader: Pubkey = Pubkey::new_unique();
+        let market: Pubkey = Pubkey::new_unique();
+        let base_mint: Pubkey = Pubkey::new_unique();
+        let quote_mint: Pubkey = Pubkey::new_unique();
+        let swap = swap_instruction(
+            &market,
+            &trader,
+            &base_mint,
+            &quote_mint,
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            1_200_000_000,
+            0,
+            true,
+            true,
+            spl_token::id(),
+            spl_token::id(),
+            false,
+        );
+        let transaction: VersionedTransaction = VersionedTransaction {
+            signatures: vec![],
+            message: VersionedMessage::Legacy(Message::new(&[swap], Some(&trader))),
+        };
+        let fill = |base_atoms: u64, quote_atoms: u64| -> Vec<u8> {
+            let log: FillLog = FillLog {
+                market,
+                maker: Pubkey::new_unique(),
+                taker: trader,
+                base_mint,
+                quote_mint,
+                price: QuoteAtomsPerBaseAtom::ZERO,
+                base_atoms: BaseAtoms::new(base_atoms),
+                quote_atoms: QuoteAtoms::new(quote_atoms),
+                maker_sequence_number: 0,
+                taker_sequence_number: 1,
+                taker_is_buy: false.into(),
+                is_maker_global: false.into(),
+                _padding: [0; 14],
+            };
+            [FillLog::discriminant().as_slice(), bytes_of(&log)].concat()
+        };
+        let meta: TransactionMeta = TransactionMeta {
+            log_data: vec![
+                fill(400_000_000, 60_000_000),
+                fill(400_000_000, 60_000_000),
+                fill(400_000_000, 60_000_000),
+            ],
+            token_balance_changes: vec![],
+        };
+
+        let summary: TransactionSummary =
+            summarize_transaction(&transaction, &meta, &ProgramIdSet::default());
+        let mints: HashMap<Pubkey, MintLabel> = HashMap::from([
+            (
+                base_mint,
+                MintLabel {
+                    symbol: "SOL".to_string(),
+                    decimals: 9,
+                },
+            ),
+            (
+                quote_mint,
+                MintLabel {
+                    symbol: "USDC".to_string(),
+                    decimals: 6,
+                },
+            ),
+        ]);
+        let mut traders: TraderRegistry = TraderRegistry::new();
+        traders.insert(&trader, "X");
+        assert_eq!(
+            summary.describe(&mints, &traders),
+            format!("Trader X ({trader}) swapped 1.2 SOL for 180 USDC on market {market} crossing 3 orders.")
+        );
+
+        // Balance changes take precedence over the fills.
+        let meta: TransactionMeta = TransactionMeta {
+            token_balance_changes: vec![
+                TokenBalanceChange {
+                    owner: trader,
+                    mint: base_mint,
+                    delta_atoms: -1_200_000_000,
+                },
+                TokenBalanceChange {
+                    owner: trader,
+                    mint: quote_mint,
+                    delta_atoms: 179_000_000,
+                },
+            ],
+            ..meta
+        };
+        let summary: TransactionSummary =
+            summarize_transaction(&transaction, &meta, &ProgramIdSet::default());
+        assert!(matches!(
+            summary.actions[0],
+            Action::Swap {
+                out_atoms: 179_000_000,
+                ..
+            }
+        ));
+    }
+}
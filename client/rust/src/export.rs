@@ -0,0 +1,588 @@
+//! Trader activity as flat tables for accounting and tax tooling. Every table
+//! can be written as CSV, and as Parquet with the `parquet-export` feature.
+//! Columns are the same in both formats, in this order:
+//!
+//! fills, one row per `OrderEvent::Fill`:
+//! - slot: u64
+//! - market, maker, taker, base_mint, quote_mint: base58 string
+//! - price: f64, quote atoms per base atom
+//! - base_atoms, quote_atoms: u64, exact amounts traded
+//! - maker_sequence_number, taker_sequence_number: u64
+//! - taker_is_buy, is_maker_global: bool
+//!
+//! orders, one row per `OrderEvent::Place` or `OrderEvent::Cancel`:
+//! - slot: u64
+//! - market, trader: base58 string
+//! - event: "place" or "cancel"
+//! - order_sequence_number: u64
+//! - price: f64, empty for cancels
+//! - base_atoms: u64, empty for cancels
+//! - is_bid: bool, empty for cancels
+//! - order_type: `OrderType` name, empty for cancels
+//! - last_valid_slot: u32, empty for cancels, 0 for no expiration
+//! - cancel_reason: `CancelReason` name, empty for places
+//!
+//! balances, one row per seat of a market at a slot:
+//! - slot: u64
+//! - market, trader: base58 string
+//! - base_withdrawable_atoms, quote_withdrawable_atoms: u64, not counting
+//!   funds locked in orders
+//!
+//! Values never contain commas or quotes, so CSV fields are not quoted.
+
+use anyhow::{Context, Result};
+use hypertree::HyperTreeValueIteratorTrait;
+use manifest::{
+    logs::FillLog,
+    quantities::WrapperU64,
+    state::{claimed_seat::ClaimedSeat, MarketValue},
+};
+use solana_program::pubkey::Pubkey;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use crate::analytics::OrderEvent;
+
+pub const FILL_COLUMNS: [&str; 13] = [
+    "slot",
+    "market",
+    "maker",
+    "taker",
+    "base_mint",
+    "quote_mint",
+    "price",
+    "base_atoms",
+    "quote_atoms",
+    "maker_sequence_number",
+    "taker_sequence_number",
+    "taker_is_buy",
+    "is_maker_global",
+];
+
+pub const ORDER_COLUMNS: [&str; 11] = [
+    "slot",
+    "market",
+    "trader",
+    "event",
+    "order_sequence_number",
+    "price",
+    "base_atoms",
+    "is_bid",
+    "order_type",
+    "last_valid_slot",
+    "cancel_reason",
+];
+
+pub const BALANCE_COLUMNS: [&str; 5] = [
+    "slot",
+    "market",
+    "trader",
+    "base_withdrawable_atoms",
+    "quote_withdrawable_atoms",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FillRecord {
+    pub slot: u64,
+    pub market: Pubkey,
+    pub maker: Pubkey,
+    pub taker: Pubkey,
+    pub base_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    pub price: f64,
+    pub base_atoms: u64,
+    pub quote_atoms: u64,
+    pub maker_sequence_number: u64,
+    pub taker_sequence_number: u64,
+    pub taker_is_buy: bool,
+    pub is_maker_global: bool,
+}
+
+impl FillRecord {
+    pub fn new(slot: u64, log: &FillLog) -> Self {
+        FillRecord {
+            slot,
+            market: log.market,
+            maker: log.maker,
+            taker: log.taker,
+            base_mint: log.base_mint,
+            quote_mint: log.quote_mint,
+            price: log.price.as_f64(),
+            base_atoms: log.base_atoms.as_u64(),
+            quote_atoms: log.quote_atoms.as_u64(),
+            maker_sequence_number: log.maker_sequence_number,
+            taker_sequence_number: log.taker_sequence_number,
+            taker_is_buy: log.taker_is_buy.0 != 0,
+            is_maker_global: log.is_maker_global.0 != 0,
+        }
+    }
+
+    fn to_fields(&self) -> Vec<String> {
+        vec![
+            self.slot.to_string(),
+            self.market.to_string(),
+            self.maker.to_string(),
+            self.taker.to_string(),
+            self.base_mint.to_string(),
+            self.quote_mint.to_string(),
+            self.price.to_string(),
+            self.base_atoms.to_string(),
+            self.quote_atoms.to_string(),
+            self.maker_sequence_number.to_string(),
+            self.taker_sequence_number.to_string(),
+            self.taker_is_buy.to_string(),
+            self.is_maker_global.to_string(),
+        ]
+    }
+}
+
+/// Place or cancel. Fields that only apply to places are None for cancels
+/// and the other way around.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderEventRecord {
+    pub slot: u64,
+    pub market: Pubkey,
+    pub trader: Pubkey,
+    pub is_place: bool,
+    pub order_sequence_number: u64,
+    pub price: Option<f64>,
+    pub base_atoms: Option<u64>,
+    pub is_bid: Option<bool>,
+    pub order_type: Option<String>,
+    pub last_valid_slot: Option<u32>,
+    pub cancel_reason: Option<String>,
+}
+
+impl OrderEventRecord {
+    fn get_event(&self) -> &'static str {
+        if self.is_place {
+            "place"
+        } else {
+            "cancel"
+        }
+    }
+
+    fn to_fields(&self) -> Vec<String> {
+        fn optional<T: ToString>(value: &Option<T>) -> String {
+            value.as_ref().map(T::to_string).unwrap_or_default()
+        }
+        vec![
+            self.slot.to_string(),
+            self.market.to_string(),
+            self.trader.to_string(),
+            self.get_event().to_string(),
+            self.order_sequence_number.to_string(),
+            optional(&self.price),
+            optional(&self.base_atoms),
+            optional(&self.is_bid),
+            optional(&self.order_type),
+            optional(&self.last_valid_slot),
+            optional(&self.cancel_reason),
+        ]
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceRecord {
+    pub slot: u64,
+    pub market: Pubkey,
+    pub trader: Pubkey,
+    pub base_withdrawable_atoms: u64,
+    pub quote_withdrawable_atoms: u64,
+}
+
+impl BalanceRecord {
+    fn to_fields(&self) -> Vec<String> {
+        vec![
+            self.slot.to_string(),
+            self.market.to_string(),
+            self.trader.to_string(),
+            self.base_withdrawable_atoms.to_string(),
+            self.quote_withdrawable_atoms.to_string(),
+        ]
+    }
+}
+
+pub fn fill_records(events: &[OrderEvent]) -> Vec<FillRecord> {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            OrderEvent::Fill { slot, log } => Some(FillRecord::new(*slot, log)),
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn order_event_records(events: &[OrderEvent]) -> Vec<OrderEventRecord> {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            OrderEvent::Place { slot, log } => Some(OrderEventRecord {
+                slot: *slot,
+                market: log.market,
+                trader: log.trader,
+                is_place: true,
+                order_sequence_number: log.order_sequence_number,
+                price: Some(log.price.as_f64()),
+                base_atoms: Some(log.base_atoms.as_u64()),
+                is_bid: Some(log.is_bid.0 != 0),
+                order_type: Some(format!("{:?}", log.order_type)),
+                last_valid_slot: Some(log.last_valid_slot),
+                cancel_reason: None,
+            }),
+            OrderEvent::Cancel { slot, log, reason } => Some(OrderEventRecord {
+                slot: *slot,
+                market: log.market,
+                trader: log.trader,
+                is_place: false,
+                order_sequence_number: log.order_sequence_number,
+                price: None,
+                base_atoms: None,
+                is_bid: None,
+                order_type: None,
+                last_valid_slot: None,
+                cancel_reason: Some(format!("{reason:?}")),
+            }),
+            OrderEvent::Fill { .. } => None,
+        })
+        .collect()
+}
+
+/// Withdrawable balances of every seat on `market` as of `slot`.
+pub fn balance_records(market_key: &Pubkey, market: &MarketValue, slot: u64) -> Vec<BalanceRecord> {
+    market
+        .get_claimed_seats()
+        .iter::<ClaimedSeat>()
+        .map(|(_, seat)| BalanceRecord {
+            slot,
+            market: *market_key,
+            trader: seat.trader,
+            base_withdrawable_atoms: seat.base_withdrawable_balance.as_u64(),
+            quote_withdrawable_atoms: seat.quote_withdrawable_balance.as_u64(),
+        })
+        .collect()
+}
+
+fn write_csv(path: &Path, columns: &[&str], rows: impl Iterator<Item = Vec<String>>) -> Result<()> {
+    let file: File =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut writer: BufWriter<File> = BufWriter::new(file);
+    writeln!(writer, "{}", columns.join(","))?;
+    for row in rows {
+        writeln!(writer, "{}", row.join(","))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Fills among `events` as CSV, see the module docs for the columns.
+pub fn write_fills_csv(events: &[OrderEvent], path: impl AsRef<Path>) -> Result<()> {
+    write_csv(
+        path.as_ref(),
+        &FILL_COLUMNS,
+        fill_records(events).iter().map(FillRecord::to_fields),
+    )
+}
+
+/// Places and cancels among `events` as CSV.
+pub fn write_orders_csv(events: &[OrderEvent], path: impl AsRef<Path>) -> Result<()> {
+    write_csv(
+        path.as_ref(),
+        &ORDER_COLUMNS,
+        order_event_records(events)
+            .iter()
+            .map(OrderEventRecord::to_fields),
+    )
+}
+
+pub fn write_balances_csv(balances: &[BalanceRecord], path: impl AsRef<Path>) -> Result<()> {
+    write_csv(
+        path.as_ref(),
+        &BALANCE_COLUMNS,
+        balances.iter().map(BalanceRecord::to_fields),
+    )
+}
+
+#[cfg(feature = "parquet-export")]
+mod parquet_export {
+    use super::*;
+    use arrow_array::{
+        ArrayRef, BooleanArray, Float64Array, RecordBatch, StringArray, UInt32Array, UInt64Array,
+    };
+    use arrow_schema::{DataType, Field, Schema};
+    use parquet::arrow::ArrowWriter;
+    use std::sync::Arc;
+
+    fn keys(keys: impl Iterator<Item = Pubkey>) -> ArrayRef {
+        Arc::new(StringArray::from(
+            keys.map(|key| key.to_string()).collect::<Vec<String>>(),
+        ))
+    }
+
+    fn write_parquet(path: &Path, fields: Vec<Field>, columns: Vec<ArrayRef>) -> Result<()> {
+        let schema: Arc<Schema> = Arc::new(Schema::new(fields));
+        let batch: RecordBatch = RecordBatch::try_new(schema.clone(), columns)?;
+        let file: File =
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut writer: ArrowWriter<File> = ArrowWriter::try_new(file, schema, None)?;
+        writer.write(&batch)?;
+        writer.close()?;
+        Ok(())
+    }
+
+    pub fn write_fills_parquet(events: &[OrderEvent], path: impl AsRef<Path>) -> Result<()> {
+        let fills: Vec<FillRecord> = fill_records(events);
+        let u64_column = |value: fn(&FillRecord) -> u64| -> ArrayRef {
+            Arc::new(UInt64Array::from(
+                fills.iter().map(value).collect::<Vec<u64>>(),
+            ))
+        };
+        let key_column =
+            |value: fn(&FillRecord) -> Pubkey| -> ArrayRef { keys(fills.iter().map(value)) };
+        let bool_column = |value: fn(&FillRecord) -> bool| -> ArrayRef {
+            Arc::new(BooleanArray::from(
+                fills.iter().map(value).collect::<Vec<bool>>(),
+            ))
+        };
+        let columns: Vec<ArrayRef> = vec![
+            u64_column(|fill| fill.slot),
+            key_column(|fill| fill.market),
+            key_column(|fill| fill.maker),
+            key_column(|fill| fill.taker),
+            key_column(|fill| fill.base_mint),
+            key_column(|fill| fill.quote_mint),
+            Arc::new(Float64Array::from(
+                fills.iter().map(|fill| fill.price).collect::<Vec<f64>>(),
+            )),
+            u64_column(|fill| fill.base_atoms),
+            u64_column(|fill| fill.quote_atoms),
+            u64_column(|fill| fill.maker_sequence_number),
+            u64_column(|fill| fill.taker_sequence_number),
+            bool_column(|fill| fill.taker_is_buy),
+            bool_column(|fill| fill.is_maker_global),
+        ];
+        let data_types: [DataType; 13] = [
+            DataType::UInt64,
+            DataType::Utf8,
+            DataType::Utf8,
+            DataType::Utf8,
+            DataType::Utf8,
+            DataType::Utf8,
+            DataType::Float64,
+            DataType::UInt64,
+            DataType::UInt64,
+            DataType::UInt64,
+            DataType::UInt64,
+            DataType::Boolean,
+            DataType::Boolean,
+        ];
+        let fields: Vec<Field> = FILL_COLUMNS
+            .iter()
+            .zip(data_types)
+            .map(|(name, data_type)| Field::new(*name, data_type, false))
+            .collect();
+        write_parquet(path.as_ref(), fields, columns)
+    }
+
+    pub fn write_orders_parquet(events: &[OrderEvent], path: impl AsRef<Path>) -> Result<()> {
+        let orders: Vec<OrderEventRecord> = order_event_records(events);
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(UInt64Array::from(
+                orders.iter().map(|order| order.slot).collect::<Vec<u64>>(),
+            )),
+            keys(orders.iter().map(|order| order.market)),
+            keys(orders.iter().map(|order| order.trader)),
+            Arc::new(StringArray::from(
+                orders
+                    .iter()
+                    .map(OrderEventRecord::get_event)
+                    .collect::<Vec<&str>>(),
+            )),
+            Arc::new(UInt64Array::from(
+                orders
+                    .iter()
+                    .map(|order| order.order_sequence_number)
+                    .collect::<Vec<u64>>(),
+            )),
+            Arc::new(Float64Array::from(
+                orders
+                    .iter()
+                    .map(|order| order.price)
+                    .collect::<Vec<Option<f64>>>(),
+            )),
+            Arc::new(UInt64Array::from(
+                orders
+                    .iter()
+                    .map(|order| order.base_atoms)
+                    .collect::<Vec<Option<u64>>>(),
+            )),
+            Arc::new(BooleanArray::from(
+                orders
+                    .iter()
+                    .map(|order| order.is_bid)
+                    .collect::<Vec<Option<bool>>>(),
+            )),
+            Arc::new(StringArray::from(
+                orders
+                    .iter()
+                    .map(|order| order.order_type.clone())
+                    .collect::<Vec<Option<String>>>(),
+            )),
+            Arc::new(UInt32Array::from(
+                orders
+                    .iter()
+                    .map(|order| order.last_valid_slot)
+                    .collect::<Vec<Option<u32>>>(),
+            )),
+            Arc::new(StringArray::from(
+                orders
+                    .iter()
+                    .map(|order| order.cancel_reason.clone())
+                    .collect::<Vec<Option<String>>>(),
+            )),
+        ];
+        let fields: Vec<Field> = vec![
+            Field::new("slot", DataType::UInt64, false),
+            Field::new("market", DataType::Utf8, false),
+            Field::new("trader", DataType::Utf8, false),
+            Field::new("event", DataType::Utf8, false),
+            Field::new("order_sequence_number", DataType::UInt64, false),
+            Field::new("price", DataType::Float64, true),
+            Field::new("base_atoms", DataType::UInt64, true),
+            Field::new("is_bid", DataType::Boolean, true),
+            Field::new("order_type", DataType::Utf8, true),
+            Field::new("last_valid_slot", DataType::UInt32, true),
+            Field::new("cancel_reason", DataType::Utf8, true),
+        ];
+        write_parquet(path.as_ref(), fields, columns)
+    }
+
+    pub fn write_balances_parquet(
+        balances: &[BalanceRecord],
+        path: impl AsRef<Path>,
+    ) -> Result<()> {
+        let u64_column = |value: fn(&BalanceRecord) -> u64| -> ArrayRef {
+            Arc::new(UInt64Array::from(
+                balances.iter().map(value).collect::<Vec<u64>>(),
+            ))
+        };
+        let columns: Vec<ArrayRef> = vec![
+            u64_column(|balance| balance.slot),
+            keys(balances.iter().map(|balance| balance.market)),
+            keys(balances.iter().map(|balance| balance.trader)),
+            u64_column(|balance| balance.base_withdrawable_atoms),
+            u64_column(|balance| balance.quote_withdrawable_atoms),
+        ];
+        let fields: Vec<Field> = vec![
+            Field::new("slot", DataType::UInt64, false),
+            Field::new("market", DataType::Utf8, false),
+            Field::new("trader", DataType::Utf8, false),
+            Field::new("base_withdrawable_atoms", DataType::UInt64, false),
+            Field::new("quote_withdrawable_atoms", DataType::UInt64, false),
+        ];
+        write_parquet(path.as_ref(), fields, columns)
+    }
+}
+
+#[cfg(feature = "parquet-export")]
+pub use parquet_export::*;
+
+#[cfg(all(test, feature = "model"))]
+mod test {
+    use super::*;
+    use crate::{
+        decoder::CancelReason,
+        model::{ModelOperation, ModelRunner},
+    };
+    use manifest::{
+        logs::CancelOrderLog,
+        quantities::{BaseAtoms, QuoteAtoms, QuoteAtomsPerBaseAtom},
+    };
+
+    #[test]
+    fn test_write_csv() {
+        let market: Pubkey = Pubkey::new_unique();
+        let maker: Pubkey = Pubkey::new_unique();
+        let events: Vec<OrderEvent> = vec![
+            OrderEvent::Fill {
+                slot: 7,
+                log: FillLog {
+                    market,
+                    maker,
+                    taker: Pubkey::new_unique(),
+                    base_mint: Pubkey::new_unique(),
+                    quote_mint: Pubkey::new_unique(),
+                    price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(2, 0).unwrap(),
+                    base_atoms: BaseAtoms::new(100),
+                    quote_atoms: QuoteAtoms::new(200),
+                    maker_sequence_number: 3,
+                    taker_sequence_number: 4,
+                    taker_is_buy: true.into(),
+                    is_maker_global: false.into(),
+                    _padding: [0; 14],
+                },
+            },
+            OrderEvent::Cancel {
+                slot: 8,
+                log: CancelOrderLog {
+                    market,
+                    trader: maker,
+                    order_sequence_number: 3,
+                },
+                reason: CancelReason::Expired,
+            },
+        ];
+        let dir: std::path::PathBuf = std::env::temp_dir();
+        let fills_path: std::path::PathBuf = dir.join(format!("fills-{market}.csv"));
+        write_fills_csv(&events, &fills_path).unwrap();
+        let fills_csv: String = std::fs::read_to_string(&fills_path).unwrap();
+        let lines: Vec<&str> = fills_csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], FILL_COLUMNS.join(","));
+        assert!(lines[1].starts_with(&format!("7,{market},{maker},")));
+        assert!(lines[1].ends_with(",2,100,200,3,4,true,false"));
+
+        let orders_path: std::path::PathBuf = dir.join(format!("orders-{market}.csv"));
+        write_orders_csv(&events, &orders_path).unwrap();
+        let orders_csv: String = std::fs::read_to_string(&orders_path).unwrap();
+        assert_eq!(
+            orders_csv.lines().nth(1).unwrap(),
+            format!("8,{market},{maker},cancel,3,,,,,,Expired")
+        );
+
+        let mut runner: ModelRunner =
+            ModelRunner::new(&market, &Pubkey::new_unique(), &Pubkey::new_unique());
+        runner.claim_seat(&maker).unwrap();
+        runner
+            .apply(&ModelOperation::Deposit {
+                trader: maker,
+                amount_atoms: 500,
+                is_base: false,
+            })
+            .unwrap();
+        let balances: Vec<BalanceRecord> = balance_records(&market, &runner.market, 9);
+        assert_eq!(
+            balances,
+            vec![BalanceRecord {
+                slot: 9,
+                market,
+                trader: maker,
+                base_withdrawable_atoms: 0,
+                quote_withdrawable_atoms: 500,
+            }]
+        );
+        let balances_path: std::path::PathBuf = dir.join(format!("balances-{market}.csv"));
+        write_balances_csv(&balances, &balances_path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&balances_path).unwrap(),
+            format!("{}\n9,{market},{maker},0,500\n", BALANCE_COLUMNS.join(","))
+        );
+
+        for path in [fills_path, orders_path, balances_path] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! A single entry point for integrators who would rather not wire the modules
+//! together themselves. `Manifest` holds the account fetcher and an optional
+//! state store and hands out a `MarketHandle` per market, which quotes, builds
+//! order instructions and captures the book. Each method is a thin call into
+//! the modules re-exported by `prelude`, which remain the way to do anything
+//! the facade does not cover.
+
+use anyhow::Result;
+use hypertree::{DataIndex, NIL};
+use jupiter_amm_interface::{
+    AccountMap, Amm, AmmContext, ClockRef, KeyedAccount, Quote, QuoteParams, SwapMode,
+};
+use manifest::{
+    program::{batch_update::PlaceOrderParams, batch_update_instruction},
+    state::{MarketValue, OrderType},
+};
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+
+use crate::{
+    book::BooksideSnapshot,
+    rpc::{fetch_account_map, AccountFetcher},
+    sync::{StampedMarket, StateStamp, StateStore},
+    ManifestMarket,
+};
+
+/// Both sides of a book captured at the same slot.
+#[derive(Debug, Clone)]
+pub struct Orderbook {
+    pub bids: BooksideSnapshot,
+    pub asks: BooksideSnapshot,
+}
+
+pub struct Manifest<F: AccountFetcher> {
+    fetcher: F,
+    store_opt: Option<Box<dyn StateStore>>,
+}
+
+impl<F: AccountFetcher> Manifest<F> {
+    pub fn new(fetcher: F) -> Self {
+        Manifest {
+            fetcher,
+            store_opt: None,
+        }
+    }
+
+    /// Save every market fetched through the facade to `store`.
+    pub fn with_store(mut self, store: Box<dyn StateStore>) -> Self {
+        self.store_opt = Some(store);
+        self
+    }
+
+    pub fn get_fetcher(&self) -> &F {
+        &self.fetcher
+    }
+
+    /// Fetch a market with its mints and globals, stamped with the slot the
+    /// fetcher reports.
+    pub fn market(&self, market_key: &Pubkey) -> Result<MarketHandle> {
+        let amm: ManifestMarket = ManifestMarket::from_keyed_account(
+            &KeyedAccount {
+                key: *market_key,
+                account: self.fetcher.get_account(market_key)?,
+                params: None,
+            },
+            &AmmContext {
+                clock_ref: ClockRef::default(),
+            },
+        )?;
+        let mut handle: MarketHandle = MarketHandle { amm };
+        self.refresh(&mut handle)?;
+        Ok(handle)
+    }
+
+    /// Fetch the accounts of `handle` again. Returns whether the fetched
+    /// state was newer than what the handle had.
+    pub fn refresh(&self, handle: &mut MarketHandle) -> Result<bool> {
+        let stamp: StateStamp = StateStamp {
+            slot: self.fetcher.get_slot()?,
+            write_version: 0,
+        };
+        let account_map: AccountMap = fetch_account_map(&handle.amm, &self.fetcher)?;
+        if !handle.update(&account_map, stamp)? {
+            return Ok(false);
+        }
+        if let Some(store) = self.store_opt.as_ref() {
+            store.save_market(
+                &handle.amm.key(),
+                &StampedMarket::new(stamp, handle.amm.market.clone()),
+            )?;
+        }
+        Ok(true)
+    }
+}
+
+/// One market as loaded by `Manifest::market`. Subscriptions keep it current
+/// by passing their account updates to `update`.
+#[derive(Clone)]
+pub struct MarketHandle {
+    amm: ManifestMarket,
+}
+
+impl MarketHandle {
+    pub fn get_amm(&self) -> &ManifestMarket {
+        &self.amm
+    }
+
+    pub fn get_stamp(&self) -> StateStamp {
+        self.amm.get_stamp()
+    }
+
+    /// Apply accounts from a subscription, see
+    /// `ManifestMarket::update_with_stamp`.
+    pub fn update(&mut self, account_map: &AccountMap, stamp: StateStamp) -> Result<bool> {
+        self.amm.update_with_stamp(account_map, stamp)
+    }
+
+    /// Exact in swap quote of `in_atoms` of the base or quote token.
+    pub fn quote(&self, is_base_in: bool, in_atoms: u64) -> Result<Quote> {
+        let (input_mint, output_mint): (Pubkey, Pubkey) = if is_base_in {
+            (self.amm.get_base_mint(), self.amm.get_quote_mint())
+        } else {
+            (self.amm.get_quote_mint(), self.amm.get_base_mint())
+        };
+        self.amm.quote(&QuoteParams {
+            amount: in_atoms,
+            swap_mode: SwapMode::ExactIn,
+            input_mint,
+            output_mint,
+        })
+    }
+
+    /// BatchUpdate that places `order` for `trader`. Global accounts are
+    /// included for the mint a global order rests in and for any loaded
+    /// global the order could match against.
+    pub fn place(&self, trader: &Pubkey, order: PlaceOrderParams) -> Instruction {
+        let market: &MarketValue = &self.amm.market;
+        let trader_index: DataIndex = market.get_trader_index(trader);
+        let is_global: bool = order.order_type() == OrderType::Global;
+        let (base_global_needed, quote_global_needed): (bool, bool) = if order.is_bid() {
+            (self.amm.base_global.is_some(), is_global)
+        } else {
+            (is_global, self.amm.quote_global.is_some())
+        };
+        batch_update_instruction(
+            &self.amm.key(),
+            trader,
+            (trader_index != NIL).then_some(trader_index),
+            Vec::new(),
+            vec![order],
+            base_global_needed.then(|| self.amm.get_base_mint()),
+            base_global_needed.then_some(self.amm.base_token_program),
+            quote_global_needed.then(|| self.amm.get_quote_mint()),
+            quote_global_needed.then_some(self.amm.quote_token_program),
+        )
+    }
+
+    pub fn orderbook(&self) -> Orderbook {
+        let slot: u64 = self.get_stamp().slot;
+        Orderbook {
+            bids: BooksideSnapshot::capture_bids(&self.amm.market, slot),
+            asks: BooksideSnapshot::capture_asks(&self.amm.market, slot),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "model"))]
+mod test {
+    use super::*;
+    use crate::{
+        model::{ModelOperation, ModelRunner},
+        rpc::MockRpc,
+    };
+    use manifest::{
+        quantities::{BaseAtoms, QuoteAtomsPerBaseAtom},
+        state::{MARKET_BLOCK_SIZE, NO_EXPIRATION_LAST_VALID_SLOT},
+    };
+
+    #[test]
+    fn test_facade() {
+        let market_key: Pubkey = Pubkey::new_unique();
+        let base_mint: Pubkey = Pubkey::new_unique();
+        let quote_mint: Pubkey = Pubkey::new_unique();
+        let maker: Pubkey = Pubkey::new_unique();
+        let mut runner: ModelRunner = ModelRunner::new(&market_key, &base_mint, &quote_mint);
+        runner.claim_seat(&maker).unwrap();
+        runner
+            .apply(&ModelOperation::Deposit {
+                trader: maker,
+                amount_atoms: 1_000,
+                is_base: true,
+            })
+            .unwrap();
+        runner
+            .apply(&ModelOperation::PlaceOrder {
+                trader: maker,
+                num_base_atoms: BaseAtoms::new(1_000),
+                price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(1, 0).unwrap(),
+                is_bid: false,
+                order_type: OrderType::Limit,
+            })
+            .unwrap();
+        // Quotes need room to expand for reverse orders.
+        let mut market: MarketValue = runner.market.clone();
+        for _ in 0..2 {
+            market.dynamic.extend_from_slice(&[0; MARKET_BLOCK_SIZE]);
+            market.market_expand().unwrap();
+        }
+        let mut rpc: MockRpc = MockRpc::new();
+        rpc.set_model(&runner);
+        rpc.set_market(&market_key, &market);
+        rpc.set_slot(7);
+
+        let manifest: Manifest<MockRpc> = Manifest::new(rpc);
+        let handle: MarketHandle = manifest.market(&market_key).unwrap();
+        assert_eq!(handle.get_stamp().slot, 7);
+        assert!(handle.quote(false, 100).unwrap().out_amount > 0);
+        assert_eq!(handle.quote(true, 100).unwrap().out_amount, 0);
+
+        let orderbook: Orderbook = handle.orderbook();
+        assert!(orderbook.bids.orders.is_empty());
+        assert_eq!(orderbook.asks.orders.len(), 1);
+        assert_eq!(orderbook.asks.slot, 7);
+
+        let instruction: Instruction = handle.place(
+            &maker,
+            PlaceOrderParams::new(
+                10,
+                1,
+                0,
+                true,
+                OrderType::Limit,
+                NO_EXPIRATION_LAST_VALID_SLOT,
+            ),
+        );
+        assert_eq!(instruction.accounts[0].pubkey, maker);
+        assert_eq!(instruction.accounts[1].pubkey, market_key);
+        // No globals are loaded and the order is not global.
+        assert_eq!(instruction.accounts.len(), 3);
+    }
+}
@@ -0,0 +1,78 @@
+//! Fees on top of what the book pays out. The program charges none today,
+//! which `ZeroFees` reflects and which is the default everywhere. Quoting
+//! takes a `FeeSchedule` so a taker or maker fee added by the protocol or by
+//! a wrapper can be modeled without another change to how quotes are made.
+
+/// Fees of one market. Amounts are in atoms and rounded in favor of whoever
+/// charges the fee.
+pub trait FeeSchedule {
+    /// Fee a taker pays in the output token on a swap that pays out
+    /// `out_atoms` before fees.
+    fn taker_fee_atoms(&self, is_base_in: bool, out_atoms: u64) -> u64;
+
+    /// Fee a maker pays in quote atoms when `quote_atoms` of its order fill.
+    /// Negative for a rebate.
+    fn maker_fee_quote_atoms(&self, _quote_atoms: u64) -> i64 {
+        0
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ZeroFees;
+
+impl FeeSchedule for ZeroFees {
+    fn taker_fee_atoms(&self, _is_base_in: bool, _out_atoms: u64) -> u64 {
+        0
+    }
+}
+
+/// Fees proportional to the amount traded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BpsFees {
+    pub taker_bps: u16,
+    /// Negative for a rebate.
+    pub maker_bps: i16,
+}
+
+impl FeeSchedule for BpsFees {
+    fn taker_fee_atoms(&self, _is_base_in: bool, out_atoms: u64) -> u64 {
+        (out_atoms as u128 * self.taker_bps as u128).div_ceil(10_000) as u64
+    }
+
+    fn maker_fee_quote_atoms(&self, quote_atoms: u64) -> i64 {
+        let fee_atoms: i128 = quote_atoms as i128 * self.maker_bps as i128;
+        // Fees round up and rebates round down, both toward the charger.
+        (if fee_atoms >= 0 {
+            (fee_atoms + 9_999) / 10_000
+        } else {
+            fee_atoms / 10_000
+        }) as i64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bps_fees() {
+        assert_eq!(ZeroFees.taker_fee_atoms(true, 1_000_000), 0);
+        assert_eq!(ZeroFees.maker_fee_quote_atoms(1_000_000), 0);
+
+        let fees: BpsFees = BpsFees {
+            taker_bps: 30,
+            maker_bps: -5,
+        };
+        assert_eq!(fees.taker_fee_atoms(true, 1_000_000), 3_000);
+        assert_eq!(fees.taker_fee_atoms(false, 1), 1);
+        assert_eq!(fees.taker_fee_atoms(false, 0), 0);
+        assert_eq!(fees.maker_fee_quote_atoms(1_000_000), -500);
+        assert_eq!(fees.maker_fee_quote_atoms(1_999), 0);
+
+        let fees: BpsFees = BpsFees {
+            taker_bps: 0,
+            maker_bps: 5,
+        };
+        assert_eq!(fees.maker_fee_quote_atoms(1_999), 1);
+    }
+}
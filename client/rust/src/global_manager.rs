@@ -0,0 +1,284 @@
+//! Global seats and deposits of one trader across many mints. Makers that
+//! quote global orders on many markets keep a deposit per mint and need to
+//! keep each of them near a target so orders stay backed without idling too
+//! much inventory in any one global.
+
+use anyhow::{bail, Result};
+use hypertree::get_helper;
+use manifest::{
+    program::{
+        global_add_trader_instruction, global_create_instruction::create_global_instruction,
+        global_deposit_instruction, global_withdraw_instruction,
+    },
+    quantities::WrapperU64,
+    state::{GlobalFixed, GlobalValue, GLOBAL_FIXED_DISCRIMINANT},
+    validation::get_global_address,
+};
+use solana_sdk::{account::Account, instruction::Instruction, pubkey::Pubkey};
+use std::{collections::BTreeMap, mem::size_of};
+
+use crate::rpc::AccountFetcher;
+
+/// Deposit to maintain in the global for a mint. Nothing is moved while the
+/// balance is within `tolerance_atoms` of `target_atoms`, so small fills do
+/// not cause a transfer every time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GlobalTarget {
+    pub target_atoms: u64,
+    pub tolerance_atoms: u64,
+}
+
+#[derive(Clone)]
+struct ManagedMint {
+    token_program: Pubkey,
+    trader_token_account: Pubkey,
+    target: Option<GlobalTarget>,
+    /// None until refreshed, or if the global for the mint does not exist.
+    global: Option<GlobalValue>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GlobalBalance {
+    pub mint: Pubkey,
+    pub global_exists: bool,
+    pub has_seat: bool,
+    pub balance_atoms: u64,
+}
+
+#[derive(Clone)]
+pub struct GlobalManager {
+    trader: Pubkey,
+    mints: BTreeMap<Pubkey, ManagedMint>,
+}
+
+fn load_global(data: &[u8]) -> Result<GlobalValue> {
+    if data.len() < size_of::<GlobalFixed>() {
+        bail!("Account is {} bytes, too short for a global", data.len());
+    }
+    let (header_bytes, dynamic_data) = data.split_at(size_of::<GlobalFixed>());
+    let global_fixed: &GlobalFixed = get_helper::<GlobalFixed>(header_bytes, 0_u32);
+    if global_fixed.discriminant != GLOBAL_FIXED_DISCRIMINANT {
+        bail!("Account is not a global");
+    }
+    Ok(GlobalValue {
+        fixed: *global_fixed,
+        dynamic: dynamic_data.to_vec(),
+    })
+}
+
+impl GlobalManager {
+    pub fn new(trader: &Pubkey) -> Self {
+        GlobalManager {
+            trader: *trader,
+            mints: BTreeMap::new(),
+        }
+    }
+
+    pub fn get_trader(&self) -> &Pubkey {
+        &self.trader
+    }
+
+    /// Track a mint. Deposits come from and withdrawals go to
+    /// `trader_token_account`. Without a target the mint is only reported.
+    pub fn add_mint(
+        &mut self,
+        mint: &Pubkey,
+        token_program: &Pubkey,
+        trader_token_account: &Pubkey,
+        target: Option<GlobalTarget>,
+    ) {
+        self.mints.insert(
+            *mint,
+            ManagedMint {
+                token_program: *token_program,
+                trader_token_account: *trader_token_account,
+                target,
+                global: None,
+            },
+        );
+    }
+
+    pub fn set_target(&mut self, mint: &Pubkey, target: Option<GlobalTarget>) -> Result<()> {
+        let Some(managed_mint) = self.mints.get_mut(mint) else {
+            bail!("Mint {mint} is not managed");
+        };
+        managed_mint.target = target;
+        Ok(())
+    }
+
+    /// Reload the globals of all managed mints in one request.
+    pub fn refresh<F: AccountFetcher>(&mut self, fetcher: &F) -> Result<()> {
+        let mints: Vec<Pubkey> = self.mints.keys().copied().collect();
+        let global_keys: Vec<Pubkey> = mints
+            .iter()
+            .map(|mint| get_global_address(mint).0)
+            .collect();
+        let accounts: Vec<Option<Account>> = fetcher.get_multiple_accounts(&global_keys)?;
+        for (mint, account) in mints.iter().zip(accounts) {
+            let global: Option<GlobalValue> = match account {
+                Some(account) => Some(load_global(&account.data)?),
+                None => None,
+            };
+            self.update_global(mint, global)?;
+        }
+        Ok(())
+    }
+
+    /// For callers that already have the global from a subscription.
+    pub fn update_global(&mut self, mint: &Pubkey, global: Option<GlobalValue>) -> Result<()> {
+        let Some(managed_mint) = self.mints.get_mut(mint) else {
+            bail!("Mint {mint} is not managed");
+        };
+        if let Some(global) = &global {
+            if global.fixed.get_mint() != mint {
+                bail!("Global is for {}, not {mint}", global.fixed.get_mint());
+            }
+        }
+        managed_mint.global = global;
+        Ok(())
+    }
+
+    pub fn get_balance(&self, mint: &Pubkey) -> Option<GlobalBalance> {
+        let managed_mint: &ManagedMint = self.mints.get(mint)?;
+        Some(match &managed_mint.global {
+            Some(global) => GlobalBalance {
+                mint: *mint,
+                global_exists: true,
+                has_seat: global.has_global_seat(&self.trader),
+                balance_atoms: global.get_balance_atoms(&self.trader).as_u64(),
+            },
+            None => GlobalBalance {
+                mint: *mint,
+                global_exists: false,
+                has_seat: false,
+                balance_atoms: 0,
+            },
+        })
+    }
+
+    /// Balances of every managed mint, ordered by mint.
+    pub fn get_balances(&self) -> Vec<GlobalBalance> {
+        self.mints
+            .keys()
+            .filter_map(|mint| self.get_balance(mint))
+            .collect()
+    }
+
+    /// Instructions that bring every mint with a target back to it: creating
+    /// the global and claiming a seat where needed, then depositing or
+    /// withdrawing the difference. The trader pays and signs. Does not check
+    /// the wallet has enough tokens for the deposits.
+    pub fn rebalance_instructions(&self) -> Vec<Instruction> {
+        let mut instructions: Vec<Instruction> = Vec::new();
+        for (mint, managed_mint) in self.mints.iter() {
+            let Some(target) = managed_mint.target else {
+                continue;
+            };
+            let balance: GlobalBalance = self.get_balance(mint).unwrap();
+            if !balance.global_exists {
+                instructions.push(create_global_instruction(
+                    mint,
+                    &self.trader,
+                    &managed_mint.token_program,
+                ));
+            }
+            if !balance.has_seat {
+                instructions.push(global_add_trader_instruction(
+                    &get_global_address(mint).0,
+                    &self.trader,
+                ));
+            }
+            if balance.balance_atoms + target.tolerance_atoms < target.target_atoms {
+                instructions.push(global_deposit_instruction(
+                    mint,
+                    &self.trader,
+                    &managed_mint.trader_token_account,
+                    &managed_mint.token_program,
+                    target.target_atoms - balance.balance_atoms,
+                ));
+            } else if balance.balance_atoms > target.target_atoms + target.tolerance_atoms {
+                instructions.push(global_withdraw_instruction(
+                    mint,
+                    &self.trader,
+                    &managed_mint.trader_token_account,
+                    &managed_mint.token_program,
+                    balance.balance_atoms - target.target_atoms,
+                ));
+            }
+        }
+        instructions
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::rpc::MockRpc;
+    use manifest::{
+        program::ManifestInstruction, quantities::GlobalAtoms, state::GLOBAL_BLOCK_SIZE,
+    };
+
+    #[test]
+    fn test_global_manager_rebalance() {
+        let trader: Pubkey = Pubkey::new_unique();
+        let funded_mint: Pubkey = Pubkey::new_unique();
+        let new_mint: Pubkey = Pubkey::new_unique();
+
+        let mut global: GlobalValue = GlobalValue {
+            fixed: GlobalFixed::new_empty(&funded_mint),
+            dynamic: vec![0; GLOBAL_BLOCK_SIZE * 2],
+        };
+        global.global_expand().unwrap();
+        global.add_trader(&trader).unwrap();
+        global
+            .deposit_global(&trader, GlobalAtoms::new(1_500))
+            .unwrap();
+        let mut rpc: MockRpc = MockRpc::new();
+        rpc.set_global(&get_global_address(&funded_mint).0, &global);
+
+        let mut manager: GlobalManager = GlobalManager::new(&trader);
+        let target: GlobalTarget = GlobalTarget {
+            target_atoms: 1_000,
+            tolerance_atoms: 100,
+        };
+        for mint in [funded_mint, new_mint] {
+            manager.add_mint(&mint, &spl_token::id(), &Pubkey::new_unique(), Some(target));
+        }
+        manager.refresh(&rpc).unwrap();
+
+        let balances: Vec<GlobalBalance> = manager.get_balances();
+        assert_eq!(balances.len(), 2);
+        let funded: GlobalBalance = manager.get_balance(&funded_mint).unwrap();
+        assert!(funded.has_seat);
+        assert_eq!(funded.balance_atoms, 1_500);
+        assert!(!manager.get_balance(&new_mint).unwrap().global_exists);
+
+        let tags: Vec<u8> = manager
+            .rebalance_instructions()
+            .iter()
+            .map(|instruction| instruction.data[0])
+            .collect();
+        let expected: Vec<ManifestInstruction> = if funded_mint < new_mint {
+            vec![
+                ManifestInstruction::GlobalWithdraw,
+                ManifestInstruction::GlobalCreate,
+                ManifestInstruction::GlobalAddTrader,
+                ManifestInstruction::GlobalDeposit,
+            ]
+        } else {
+            vec![
+                ManifestInstruction::GlobalCreate,
+                ManifestInstruction::GlobalAddTrader,
+                ManifestInstruction::GlobalDeposit,
+                ManifestInstruction::GlobalWithdraw,
+            ]
+        };
+        assert_eq!(
+            tags,
+            expected
+                .into_iter()
+                .map(|instruction| instruction as u8)
+                .collect::<Vec<u8>>()
+        );
+    }
+}
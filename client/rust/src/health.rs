@@ -0,0 +1,152 @@
+//! Detection of markets that should not be quoted. Markets are never paused on
+//! chain, but they can still end up in a state where quotes are wrong or every
+//! swap fails, and routers are better off skipping them until that clears.
+
+use hypertree::HyperTreeValueIteratorTrait;
+use manifest::{
+    quantities::{crosses, QuoteAtomsPerBaseAtom},
+    state::{BooksideReadOnly, MarketValue, RestingOrder},
+};
+
+use crate::sync::{QuoteGuard, StateStamp};
+
+/// Most severe condition first when a market has several of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarketStatus {
+    Healthy,
+    /// The local copy is more than the allowed number of slots behind.
+    Stale {
+        age_slots: u64,
+    },
+    /// A global backing orders on the market is more than the allowed number
+    /// of slots behind the market, so global orders may no longer be funded.
+    StaleGlobal {
+        lag_slots: u64,
+    },
+    /// Swaps can need to expand the market, which the payer should not be
+    /// charged for, so quotes come back empty.
+    NoFreeBlocks,
+    /// The best bid is at or above the best ask, which the program never
+    /// leaves behind, so the copy is corrupt or partially updated.
+    Crossed,
+    /// Neither side has an order that is not expired.
+    NoLiquidity,
+}
+
+impl MarketStatus {
+    pub fn is_quotable(&self) -> bool {
+        *self == MarketStatus::Healthy
+    }
+}
+
+fn best_price(book: &BooksideReadOnly, now_slot: u32) -> Option<QuoteAtomsPerBaseAtom> {
+    book.iter::<RestingOrder>()
+        .find(|(_, order)| !order.is_expired(now_slot))
+        .map(|(_, order)| order.get_price())
+}
+
+pub fn is_crossed(market: &MarketValue, now_slot: u32) -> bool {
+    match (
+        best_price(&market.get_bids(), now_slot),
+        best_price(&market.get_asks(), now_slot),
+    ) {
+        (Some(bid_price), Some(ask_price)) => crosses(bid_price, ask_price),
+        _ => false,
+    }
+}
+
+pub fn has_liquidity(market: &MarketValue, now_slot: u32) -> bool {
+    best_price(&market.get_bids(), now_slot).is_some()
+        || best_price(&market.get_asks(), now_slot).is_some()
+}
+
+/// Status of a copy of a market stamped with `stamp`. Staleness is only
+/// checked when `max_age_slots` is set.
+pub fn market_status(
+    market: &MarketValue,
+    stamp: StateStamp,
+    current_slot: u64,
+    max_age_slots: Option<u64>,
+) -> MarketStatus {
+    if let Some(max_age_slots) = max_age_slots {
+        if QuoteGuard::is_stale(stamp, current_slot, max_age_slots) {
+            return MarketStatus::Stale {
+                age_slots: QuoteGuard::age_slots(stamp, current_slot),
+            };
+        }
+    }
+    if !market.has_two_free_blocks() {
+        return MarketStatus::NoFreeBlocks;
+    }
+    let now_slot: u32 = current_slot.min(u32::MAX as u64) as u32;
+    if is_crossed(market, now_slot) {
+        return MarketStatus::Crossed;
+    }
+    if !has_liquidity(market, now_slot) {
+        return MarketStatus::NoLiquidity;
+    }
+    MarketStatus::Healthy
+}
+
+#[cfg(all(test, feature = "model"))]
+mod test {
+    use super::*;
+    use crate::model::{ModelOperation, ModelRunner};
+    use manifest::{
+        quantities::{BaseAtoms, WrapperU64},
+        state::{OrderType, MARKET_BLOCK_SIZE},
+    };
+    use solana_program::pubkey::Pubkey;
+
+    #[test]
+    fn test_market_status() {
+        let maker: Pubkey = Pubkey::new_unique();
+        let mut runner: ModelRunner = ModelRunner::new(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        );
+        runner.claim_seat(&maker).unwrap();
+        runner
+            .apply(&ModelOperation::Deposit {
+                trader: maker,
+                amount_atoms: 1_000,
+                is_base: true,
+            })
+            .unwrap();
+        let stamp: StateStamp = StateStamp {
+            slot: 100,
+            write_version: 0,
+        };
+        let mut market: MarketValue = runner.market.clone();
+        while !market.has_two_free_blocks() {
+            market.dynamic.extend_from_slice(&[0; MARKET_BLOCK_SIZE]);
+            market.market_expand().unwrap();
+        }
+        assert_eq!(
+            market_status(&market, stamp, 100, None),
+            MarketStatus::NoLiquidity
+        );
+
+        runner
+            .apply(&ModelOperation::PlaceOrder {
+                trader: maker,
+                num_base_atoms: BaseAtoms::new(100),
+                price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(1, 0).unwrap(),
+                is_bid: false,
+                order_type: OrderType::Limit,
+            })
+            .unwrap();
+        let mut market: MarketValue = runner.market.clone();
+        while !market.has_two_free_blocks() {
+            market.dynamic.extend_from_slice(&[0; MARKET_BLOCK_SIZE]);
+            market.market_expand().unwrap();
+        }
+        assert!(market_status(&market, stamp, 110, Some(10)).is_quotable());
+        assert_eq!(
+            market_status(&market, stamp, 111, Some(10)),
+            MarketStatus::Stale { age_slots: 11 }
+        );
+        assert!(!is_crossed(&market, 100));
+    }
+}
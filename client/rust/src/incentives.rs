@@ -0,0 +1,247 @@
+//! Scoring for maker incentive programs. All amounts are integers and ties
+//! are broken by trader key, so the same inputs always produce the same
+//! distribution table.
+
+use hypertree::HyperTreeValueIteratorTrait;
+use manifest::{
+    logs::FillLog,
+    quantities::{QuoteAtomsPerBaseAtom, WrapperU64},
+    state::{MarketValue, RestingOrder},
+};
+use solana_program::pubkey::Pubkey;
+use std::collections::BTreeMap;
+
+/// Market state at a slot during the epoch.
+#[derive(Clone)]
+pub struct MarketSnapshot {
+    pub slot: u64,
+    pub market: MarketValue,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IncentiveConfig {
+    /// Orders at or further than this from the mid do not count for depth.
+    /// Closer orders are weighted linearly by how close they are.
+    pub depth_band_bps: u64,
+    /// Only the ratio between the weights matters.
+    pub uptime_weight: u64,
+    pub depth_weight: u64,
+    pub volume_weight: u64,
+    pub total_rewards: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraderScore {
+    pub trader: Pubkey,
+    /// Number of (snapshot, side) pairs where the trader was at the touch.
+    pub touch_count: u64,
+    pub depth_score: u128,
+    pub maker_quote_volume: u64,
+    pub reward: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EpochDistribution {
+    /// Sorted by trader.
+    pub scores: Vec<TraderScore>,
+    /// Left over from rounding down or from components nobody scored in.
+    pub undistributed: u64,
+}
+
+/// Score every maker over the epoch and split `total_rewards` between the
+/// uptime, depth and volume pools in proportion to the weights. Fills are
+/// expected to already be filtered to the market and epoch.
+pub fn score_epoch(
+    market_snapshots: &[MarketSnapshot],
+    fills: &[FillLog],
+    config: &IncentiveConfig,
+) -> EpochDistribution {
+    let mut scores: BTreeMap<Pubkey, TraderScore> = BTreeMap::new();
+
+    for snapshot in market_snapshots {
+        let market: &MarketValue = &snapshot.market;
+        let now_slot: u32 = snapshot.slot as u32;
+        let mut best_prices: [Option<QuoteAtomsPerBaseAtom>; 2] = [None, None];
+
+        for (side, bookside) in [market.get_bids(), market.get_asks()]
+            .into_iter()
+            .enumerate()
+        {
+            for (_, order) in bookside.iter::<RestingOrder>() {
+                if order.is_expired(now_slot) {
+                    continue;
+                }
+                let best_price: QuoteAtomsPerBaseAtom =
+                    *best_prices[side].get_or_insert(order.get_price());
+                if order.get_price() != best_price {
+                    break;
+                }
+                let trader: Pubkey = *market.get_trader_key_by_index(order.get_trader_index());
+                scores.entry(trader).or_default().touch_count += 1;
+            }
+        }
+
+        let [Some(best_bid), Some(best_ask)] = best_prices else {
+            continue;
+        };
+        let mid: f64 = (best_bid.as_f64() + best_ask.as_f64()) / 2.0;
+        for bookside in [market.get_bids(), market.get_asks()] {
+            for (_, order) in bookside.iter::<RestingOrder>() {
+                if order.is_expired(now_slot) {
+                    continue;
+                }
+                let distance_bps: u64 =
+                    ((order.get_price().as_f64() - mid).abs() / mid * 10_000.0) as u64;
+                // Orders are walked best to worst, so the rest are outside too.
+                if distance_bps >= config.depth_band_bps {
+                    break;
+                }
+                let quote_atoms: u64 = order
+                    .get_price()
+                    .checked_quote_for_base(order.get_num_base_atoms(), false)
+                    .map(|quote_atoms| quote_atoms.as_u64())
+                    .unwrap_or(u64::MAX);
+                let trader: Pubkey = *market.get_trader_key_by_index(order.get_trader_index());
+                scores.entry(trader).or_default().depth_score +=
+                    quote_atoms as u128 * (config.depth_band_bps - distance_bps) as u128;
+            }
+        }
+    }
+
+    for fill in fills {
+        let score: &mut TraderScore = scores.entry(fill.maker).or_default();
+        score.maker_quote_volume = score
+            .maker_quote_volume
+            .saturating_add(fill.quote_atoms.as_u64());
+    }
+
+    let total_weight: u128 =
+        (config.uptime_weight + config.depth_weight + config.volume_weight) as u128;
+    let total_touch: u128 = scores.values().map(|s| s.touch_count as u128).sum();
+    let total_depth: u128 = scores.values().map(|s| s.depth_score).sum();
+    let total_volume: u128 = scores.values().map(|s| s.maker_quote_volume as u128).sum();
+
+    let mut distributed: u64 = 0;
+    for (trader, score) in scores.iter_mut() {
+        score.trader = *trader;
+        if total_weight == 0 {
+            continue;
+        }
+        let mut reward: u128 = 0;
+        for (weight, amount, total) in [
+            (config.uptime_weight, score.touch_count as u128, total_touch),
+            (config.depth_weight, score.depth_score, total_depth),
+            (
+                config.volume_weight,
+                score.maker_quote_volume as u128,
+                total_volume,
+            ),
+        ] {
+            if total == 0 {
+                continue;
+            }
+            let pool: u128 = config.total_rewards as u128 * weight as u128 / total_weight;
+            reward += pool * amount / total;
+        }
+        score.reward = reward as u64;
+        distributed += score.reward;
+    }
+
+    EpochDistribution {
+        scores: scores.into_values().collect(),
+        undistributed: config.total_rewards - distributed,
+    }
+}
+
+#[cfg(all(test, feature = "model"))]
+mod test {
+    use super::*;
+    use crate::model::{ModelOperation, ModelRunner};
+    use manifest::{
+        quantities::{BaseAtoms, QuoteAtoms},
+        state::OrderType,
+    };
+
+    #[test]
+    fn test_score_epoch() {
+        let maker_a: Pubkey = Pubkey::new_unique();
+        let maker_b: Pubkey = Pubkey::new_unique();
+        let mut runner: ModelRunner = ModelRunner::new(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        );
+        for (trader, amount_atoms, is_base) in [
+            (maker_a, 100, true),
+            (maker_b, 100, false),
+            (maker_b, 100, true),
+        ] {
+            runner.claim_seat(&trader).unwrap();
+            runner
+                .apply(&ModelOperation::Deposit {
+                    trader,
+                    amount_atoms,
+                    is_base,
+                })
+                .unwrap();
+        }
+        // A asks at 2, B bids at 1 and asks at 3, which is outside the band.
+        for (trader, mantissa, is_bid) in
+            [(maker_a, 2, false), (maker_b, 1, true), (maker_b, 3, false)]
+        {
+            runner
+                .apply(&ModelOperation::PlaceOrder {
+                    trader,
+                    num_base_atoms: BaseAtoms::new(100),
+                    price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(mantissa, 0)
+                        .unwrap(),
+                    is_bid,
+                    order_type: OrderType::Limit,
+                })
+                .unwrap();
+        }
+
+        let fill: FillLog = FillLog {
+            market: Pubkey::new_unique(),
+            maker: maker_a,
+            taker: Pubkey::new_unique(),
+            base_mint: Pubkey::new_unique(),
+            quote_mint: Pubkey::new_unique(),
+            price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(2, 0).unwrap(),
+            base_atoms: BaseAtoms::new(25),
+            quote_atoms: QuoteAtoms::new(50),
+            maker_sequence_number: 0,
+            taker_sequence_number: 3,
+            taker_is_buy: true.into(),
+            is_maker_global: false.into(),
+            _padding: [0; 14],
+        };
+
+        let distribution: EpochDistribution = score_epoch(
+            &[MarketSnapshot {
+                slot: 0,
+                market: runner.market.clone(),
+            }],
+            &[fill],
+            &IncentiveConfig {
+                depth_band_bps: 10_000,
+                uptime_weight: 1,
+                depth_weight: 1,
+                volume_weight: 1,
+                total_rewards: 300,
+            },
+        );
+        let reward_of = |trader: &Pubkey| {
+            distribution
+                .scores
+                .iter()
+                .find(|score| score.trader == *trader)
+                .unwrap()
+                .reward
+        };
+        // Uptime splits 50 / 50, depth 2 : 1 with rounding, volume all to A.
+        assert_eq!(reward_of(&maker_a), 50 + 66 + 100);
+        assert_eq!(reward_of(&maker_b), 50 + 33);
+        assert_eq!(distribution.undistributed, 1);
+    }
+}
@@ -0,0 +1,946 @@
+//! Typed builders for Manifest instructions. They take the quantities the
+//! rest of the client works with and derive every account from the market
+//! and its mints with the address helpers of the program, so callers never
+//! assemble account lists by hand.
+
+use anyhow::{bail, Error, Result};
+use hypertree::{DataIndex, HyperTreeValueIteratorTrait, NIL};
+use manifest::{
+    program::{
+        batch_update::{CancelOrderParams, PlaceOrderParams},
+        batch_update_instruction, create_market_instruction, deposit_instruction,
+        expand_market_instruction, swap_instruction, withdraw_instruction,
+    },
+    quantities::{BaseAtoms, QuoteAtoms, QuoteAtomsPerBaseAtom, WrapperU64},
+    state::{
+        BooksideReadOnly, MarketValue, OrderType, RestingOrder, MARKET_BLOCK_SIZE,
+        MARKET_FIXED_SIZE,
+    },
+};
+use solana_sdk::{
+    instruction::Instruction, message::Message, packet::PACKET_DATA_SIZE, pubkey::Pubkey,
+    rent::Rent, system_instruction,
+};
+
+use std::collections::HashSet;
+
+use crate::{instructions::ManifestInstructionTag, jupiter::ASSOCIATED_TOKEN_PROGRAM_ID};
+
+#[derive(Debug, Clone, Copy)]
+pub struct PlaceOrderArgs {
+    pub market: Pubkey,
+    pub trader: Pubkey,
+    pub base_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    /// Token programs of the mints, None for the spl token program. Only
+    /// global orders use them.
+    pub base_token_program_opt: Option<Pubkey>,
+    pub quote_token_program_opt: Option<Pubkey>,
+    /// Seat of the trader if known, which saves the program a lookup.
+    pub trader_index_hint: Option<DataIndex>,
+    pub num_base_atoms: BaseAtoms,
+    pub price: QuoteAtomsPerBaseAtom,
+    pub is_bid: bool,
+    pub order_type: OrderType,
+    /// Last slot the order can be filled in, `NO_EXPIRATION_LAST_VALID_SLOT`
+    /// for none. Reverse orders take their spread here instead.
+    pub last_valid_slot: u32,
+}
+
+/// BatchUpdate that places a single order. A global order gets the global
+/// accounts of the mint it is backed by, the quote mint for bids and the
+/// base mint for asks. Globals on the other side of the book are not
+/// included, so a limit order stops matching at the first global order.
+pub fn place_order(args: &PlaceOrderArgs) -> Result<Instruction> {
+    let (price_mantissa, price_exponent): (u32, i8) = args
+        .price
+        .try_to_mantissa_and_exponent()
+        .map_err(|_| Error::msg(format!("Price {} is not a valid order price", args.price)))?;
+    let is_global: bool = args.order_type == OrderType::Global;
+    let (base_global_mint_opt, quote_global_mint_opt): (Option<Pubkey>, Option<Pubkey>) =
+        match (is_global, args.is_bid) {
+            (false, _) => (None, None),
+            (true, true) => (None, Some(args.quote_mint)),
+            (true, false) => (Some(args.base_mint), None),
+        };
+    Ok(batch_update_instruction(
+        &args.market,
+        &args.trader,
+        args.trader_index_hint,
+        Vec::new(),
+        vec![PlaceOrderParams::new(
+            args.num_base_atoms.as_u64(),
+            price_mantissa,
+            price_exponent,
+            args.is_bid,
+            args.order_type,
+            args.last_valid_slot,
+        )],
+        base_global_mint_opt,
+        base_global_mint_opt.and(args.base_token_program_opt),
+        quote_global_mint_opt,
+        quote_global_mint_opt.and(args.quote_token_program_opt),
+    ))
+}
+
+/// Cancel of the resting order of `trader` with `order_sequence_number`.
+/// The program searches the book for it, see `cancel_order_by_index` for the
+/// cheaper cancel when the index is known. Global orders get the global
+/// accounts of the regular token program.
+pub fn cancel_order(
+    market_key: &Pubkey,
+    market: &MarketValue,
+    trader: &Pubkey,
+    order_sequence_number: u64,
+) -> Result<Instruction> {
+    let Some((order_index, order)) = find_resting_order(market, |_, order| {
+        order.get_sequence_number() == order_sequence_number
+    }) else {
+        bail!("Order {order_sequence_number} is not on the book");
+    };
+    cancel_instruction(market_key, market, trader, order_index, &order, false)
+}
+
+/// Cancel of the resting order at `order_index`, as found in a
+/// `book::OrderView`. The index is passed as a hint, so the program does not
+/// search the book. Global orders get the global accounts of the regular
+/// token program.
+pub fn cancel_order_by_index(
+    market_key: &Pubkey,
+    market: &MarketValue,
+    trader: &Pubkey,
+    order_index: DataIndex,
+) -> Result<Instruction> {
+    let Some((order_index, order)) = find_resting_order(market, |index, _| index == order_index)
+    else {
+        bail!("No order rests at index {order_index}");
+    };
+    cancel_instruction(market_key, market, trader, order_index, &order, true)
+}
+
+fn find_resting_order(
+    market: &MarketValue,
+    predicate: impl Fn(DataIndex, &RestingOrder) -> bool,
+) -> Option<(DataIndex, RestingOrder)> {
+    market
+        .get_bids()
+        .iter::<RestingOrder>()
+        .chain(market.get_asks().iter::<RestingOrder>())
+        .find(|(order_index, order)| predicate(*order_index, order))
+        .map(|(order_index, order)| (order_index, *order))
+}
+
+fn cancel_instruction(
+    market_key: &Pubkey,
+    market: &MarketValue,
+    trader: &Pubkey,
+    order_index: DataIndex,
+    order: &RestingOrder,
+    use_index_hint: bool,
+) -> Result<Instruction> {
+    let trader_index: DataIndex = market.get_trader_index(trader);
+    if trader_index == NIL {
+        bail!("Trader {trader} has no seat on {market_key}");
+    }
+    if order.get_trader_index() != trader_index {
+        bail!(
+            "Order {} does not belong to {trader}",
+            order.get_sequence_number()
+        );
+    }
+    // Cancelling a global order returns its gas prepayment through the
+    // global of the mint backing it.
+    let (base_global_mint_opt, quote_global_mint_opt): (Option<Pubkey>, Option<Pubkey>) =
+        match (order.is_global(), order.get_is_bid()) {
+            (false, _) => (None, None),
+            (true, true) => (None, Some(*market.get_quote_mint())),
+            (true, false) => (Some(*market.get_base_mint()), None),
+        };
+    Ok(batch_update_instruction(
+        market_key,
+        trader,
+        Some(trader_index),
+        vec![CancelOrderParams::new_with_hint(
+            order.get_sequence_number(),
+            use_index_hint.then_some(order_index),
+        )],
+        Vec::new(),
+        base_global_mint_opt,
+        None,
+        quote_global_mint_opt,
+        None,
+    ))
+}
+
+/// Order of a `BatchUpdateArgs`, see `PlaceOrderArgs` for the fields.
+#[derive(Debug, Clone, Copy)]
+pub struct BatchOrder {
+    pub num_base_atoms: BaseAtoms,
+    pub price: QuoteAtomsPerBaseAtom,
+    pub is_bid: bool,
+    pub order_type: OrderType,
+    pub last_valid_slot: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchUpdateArgs {
+    pub market: Pubkey,
+    pub trader: Pubkey,
+    pub base_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    /// Token programs of the mints, None for the spl token program. Only
+    /// global orders use them.
+    pub base_token_program_opt: Option<Pubkey>,
+    pub quote_token_program_opt: Option<Pubkey>,
+    pub trader_index_hint: Option<DataIndex>,
+    /// Run before any of the orders are placed.
+    pub cancels: Vec<CancelOrderParams>,
+    pub orders: Vec<BatchOrder>,
+}
+
+/// Serialized size of a transaction with `instructions` that only the
+/// payer signs, to compare against `PACKET_DATA_SIZE`.
+pub fn transaction_size(instructions: &[Instruction], payer: &Pubkey) -> usize {
+    let message: Message = Message::new(instructions, Some(payer));
+    let num_signatures: usize = message.header.num_required_signatures as usize;
+    // The signature count is a compact u16, one byte below 128.
+    1 + num_signatures * 64 + message.serialize().len()
+}
+
+/// BatchUpdate that cancels and places in one instruction, so quotes are
+/// replaced atomically. Global orders get the global accounts of the mint
+/// they are backed by, which also covers cancelling global orders on the
+/// same side. Fails if the instruction does not fit in a transaction on its
+/// own, leaving no room for compute budget instructions, which a caller
+/// adding them checks with `transaction_size`.
+pub fn batch_update(args: &BatchUpdateArgs) -> Result<Instruction> {
+    let mut orders: Vec<PlaceOrderParams> = Vec::with_capacity(args.orders.len());
+    let (mut base_global_needed, mut quote_global_needed): (bool, bool) = (false, false);
+    for order in args.orders.iter() {
+        let (price_mantissa, price_exponent): (u32, i8) = order
+            .price
+            .try_to_mantissa_and_exponent()
+            .map_err(|_| Error::msg(format!("Price {} is not a valid order price", order.price)))?;
+        if order.order_type == OrderType::Global {
+            if order.is_bid {
+                quote_global_needed = true;
+            } else {
+                base_global_needed = true;
+            }
+        }
+        orders.push(PlaceOrderParams::new(
+            order.num_base_atoms.as_u64(),
+            price_mantissa,
+            price_exponent,
+            order.is_bid,
+            order.order_type,
+            order.last_valid_slot,
+        ));
+    }
+    let instruction: Instruction = batch_update_instruction(
+        &args.market,
+        &args.trader,
+        args.trader_index_hint,
+        args.cancels.clone(),
+        orders,
+        base_global_needed.then_some(args.base_mint),
+        base_global_needed
+            .then_some(args.base_token_program_opt)
+            .flatten(),
+        quote_global_needed.then_some(args.quote_mint),
+        quote_global_needed
+            .then_some(args.quote_token_program_opt)
+            .flatten(),
+    );
+    let size: usize = transaction_size(std::slice::from_ref(&instruction), &args.trader);
+    if size > PACKET_DATA_SIZE {
+        bail!(
+            "{} cancels and {} orders need a {size} byte transaction, over {PACKET_DATA_SIZE}",
+            args.cancels.len(),
+            args.orders.len()
+        );
+    }
+    Ok(instruction)
+}
+
+pub fn get_associated_token_address(
+    owner: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Pubkey {
+    Pubkey::find_program_address(
+        &[owner.as_ref(), token_program.as_ref(), mint.as_ref()],
+        &ASSOCIATED_TOKEN_PROGRAM_ID,
+    )
+    .0
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SwapArgs {
+    /// Signs and owns the token accounts, which are its associated token
+    /// accounts.
+    pub trader: Pubkey,
+    pub in_atoms: u64,
+    /// Min out for exact in swaps and max in for exact out swaps.
+    pub out_atoms: u64,
+    pub is_base_in: bool,
+    pub is_exact_in: bool,
+    /// Token programs of the mints, None for the spl token program.
+    pub base_token_program_opt: Option<Pubkey>,
+    pub quote_token_program_opt: Option<Pubkey>,
+}
+
+/// Swap on `market` with every account resolved from the market and the
+/// trader. The global of the output mint is only included when the side of
+/// the book the swap takes from has unexpired global orders at `now_slot`,
+/// which keeps swaps that cannot touch a global off its write lock.
+pub fn swap(
+    market_key: &Pubkey,
+    market: &MarketValue,
+    args: &SwapArgs,
+    now_slot: u32,
+) -> Instruction {
+    let base_mint: &Pubkey = market.get_base_mint();
+    let quote_mint: &Pubkey = market.get_quote_mint();
+    let base_token_program: Pubkey = args.base_token_program_opt.unwrap_or(spl_token::id());
+    let quote_token_program: Pubkey = args.quote_token_program_opt.unwrap_or(spl_token::id());
+    // Selling base fills bids and buying base fills asks.
+    let book: BooksideReadOnly = if args.is_base_in {
+        market.get_bids()
+    } else {
+        market.get_asks()
+    };
+    let include_global: bool = book
+        .iter::<RestingOrder>()
+        .any(|(_, order)| order.is_global() && !order.is_expired(now_slot));
+    swap_instruction(
+        market_key,
+        &args.trader,
+        base_mint,
+        quote_mint,
+        &get_associated_token_address(&args.trader, base_mint, &base_token_program),
+        &get_associated_token_address(&args.trader, quote_mint, &quote_token_program),
+        args.in_atoms,
+        args.out_atoms,
+        args.is_base_in,
+        args.is_exact_in,
+        base_token_program,
+        quote_token_program,
+        include_global,
+    )
+}
+
+/// Amount of a token, either in atoms or in the units shown to users.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenAmount {
+    Atoms(u64),
+    /// `amount` whole tokens of a mint with `decimals`.
+    Ui {
+        amount: f64,
+        decimals: u8,
+    },
+}
+
+impl TokenAmount {
+    /// UI amounts are rounded to the nearest atom, which absorbs the error of
+    /// amounts like 0.1 that have no exact float.
+    pub fn to_atoms(&self) -> Result<u64> {
+        match *self {
+            TokenAmount::Atoms(atoms) => Ok(atoms),
+            TokenAmount::Ui { amount, decimals } => {
+                let atoms: f64 = (amount * 10_f64.powi(decimals as i32)).round();
+                if !atoms.is_finite() || atoms < 0.0 || atoms > u64::MAX as f64 {
+                    bail!("{amount} is not a valid amount of a mint with {decimals} decimals");
+                }
+                Ok(atoms as u64)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SeatTransferArgs {
+    /// Signs and owns the seat.
+    pub trader: Pubkey,
+    pub is_base: bool,
+    /// UI amounts have to use the decimals of the mint.
+    pub amount: TokenAmount,
+    /// Token account the tokens come from or go to, None for the associated
+    /// token account of the trader.
+    pub trader_token_account_opt: Option<Pubkey>,
+    /// Token program of the mint, None for the spl token program.
+    pub token_program_opt: Option<Pubkey>,
+}
+
+/// Deposit into the seat of `args.trader`, which has to be claimed already.
+pub fn deposit(
+    market_key: &Pubkey,
+    market: &MarketValue,
+    args: &SeatTransferArgs,
+) -> Result<Instruction> {
+    let (mint, amount_atoms, trader_token_account, token_program, trader_index): (
+        Pubkey,
+        u64,
+        Pubkey,
+        Pubkey,
+        DataIndex,
+    ) = resolve_seat_transfer(market_key, market, args)?;
+    Ok(deposit_instruction(
+        market_key,
+        &args.trader,
+        &mint,
+        amount_atoms,
+        &trader_token_account,
+        token_program,
+        Some(trader_index),
+    ))
+}
+
+/// Withdraw from the seat of `args.trader`. Fails if the seat does not have
+/// the amount free in `market`, so a stale market can reject a withdraw the
+/// program would accept.
+pub fn withdraw(
+    market_key: &Pubkey,
+    market: &MarketValue,
+    args: &SeatTransferArgs,
+) -> Result<Instruction> {
+    let (mint, amount_atoms, trader_token_account, token_program, trader_index): (
+        Pubkey,
+        u64,
+        Pubkey,
+        Pubkey,
+        DataIndex,
+    ) = resolve_seat_transfer(market_key, market, args)?;
+    let (base_atoms, quote_atoms): (BaseAtoms, QuoteAtoms) =
+        market.get_trader_balance(&args.trader);
+    let free_atoms: u64 = if args.is_base {
+        base_atoms.as_u64()
+    } else {
+        quote_atoms.as_u64()
+    };
+    if amount_atoms > free_atoms {
+        bail!(
+            "Trader {} has {free_atoms} atoms of {mint} free, cannot withdraw {amount_atoms}",
+            args.trader
+        );
+    }
+    Ok(withdraw_instruction(
+        market_key,
+        &args.trader,
+        &mint,
+        amount_atoms,
+        &trader_token_account,
+        token_program,
+        Some(trader_index),
+    ))
+}
+
+fn resolve_seat_transfer(
+    market_key: &Pubkey,
+    market: &MarketValue,
+    args: &SeatTransferArgs,
+) -> Result<(Pubkey, u64, Pubkey, Pubkey, DataIndex)> {
+    let trader_index: DataIndex = market.get_trader_index(&args.trader);
+    if trader_index == NIL {
+        bail!("Trader {} has no seat on {market_key}", args.trader);
+    }
+    let (mint, mint_decimals): (Pubkey, u8) = if args.is_base {
+        (
+            *market.get_base_mint(),
+            market.fixed.get_base_mint_decimals(),
+        )
+    } else {
+        (
+            *market.get_quote_mint(),
+            market.fixed.get_quote_mint_decimals(),
+        )
+    };
+    if let TokenAmount::Ui { decimals, .. } = args.amount {
+        if decimals != mint_decimals {
+            bail!("Mint {mint} has {mint_decimals} decimals, not {decimals}");
+        }
+    }
+    let token_program: Pubkey = args.token_program_opt.unwrap_or(spl_token::id());
+    let trader_token_account: Pubkey = args
+        .trader_token_account_opt
+        .unwrap_or_else(|| get_associated_token_address(&args.trader, &mint, &token_program));
+    Ok((
+        mint,
+        args.amount.to_atoms()?,
+        trader_token_account,
+        token_program,
+        trader_index,
+    ))
+}
+
+/// Token accounts that withdraws may pay out to. Bots that hold funds in
+/// seats build their withdraws through `withdraw` here, so a compromised
+/// config or a bug cannot send funds anywhere the operator did not approve.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WithdrawPolicy {
+    allowed_token_accounts: HashSet<Pubkey>,
+}
+
+impl WithdrawPolicy {
+    pub fn new(allowed_token_accounts: impl IntoIterator<Item = Pubkey>) -> Self {
+        WithdrawPolicy {
+            allowed_token_accounts: allowed_token_accounts.into_iter().collect(),
+        }
+    }
+
+    pub fn allow(&mut self, token_account: &Pubkey) {
+        self.allowed_token_accounts.insert(*token_account);
+    }
+
+    pub fn is_allowed(&self, token_account: &Pubkey) -> bool {
+        self.allowed_token_accounts.contains(token_account)
+    }
+
+    /// Same as `withdraw`, failing when the destination is not allowed.
+    pub fn withdraw(
+        &self,
+        market_key: &Pubkey,
+        market: &MarketValue,
+        args: &SeatTransferArgs,
+    ) -> Result<Instruction> {
+        let instruction: Instruction = withdraw(market_key, market, args)?;
+        self.check(&instruction)?;
+        Ok(instruction)
+    }
+
+    /// Fails for a Withdraw or GlobalWithdraw that pays out to a token
+    /// account that is not allowed. Other instructions pass, so whole
+    /// transactions built elsewhere can be checked before signing.
+    pub fn check(&self, instruction: &Instruction) -> Result<()> {
+        if instruction.program_id != manifest::id() {
+            return Ok(());
+        }
+        let destination_index: usize =
+            match ManifestInstructionTag::from_instruction_data(&instruction.data) {
+                Some(ManifestInstructionTag::Withdraw) => 2,
+                Some(ManifestInstructionTag::GlobalWithdraw) => 4,
+                _ => return Ok(()),
+            };
+        let Some(destination) = instruction.accounts.get(destination_index) else {
+            bail!("Withdraw is missing its token account");
+        };
+        if !self.is_allowed(&destination.pubkey) {
+            bail!(
+                "Withdraw to {} is not allowed by the policy",
+                destination.pubkey
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Size of a market account with the fixed header and `num_blocks` blocks.
+pub fn market_account_size(num_blocks: u32) -> usize {
+    MARKET_FIXED_SIZE + num_blocks as usize * MARKET_BLOCK_SIZE
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CreateMarketArgs {
+    /// New account for the market, which signs its creation.
+    pub market: Pubkey,
+    pub base_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    /// Signs and pays for the market and its vaults.
+    pub creator: Pubkey,
+    /// Free blocks to leave on the market. CreateMarket always leaves one,
+    /// each block holds an order or a seat.
+    pub num_free_blocks: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct CreateMarketBundle {
+    /// Account creation, CreateMarket and, for more than one free block, an
+    /// Expand. They have to land in order and are small enough for a single
+    /// transaction.
+    pub instructions: Vec<Instruction>,
+    pub account_size: usize,
+    /// Rent exemption of the market account at `account_size`, which the
+    /// creator pays across the instructions. The vaults cost rent on top.
+    pub rent_exempt_lamports: u64,
+}
+
+/// Instructions that create a market with `args.num_free_blocks` free
+/// blocks. The account is created at the size of the fixed header, which
+/// CreateMarket requires, and the program grows it by a block at a time.
+pub fn create_market(args: &CreateMarketArgs, rent: &Rent) -> CreateMarketBundle {
+    let num_free_blocks: u32 = args.num_free_blocks.max(1);
+    let account_size: usize = market_account_size(num_free_blocks);
+    let mut instructions: Vec<Instruction> = vec![
+        system_instruction::create_account(
+            &args.creator,
+            &args.market,
+            rent.minimum_balance(MARKET_FIXED_SIZE),
+            MARKET_FIXED_SIZE as u64,
+            &manifest::id(),
+        ),
+        create_market_instruction(
+            &args.market,
+            &args.base_mint,
+            &args.quote_mint,
+            &args.creator,
+        ),
+    ];
+    if num_free_blocks > 1 {
+        let mut expand: Instruction = expand_market_instruction(&args.market, &args.creator);
+        expand
+            .data
+            .extend_from_slice(&num_free_blocks.to_le_bytes());
+        instructions.push(expand);
+    }
+    CreateMarketBundle {
+        instructions,
+        account_size,
+        rent_exempt_lamports: rent.minimum_balance(account_size),
+    }
+}
+
+#[cfg(all(test, feature = "model"))]
+mod test {
+    use super::*;
+    use crate::{
+        book::{BooksideSnapshot, OrderView},
+        model::{ModelOperation, ModelRunner},
+    };
+    use manifest::{
+        state::NO_EXPIRATION_LAST_VALID_SLOT,
+        validation::{get_global_address, get_global_vault_address, get_vault_address},
+    };
+
+    #[test]
+    fn test_place_order() {
+        let mut args: PlaceOrderArgs = PlaceOrderArgs {
+            market: Pubkey::new_unique(),
+            trader: Pubkey::new_unique(),
+            base_mint: Pubkey::new_unique(),
+            quote_mint: Pubkey::new_unique(),
+            base_token_program_opt: None,
+            quote_token_program_opt: Some(spl_token_2022::id()),
+            trader_index_hint: Some(7),
+            num_base_atoms: BaseAtoms::new(1_000),
+            price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(95, -2).unwrap(),
+            is_bid: true,
+            order_type: OrderType::Limit,
+            last_valid_slot: NO_EXPIRATION_LAST_VALID_SLOT,
+        };
+        let instruction: Instruction = place_order(&args).unwrap();
+        assert_eq!(instruction.program_id, manifest::id());
+        assert_eq!(
+            ManifestInstructionTag::from_instruction_data(&instruction.data),
+            Some(ManifestInstructionTag::BatchUpdate)
+        );
+        assert_eq!(instruction.accounts.len(), 3);
+        assert_eq!(instruction.accounts[0].pubkey, args.trader);
+        assert!(instruction.accounts[0].is_signer);
+        assert_eq!(instruction.accounts[1].pubkey, args.market);
+
+        // A global bid is backed by the quote global.
+        args.order_type = OrderType::Global;
+        let instruction: Instruction = place_order(&args).unwrap();
+        let keys: Vec<Pubkey> = instruction
+            .accounts
+            .iter()
+            .map(|account_meta| account_meta.pubkey)
+            .collect();
+        assert_eq!(
+            keys[3..],
+            [
+                args.quote_mint,
+                get_global_address(&args.quote_mint).0,
+                get_global_vault_address(&args.quote_mint).0,
+                get_vault_address(&args.market, &args.quote_mint).0,
+                spl_token_2022::id(),
+            ]
+        );
+
+        // Prices from division have no exact mantissa and exponent.
+        args.price = QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(1, 0)
+            .unwrap()
+            .checked_multiply_rational(1, 3, false)
+            .unwrap();
+        assert!(place_order(&args).is_err());
+    }
+
+    #[test]
+    fn test_cancel_order() {
+        let market_key: Pubkey = Pubkey::new_unique();
+        let maker: Pubkey = Pubkey::new_unique();
+        let mut runner: ModelRunner =
+            ModelRunner::new(&market_key, &Pubkey::new_unique(), &Pubkey::new_unique());
+        runner.claim_seat(&maker).unwrap();
+        runner
+            .apply(&ModelOperation::Deposit {
+                trader: maker,
+                amount_atoms: 1_000,
+                is_base: true,
+            })
+            .unwrap();
+        runner
+            .apply(&ModelOperation::PlaceOrder {
+                trader: maker,
+                num_base_atoms: BaseAtoms::new(1_000),
+                price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(1, 0).unwrap(),
+                is_bid: false,
+                order_type: OrderType::Limit,
+            })
+            .unwrap();
+        let ask: OrderView = BooksideSnapshot::capture_asks(&runner.market, 0).orders[0];
+        let sequence_number: u64 = ask.order.get_sequence_number();
+
+        let by_sequence_number: Instruction =
+            cancel_order(&market_key, &runner.market, &maker, sequence_number).unwrap();
+        let by_index: Instruction =
+            cancel_order_by_index(&market_key, &runner.market, &maker, ask.order_index).unwrap();
+        assert_eq!(by_sequence_number.accounts, by_index.accounts);
+        assert_eq!(by_index.accounts.len(), 3);
+        // Only the hint differs.
+        assert!(by_index.data.len() > by_sequence_number.data.len());
+
+        let other: Pubkey = Pubkey::new_unique();
+        runner.claim_seat(&other).unwrap();
+        assert!(cancel_order(&market_key, &runner.market, &other, sequence_number).is_err());
+        assert!(cancel_order(&market_key, &runner.market, &maker, sequence_number + 1).is_err());
+        assert!(cancel_order_by_index(&market_key, &runner.market, &maker, NIL).is_err());
+    }
+
+    #[test]
+    fn test_swap() {
+        let market_key: Pubkey = Pubkey::new_unique();
+        let base_mint: Pubkey = Pubkey::new_unique();
+        let quote_mint: Pubkey = Pubkey::new_unique();
+        let runner: ModelRunner = ModelRunner::new(&market_key, &base_mint, &quote_mint);
+        let args: SwapArgs = SwapArgs {
+            trader: Pubkey::new_unique(),
+            in_atoms: 1_000,
+            out_atoms: 900,
+            is_base_in: false,
+            is_exact_in: true,
+            base_token_program_opt: None,
+            quote_token_program_opt: Some(spl_token_2022::id()),
+        };
+        let instruction: Instruction = swap(&market_key, &runner.market, &args, 0);
+        assert_eq!(
+            ManifestInstructionTag::from_instruction_data(&instruction.data),
+            Some(ManifestInstructionTag::Swap)
+        );
+        let keys: Vec<Pubkey> = instruction
+            .accounts
+            .iter()
+            .map(|account_meta| account_meta.pubkey)
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                args.trader,
+                market_key,
+                solana_sdk::system_program::id(),
+                get_associated_token_address(&args.trader, &base_mint, &spl_token::id()),
+                get_associated_token_address(&args.trader, &quote_mint, &spl_token_2022::id()),
+                get_vault_address(&market_key, &base_mint).0,
+                get_vault_address(&market_key, &quote_mint).0,
+                spl_token::id(),
+                spl_token_2022::id(),
+                quote_mint,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deposit_withdraw() {
+        let market_key: Pubkey = Pubkey::new_unique();
+        let base_mint: Pubkey = Pubkey::new_unique();
+        let quote_mint: Pubkey = Pubkey::new_unique();
+        let trader: Pubkey = Pubkey::new_unique();
+        let mut runner: ModelRunner = ModelRunner::new(&market_key, &base_mint, &quote_mint);
+        let mut args: SeatTransferArgs = SeatTransferArgs {
+            trader,
+            is_base: false,
+            amount: TokenAmount::Ui {
+                amount: 0.1,
+                decimals: 6,
+            },
+            trader_token_account_opt: None,
+            token_program_opt: None,
+        };
+        assert!(deposit(&market_key, &runner.market, &args).is_err());
+        runner.claim_seat(&trader).unwrap();
+
+        let instruction: Instruction = deposit(&market_key, &runner.market, &args).unwrap();
+        assert_eq!(
+            ManifestInstructionTag::from_instruction_data(&instruction.data),
+            Some(ManifestInstructionTag::Deposit)
+        );
+        let keys: Vec<Pubkey> = instruction
+            .accounts
+            .iter()
+            .map(|account_meta| account_meta.pubkey)
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                trader,
+                market_key,
+                get_associated_token_address(&trader, &quote_mint, &spl_token::id()),
+                get_vault_address(&market_key, &quote_mint).0,
+                spl_token::id(),
+                quote_mint,
+            ]
+        );
+        assert_eq!(args.amount.to_atoms().unwrap(), 100_000);
+
+        // The base mint of the model has 9 decimals.
+        args.is_base = true;
+        assert!(deposit(&market_key, &runner.market, &args).is_err());
+
+        args.amount = TokenAmount::Atoms(1_000);
+        assert!(withdraw(&market_key, &runner.market, &args).is_err());
+        runner
+            .apply(&ModelOperation::Deposit {
+                trader,
+                amount_atoms: 1_000,
+                is_base: true,
+            })
+            .unwrap();
+        let instruction: Instruction = withdraw(&market_key, &runner.market, &args).unwrap();
+        assert_eq!(
+            ManifestInstructionTag::from_instruction_data(&instruction.data),
+            Some(ManifestInstructionTag::Withdraw)
+        );
+        assert_eq!(
+            instruction.accounts[3].pubkey,
+            get_vault_address(&market_key, &base_mint).0
+        );
+        assert_eq!(instruction.accounts[5].pubkey, base_mint);
+
+        assert!(TokenAmount::Ui {
+            amount: -1.0,
+            decimals: 6
+        }
+        .to_atoms()
+        .is_err());
+    }
+
+    #[test]
+    fn test_withdraw_policy() {
+        let market_key: Pubkey = Pubkey::new_unique();
+        let base_mint: Pubkey = Pubkey::new_unique();
+        let trader: Pubkey = Pubkey::new_unique();
+        let mut runner: ModelRunner =
+            ModelRunner::new(&market_key, &base_mint, &Pubkey::new_unique());
+        runner.claim_seat(&trader).unwrap();
+        runner
+            .apply(&ModelOperation::Deposit {
+                trader,
+                amount_atoms: 1_000,
+                is_base: true,
+            })
+            .unwrap();
+        let mut args: SeatTransferArgs = SeatTransferArgs {
+            trader,
+            is_base: true,
+            amount: TokenAmount::Atoms(1_000),
+            trader_token_account_opt: None,
+            token_program_opt: None,
+        };
+        let trader_ata: Pubkey =
+            get_associated_token_address(&trader, &base_mint, &spl_token::id());
+        let mut policy: WithdrawPolicy = WithdrawPolicy::default();
+        assert!(policy.withdraw(&market_key, &runner.market, &args).is_err());
+
+        policy.allow(&trader_ata);
+        let instruction: Instruction = policy.withdraw(&market_key, &runner.market, &args).unwrap();
+        assert_eq!(instruction.accounts[2].pubkey, trader_ata);
+
+        args.trader_token_account_opt = Some(Pubkey::new_unique());
+        assert!(policy.withdraw(&market_key, &runner.market, &args).is_err());
+        let instruction: Instruction = withdraw(&market_key, &runner.market, &args).unwrap();
+        assert!(policy.check(&instruction).is_err());
+        // Deposits go the other way and are never restricted.
+        assert!(policy
+            .check(&deposit(&market_key, &runner.market, &args).unwrap())
+            .is_ok());
+    }
+
+    #[test]
+    fn test_create_market() {
+        let mut args: CreateMarketArgs = CreateMarketArgs {
+            market: Pubkey::new_unique(),
+            base_mint: Pubkey::new_unique(),
+            quote_mint: Pubkey::new_unique(),
+            creator: Pubkey::new_unique(),
+            num_free_blocks: 0,
+        };
+        let rent: Rent = Rent::default();
+        let bundle: CreateMarketBundle = create_market(&args, &rent);
+        assert_eq!(bundle.instructions.len(), 2);
+        assert_eq!(
+            bundle.instructions[0].program_id,
+            solana_sdk::system_program::id()
+        );
+        assert_eq!(
+            ManifestInstructionTag::from_instruction_data(&bundle.instructions[1].data),
+            Some(ManifestInstructionTag::CreateMarket)
+        );
+        assert_eq!(bundle.account_size, MARKET_FIXED_SIZE + MARKET_BLOCK_SIZE);
+        assert_eq!(
+            bundle.rent_exempt_lamports,
+            rent.minimum_balance(bundle.account_size)
+        );
+
+        args.num_free_blocks = 10;
+        let bundle: CreateMarketBundle = create_market(&args, &rent);
+        assert_eq!(bundle.instructions.len(), 3);
+        assert_eq!(
+            ManifestInstructionTag::from_instruction_data(&bundle.instructions[2].data),
+            Some(ManifestInstructionTag::Expand)
+        );
+        assert_eq!(bundle.instructions[2].data[1..], 10_u32.to_le_bytes());
+        assert_eq!(bundle.account_size, market_account_size(10));
+    }
+
+    #[test]
+    fn test_batch_update() {
+        let order: BatchOrder = BatchOrder {
+            num_base_atoms: BaseAtoms::new(1_000),
+            price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(95, -2).unwrap(),
+            is_bid: true,
+            order_type: OrderType::Limit,
+            last_valid_slot: NO_EXPIRATION_LAST_VALID_SLOT,
+        };
+        let mut args: BatchUpdateArgs = BatchUpdateArgs {
+            market: Pubkey::new_unique(),
+            trader: Pubkey::new_unique(),
+            base_mint: Pubkey::new_unique(),
+            quote_mint: Pubkey::new_unique(),
+            base_token_program_opt: None,
+            quote_token_program_opt: None,
+            trader_index_hint: Some(0),
+            cancels: (0..4).map(CancelOrderParams::new).collect(),
+            orders: vec![order; 4],
+        };
+        let instruction: Instruction = batch_update(&args).unwrap();
+        assert_eq!(
+            ManifestInstructionTag::from_instruction_data(&instruction.data),
+            Some(ManifestInstructionTag::BatchUpdate)
+        );
+        assert_eq!(instruction.accounts.len(), 3);
+        assert!(transaction_size(&[instruction], &args.trader) <= PACKET_DATA_SIZE);
+
+        // A global ask brings the base global.
+        args.orders.push(BatchOrder {
+            is_bid: false,
+            order_type: OrderType::Global,
+            ..order
+        });
+        let instruction: Instruction = batch_update(&args).unwrap();
+        assert_eq!(instruction.accounts.len(), 8);
+        assert_eq!(instruction.accounts[3].pubkey, args.base_mint);
+
+        args.orders = vec![order; 100];
+        assert!(batch_update(&args).is_err());
+    }
+}
@@ -0,0 +1,314 @@
+//! Instruction numbering used by the client. Kept as its own enum with the
+//! values written out so that a renumbering in the program shows up as a test
+//! failure here rather than as silently misdecoded transactions.
+
+use manifest::program::ManifestInstruction;
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ManifestInstructionTag {
+    CreateMarket = 0,
+    ClaimSeat = 1,
+    Deposit = 2,
+    Withdraw = 3,
+    Swap = 4,
+    Expand = 5,
+    BatchUpdate = 6,
+    GlobalCreate = 7,
+    GlobalAddTrader = 8,
+    GlobalDeposit = 9,
+    GlobalWithdraw = 10,
+    GlobalEvict = 11,
+    GlobalClean = 12,
+    SwapV2 = 13,
+}
+
+impl ManifestInstructionTag {
+    pub const ALL: [ManifestInstructionTag; 14] = [
+        ManifestInstructionTag::CreateMarket,
+        ManifestInstructionTag::ClaimSeat,
+        ManifestInstructionTag::Deposit,
+        ManifestInstructionTag::Withdraw,
+        ManifestInstructionTag::Swap,
+        ManifestInstructionTag::Expand,
+        ManifestInstructionTag::BatchUpdate,
+        ManifestInstructionTag::GlobalCreate,
+        ManifestInstructionTag::GlobalAddTrader,
+        ManifestInstructionTag::GlobalDeposit,
+        ManifestInstructionTag::GlobalWithdraw,
+        ManifestInstructionTag::GlobalEvict,
+        ManifestInstructionTag::GlobalClean,
+        ManifestInstructionTag::SwapV2,
+    ];
+
+    /// Tag of encoded instruction data, None if empty or unknown.
+    pub fn from_instruction_data(data: &[u8]) -> Option<Self> {
+        Self::try_from(*data.first()?).ok()
+    }
+}
+
+impl TryFrom<u8> for ManifestInstructionTag {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, u8> {
+        Self::ALL
+            .into_iter()
+            .find(|tag| *tag as u8 == value)
+            .ok_or(value)
+    }
+}
+
+impl From<ManifestInstructionTag> for u8 {
+    fn from(tag: ManifestInstructionTag) -> u8 {
+        tag as u8
+    }
+}
+
+// Both conversions are exhaustive matches so adding an instruction to the
+// program fails to compile until it is added here too.
+impl From<ManifestInstruction> for ManifestInstructionTag {
+    fn from(instruction: ManifestInstruction) -> Self {
+        match instruction {
+            ManifestInstruction::CreateMarket => ManifestInstructionTag::CreateMarket,
+            ManifestInstruction::ClaimSeat => ManifestInstructionTag::ClaimSeat,
+            ManifestInstruction::Deposit => ManifestInstructionTag::Deposit,
+            ManifestInstruction::Withdraw => ManifestInstructionTag::Withdraw,
+            ManifestInstruction::Swap => ManifestInstructionTag::Swap,
+            ManifestInstruction::Expand => ManifestInstructionTag::Expand,
+            ManifestInstruction::BatchUpdate => ManifestInstructionTag::BatchUpdate,
+            ManifestInstruction::GlobalCreate => ManifestInstructionTag::GlobalCreate,
+            ManifestInstruction::GlobalAddTrader => ManifestInstructionTag::GlobalAddTrader,
+            ManifestInstruction::GlobalDeposit => ManifestInstructionTag::GlobalDeposit,
+            ManifestInstruction::GlobalWithdraw => ManifestInstructionTag::GlobalWithdraw,
+            ManifestInstruction::GlobalEvict => ManifestInstructionTag::GlobalEvict,
+            ManifestInstruction::GlobalClean => ManifestInstructionTag::GlobalClean,
+            ManifestInstruction::SwapV2 => ManifestInstructionTag::SwapV2,
+        }
+    }
+}
+
+impl From<ManifestInstructionTag> for ManifestInstruction {
+    fn from(tag: ManifestInstructionTag) -> Self {
+        match tag {
+            ManifestInstructionTag::CreateMarket => ManifestInstruction::CreateMarket,
+            ManifestInstructionTag::ClaimSeat => ManifestInstruction::ClaimSeat,
+            ManifestInstructionTag::Deposit => ManifestInstruction::Deposit,
+            ManifestInstructionTag::Withdraw => ManifestInstruction::Withdraw,
+            ManifestInstructionTag::Swap => ManifestInstruction::Swap,
+            ManifestInstructionTag::Expand => ManifestInstruction::Expand,
+            ManifestInstructionTag::BatchUpdate => ManifestInstruction::BatchUpdate,
+            ManifestInstructionTag::GlobalCreate => ManifestInstruction::GlobalCreate,
+            ManifestInstructionTag::GlobalAddTrader => ManifestInstruction::GlobalAddTrader,
+            ManifestInstructionTag::GlobalDeposit => ManifestInstruction::GlobalDeposit,
+            ManifestInstructionTag::GlobalWithdraw => ManifestInstruction::GlobalWithdraw,
+            ManifestInstructionTag::GlobalEvict => ManifestInstruction::GlobalEvict,
+            ManifestInstructionTag::GlobalClean => ManifestInstruction::GlobalClean,
+            ManifestInstructionTag::SwapV2 => ManifestInstruction::SwapV2,
+        }
+    }
+}
+
+/// What an account passed to an instruction is for, so wallet previews and
+/// explorers can show more than a list of pubkeys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccountRole {
+    /// Name in the program's instruction definition, like `base_vault`.
+    pub name: &'static str,
+    /// Human readable name, like "Base vault".
+    pub label: &'static str,
+    /// Optional accounts are left out from the end when not needed.
+    pub is_optional: bool,
+}
+
+const fn role(name: &'static str, label: &'static str) -> AccountRole {
+    AccountRole {
+        name,
+        label,
+        is_optional: false,
+    }
+}
+
+const fn optional(name: &'static str, label: &'static str) -> AccountRole {
+    AccountRole {
+        name,
+        label,
+        is_optional: true,
+    }
+}
+
+const PAYER: AccountRole = role("payer", "Payer");
+const TRADER: AccountRole = role("payer", "Trader");
+const MARKET: AccountRole = role("market", "Market");
+const SYSTEM_PROGRAM: AccountRole = role("system_program", "System program");
+const GLOBAL: AccountRole = role("global", "Global account");
+const GLOBAL_VAULT: AccountRole = role("global_vault", "Global vault");
+const MINT: AccountRole = role("mint", "Mint");
+const TOKEN_PROGRAM: AccountRole = role("token_program", "Token program");
+
+/// Roles of the accounts of `instruction` in the order they are passed, as
+/// in the program's instruction definition.
+pub fn describe(instruction: &ManifestInstruction) -> Vec<AccountRole> {
+    let swap_tail: [AccountRole; 10] = [
+        role("trader_base", "Trader base token account"),
+        role("trader_quote", "Trader quote token account"),
+        role("base_vault", "Base vault"),
+        role("quote_vault", "Quote vault"),
+        role("token_program_base", "Base token program"),
+        optional("base_mint", "Base mint"),
+        optional("token_program_quote", "Quote token program"),
+        optional("quote_mint", "Quote mint"),
+        optional("global", "Global account"),
+        optional("global_vault", "Global vault"),
+    ];
+    match instruction {
+        ManifestInstruction::CreateMarket => vec![
+            PAYER,
+            MARKET,
+            SYSTEM_PROGRAM,
+            role("base_mint", "Base mint"),
+            role("quote_mint", "Quote mint"),
+            role("base_vault", "Base vault"),
+            role("quote_vault", "Quote vault"),
+            TOKEN_PROGRAM,
+            role("token_program_22", "Token 2022 program"),
+        ],
+        ManifestInstruction::ClaimSeat => vec![TRADER, MARKET, SYSTEM_PROGRAM],
+        ManifestInstruction::Deposit | ManifestInstruction::Withdraw => vec![
+            TRADER,
+            MARKET,
+            role("trader_token", "Trader token account"),
+            role("vault", "Market vault"),
+            TOKEN_PROGRAM,
+            MINT,
+        ],
+        ManifestInstruction::Swap => [TRADER, MARKET, SYSTEM_PROGRAM]
+            .into_iter()
+            .chain(swap_tail)
+            .collect(),
+        ManifestInstruction::SwapV2 => [PAYER, role("owner", "Trader"), MARKET, SYSTEM_PROGRAM]
+            .into_iter()
+            .chain(swap_tail)
+            .collect(),
+        ManifestInstruction::Expand => vec![PAYER, MARKET, SYSTEM_PROGRAM],
+        ManifestInstruction::BatchUpdate => vec![
+            TRADER,
+            MARKET,
+            SYSTEM_PROGRAM,
+            optional("base_mint", "Base mint"),
+            optional("base_global", "Base global account"),
+            optional("base_global_vault", "Base global vault"),
+            optional("base_market_vault", "Base vault"),
+            optional("base_token_program", "Base token program"),
+            optional("quote_mint", "Quote mint"),
+            optional("quote_global", "Quote global account"),
+            optional("quote_global_vault", "Quote global vault"),
+            optional("quote_market_vault", "Quote vault"),
+            optional("quote_token_program", "Quote token program"),
+        ],
+        ManifestInstruction::GlobalCreate => vec![
+            PAYER,
+            GLOBAL,
+            SYSTEM_PROGRAM,
+            MINT,
+            GLOBAL_VAULT,
+            TOKEN_PROGRAM,
+        ],
+        ManifestInstruction::GlobalAddTrader => vec![TRADER, GLOBAL, SYSTEM_PROGRAM],
+        ManifestInstruction::GlobalDeposit | ManifestInstruction::GlobalWithdraw => vec![
+            TRADER,
+            GLOBAL,
+            MINT,
+            GLOBAL_VAULT,
+            role("trader_token", "Trader token account"),
+            TOKEN_PROGRAM,
+        ],
+        ManifestInstruction::GlobalEvict => vec![
+            role("payer", "Evictor"),
+            GLOBAL,
+            MINT,
+            GLOBAL_VAULT,
+            role("trader_token", "Evictor token account"),
+            role("evictee_token", "Evictee token account"),
+            TOKEN_PROGRAM,
+        ],
+        ManifestInstruction::GlobalClean => {
+            vec![role("payer", "Cleaner"), MARKET, SYSTEM_PROGRAM, GLOBAL]
+        }
+    }
+}
+
+/// Each account of a Manifest instruction with its role. Accounts past the
+/// known ones have no role. None when the data is not a known instruction.
+pub fn describe_instruction(
+    instruction: &Instruction,
+) -> Option<Vec<(Pubkey, Option<AccountRole>)>> {
+    let tag: ManifestInstructionTag =
+        ManifestInstructionTag::from_instruction_data(&instruction.data)?;
+    let roles: Vec<AccountRole> = describe(&ManifestInstruction::from(tag));
+    Some(
+        instruction
+            .accounts
+            .iter()
+            .enumerate()
+            .map(|(index, meta)| (meta.pubkey, roles.get(index).copied()))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_describe_instruction() {
+        let market: Pubkey = Pubkey::new_unique();
+        let trader: Pubkey = Pubkey::new_unique();
+        let instruction: Instruction = manifest::program::deposit_instruction(
+            &market,
+            &trader,
+            &Pubkey::new_unique(),
+            1,
+            &Pubkey::new_unique(),
+            spl_token::id(),
+            None,
+        );
+        let described: Vec<(Pubkey, Option<AccountRole>)> =
+            describe_instruction(&instruction).unwrap();
+        assert_eq!(
+            described.len(),
+            describe(&ManifestInstruction::Deposit).len()
+        );
+        assert_eq!(described[0], (trader, Some(TRADER)));
+        assert_eq!(described[1].1.unwrap().label, "Market");
+        assert_eq!(described[3].1.unwrap().name, "vault");
+
+        for tag in ManifestInstructionTag::ALL {
+            let roles: Vec<AccountRole> = describe(&ManifestInstruction::from(tag));
+            // Optional accounts only come after the required ones.
+            assert!(roles
+                .windows(2)
+                .all(|pair| !pair[0].is_optional || pair[1].is_optional));
+        }
+        assert!(describe_instruction(&Instruction::new_with_bytes(market, &[], vec![])).is_none());
+    }
+
+    #[test]
+    fn test_tags_match_program() {
+        for value in 0..=u8::MAX {
+            match (
+                ManifestInstruction::try_from(value),
+                ManifestInstructionTag::try_from(value),
+            ) {
+                (Ok(instruction), Ok(tag)) => {
+                    assert_eq!(ManifestInstructionTag::from(instruction), tag);
+                    assert_eq!(ManifestInstruction::from(tag), instruction);
+                    assert_eq!(format!("{:?}", instruction), format!("{:?}", tag));
+                    assert_eq!(instruction.to_vec(), vec![u8::from(tag)]);
+                }
+                (Err(_), Err(unknown)) => assert_eq!(unknown, value),
+                _ => panic!("Instruction {value} is not known to both program and client"),
+            }
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Consistency between the orders and the seats of a market. Every resting
+//! order points at the seat of its trader by `trader_index`, which the program
+//! keeps valid because a seat is never released while it has orders. A
+//! monitor runs `seat_report` on each update and alerts when an order points
+//! anywhere else, which means the copy of the market is corrupt.
+
+use std::collections::HashSet;
+
+use hypertree::{DataIndex, HyperTreeValueIteratorTrait};
+use manifest::{
+    quantities::WrapperU64,
+    state::{claimed_seat::ClaimedSeat, MarketValue, RestingOrder},
+};
+use solana_program::pubkey::Pubkey;
+
+/// Resting order whose `trader_index` is not a claimed seat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrphanedOrder {
+    pub order_index: DataIndex,
+    pub sequence_number: u64,
+    pub trader_index: DataIndex,
+    pub is_bid: bool,
+}
+
+/// Seat with nothing withdrawable and no orders. It only holds on to a block
+/// of the market, so it is the first to give up if seats can be released.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReclaimableSeat {
+    pub trader_index: DataIndex,
+    pub trader: Pubkey,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SeatReport {
+    pub num_seats: usize,
+    pub num_orders: usize,
+    pub orphaned_orders: Vec<OrphanedOrder>,
+    pub reclaimable_seats: Vec<ReclaimableSeat>,
+}
+
+impl SeatReport {
+    /// Whether every order belongs to a seat. Reclaimable seats are normal.
+    pub fn is_consistent(&self) -> bool {
+        self.orphaned_orders.is_empty()
+    }
+}
+
+pub fn seat_report(market: &MarketValue) -> SeatReport {
+    let seats: Vec<(DataIndex, ClaimedSeat)> = market
+        .get_claimed_seats()
+        .iter::<ClaimedSeat>()
+        .map(|(seat_index, seat)| (seat_index, *seat))
+        .collect();
+    let seat_indexes: HashSet<DataIndex> =
+        seats.iter().map(|(seat_index, _)| *seat_index).collect();
+
+    let mut report: SeatReport = SeatReport {
+        num_seats: seats.len(),
+        ..SeatReport::default()
+    };
+    let mut traders_with_orders: HashSet<DataIndex> = HashSet::new();
+    for (order_index, order) in market
+        .get_bids()
+        .iter::<RestingOrder>()
+        .chain(market.get_asks().iter::<RestingOrder>())
+    {
+        report.num_orders += 1;
+        let trader_index: DataIndex = order.get_trader_index();
+        traders_with_orders.insert(trader_index);
+        if !seat_indexes.contains(&trader_index) {
+            report.orphaned_orders.push(OrphanedOrder {
+                order_index,
+                sequence_number: order.get_sequence_number(),
+                trader_index,
+                is_bid: order.get_is_bid(),
+            });
+        }
+    }
+
+    report.reclaimable_seats = seats
+        .iter()
+        .filter(|(seat_index, seat)| {
+            seat.base_withdrawable_balance.as_u64() == 0
+                && seat.quote_withdrawable_balance.as_u64() == 0
+                && !traders_with_orders.contains(seat_index)
+        })
+        .map(|(seat_index, seat)| ReclaimableSeat {
+            trader_index: *seat_index,
+            trader: seat.trader,
+        })
+        .collect();
+    report
+}
+
+#[cfg(all(test, feature = "model"))]
+mod test {
+    use super::*;
+    use crate::model::{ModelOperation, ModelRunner};
+    use manifest::{
+        quantities::{BaseAtoms, QuoteAtomsPerBaseAtom},
+        state::OrderType,
+    };
+
+    #[test]
+    fn test_seat_report() {
+        let maker: Pubkey = Pubkey::new_unique();
+        let idle: Pubkey = Pubkey::new_unique();
+        let mut runner: ModelRunner = ModelRunner::new(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        );
+        runner.claim_seat(&maker).unwrap();
+        runner.claim_seat(&idle).unwrap();
+        runner
+            .apply(&ModelOperation::Deposit {
+                trader: maker,
+                amount_atoms: 1_000,
+                is_base: true,
+            })
+            .unwrap();
+        // Everything the maker has goes into the order.
+        runner
+            .apply(&ModelOperation::PlaceOrder {
+                trader: maker,
+                num_base_atoms: BaseAtoms::new(1_000),
+                price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(1, 0).unwrap(),
+                is_bid: false,
+                order_type: OrderType::Limit,
+            })
+            .unwrap();
+
+        let report: SeatReport = seat_report(&runner.market);
+        assert!(report.is_consistent());
+        assert_eq!(report.num_seats, 2);
+        assert_eq!(report.num_orders, 1);
+        assert_eq!(
+            report.reclaimable_seats,
+            [ReclaimableSeat {
+                trader_index: runner.market.get_trader_index(&idle),
+                trader: idle,
+            }]
+        );
+
+        let maker_index: DataIndex = runner.market.get_trader_index(&maker);
+        let mut market: MarketValue = runner.market.clone();
+        market.release_seat(&maker).unwrap();
+        let report: SeatReport = seat_report(&market);
+        assert!(!report.is_consistent());
+        assert_eq!(report.orphaned_orders.len(), 1);
+        assert_eq!(report.orphaned_orders[0].trader_index, maker_index);
+        assert!(!report.orphaned_orders[0].is_bid);
+    }
+}
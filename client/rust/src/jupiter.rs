@@ -0,0 +1,154 @@
+//! Manifest legs of Jupiter routes that use the shared accounts instructions.
+//! Those route through token accounts owned by a program authority of the
+//! Jupiter program, so the leg accounts differ from `get_swap_and_account_metas`
+//! where the user is the authority.
+
+use anyhow::{Error, Result};
+use jupiter_amm_interface::Side;
+use solana_sdk::{instruction::AccountMeta, pubkey, pubkey::Pubkey};
+
+use crate::{instruction_builders::get_associated_token_address, ManifestMarket};
+
+pub const JUPITER_PROGRAM_ID: Pubkey = pubkey!("JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4");
+pub const ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey =
+    pubkey!("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+
+/// Index of the `Openbook { side }` variant in the routing program's `Swap`
+/// enum, which Manifest legs use. The side follows as one byte, bid first.
+pub const OPENBOOK_SWAP_VARIANT: u8 = 27;
+
+/// Number of program authorities shared accounts routes can pick from.
+pub const NUM_PROGRAM_AUTHORITIES: u8 = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteLeg {
+    /// Remaining accounts of the route instruction for this leg, starting
+    /// with the Manifest program.
+    pub account_metas: Vec<AccountMeta>,
+    /// Borsh encoded `Swap` of the leg in the route plan.
+    pub data: Vec<u8>,
+}
+
+pub fn get_program_authority(authority_id: u8) -> Pubkey {
+    Pubkey::find_program_address(&[b"authority", &[authority_id]], &JUPITER_PROGRAM_ID).0
+}
+
+/// Token account of the program authority for `mint`, the associated token
+/// account for the mint's token program.
+pub fn get_program_token_account(
+    authority: &Pubkey,
+    mint: &Pubkey,
+    token_program: &Pubkey,
+) -> Pubkey {
+    get_associated_token_address(authority, mint, token_program)
+}
+
+/// Leg for the first program authority, see `encode_route_leg_with_authority`.
+pub fn encode_route_leg(market: &ManifestMarket, side: Side) -> Result<RouteLeg> {
+    encode_route_leg_with_authority(market, side, 0)
+}
+
+/// Leg that swaps through the token accounts of program authority
+/// `authority_id`. The authority signs inside the route, so it is not a
+/// signer of the transaction.
+pub fn encode_route_leg_with_authority(
+    market: &ManifestMarket,
+    side: Side,
+    authority_id: u8,
+) -> Result<RouteLeg> {
+    if authority_id >= NUM_PROGRAM_AUTHORITIES {
+        return Err(Error::msg(format!(
+            "Program authority {authority_id} does not exist"
+        )));
+    }
+    let authority: Pubkey = get_program_authority(authority_id);
+    let base_account: Pubkey = get_program_token_account(
+        &authority,
+        &market.get_base_mint(),
+        &market.base_token_program,
+    );
+    let quote_account: Pubkey = get_program_token_account(
+        &authority,
+        &market.get_quote_mint(),
+        &market.quote_token_program,
+    );
+    let account_metas: Vec<AccountMeta> = market
+        .get_swap_account_metas(&side, &base_account, &quote_account, &authority)
+        .into_iter()
+        .map(|account_meta| AccountMeta {
+            is_signer: false,
+            ..account_meta
+        })
+        .collect();
+    let side_byte: u8 = match side {
+        Side::Bid => 0,
+        Side::Ask => 1,
+    };
+    Ok(RouteLeg {
+        account_metas,
+        data: vec![OPENBOOK_SWAP_VARIANT, side_byte],
+    })
+}
+
+#[cfg(all(test, feature = "model"))]
+mod test {
+    use super::*;
+    use crate::{model::ModelRunner, snapshot::dynamic_account_data};
+    use jupiter_amm_interface::{Amm, AmmContext, ClockRef, KeyedAccount};
+    use manifest::{state::MarketFixed, validation::get_vault_address};
+    use solana_sdk::account::Account;
+
+    #[test]
+    fn test_encode_route_leg() {
+        let market_key: Pubkey = Pubkey::new_unique();
+        let base_mint: Pubkey = Pubkey::new_unique();
+        let quote_mint: Pubkey = Pubkey::new_unique();
+        let runner: ModelRunner = ModelRunner::new(&market_key, &base_mint, &quote_mint);
+        let market: ManifestMarket = ManifestMarket::from_keyed_account(
+            &KeyedAccount {
+                key: market_key,
+                account: Account {
+                    lamports: 0,
+                    data: dynamic_account_data::<MarketFixed>(
+                        &runner.market.fixed,
+                        &runner.market.dynamic,
+                    ),
+                    owner: manifest::id(),
+                    executable: false,
+                    rent_epoch: 0,
+                },
+                params: None,
+            },
+            &AmmContext {
+                clock_ref: ClockRef::default(),
+            },
+        )
+        .unwrap();
+
+        let leg: RouteLeg = encode_route_leg(&market, Side::Ask).unwrap();
+        assert_eq!(leg.data, vec![OPENBOOK_SWAP_VARIANT, 1]);
+        let authority: Pubkey = get_program_authority(0);
+        let keys: Vec<Pubkey> = leg
+            .account_metas
+            .iter()
+            .map(|account_meta| account_meta.pubkey)
+            .collect();
+        assert_eq!(keys[0], manifest::id());
+        assert_eq!(keys[1..3], [authority, authority]);
+        assert_eq!(keys[3], market_key);
+        assert_eq!(
+            keys[5],
+            get_program_token_account(&authority, &base_mint, &spl_token::id())
+        );
+        assert_eq!(keys[7], get_vault_address(&market_key, &base_mint).0);
+        assert!(leg
+            .account_metas
+            .iter()
+            .all(|account_meta| !account_meta.is_signer));
+
+        let leg: RouteLeg = encode_route_leg_with_authority(&market, Side::Bid, 3).unwrap();
+        assert_eq!(leg.data, vec![OPENBOOK_SWAP_VARIANT, 0]);
+        assert_eq!(leg.account_metas[1].pubkey, get_program_authority(3));
+        assert!(encode_route_leg_with_authority(&market, Side::Bid, 8).is_err());
+    }
+}
@@ -0,0 +1,319 @@
+//! Moving a trader's liquidity from one market to another for the same pair,
+//! for when a community moves to a new market account.
+
+use anyhow::{bail, Result};
+use hypertree::{DataIndex, HyperTreeValueIteratorTrait, NIL};
+use manifest::{
+    program::{
+        batch_update::{CancelOrderParams, PlaceOrderParams},
+        batch_update_instruction, claim_seat_instruction, deposit_instruction,
+        withdraw_instruction,
+    },
+    quantities::{BaseAtoms, QuoteAtoms, QuoteAtomsPerBaseAtom, ScaledPrice, WrapperU64},
+    state::{MarketValue, OrderType, RestingOrder},
+};
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+
+/// Cancels or places per batch update, small enough that a transaction with
+/// the deposits or withdrawals stays under the size limit.
+const ORDERS_PER_TRANSACTION: usize = 10;
+
+/// Token accounts of the trader, used for both markets since the mints are
+/// the same.
+#[derive(Debug, Clone, Copy)]
+pub struct MigrationAccounts {
+    pub trader_base_account: Pubkey,
+    pub trader_quote_account: Pubkey,
+    pub base_token_program: Pubkey,
+    pub quote_token_program: Pubkey,
+}
+
+/// Smallest exponent the price can be placed at exactly, so the order on the
+/// new market has the same price as the old one.
+fn mantissa_and_exponent(price: QuoteAtomsPerBaseAtom, max_exponent: i8) -> Option<(u32, i8)> {
+    (QuoteAtomsPerBaseAtom::MIN_EXP..=max_exponent).find_map(|exponent| {
+        let mantissa: u64 = ScaledPrice::try_from_price(price, 0, 0, exponent, false)
+            .ok()?
+            .value;
+        let mantissa: u32 = u32::try_from(mantissa).ok()?;
+        let round_trip: QuoteAtomsPerBaseAtom =
+            QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(mantissa, exponent).ok()?;
+        (round_trip == price).then_some((mantissa, exponent))
+    })
+}
+
+fn place_params(order: &RestingOrder) -> Result<PlaceOrderParams> {
+    let order_type: OrderType = order.get_order_type();
+    let Some((mantissa, exponent)) =
+        mantissa_and_exponent(order.get_price(), order_type.max_exponent())
+    else {
+        bail!(
+            "Price of order {} cannot be placed again",
+            order.get_sequence_number()
+        );
+    };
+    // Reverse orders take their spread in place of an expiration.
+    let last_valid_slot: u32 = if order.is_reversible() {
+        order.get_reverse_spread() as u32
+    } else {
+        order.get_last_valid_slot()
+    };
+    Ok(PlaceOrderParams::new(
+        order.get_num_base_atoms().as_u64(),
+        mantissa,
+        exponent,
+        order.get_is_bid(),
+        order_type,
+        last_valid_slot,
+    ))
+}
+
+/// Transactions to run in order that cancel the trader's orders on
+/// `from_market`, withdraw everything, deposit it on `to_market` and place
+/// the same orders there. Orders already expired at `now_slot` are cancelled
+/// but not placed again. Global orders are placed again backed by the same
+/// global accounts. The trader pays and signs every transaction.
+pub fn migrate_liquidity(
+    from_key: &Pubkey,
+    from_market: &MarketValue,
+    to_key: &Pubkey,
+    to_market: &MarketValue,
+    trader: &Pubkey,
+    accounts: &MigrationAccounts,
+    now_slot: u32,
+) -> Result<Vec<Vec<Instruction>>> {
+    let base_mint: Pubkey = *from_market.get_base_mint();
+    let quote_mint: Pubkey = *from_market.get_quote_mint();
+    if *to_market.get_base_mint() != base_mint || *to_market.get_quote_mint() != quote_mint {
+        bail!("Markets {from_key} and {to_key} are not for the same mints");
+    }
+    let from_trader_index: DataIndex = from_market.get_trader_index(trader);
+    if from_trader_index == NIL {
+        bail!("Trader {trader} has no seat on {from_key}");
+    }
+
+    let (mut base_atoms, mut quote_atoms): (BaseAtoms, QuoteAtoms) =
+        from_market.get_trader_balance(trader);
+    let mut cancels: Vec<CancelOrderParams> = Vec::new();
+    let mut places: Vec<PlaceOrderParams> = Vec::new();
+    let mut has_global: bool = false;
+    for book in [from_market.get_bids(), from_market.get_asks()] {
+        for (order_index, order) in book.iter::<RestingOrder>() {
+            if order.get_trader_index() != from_trader_index {
+                continue;
+            }
+            cancels.push(CancelOrderParams::new_with_hint(
+                order.get_sequence_number(),
+                Some(order_index),
+            ));
+            if order.is_global() {
+                has_global = true;
+            } else if order.get_is_bid() {
+                quote_atoms = quote_atoms.checked_add(
+                    order
+                        .get_price()
+                        .checked_quote_for_base(order.get_num_base_atoms(), true)?,
+                )?;
+            } else {
+                base_atoms = base_atoms.checked_add(order.get_num_base_atoms())?;
+            }
+            if !order.is_expired(now_slot) {
+                places.push(place_params(order)?);
+            }
+        }
+    }
+
+    let mut transactions: Vec<Vec<Instruction>> = Vec::new();
+    for cancel_batch in cancels.chunks(ORDERS_PER_TRANSACTION) {
+        transactions.push(vec![batch_update_instruction(
+            from_key,
+            trader,
+            Some(from_trader_index),
+            cancel_batch.to_vec(),
+            Vec::new(),
+            None,
+            None,
+            None,
+            None,
+        )]);
+    }
+
+    let mut withdraws: Vec<Instruction> = Vec::new();
+    let mut deposits: Vec<Instruction> = Vec::new();
+    if to_market.get_trader_index(trader) == NIL {
+        deposits.push(claim_seat_instruction(to_key, trader));
+    }
+    for (mint, num_atoms, trader_account, token_program) in [
+        (
+            base_mint,
+            base_atoms.as_u64(),
+            accounts.trader_base_account,
+            accounts.base_token_program,
+        ),
+        (
+            quote_mint,
+            quote_atoms.as_u64(),
+            accounts.trader_quote_account,
+            accounts.quote_token_program,
+        ),
+    ] {
+        if num_atoms == 0 {
+            continue;
+        }
+        withdraws.push(withdraw_instruction(
+            from_key,
+            trader,
+            &mint,
+            num_atoms,
+            &trader_account,
+            token_program,
+            Some(from_trader_index),
+        ));
+        deposits.push(deposit_instruction(
+            to_key,
+            trader,
+            &mint,
+            num_atoms,
+            &trader_account,
+            token_program,
+            None,
+        ));
+    }
+    // Withdraw in the same transaction as the last cancels so the funds are
+    // never left unused on the old market.
+    match transactions.last_mut() {
+        Some(last_transaction) => last_transaction.extend(withdraws),
+        None if !withdraws.is_empty() => transactions.push(withdraws),
+        None => {}
+    }
+    if !deposits.is_empty() {
+        transactions.push(deposits);
+    }
+
+    let (
+        base_global_mint,
+        base_global_token_program,
+        quote_global_mint,
+        quote_global_token_program,
+    ) = if has_global {
+        (
+            Some(base_mint),
+            Some(accounts.base_token_program),
+            Some(quote_mint),
+            Some(accounts.quote_token_program),
+        )
+    } else {
+        (None, None, None, None)
+    };
+    for place_batch in places.chunks(ORDERS_PER_TRANSACTION) {
+        transactions.push(vec![batch_update_instruction(
+            to_key,
+            trader,
+            None,
+            Vec::new(),
+            place_batch.to_vec(),
+            base_global_mint,
+            base_global_token_program,
+            quote_global_mint,
+            quote_global_token_program,
+        )]);
+    }
+    Ok(transactions)
+}
+
+#[cfg(all(test, feature = "model"))]
+mod test {
+    use super::*;
+    use crate::model::{ModelOperation, ModelRunner};
+
+    #[test]
+    fn test_mantissa_and_exponent() {
+        for (mantissa, exponent) in [(1, 0), (15, -1), (u32::MAX, -18), (7, 8)] {
+            let price: QuoteAtomsPerBaseAtom =
+                QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(mantissa, exponent).unwrap();
+            let (new_mantissa, new_exponent) = mantissa_and_exponent(price, 8).unwrap();
+            assert_eq!(
+                QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(new_mantissa, new_exponent)
+                    .unwrap(),
+                price
+            );
+        }
+        assert_eq!(
+            mantissa_and_exponent(
+                QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(7, 8).unwrap(),
+                0
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_migrate_liquidity() {
+        let base_mint: Pubkey = Pubkey::new_unique();
+        let quote_mint: Pubkey = Pubkey::new_unique();
+        let trader: Pubkey = Pubkey::new_unique();
+        let from_key: Pubkey = Pubkey::new_unique();
+        let to_key: Pubkey = Pubkey::new_unique();
+        let mut from: ModelRunner = ModelRunner::new(&from_key, &base_mint, &quote_mint);
+        let to: ModelRunner = ModelRunner::new(&to_key, &base_mint, &quote_mint);
+        from.claim_seat(&trader).unwrap();
+        for is_base in [true, false] {
+            from.apply(&ModelOperation::Deposit {
+                trader,
+                amount_atoms: 1_000,
+                is_base,
+            })
+            .unwrap();
+        }
+        for (mantissa, is_bid) in [(1, true), (3, false)] {
+            from.apply(&ModelOperation::PlaceOrder {
+                trader,
+                num_base_atoms: BaseAtoms::new(100),
+                price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(mantissa, 0).unwrap(),
+                is_bid,
+                order_type: OrderType::Limit,
+            })
+            .unwrap();
+        }
+
+        let accounts: MigrationAccounts = MigrationAccounts {
+            trader_base_account: Pubkey::new_unique(),
+            trader_quote_account: Pubkey::new_unique(),
+            base_token_program: spl_token::id(),
+            quote_token_program: spl_token::id(),
+        };
+        let transactions: Vec<Vec<Instruction>> = migrate_liquidity(
+            &from_key,
+            &from.market,
+            &to_key,
+            &to.market,
+            &trader,
+            &accounts,
+            0,
+        )
+        .unwrap();
+        let tags: Vec<Vec<u8>> = transactions
+            .iter()
+            .map(|instructions| instructions.iter().map(|ix| ix.data[0]).collect())
+            .collect();
+        let batch_update: u8 = manifest::program::ManifestInstruction::BatchUpdate as u8;
+        let claim_seat: u8 = manifest::program::ManifestInstruction::ClaimSeat as u8;
+        let deposit: u8 = manifest::program::ManifestInstruction::Deposit as u8;
+        let withdraw: u8 = manifest::program::ManifestInstruction::Withdraw as u8;
+        assert_eq!(
+            tags,
+            vec![
+                vec![batch_update, withdraw, withdraw],
+                vec![claim_seat, deposit, deposit],
+                vec![batch_update],
+            ]
+        );
+        // Both withdrawals include the funds that were locked in orders.
+        for withdraw in &transactions[0][1..] {
+            assert_eq!(
+                u64::from_le_bytes(withdraw.data[1..9].try_into().unwrap()),
+                1_000
+            );
+        }
+    }
+}
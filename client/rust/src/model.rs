@@ -0,0 +1,693 @@
+//! Simplified reference implementation of the orderbook.
+//!
+//! The byte level matching engine in the program manipulates red black trees
+//! in place. This module keeps the same book in plain `BTreeMap`s so that the
+//! two can be driven with the same operations and compared after every step.
+//! Only non-global, non-reverse orders without expiration are modeled.
+
+use anyhow::{Error, Result};
+use hypertree::{DataIndex, HyperTreeValueIteratorTrait, NIL};
+use manifest::{
+    program::ManifestError,
+    quantities::{BaseAtoms, QuoteAtoms, QuoteAtomsPerBaseAtom, WrapperU64},
+    state::{
+        constants::NO_EXPIRATION_LAST_VALID_SLOT, AddOrderToMarketArgs, MarketFixed, MarketValue,
+        OrderType, RestingOrder,
+    },
+    validation::MintAccountInfo,
+};
+use solana_program::{account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey};
+use spl_token_2022::state::Mint;
+use std::{cell::RefCell, cmp::Reverse, collections::BTreeMap, rc::Rc};
+
+#[cfg(feature = "repro")]
+use crate::repro::{CaseStep, ReproCase};
+
+/// Operation that can be applied to both the model and the real market.
+#[derive(Debug, Clone)]
+pub enum ModelOperation {
+    Deposit {
+        trader: Pubkey,
+        amount_atoms: u64,
+        is_base: bool,
+    },
+    Withdraw {
+        trader: Pubkey,
+        amount_atoms: u64,
+        is_base: bool,
+    },
+    PlaceOrder {
+        trader: Pubkey,
+        num_base_atoms: BaseAtoms,
+        price: QuoteAtomsPerBaseAtom,
+        is_bid: bool,
+        order_type: OrderType,
+    },
+    CancelOrder {
+        trader: Pubkey,
+        order_sequence_number: u64,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelOrder {
+    pub trader: Pubkey,
+    pub num_base_atoms: BaseAtoms,
+    pub price: QuoteAtomsPerBaseAtom,
+    pub sequence_number: u64,
+    pub is_bid: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelPlaceResult {
+    pub order_sequence_number: u64,
+    pub base_atoms_traded: BaseAtoms,
+    pub quote_atoms_traded: QuoteAtoms,
+}
+
+/// Reference book. Orders at the same price are kept in time priority by
+/// using the sequence number as the second part of the key.
+#[derive(Debug, Clone, Default)]
+pub struct ModelBook {
+    bids: BTreeMap<(Reverse<QuoteAtomsPerBaseAtom>, u64), ModelOrder>,
+    asks: BTreeMap<(QuoteAtomsPerBaseAtom, u64), ModelOrder>,
+    balances: BTreeMap<Pubkey, (BaseAtoms, QuoteAtoms)>,
+    order_sequence_number: u64,
+}
+
+impl ModelBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn claim_seat(&mut self, trader: &Pubkey) -> Result<(), ProgramError> {
+        if self.balances.contains_key(trader) {
+            return Err(ManifestError::AlreadyClaimedSeat.into());
+        }
+        self.balances
+            .insert(*trader, (BaseAtoms::ZERO, QuoteAtoms::ZERO));
+        Ok(())
+    }
+
+    pub fn get_trader_balance(&self, trader: &Pubkey) -> (BaseAtoms, QuoteAtoms) {
+        self.balances
+            .get(trader)
+            .copied()
+            .unwrap_or((BaseAtoms::ZERO, QuoteAtoms::ZERO))
+    }
+
+    /// Bids from best to worst.
+    pub fn bids(&self) -> Vec<ModelOrder> {
+        self.bids.values().copied().collect()
+    }
+
+    /// Asks from best to worst.
+    pub fn asks(&self) -> Vec<ModelOrder> {
+        self.asks.values().copied().collect()
+    }
+
+    /// Applies the operation. On error the model is left unchanged, which
+    /// matches a failed transaction on chain.
+    pub fn apply(
+        &mut self,
+        operation: &ModelOperation,
+    ) -> Result<Option<ModelPlaceResult>, ProgramError> {
+        let mut next: ModelBook = self.clone();
+        let result: Option<ModelPlaceResult> = match *operation {
+            ModelOperation::Deposit {
+                trader,
+                amount_atoms,
+                is_base,
+            } => {
+                next.update_balance(&trader, is_base, true, amount_atoms)?;
+                None
+            }
+            ModelOperation::Withdraw {
+                trader,
+                amount_atoms,
+                is_base,
+            } => {
+                next.update_balance(&trader, is_base, false, amount_atoms)?;
+                None
+            }
+            ModelOperation::PlaceOrder {
+                trader,
+                num_base_atoms,
+                price,
+                is_bid,
+                order_type,
+            } => Some(next.place_order(&trader, num_base_atoms, price, is_bid, order_type)?),
+            ModelOperation::CancelOrder {
+                trader,
+                order_sequence_number,
+            } => {
+                next.cancel_order(&trader, order_sequence_number)?;
+                None
+            }
+        };
+        *self = next;
+        Ok(result)
+    }
+
+    fn update_balance(
+        &mut self,
+        trader: &Pubkey,
+        is_base: bool,
+        is_increase: bool,
+        amount_atoms: u64,
+    ) -> Result<(), ProgramError> {
+        let (base, quote) = self
+            .balances
+            .get_mut(trader)
+            .ok_or(ManifestError::InvalidDepositAccounts)?;
+        if is_base {
+            *base = if is_increase {
+                base.checked_add(BaseAtoms::new(amount_atoms))?
+            } else if *base >= BaseAtoms::new(amount_atoms) {
+                base.checked_sub(BaseAtoms::new(amount_atoms))?
+            } else {
+                return Err(ProgramError::InsufficientFunds);
+            };
+        } else {
+            *quote = if is_increase {
+                quote.checked_add(QuoteAtoms::new(amount_atoms))?
+            } else if *quote >= QuoteAtoms::new(amount_atoms) {
+                quote.checked_sub(QuoteAtoms::new(amount_atoms))?
+            } else {
+                return Err(ProgramError::InsufficientFunds);
+            };
+        }
+        Ok(())
+    }
+
+    fn best_maker(&self, is_bid: bool) -> Option<ModelOrder> {
+        if is_bid {
+            self.asks.values().next().copied()
+        } else {
+            self.bids.values().next().copied()
+        }
+    }
+
+    fn remove(&mut self, order: &ModelOrder) {
+        if order.is_bid {
+            self.bids
+                .remove(&(Reverse(order.price), order.sequence_number));
+        } else {
+            self.asks.remove(&(order.price, order.sequence_number));
+        }
+    }
+
+    fn insert(&mut self, order: ModelOrder) {
+        if order.is_bid {
+            self.bids
+                .insert((Reverse(order.price), order.sequence_number), order);
+        } else {
+            self.asks
+                .insert((order.price, order.sequence_number), order);
+        }
+    }
+
+    fn place_order(
+        &mut self,
+        trader: &Pubkey,
+        num_base_atoms: BaseAtoms,
+        price: QuoteAtomsPerBaseAtom,
+        is_bid: bool,
+        order_type: OrderType,
+    ) -> Result<ModelPlaceResult, ProgramError> {
+        if !self.balances.contains_key(trader) {
+            return Err(ManifestError::InvalidDepositAccounts.into());
+        }
+        let mut base_atoms_traded: BaseAtoms = BaseAtoms::ZERO;
+        let mut quote_atoms_traded: QuoteAtoms = QuoteAtoms::ZERO;
+        let mut remaining_base_atoms: BaseAtoms = num_base_atoms;
+
+        while remaining_base_atoms > BaseAtoms::ZERO {
+            let Some(maker_order) = self.best_maker(is_bid) else {
+                break;
+            };
+            if (is_bid && maker_order.price > price) || (!is_bid && maker_order.price < price) {
+                break;
+            }
+            if order_type == OrderType::PostOnly {
+                return Err(ManifestError::PostOnlyCrosses.into());
+            }
+
+            let did_fully_match: bool = remaining_base_atoms >= maker_order.num_base_atoms;
+            let base_atoms: BaseAtoms = if did_fully_match {
+                maker_order.num_base_atoms
+            } else {
+                remaining_base_atoms
+            };
+            let quote_atoms: QuoteAtoms = maker_order
+                .price
+                .checked_quote_for_base(base_atoms, is_bid != did_fully_match)?;
+            base_atoms_traded = base_atoms_traded.checked_add(base_atoms)?;
+            quote_atoms_traded = quote_atoms_traded.checked_add(quote_atoms)?;
+
+            if !is_bid {
+                // Maker recoups the atom it locked for rounding up.
+                let previous_allocated: QuoteAtoms = maker_order
+                    .price
+                    .checked_quote_for_base(maker_order.num_base_atoms, true)?;
+                let new_allocated: QuoteAtoms = maker_order.price.checked_quote_for_base(
+                    maker_order.num_base_atoms.checked_sub(base_atoms)?,
+                    true,
+                )?;
+                let bonus_atom_or_zero: QuoteAtoms = previous_allocated
+                    .checked_sub(new_allocated)?
+                    .checked_sub(quote_atoms)?;
+                self.update_balance(
+                    &maker_order.trader,
+                    false,
+                    true,
+                    bonus_atom_or_zero.as_u64(),
+                )?;
+            }
+
+            let (taker_pays, taker_gets): (u64, u64) = if is_bid {
+                (quote_atoms.as_u64(), base_atoms.as_u64())
+            } else {
+                (base_atoms.as_u64(), quote_atoms.as_u64())
+            };
+            self.update_balance(&maker_order.trader, !is_bid, true, taker_pays)?;
+            self.update_balance(trader, !is_bid, false, taker_pays)?;
+            self.update_balance(trader, is_bid, true, taker_gets)?;
+
+            self.remove(&maker_order);
+            if did_fully_match {
+                remaining_base_atoms = remaining_base_atoms.checked_sub(base_atoms)?;
+            } else {
+                self.insert(ModelOrder {
+                    num_base_atoms: maker_order.num_base_atoms.checked_sub(base_atoms)?,
+                    ..maker_order
+                });
+                remaining_base_atoms = BaseAtoms::ZERO;
+            }
+        }
+
+        let order_sequence_number: u64 = self.order_sequence_number;
+        self.order_sequence_number = order_sequence_number.wrapping_add(1);
+
+        if order_type != OrderType::ImmediateOrCancel
+            && remaining_base_atoms > BaseAtoms::ZERO
+            && price != QuoteAtomsPerBaseAtom::ZERO
+        {
+            let locked_atoms: u64 = if is_bid {
+                remaining_base_atoms.checked_mul(price, true)?.as_u64()
+            } else {
+                remaining_base_atoms.as_u64()
+            };
+            self.update_balance(trader, !is_bid, false, locked_atoms)?;
+            self.insert(ModelOrder {
+                trader: *trader,
+                num_base_atoms: remaining_base_atoms,
+                price,
+                sequence_number: order_sequence_number,
+                is_bid,
+            });
+        }
+
+        Ok(ModelPlaceResult {
+            order_sequence_number,
+            base_atoms_traded,
+            quote_atoms_traded,
+        })
+    }
+
+    fn cancel_order(
+        &mut self,
+        trader: &Pubkey,
+        order_sequence_number: u64,
+    ) -> Result<(), ProgramError> {
+        let order: ModelOrder = self
+            .bids
+            .values()
+            .chain(self.asks.values())
+            .find(|order| order.sequence_number == order_sequence_number)
+            .copied()
+            .ok_or(ManifestError::InvalidCancel)?;
+        if order.trader != *trader {
+            return Err(ManifestError::InvalidCancel.into());
+        }
+        let refund_atoms: u64 = if order.is_bid {
+            order
+                .price
+                .checked_quote_for_base(order.num_base_atoms, true)?
+                .as_u64()
+        } else {
+            order.num_base_atoms.as_u64()
+        };
+        self.update_balance(trader, !order.is_bid, true, refund_atoms)?;
+        self.remove(&order);
+        Ok(())
+    }
+}
+
+/// Drives a `ModelBook` and a real market with the same operations and
+/// reports the first divergence.
+pub struct ModelRunner {
+    pub model: ModelBook,
+    pub market: MarketValue,
+    market_key: Pubkey,
+    #[cfg(feature = "repro")]
+    recording_opt: Option<ReproCase>,
+}
+
+impl ModelRunner {
+    pub fn new(market_key: &Pubkey, base_mint: &Pubkey, quote_mint: &Pubkey) -> Self {
+        // Values on the mints are not important.
+        let mut base_lamports: u64 = 0;
+        let base_mint: MintAccountInfo = MintAccountInfo {
+            mint: Mint {
+                mint_authority: None.into(),
+                supply: 0,
+                decimals: 9,
+                is_initialized: true,
+                freeze_authority: None.into(),
+            },
+            info: &AccountInfo {
+                key: base_mint,
+                lamports: Rc::new(RefCell::new(&mut base_lamports)),
+                data: Rc::new(RefCell::new(&mut [])),
+                owner: &spl_token::id(),
+                rent_epoch: 0,
+                is_signer: false,
+                is_writable: false,
+                executable: false,
+            },
+        };
+        let mut quote_lamports: u64 = 0;
+        let quote_mint: MintAccountInfo = MintAccountInfo {
+            mint: Mint {
+                mint_authority: None.into(),
+                supply: 0,
+                decimals: 6,
+                is_initialized: true,
+                freeze_authority: None.into(),
+            },
+            info: &AccountInfo {
+                key: quote_mint,
+                lamports: Rc::new(RefCell::new(&mut quote_lamports)),
+                data: Rc::new(RefCell::new(&mut [])),
+                owner: &spl_token::id(),
+                rent_epoch: 0,
+                is_signer: false,
+                is_writable: false,
+                executable: false,
+            },
+        };
+        ModelRunner {
+            model: ModelBook::new(),
+            market: MarketValue {
+                fixed: MarketFixed::new_empty(&base_mint, &quote_mint, market_key),
+                dynamic: Vec::new(),
+            },
+            market_key: *market_key,
+            #[cfg(feature = "repro")]
+            recording_opt: None,
+        }
+    }
+
+    /// Runner that records every seat claim and operation, see `repro`.
+    #[cfg(feature = "repro")]
+    pub fn new_recording(market_key: &Pubkey, base_mint: &Pubkey, quote_mint: &Pubkey) -> Self {
+        ModelRunner {
+            recording_opt: Some(ReproCase::new(market_key, base_mint, quote_mint)),
+            ..Self::new(market_key, base_mint, quote_mint)
+        }
+    }
+
+    /// Everything applied so far, None unless created with `new_recording`.
+    #[cfg(feature = "repro")]
+    pub fn get_recording(&self) -> Option<&ReproCase> {
+        self.recording_opt.as_ref()
+    }
+
+    pub fn get_market_key(&self) -> &Pubkey {
+        &self.market_key
+    }
+
+    /// Makes sure there is room for one more seat or order plus the extra
+    /// block that matching may need.
+    fn ensure_free_blocks(&mut self) -> Result<()> {
+        while !self.market.has_two_free_blocks() {
+            self.market
+                .dynamic
+                .extend_from_slice(&[0; manifest::state::MARKET_BLOCK_SIZE]);
+            self.market.market_expand().map_err(Error::msg)?;
+        }
+        Ok(())
+    }
+
+    pub fn claim_seat(&mut self, trader: &Pubkey) -> Result<()> {
+        #[cfg(feature = "repro")]
+        if let Some(recording) = self.recording_opt.as_mut() {
+            recording
+                .steps
+                .push(CaseStep::ClaimSeat { trader: *trader });
+        }
+        self.ensure_free_blocks()?;
+        let model_result: Result<(), ProgramError> = self.model.claim_seat(trader);
+        let market_result: Result<(), ProgramError> = self.market.claim_seat(trader);
+        if model_result.is_ok() != market_result.is_ok() {
+            return Err(Error::msg(format!(
+                "Claim seat diverged model:{model_result:?} market:{market_result:?}"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Applies the operation to both books and checks that results, resting
+    /// orders and balances all agree.
+    pub fn apply(&mut self, operation: &ModelOperation) -> Result<()> {
+        #[cfg(feature = "repro")]
+        if let Some(recording) = self.recording_opt.as_mut() {
+            recording.steps.push(CaseStep::Operation(operation.clone()));
+        }
+        self.ensure_free_blocks()?;
+
+        let model_result: Result<Option<ModelPlaceResult>, ProgramError> =
+            self.model.apply(operation);
+
+        // A failed instruction does not land, so undo any partial writes.
+        let snapshot: MarketValue = self.market.clone();
+        let market_result: Result<Option<ModelPlaceResult>, ProgramError> =
+            self.apply_to_market(operation);
+        if market_result.is_err() {
+            self.market = snapshot;
+        }
+
+        match (&model_result, &market_result) {
+            (Ok(model), Ok(market)) if model == market => {}
+            (Err(_), Err(_)) => {}
+            _ => {
+                return Err(Error::msg(format!(
+                    "{operation:?} diverged model:{model_result:?} market:{market_result:?}"
+                )))
+            }
+        }
+        self.check_consistent()
+            .map_err(|e| Error::msg(format!("After {operation:?}: {e}")))
+    }
+
+    fn apply_to_market(
+        &mut self,
+        operation: &ModelOperation,
+    ) -> Result<Option<ModelPlaceResult>, ProgramError> {
+        match *operation {
+            ModelOperation::Deposit {
+                trader,
+                amount_atoms,
+                is_base,
+            } => {
+                let trader_index: DataIndex = self.market.get_trader_index(&trader);
+                self.market.deposit(trader_index, amount_atoms, is_base)?;
+                Ok(None)
+            }
+            ModelOperation::Withdraw {
+                trader,
+                amount_atoms,
+                is_base,
+            } => {
+                let trader_index: DataIndex = self.market.get_trader_index(&trader);
+                if trader_index == NIL {
+                    return Err(ManifestError::InvalidWithdrawAccounts.into());
+                }
+                self.market.withdraw(trader_index, amount_atoms, is_base)?;
+                Ok(None)
+            }
+            ModelOperation::PlaceOrder {
+                trader,
+                num_base_atoms,
+                price,
+                is_bid,
+                order_type,
+            } => {
+                let trader_index: DataIndex = self.market.get_trader_index(&trader);
+                let result = self.market.place_order(AddOrderToMarketArgs {
+                    market: self.market_key,
+                    trader_index,
+                    num_base_atoms,
+                    price,
+                    is_bid,
+                    last_valid_slot: NO_EXPIRATION_LAST_VALID_SLOT,
+                    order_type,
+                    global_trade_accounts_opts: &[None, None],
+                    current_slot: Some(0),
+                })?;
+                Ok(Some(ModelPlaceResult {
+                    order_sequence_number: result.order_sequence_number,
+                    base_atoms_traded: result.base_atoms_traded,
+                    quote_atoms_traded: result.quote_atoms_traded,
+                }))
+            }
+            ModelOperation::CancelOrder {
+                trader,
+                order_sequence_number,
+            } => {
+                let trader_index: DataIndex = self.market.get_trader_index(&trader);
+                self.market
+                    .cancel_order(trader_index, order_sequence_number, &[None, None])?;
+                Ok(None)
+            }
+        }
+    }
+
+    fn market_orders(&self, is_bid: bool) -> Vec<ModelOrder> {
+        let bookside = if is_bid {
+            self.market.get_bids()
+        } else {
+            self.market.get_asks()
+        };
+        bookside
+            .iter::<RestingOrder>()
+            .map(|(_, order)| ModelOrder {
+                trader: *self
+                    .market
+                    .get_trader_key_by_index(order.get_trader_index()),
+                num_base_atoms: order.get_num_base_atoms(),
+                price: order.get_price(),
+                sequence_number: order.get_sequence_number(),
+                is_bid: order.get_is_bid(),
+            })
+            .collect()
+    }
+
+    /// Compares both sides of the book and every trader balance.
+    pub fn check_consistent(&self) -> Result<()> {
+        let (market_bids, market_asks) = (self.market_orders(true), self.market_orders(false));
+        if market_bids != self.model.bids() {
+            return Err(Error::msg(format!(
+                "Bids diverged model:{:?} market:{market_bids:?}",
+                self.model.bids()
+            )));
+        }
+        if market_asks != self.model.asks() {
+            return Err(Error::msg(format!(
+                "Asks diverged model:{:?} market:{market_asks:?}",
+                self.model.asks()
+            )));
+        }
+        for trader in self.model.balances.keys() {
+            let model_balance = self.model.get_trader_balance(trader);
+            let market_balance = self.market.get_trader_balance(trader);
+            if model_balance != market_balance {
+                return Err(Error::msg(format!(
+                    "Balance of {trader} diverged model:{model_balance:?} market:{market_balance:?}"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "repro"))]
+mod test {
+    use super::*;
+    use crate::repro::CASE_FILE_EXTENSION;
+
+    /// Small deterministic generator so failures can be replayed by seed.
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+    }
+
+    fn random_operation(
+        rng: &mut XorShift,
+        traders: &[Pubkey],
+        max_sequence_number: u64,
+    ) -> ModelOperation {
+        let trader: Pubkey = traders[rng.below(traders.len() as u64) as usize];
+        match rng.below(10) {
+            0..=1 => ModelOperation::Deposit {
+                trader,
+                amount_atoms: rng.below(10_000),
+                is_base: rng.below(2) == 0,
+            },
+            2 => ModelOperation::Withdraw {
+                trader,
+                amount_atoms: rng.below(1_000),
+                is_base: rng.below(2) == 0,
+            },
+            3..=7 => ModelOperation::PlaceOrder {
+                trader,
+                num_base_atoms: BaseAtoms::new(1 + rng.below(200)),
+                // Small price grid so that orders frequently share a level and
+                // the fractional prices exercise rounding.
+                price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(
+                    90 + rng.below(20) as u32,
+                    -2,
+                )
+                .unwrap(),
+                is_bid: rng.below(2) == 0,
+                order_type: match rng.below(4) {
+                    0 => OrderType::ImmediateOrCancel,
+                    1 => OrderType::PostOnly,
+                    _ => OrderType::Limit,
+                },
+            },
+            _ => ModelOperation::CancelOrder {
+                trader,
+                order_sequence_number: rng.below(max_sequence_number + 1),
+            },
+        }
+    }
+
+    #[test]
+    fn test_model_matches_market() {
+        let traders: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        for seed in 1..=20_u64 {
+            let mut rng: XorShift = XorShift(seed);
+            let mut runner: ModelRunner = ModelRunner::new_recording(
+                &Pubkey::new_unique(),
+                &Pubkey::new_unique(),
+                &Pubkey::new_unique(),
+            );
+            for trader in traders.iter() {
+                runner.claim_seat(trader).unwrap();
+            }
+            for step in 0..300 {
+                let operation: ModelOperation =
+                    random_operation(&mut rng, &traders, runner.model.order_sequence_number);
+                if let Err(e) = runner.apply(&operation) {
+                    // Saved for `manifest-cli repro` to attach to the report.
+                    let path: std::path::PathBuf = std::env::temp_dir()
+                        .join(format!("model-seed-{seed}.{CASE_FILE_EXTENSION}"));
+                    runner.get_recording().unwrap().save(&path).unwrap();
+                    panic!("seed {seed} step {step}: {e}, case in {}", path.display());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,426 @@
+//! Alerts on market activity, delivered as webhooks. `MarketEvent`s come
+//! from fill logs and from market updates, and every `AlertRule` they match
+//! becomes a JSON payload signed with HMAC-SHA256 of a shared secret, so the
+//! receiver can tell the call came from the dispatcher. The HTTP client is
+//! whatever the application uses, behind `WebhookTransport`, and failed
+//! deliveries are retried with backoff.
+
+use anyhow::Result;
+use manifest::{
+    logs::FillLog,
+    quantities::{QuoteAtomsPerBaseAtom, WrapperU64},
+    state::MarketValue,
+};
+use solana_program::{
+    hash::{hashv, Hash},
+    pubkey::Pubkey,
+};
+use std::collections::{HashSet, VecDeque};
+
+pub const SIGNATURE_HEADER: &str = "X-Manifest-Signature";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MarketEvent {
+    Fill {
+        market: Pubkey,
+        slot: u64,
+        base_atoms: u64,
+        quote_atoms: u64,
+        taker_is_buy: bool,
+    },
+    /// Top of the book after an update. Prices are in quote atoms per base
+    /// atom.
+    Book {
+        market: Pubkey,
+        slot: u64,
+        best_bid_opt: Option<f64>,
+        best_ask_opt: Option<f64>,
+    },
+}
+
+impl MarketEvent {
+    pub fn from_fill_log(fill: &FillLog, slot: u64) -> Self {
+        MarketEvent::Fill {
+            market: fill.market,
+            slot,
+            base_atoms: fill.base_atoms.as_u64(),
+            quote_atoms: fill.quote_atoms.as_u64(),
+            taker_is_buy: fill.taker_is_buy.0 == 1,
+        }
+    }
+
+    /// Best prices of `market` at `slot`, without global orders beyond the
+    /// first one on each side since no global accounts are used.
+    pub fn from_market(market_key: &Pubkey, market: &MarketValue, slot: u64) -> Result<Self> {
+        let now_slot: u32 = slot as u32;
+        let best_bid_opt: Option<QuoteAtomsPerBaseAtom> =
+            market.best_bid(&[None, None], now_slot)?;
+        let best_ask_opt: Option<QuoteAtomsPerBaseAtom> =
+            market.best_ask(&[None, None], now_slot)?;
+        Ok(MarketEvent::Book {
+            market: *market_key,
+            slot,
+            best_bid_opt: best_bid_opt.map(|price| price.as_f64()),
+            best_ask_opt: best_ask_opt.map(|price| price.as_f64()),
+        })
+    }
+
+    pub fn get_market(&self) -> &Pubkey {
+        match self {
+            MarketEvent::Fill { market, .. } | MarketEvent::Book { market, .. } => market,
+        }
+    }
+
+    pub fn get_slot(&self) -> u64 {
+        match self {
+            MarketEvent::Fill { slot, .. } | MarketEvent::Book { slot, .. } => *slot,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlertRule {
+    /// A single fill of at least this many quote atoms.
+    LargeFill { min_quote_atoms: u64 },
+    /// Spread wider than this many basis points of the mid price. Reported
+    /// when it starts, not on every update while it lasts.
+    SpreadAbove { bps: f64 },
+    /// Neither side has an order. Reported when it starts, like spreads.
+    BookEmpty,
+}
+
+impl AlertRule {
+    fn get_name(&self) -> &'static str {
+        match self {
+            AlertRule::LargeFill { .. } => "large_fill",
+            AlertRule::SpreadAbove { .. } => "spread_above",
+            AlertRule::BookEmpty => "book_empty",
+        }
+    }
+
+    /// Detail for the payload when `event` matches, as a JSON value.
+    fn evaluate(&self, event: &MarketEvent) -> Option<String> {
+        match (self, event) {
+            (
+                AlertRule::LargeFill { min_quote_atoms },
+                MarketEvent::Fill {
+                    base_atoms,
+                    quote_atoms,
+                    taker_is_buy,
+                    ..
+                },
+            ) if quote_atoms >= min_quote_atoms => Some(format!(
+                "{{\"base_atoms\":{base_atoms},\"quote_atoms\":{quote_atoms},\"taker_is_buy\":{taker_is_buy}}}"
+            )),
+            (
+                AlertRule::SpreadAbove { bps },
+                MarketEvent::Book {
+                    best_bid_opt: Some(best_bid),
+                    best_ask_opt: Some(best_ask),
+                    ..
+                },
+            ) => {
+                let spread_bps: f64 =
+                    (best_ask - best_bid) / ((best_ask + best_bid) / 2.0) * 10_000.0;
+                (spread_bps > *bps).then(|| format!("{{\"spread_bps\":{spread_bps:.2}}}"))
+            }
+            (
+                AlertRule::BookEmpty,
+                MarketEvent::Book {
+                    best_bid_opt: None,
+                    best_ask_opt: None,
+                    ..
+                },
+            ) => Some("{}".to_string()),
+            _ => None,
+        }
+    }
+}
+
+/// Sends one webhook. An error, or a status outside 2xx, is retried.
+pub trait WebhookTransport {
+    fn post(&self, url: &str, headers: &[(&str, String)], body: &[u8]) -> Result<u16>;
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    pub secret: Vec<u8>,
+    /// Attempts per delivery, including the first.
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled for every one after.
+    pub retry_delay_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delivery {
+    pub body: String,
+    pub num_attempts: u32,
+    pub next_attempt_ms: u64,
+}
+
+pub struct WebhookDispatcher<T: WebhookTransport> {
+    transport: T,
+    config: WebhookConfig,
+    rules: Vec<AlertRule>,
+    // (market, rule index) of book rules that matched on the last update.
+    active: HashSet<(Pubkey, usize)>,
+    queue: VecDeque<Delivery>,
+    num_delivered: u64,
+    num_dropped: u64,
+}
+
+impl<T: WebhookTransport> WebhookDispatcher<T> {
+    pub fn new(transport: T, config: WebhookConfig) -> Self {
+        WebhookDispatcher {
+            transport,
+            config,
+            rules: Vec::new(),
+            active: HashSet::new(),
+            queue: VecDeque::new(),
+            num_delivered: 0,
+            num_dropped: 0,
+        }
+    }
+
+    pub fn add_rule(&mut self, rule: AlertRule) {
+        self.rules.push(rule);
+    }
+
+    /// Queue a delivery for every rule `event` matches. Returns how many
+    /// were queued.
+    pub fn handle(&mut self, event: &MarketEvent, now_ms: u64) -> usize {
+        let mut num_queued: usize = 0;
+        for (rule_index, rule) in self.rules.iter().enumerate() {
+            let detail_opt: Option<String> = rule.evaluate(event);
+            if let MarketEvent::Book { market, .. } = event {
+                let key: (Pubkey, usize) = (*market, rule_index);
+                let was_active: bool = if detail_opt.is_some() {
+                    !self.active.insert(key)
+                } else {
+                    self.active.remove(&key);
+                    false
+                };
+                if was_active {
+                    continue;
+                }
+            }
+            let Some(detail) = detail_opt else {
+                continue;
+            };
+            self.queue.push_back(Delivery {
+                body: format!(
+                    "{{\"rule\":\"{}\",\"market\":\"{}\",\"slot\":{},\"detail\":{detail}}}",
+                    rule.get_name(),
+                    event.get_market(),
+                    event.get_slot(),
+                ),
+                num_attempts: 0,
+                next_attempt_ms: now_ms,
+            });
+            num_queued += 1;
+        }
+        num_queued
+    }
+
+    /// Attempt every delivery that is due. Returns how many went through.
+    pub fn flush(&mut self, now_ms: u64) -> usize {
+        let mut num_sent: usize = 0;
+        let mut waiting: VecDeque<Delivery> = VecDeque::new();
+        while let Some(mut delivery) = self.queue.pop_front() {
+            if delivery.next_attempt_ms > now_ms {
+                waiting.push_back(delivery);
+                continue;
+            }
+            let headers: [(&str, String); 2] = [
+                ("Content-Type", "application/json".to_string()),
+                (
+                    SIGNATURE_HEADER,
+                    format!(
+                        "sha256={}",
+                        sign_payload(&self.config.secret, delivery.body.as_bytes())
+                    ),
+                ),
+            ];
+            delivery.num_attempts += 1;
+            let is_delivered: bool = matches!(
+                self.transport
+                    .post(&self.config.url, &headers, delivery.body.as_bytes()),
+                Ok(status) if (200..300).contains(&status)
+            );
+            if is_delivered {
+                self.num_delivered += 1;
+                num_sent += 1;
+            } else if delivery.num_attempts >= self.config.max_attempts {
+                self.num_dropped += 1;
+            } else {
+                delivery.next_attempt_ms = now_ms.saturating_add(
+                    self.config
+                        .retry_delay_ms
+                        .saturating_mul(1 << (delivery.num_attempts - 1).min(16)),
+                );
+                waiting.push_back(delivery);
+            }
+        }
+        self.queue = waiting;
+        num_sent
+    }
+
+    pub fn get_transport(&self) -> &T {
+        &self.transport
+    }
+
+    pub fn get_pending(&self) -> &VecDeque<Delivery> {
+        &self.queue
+    }
+
+    pub fn get_num_delivered(&self) -> u64 {
+        self.num_delivered
+    }
+
+    /// Deliveries given up on after `max_attempts`.
+    pub fn get_num_dropped(&self) -> u64 {
+        self.num_dropped
+    }
+}
+
+/// Hex HMAC-SHA256 of `body` under `secret`, the value of
+/// `SIGNATURE_HEADER` after its `sha256=` prefix.
+pub fn sign_payload(secret: &[u8], body: &[u8]) -> String {
+    const BLOCK_SIZE: usize = 64;
+    let mut key: [u8; BLOCK_SIZE] = [0; BLOCK_SIZE];
+    if secret.len() > BLOCK_SIZE {
+        key[..32].copy_from_slice(hashv(&[secret]).as_ref());
+    } else {
+        key[..secret.len()].copy_from_slice(secret);
+    }
+    let inner_pad: Vec<u8> = key.iter().map(|byte| byte ^ 0x36).collect();
+    let outer_pad: Vec<u8> = key.iter().map(|byte| byte ^ 0x5c).collect();
+    let inner: Hash = hashv(&[&inner_pad[..], body]);
+    hashv(&[&outer_pad[..], inner.as_ref()])
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+
+    #[derive(Default)]
+    struct MockTransport {
+        // Statuses to return, 200 once they run out.
+        statuses: RefCell<VecDeque<u16>>,
+        posts: RefCell<Vec<(String, String)>>,
+    }
+
+    impl WebhookTransport for MockTransport {
+        fn post(&self, _url: &str, headers: &[(&str, String)], body: &[u8]) -> Result<u16> {
+            let signature: String = headers
+                .iter()
+                .find(|(name, _)| *name == SIGNATURE_HEADER)
+                .unwrap()
+                .1
+                .clone();
+            self.posts
+                .borrow_mut()
+                .push((signature, String::from_utf8(body.to_vec()).unwrap()));
+            Ok(self.statuses.borrow_mut().pop_front().unwrap_or(200))
+        }
+    }
+
+    #[test]
+    fn test_sign_payload() {
+        // RFC 4231 test case 2.
+        assert_eq!(
+            sign_payload(b"Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn test_webhook_dispatcher() {
+        let market: Pubkey = Pubkey::new_unique();
+        let mut dispatcher: WebhookDispatcher<MockTransport> = WebhookDispatcher::new(
+            MockTransport::default(),
+            WebhookConfig {
+                url: "https://alerts.example.com/manifest".to_string(),
+                secret: b"secret".to_vec(),
+                max_attempts: 2,
+                retry_delay_ms: 1_000,
+            },
+        );
+        dispatcher.add_rule(AlertRule::LargeFill {
+            min_quote_atoms: 1_000,
+        });
+        dispatcher.add_rule(AlertRule::SpreadAbove { bps: 100.0 });
+        dispatcher.add_rule(AlertRule::BookEmpty);
+
+        let fill = |quote_atoms: u64| -> MarketEvent {
+            MarketEvent::Fill {
+                market,
+                slot: 5,
+                base_atoms: 10,
+                quote_atoms,
+                taker_is_buy: true,
+            }
+        };
+        let book = |best_bid_opt: Option<f64>, best_ask_opt: Option<f64>| -> MarketEvent {
+            MarketEvent::Book {
+                market,
+                slot: 6,
+                best_bid_opt,
+                best_ask_opt,
+            }
+        };
+        assert_eq!(dispatcher.handle(&fill(999), 0), 0);
+        assert_eq!(dispatcher.handle(&fill(1_000), 0), 1);
+        // 2% wide, then still wide, then tight and wide again.
+        assert_eq!(dispatcher.handle(&book(Some(0.99), Some(1.01)), 0), 1);
+        assert_eq!(dispatcher.handle(&book(Some(0.99), Some(1.01)), 0), 0);
+        assert_eq!(dispatcher.handle(&book(Some(0.999), Some(1.0)), 0), 0);
+        assert_eq!(dispatcher.handle(&book(Some(0.99), Some(1.01)), 0), 1);
+        assert_eq!(dispatcher.handle(&book(None, None), 0), 1);
+
+        // The receiver rejects the first two attempts.
+        dispatcher
+            .get_transport()
+            .statuses
+            .borrow_mut()
+            .extend([500, 503]);
+        assert_eq!(dispatcher.flush(0), 2);
+        assert_eq!(dispatcher.get_pending().len(), 2);
+        assert_eq!(dispatcher.flush(999), 0);
+        assert_eq!(dispatcher.flush(1_000), 2);
+        assert_eq!(dispatcher.get_num_delivered(), 4);
+
+        let posts: Vec<(String, String)> = dispatcher.get_transport().posts.borrow().clone();
+        assert_eq!(posts.len(), 6);
+        assert_eq!(
+            posts[0].1,
+            format!(
+                "{{\"rule\":\"large_fill\",\"market\":\"{market}\",\"slot\":5,\"detail\":{{\"base_atoms\":10,\"quote_atoms\":1000,\"taker_is_buy\":true}}}}"
+            )
+        );
+        for (signature, body) in posts.iter() {
+            assert_eq!(
+                *signature,
+                format!("sha256={}", sign_payload(b"secret", body.as_bytes()))
+            );
+        }
+
+        // Gives up after max_attempts.
+        dispatcher.handle(&fill(5_000), 2_000);
+        dispatcher
+            .get_transport()
+            .statuses
+            .borrow_mut()
+            .extend([500, 500]);
+        dispatcher.flush(2_000);
+        dispatcher.flush(3_000);
+        assert_eq!(dispatcher.get_num_dropped(), 1);
+        assert!(dispatcher.get_pending().is_empty());
+    }
+}
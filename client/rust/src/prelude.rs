@@ -0,0 +1,27 @@
+//! The types most integrations need, for a single glob import:
+//!
+//! ```ignore
+//! use manifest_jupiter::prelude::*;
+//! ```
+
+pub use crate::{
+    book::{BooksideSnapshot, OrderSource, OrderView},
+    facade::{Manifest, MarketHandle, Orderbook},
+    fees::{BpsFees, FeeSchedule, ZeroFees},
+    instruction_builders::{
+        batch_update, cancel_order, cancel_order_by_index, create_market, deposit, place_order,
+        swap, withdraw, BatchOrder, BatchUpdateArgs, CreateMarketArgs, CreateMarketBundle,
+        PlaceOrderArgs, SeatTransferArgs, SwapArgs, TokenAmount, WithdrawPolicy,
+    },
+    quoting::{approx_quote, exact_quote},
+    rpc::{refresh_amm, AccountFetcher, MockRpc},
+    source::{QuoteSource, RpcMarket},
+    sync::{Stamped, StampedMarket, StateStamp, StateStore},
+    ManifestMarket,
+};
+pub use jupiter_amm_interface::{Amm, Quote, QuoteParams, SwapMode};
+pub use manifest::{
+    program::batch_update::{CancelOrderParams, PlaceOrderParams},
+    quantities::{BaseAtoms, GlobalAtoms, QuoteAtoms, QuoteAtomsPerBaseAtom, WrapperU64},
+    state::{MarketValue, OrderType, NO_EXPIRATION_LAST_VALID_SLOT},
+};
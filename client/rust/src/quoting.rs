@@ -0,0 +1,1019 @@
+//! Swap quotes against a market snapshot.
+//!
+//! `exact_quote` uses the same integer math as the program and is what a
+//! swap would actually return. `approx_quote` walks the same orders with
+//! floating point, which is much cheaper for hot paths like route search but
+//! can be off by rounding. Never use the approximation to set slippage
+//! limits or anything else that is checked on chain. `quote_from_slices` is
+//! the exact quote on raw account data for callers that cannot allocate.
+//! `vwap_for_size` is the average price of an exact fill of a target size.
+//! `exact_out_quote` is what the swap instruction charges in exact out mode.
+//! `execute_swap` applies a whole swap instruction to a copy of the market.
+//! `QuoteCache` keeps exact quotes for as long as the book they were made on
+//! is unchanged, which on quiet markets is most of the time.
+
+use anyhow::{bail, Result};
+use hypertree::{get_helper, DataIndex, HyperTreeValueIteratorTrait, NIL};
+use manifest::{
+    program::SwapParams,
+    quantities::{BaseAtoms, GlobalAtoms, QuoteAtoms, QuoteAtomsPerBaseAtom, WrapperU64},
+    state::{
+        AddOrderToMarketArgs, AddOrderToMarketResult, BooksideReadOnly, GlobalFixed, GlobalRef,
+        MarketFixed, MarketRef, MarketValue, OrderFilter, OrderType, QuoteResult, RestingOrder,
+        GLOBAL_FIXED_DISCRIMINANT, MARKET_BLOCK_SIZE, MARKET_FIXED_DISCRIMINANT,
+        NO_EXPIRATION_LAST_VALID_SLOT,
+    },
+    validation::loaders::GlobalTradeAccounts,
+};
+use solana_program::{
+    hash::{Hash, Hasher},
+    pubkey::Pubkey,
+};
+use std::{collections::HashMap, mem::size_of};
+
+use crate::fees::FeeSchedule;
+
+/// Out atoms for swapping exactly `in_atoms`. Global orders are treated as
+/// if the global accounts were not passed, which stops the walk.
+pub fn exact_quote(
+    market: &MarketValue,
+    is_base_in: bool,
+    in_atoms: u64,
+    now_slot: u32,
+) -> Result<u64> {
+    let out_atoms: u64 = if is_base_in {
+        market
+            .impact_quote_atoms_with_slot(false, BaseAtoms::new(in_atoms), &[None, None], now_slot)?
+            .as_u64()
+    } else {
+        market
+            .impact_base_atoms_with_slot(true, QuoteAtoms::new(in_atoms), &[None, None], now_slot)?
+            .as_u64()
+    };
+    Ok(out_atoms)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExactOutQuote {
+    /// Atoms the swap takes from the trader.
+    pub in_atoms: u64,
+    /// Atoms the swap pays out. Selling base for quote can pay a little more
+    /// than asked for because the base is rounded up, and any swap pays less
+    /// when the book runs out.
+    pub out_atoms: u64,
+}
+
+/// In atoms for receiving exactly `out_atoms`, rounded the same way as the
+/// swap instruction with `is_exact_in` false, so the max in of a receive
+/// fixed swap can be set from it without slack for rounding. Global orders
+/// stop the walk as in `exact_quote`.
+pub fn exact_out_quote(
+    market: &MarketValue,
+    is_base_in: bool,
+    out_atoms: u64,
+    now_slot: u32,
+) -> Result<ExactOutQuote> {
+    // The instruction sizes the order with one walk and fills it with
+    // another. The walk that sizes it already matches what the fill gets,
+    // so one is enough here.
+    let quote_result: QuoteResult = if is_base_in {
+        market.impact_base_atoms_result(
+            false,
+            QuoteAtoms::new(out_atoms),
+            &[None, None],
+            now_slot,
+            &OrderFilter::default(),
+        )?
+    } else {
+        market.impact_quote_atoms_result(
+            true,
+            BaseAtoms::new(out_atoms),
+            &[None, None],
+            now_slot,
+            &OrderFilter::default(),
+        )?
+    };
+    Ok(ExactOutQuote {
+        in_atoms: quote_result.get_in_atoms(),
+        out_atoms: quote_result.get_out_atoms(),
+    })
+}
+
+/// Result of `execute_swap`. The in and out atoms are the net token amounts
+/// the swap instruction would transfer from and to the trader wallets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapOutcome {
+    pub order_sequence_number: u64,
+    pub base_atoms: BaseAtoms,
+    pub base_atoms_traded: BaseAtoms,
+    pub quote_atoms_traded: QuoteAtoms,
+    pub in_atoms: u64,
+    pub out_atoms: u64,
+}
+
+/// Apply the market side of a swap instruction to `market`, in the same
+/// steps as the program, without moving any tokens. A seat is claimed for
+/// `trader` when it has none and released after, and the market is expanded
+/// when the instruction would expand it. Fails where the instruction would,
+/// except for the wallet balance checks.
+pub fn execute_swap(
+    market: &mut MarketValue,
+    market_key: &Pubkey,
+    trader: &Pubkey,
+    params: &SwapParams,
+    global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2],
+    now_slot: u32,
+) -> Result<SwapOutcome> {
+    let &SwapParams {
+        in_atoms,
+        out_atoms,
+        is_base_in,
+        is_exact_in,
+    } = params;
+
+    let existing_seat_index: DataIndex = market.get_trader_index(trader);
+    if existing_seat_index == NIL {
+        market.claim_seat(trader)?;
+    }
+    if !market.has_free_block() {
+        market.dynamic.extend_from_slice(&[0; MARKET_BLOCK_SIZE]);
+        market.market_expand()?;
+    }
+
+    let trader_index: DataIndex = market.get_trader_index(trader);
+    let (initial_base_atoms, initial_quote_atoms) = market.get_trader_balance(trader);
+
+    // Virtual credit, same as the instruction.
+    market.deposit(trader_index, in_atoms, is_base_in)?;
+
+    let base_atoms: BaseAtoms = if is_exact_in {
+        if is_base_in {
+            BaseAtoms::new(in_atoms)
+        } else {
+            market.impact_base_atoms_with_slot(
+                true,
+                QuoteAtoms::new(in_atoms),
+                global_trade_accounts_opts,
+                now_slot,
+            )?
+        }
+    } else if is_base_in {
+        market.impact_base_atoms_with_slot(
+            false,
+            QuoteAtoms::new(out_atoms),
+            global_trade_accounts_opts,
+            now_slot,
+        )?
+    } else {
+        BaseAtoms::new(out_atoms)
+    };
+
+    let AddOrderToMarketResult {
+        order_sequence_number,
+        base_atoms_traded,
+        quote_atoms_traded,
+        ..
+    } = market.place_order(AddOrderToMarketArgs {
+        market: *market_key,
+        trader_index,
+        num_base_atoms: base_atoms,
+        price: if is_base_in {
+            QuoteAtomsPerBaseAtom::MIN
+        } else {
+            QuoteAtomsPerBaseAtom::MAX
+        },
+        is_bid: !is_base_in,
+        last_valid_slot: NO_EXPIRATION_LAST_VALID_SLOT,
+        order_type: OrderType::ImmediateOrCancel,
+        global_trade_accounts_opts,
+        current_slot: Some(now_slot),
+    })?;
+
+    if is_exact_in {
+        let out_atoms_traded: u64 = if is_base_in {
+            quote_atoms_traded.as_u64()
+        } else {
+            base_atoms_traded.as_u64()
+        };
+        if out_atoms > out_atoms_traded {
+            bail!(
+                "Insufficient out atoms returned. Minimum: {out_atoms} Actual: {out_atoms_traded}"
+            );
+        }
+    } else {
+        let in_atoms_traded: u64 = if is_base_in {
+            base_atoms_traded.as_u64()
+        } else {
+            quote_atoms_traded.as_u64()
+        };
+        if in_atoms < in_atoms_traded {
+            bail!("Excessive in atoms charged. Maximum: {in_atoms} Actual: {in_atoms_traded}");
+        }
+    }
+
+    let (end_base_atoms, end_quote_atoms) = market.get_trader_balance(trader);
+    let extra_base_atoms: BaseAtoms = end_base_atoms.checked_sub(initial_base_atoms)?;
+    let extra_quote_atoms: QuoteAtoms = end_quote_atoms.checked_sub(initial_quote_atoms)?;
+
+    // Repay the credit minus what was unused and give out everything else.
+    let (net_in_atoms, net_out_atoms) = if is_base_in {
+        (
+            BaseAtoms::new(in_atoms)
+                .checked_sub(extra_base_atoms)?
+                .as_u64(),
+            extra_quote_atoms.as_u64(),
+        )
+    } else {
+        (
+            QuoteAtoms::new(in_atoms)
+                .checked_sub(extra_quote_atoms)?
+                .as_u64(),
+            extra_base_atoms.as_u64(),
+        )
+    };
+
+    if existing_seat_index == NIL {
+        market.release_seat(trader)?;
+    } else {
+        market.withdraw(trader_index, extra_base_atoms.as_u64(), true)?;
+        market.withdraw(trader_index, extra_quote_atoms.as_u64(), false)?;
+    }
+    if !market.has_free_block() {
+        bail!("Cannot swap against a reverse order unless there is a free block");
+    }
+
+    Ok(SwapOutcome {
+        order_sequence_number,
+        base_atoms,
+        base_atoms_traded,
+        quote_atoms_traded,
+        in_atoms: net_in_atoms,
+        out_atoms: net_out_atoms,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeQuote {
+    /// Out atoms after the fee.
+    pub out_atoms: u64,
+    /// Fee in atoms of the output token.
+    pub fee_atoms: u64,
+}
+
+/// `exact_quote` with the taker fee of `fee_schedule` taken out of the
+/// output.
+pub fn exact_quote_with_fees(
+    market: &MarketValue,
+    is_base_in: bool,
+    in_atoms: u64,
+    now_slot: u32,
+    fee_schedule: &dyn FeeSchedule,
+) -> Result<FeeQuote> {
+    let gross_out_atoms: u64 = exact_quote(market, is_base_in, in_atoms, now_slot)?;
+    let fee_atoms: u64 = fee_schedule
+        .taker_fee_atoms(is_base_in, gross_out_atoms)
+        .min(gross_out_atoms);
+    Ok(FeeQuote {
+        out_atoms: gross_out_atoms - fee_atoms,
+        fee_atoms,
+    })
+}
+
+/// Base atoms a taker can trade against one side before the walk stops,
+/// which is at the first global order since no global accounts are used.
+fn takeable_base_atoms(market: &MarketValue, is_bids: bool, now_slot: u32) -> Result<BaseAtoms> {
+    let book: BooksideReadOnly = if is_bids {
+        market.get_bids()
+    } else {
+        market.get_asks()
+    };
+    let mut base_atoms: BaseAtoms = BaseAtoms::ZERO;
+    for (_, order) in book.iter::<RestingOrder>() {
+        if order.is_expired(now_slot) {
+            continue;
+        }
+        if order.get_order_type() == OrderType::Global {
+            break;
+        }
+        base_atoms = base_atoms.checked_add(order.get_num_base_atoms())?;
+    }
+    Ok(base_atoms)
+}
+
+/// Fill-or-kill version of `exact_quote`. Errors instead of quoting a partial
+/// amount when the book cannot absorb all of `in_atoms`.
+pub fn exact_quote_fok(
+    market: &MarketValue,
+    is_base_in: bool,
+    in_atoms: u64,
+    now_slot: u32,
+) -> Result<u64> {
+    let depth_base_atoms: BaseAtoms = takeable_base_atoms(market, is_base_in, now_slot)?;
+    let capacity_atoms: u64 = if is_base_in {
+        depth_base_atoms.as_u64()
+    } else {
+        // Cost of buying everything.
+        market
+            .impact_quote_atoms_with_slot(true, depth_base_atoms, &[None, None], now_slot)?
+            .as_u64()
+    };
+    if in_atoms > capacity_atoms {
+        bail!("Book can only absorb {capacity_atoms} of {in_atoms} in atoms");
+    }
+    exact_quote(market, is_base_in, in_atoms, now_slot)
+}
+
+/// Min out that gives a swap of `in_atoms` all or nothing semantics. The
+/// program lets an exact in swap fill partially as long as the min out is
+/// met, so this requires the full size quote less `slippage_bps`. A partial
+/// fill pays out less than that and fails, unless the book improved enough
+/// in between to cover the difference.
+pub fn fok_min_out_atoms(
+    market: &MarketValue,
+    is_base_in: bool,
+    in_atoms: u64,
+    now_slot: u32,
+    slippage_bps: u16,
+) -> Result<u64> {
+    let out_atoms: u64 = exact_quote_fok(market, is_base_in, in_atoms, now_slot)?;
+    Ok((out_atoms as u128 * 10_000_u128.saturating_sub(slippage_bps as u128) / 10_000) as u64)
+}
+
+/// What a taker order for a target size fills against the book.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VwapFill {
+    pub base_atoms: u64,
+    pub quote_atoms: u64,
+    /// Whether the book had enough to fill the whole target size.
+    pub is_complete: bool,
+}
+
+impl VwapFill {
+    /// Volume weighted average price in quote atoms per base atom. None when
+    /// nothing fills.
+    pub fn price(&self) -> Option<f64> {
+        if self.base_atoms == 0 {
+            return None;
+        }
+        Some(self.quote_atoms as f64 / self.base_atoms as f64)
+    }
+}
+
+/// Walks the book for a taker buying (`is_bid`) or selling base until
+/// `size_atoms` of base or quote, depending on `is_base_size`, are traded.
+/// Atoms match `impact_quote_atoms_with_slot` and
+/// `impact_base_atoms_with_slot`, so the price is what a swap of that size
+/// would actually get. Global orders stop the walk as in `exact_quote`.
+pub fn vwap_for_size(
+    market: &MarketValue,
+    is_bid: bool,
+    size_atoms: u64,
+    is_base_size: bool,
+    now_slot: u32,
+) -> Result<VwapFill> {
+    // Both legs and whether the book ran out come from a single walk.
+    let quote_result: QuoteResult = if is_base_size {
+        market.impact_quote_atoms_result(
+            is_bid,
+            BaseAtoms::new(size_atoms),
+            &[None, None],
+            now_slot,
+            &OrderFilter::default(),
+        )?
+    } else {
+        market.impact_base_atoms_result(
+            is_bid,
+            QuoteAtoms::new(size_atoms),
+            &[None, None],
+            now_slot,
+            &OrderFilter::default(),
+        )?
+    };
+    Ok(VwapFill {
+        base_atoms: quote_result.base_atoms.as_u64(),
+        quote_atoms: quote_result.quote_atoms.as_u64(),
+        is_complete: !quote_result.is_book_exhausted,
+    })
+}
+
+/// Floating point version of `exact_quote`. APPROXIMATE, see module docs.
+pub fn approx_quote(market: &MarketValue, is_base_in: bool, in_atoms: u64, now_slot: u32) -> f64 {
+    let book: BooksideReadOnly = if is_base_in {
+        market.get_bids()
+    } else {
+        market.get_asks()
+    };
+    let mut remaining: f64 = in_atoms as f64;
+    let mut out_atoms: f64 = 0.0;
+    for (_, order) in book.iter::<RestingOrder>() {
+        if order.is_expired(now_slot) {
+            continue;
+        }
+        if order.get_order_type() == OrderType::Global {
+            break;
+        }
+        let price: f64 = order.get_price().as_f64();
+        let base_atoms: f64 = order.get_num_base_atoms().as_u64() as f64;
+        if is_base_in {
+            let matched_base_atoms: f64 = base_atoms.min(remaining);
+            out_atoms += matched_base_atoms * price;
+            remaining -= matched_base_atoms;
+        } else {
+            let matched_base_atoms: f64 = base_atoms.min(remaining / price);
+            out_atoms += matched_base_atoms;
+            remaining -= matched_base_atoms * price;
+        }
+        if remaining <= 0.0 {
+            break;
+        }
+    }
+    out_atoms
+}
+
+fn market_ref_from_slice(market_bytes: &[u8]) -> Result<MarketRef> {
+    if market_bytes.len() < size_of::<MarketFixed>() {
+        bail!("Market data too short");
+    }
+    let (header_bytes, dynamic) = market_bytes.split_at(size_of::<MarketFixed>());
+    let fixed: &MarketFixed = get_helper::<MarketFixed>(header_bytes, 0_u32);
+    if fixed.discriminant != MARKET_FIXED_DISCRIMINANT {
+        bail!("Not a market account");
+    }
+    Ok(MarketRef { fixed, dynamic })
+}
+
+fn global_ref_from_slice(global_bytes: &[u8]) -> Result<GlobalRef> {
+    if global_bytes.len() < size_of::<GlobalFixed>() {
+        bail!("Global data too short");
+    }
+    let (header_bytes, dynamic) = global_bytes.split_at(size_of::<GlobalFixed>());
+    let fixed: &GlobalFixed = get_helper::<GlobalFixed>(header_bytes, 0_u32);
+    if fixed.discriminant != GLOBAL_FIXED_DISCRIMINANT {
+        bail!("Not a global account");
+    }
+    Ok(GlobalRef { fixed, dynamic })
+}
+
+/// Same result as the program for swapping exactly `in_atoms`, computed
+/// straight from account data without copying or building account infos, so
+/// it does not allocate. Meant for quoting inside a geyser plugin on the
+/// bytes it is handed. `global_bytes_opt` is the global account of the token
+/// that the matched resting orders pay with, the quote mint when base is in
+/// and the base mint otherwise. Without it the walk stops at the first
+/// global order, the same as a swap without global accounts.
+pub fn quote_from_slices(
+    market_bytes: &[u8],
+    global_bytes_opt: Option<&[u8]>,
+    is_base_in: bool,
+    in_atoms: u64,
+    now_slot: u32,
+) -> Result<u64> {
+    let market: MarketRef = market_ref_from_slice(market_bytes)?;
+    let global_opt: Option<GlobalRef> = match global_bytes_opt {
+        Some(global_bytes) => {
+            let global: GlobalRef = global_ref_from_slice(global_bytes)?;
+            let expected_mint: &Pubkey = if is_base_in {
+                market.fixed.get_quote_mint()
+            } else {
+                market.fixed.get_base_mint()
+            };
+            if global.fixed.get_mint() != expected_mint {
+                bail!("Global is for the wrong mint");
+            }
+            Some(global)
+        }
+        None => None,
+    };
+
+    // Taker side as the program sees it.
+    let is_bid: bool = !is_base_in;
+    let book: BooksideReadOnly = if is_bid {
+        market.get_asks()
+    } else {
+        market.get_bids()
+    };
+    let mut remaining_atoms: u64 = in_atoms;
+    let mut out_atoms: u64 = 0;
+    for (_, resting_order) in book.iter::<RestingOrder>() {
+        if resting_order.is_expired(now_slot) {
+            continue;
+        }
+        let matched_price: QuoteAtomsPerBaseAtom = resting_order.get_price();
+        let base_atoms_limit: BaseAtoms = if is_bid {
+            matched_price.checked_base_for_quote(QuoteAtoms::new(remaining_atoms), !is_bid)?
+        } else {
+            BaseAtoms::new(remaining_atoms)
+        };
+        let matched_base_atoms: BaseAtoms =
+            resting_order.get_num_base_atoms().min(base_atoms_limit);
+        let did_fully_match_resting_order: bool =
+            base_atoms_limit >= resting_order.get_num_base_atoms();
+        let matched_quote_atoms: QuoteAtoms = matched_price
+            .checked_quote_for_base(matched_base_atoms, is_bid != did_fully_match_resting_order)?;
+
+        if resting_order.get_order_type() == OrderType::Global {
+            let Some(global) = &global_opt else {
+                break;
+            };
+            // Each order is checked on its own, like the program does.
+            let desired_global_atoms: GlobalAtoms = GlobalAtoms::new(if is_bid {
+                matched_base_atoms.as_u64()
+            } else {
+                matched_quote_atoms.as_u64()
+            });
+            let trader: &Pubkey = market.get_trader_key_by_index(resting_order.get_trader_index());
+            if global.get_balance_atoms(trader) < desired_global_atoms {
+                continue;
+            }
+        }
+
+        let (out_matched, in_matched): (u64, u64) = if is_bid {
+            (matched_base_atoms.as_u64(), matched_quote_atoms.as_u64())
+        } else {
+            (matched_quote_atoms.as_u64(), matched_base_atoms.as_u64())
+        };
+        out_atoms = out_atoms.checked_add(out_matched).unwrap_or(u64::MAX);
+        if !did_fully_match_resting_order {
+            break;
+        }
+        remaining_atoms = remaining_atoms.saturating_sub(in_matched);
+        if is_bid && remaining_atoms == 0 {
+            break;
+        }
+    }
+    Ok(out_atoms)
+}
+
+/// Checksum of the resting orders on both sides. Books with the same
+/// checksum give the same quotes at the same slot, whatever else changed in
+/// the market account.
+pub fn book_checksum(market: &MarketValue) -> Hash {
+    let mut hasher: Hasher = Hasher::default();
+    for book in [market.get_bids(), market.get_asks()] {
+        for (_, order) in book.iter::<RestingOrder>() {
+            hasher.hash(bytemuck::bytes_of(order));
+        }
+    }
+    hasher.result()
+}
+
+/// First and last slot around `now_slot` during which the same orders are
+/// expired, so the book quotes the same throughout.
+fn expiry_window(market: &MarketValue, now_slot: u32) -> (u32, u32) {
+    let mut first_slot: u32 = 0;
+    let mut last_slot: u32 = u32::MAX;
+    for book in [market.get_bids(), market.get_asks()] {
+        for (_, order) in book.iter::<RestingOrder>() {
+            let last_valid_slot: u32 = order.get_last_valid_slot();
+            if last_valid_slot == NO_EXPIRATION_LAST_VALID_SLOT {
+                continue;
+            }
+            if order.is_expired(now_slot) {
+                first_slot = first_slot.max(last_valid_slot + 1);
+            } else {
+                last_slot = last_slot.min(last_valid_slot);
+            }
+        }
+    }
+    (first_slot, last_slot)
+}
+
+/// Exact quotes of one market, kept across updates that leave the book as it
+/// was. Updates are compared by `book_checksum`, so a market that only
+/// changed slots keeps its quotes. Quotes are also dropped when the slot
+/// moves past the expiration of an order.
+pub struct QuoteCache {
+    market: MarketValue,
+    checksum: Hash,
+    max_entries: usize,
+    window_opt: Option<(u32, u32)>,
+    quotes: HashMap<(bool, u64), u64>,
+    num_hits: u64,
+    num_misses: u64,
+}
+
+impl QuoteCache {
+    /// Once `max_entries` quotes are cached, the next miss starts over.
+    pub fn new(market: MarketValue, max_entries: usize) -> Self {
+        QuoteCache {
+            checksum: book_checksum(&market),
+            market,
+            max_entries,
+            window_opt: None,
+            quotes: HashMap::new(),
+            num_hits: 0,
+            num_misses: 0,
+        }
+    }
+
+    /// Replace the market. Returns whether the book changed, which is when
+    /// cached quotes are dropped.
+    pub fn update(&mut self, market: MarketValue) -> bool {
+        let checksum: Hash = book_checksum(&market);
+        self.market = market;
+        if checksum == self.checksum {
+            return false;
+        }
+        self.checksum = checksum;
+        self.window_opt = None;
+        self.quotes.clear();
+        true
+    }
+
+    /// Same as `exact_quote` on the current market.
+    pub fn exact_quote(&mut self, is_base_in: bool, in_atoms: u64, now_slot: u32) -> Result<u64> {
+        let is_in_window: bool = matches!(
+            self.window_opt,
+            Some((first_slot, last_slot)) if first_slot <= now_slot && now_slot <= last_slot
+        );
+        if !is_in_window {
+            self.window_opt = Some(expiry_window(&self.market, now_slot));
+            self.quotes.clear();
+        }
+        if let Some(out_atoms) = self.quotes.get(&(is_base_in, in_atoms)) {
+            self.num_hits += 1;
+            return Ok(*out_atoms);
+        }
+        self.num_misses += 1;
+        let out_atoms: u64 = exact_quote(&self.market, is_base_in, in_atoms, now_slot)?;
+        if self.quotes.len() >= self.max_entries {
+            self.quotes.clear();
+        }
+        self.quotes.insert((is_base_in, in_atoms), out_atoms);
+        Ok(out_atoms)
+    }
+
+    pub fn get_market(&self) -> &MarketValue {
+        &self.market
+    }
+
+    pub fn get_checksum(&self) -> Hash {
+        self.checksum
+    }
+
+    pub fn get_num_hits(&self) -> u64 {
+        self.num_hits
+    }
+
+    pub fn get_num_misses(&self) -> u64 {
+        self.num_misses
+    }
+}
+
+#[cfg(all(test, feature = "model"))]
+mod test {
+    use super::*;
+    use crate::{
+        fees::{BpsFees, ZeroFees},
+        model::{ModelOperation, ModelRunner},
+        snapshot::dynamic_account_data,
+    };
+
+    // Allowed distance between the approximate and exact quote.
+    const APPROX_QUOTE_BOUND_BPS: f64 = 1.0;
+
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn between(&mut self, low: u64, high: u64) -> u64 {
+            low + self.next() % (high - low)
+        }
+    }
+
+    #[test]
+    fn test_approx_quote_within_bound() {
+        for seed in 1..=20 {
+            let mut rng: XorShift = XorShift(seed * 0x9E37_79B9_7F4A_7C15);
+            let mut runner: ModelRunner = ModelRunner::new(
+                &Pubkey::new_unique(),
+                &Pubkey::new_unique(),
+                &Pubkey::new_unique(),
+            );
+            let traders: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+            for trader in traders.iter() {
+                runner.claim_seat(trader).unwrap();
+                for is_base in [true, false] {
+                    runner
+                        .apply(&ModelOperation::Deposit {
+                            trader: *trader,
+                            amount_atoms: 1_000_000_000_000,
+                            is_base,
+                        })
+                        .unwrap();
+                }
+            }
+            // Bids below 1 and asks above so nothing crosses.
+            for _ in 0..30 {
+                let is_bid: bool = rng.next() % 2 == 0;
+                let mantissa: u64 = if is_bid {
+                    rng.between(100, 1_000)
+                } else {
+                    rng.between(1_000, 10_000)
+                };
+                runner
+                    .apply(&ModelOperation::PlaceOrder {
+                        trader: traders[rng.between(0, 3) as usize],
+                        num_base_atoms: BaseAtoms::new(rng.between(1_000_000, 100_000_000)),
+                        price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(
+                            mantissa as u32,
+                            -3,
+                        )
+                        .unwrap(),
+                        is_bid,
+                        order_type: OrderType::Limit,
+                    })
+                    .unwrap();
+            }
+
+            let market_bytes: Vec<u8> =
+                dynamic_account_data(&runner.market.fixed, &runner.market.dynamic);
+            for _ in 0..20 {
+                let is_base_in: bool = rng.next() % 2 == 0;
+                let in_atoms: u64 = rng.between(1_000_000, 1_000_000_000);
+                let exact: f64 =
+                    exact_quote(&runner.market, is_base_in, in_atoms, 0).unwrap() as f64;
+                let approx: f64 = approx_quote(&runner.market, is_base_in, in_atoms, 0);
+                assert_eq!(
+                    quote_from_slices(&market_bytes, None, is_base_in, in_atoms, 0).unwrap(),
+                    exact as u64
+                );
+                assert!(
+                    (approx - exact).abs() <= exact * APPROX_QUOTE_BOUND_BPS / 10_000.0 + 1.0,
+                    "seed {seed} exact {exact} approx {approx}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_exact_quote_fok() {
+        let maker: Pubkey = Pubkey::new_unique();
+        let mut runner: ModelRunner = ModelRunner::new(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        );
+        runner.claim_seat(&maker).unwrap();
+        runner
+            .apply(&ModelOperation::Deposit {
+                trader: maker,
+                amount_atoms: 1_000,
+                is_base: true,
+            })
+            .unwrap();
+        // 100 base at 2.
+        runner
+            .apply(&ModelOperation::PlaceOrder {
+                trader: maker,
+                num_base_atoms: BaseAtoms::new(100),
+                price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(2, 0).unwrap(),
+                is_bid: false,
+                order_type: OrderType::Limit,
+            })
+            .unwrap();
+
+        assert_eq!(exact_quote_fok(&runner.market, false, 200, 0).unwrap(), 100);
+        assert!(exact_quote_fok(&runner.market, false, 201, 0).is_err());
+        assert_eq!(exact_quote(&runner.market, false, 201, 0).unwrap(), 100);
+        assert!(exact_quote_fok(&runner.market, true, 1, 0).is_err());
+        assert_eq!(
+            fok_min_out_atoms(&runner.market, false, 100, 0, 100).unwrap(),
+            49
+        );
+
+        let fee_quote = |fee_schedule: &dyn FeeSchedule| -> FeeQuote {
+            exact_quote_with_fees(&runner.market, false, 200, 0, fee_schedule).unwrap()
+        };
+        assert_eq!(
+            fee_quote(&ZeroFees),
+            FeeQuote {
+                out_atoms: 100,
+                fee_atoms: 0
+            }
+        );
+        assert_eq!(
+            fee_quote(&BpsFees {
+                taker_bps: 30,
+                maker_bps: 0
+            }),
+            FeeQuote {
+                out_atoms: 99,
+                fee_atoms: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_exact_out_quote_matches_swap() {
+        let market_key: Pubkey = Pubkey::new_unique();
+        for seed in 1..=10 {
+            let mut rng: XorShift = XorShift(seed * 0x9E37_79B9_7F4A_7C15);
+            let maker: Pubkey = Pubkey::new_unique();
+            let mut runner: ModelRunner =
+                ModelRunner::new(&market_key, &Pubkey::new_unique(), &Pubkey::new_unique());
+            runner.claim_seat(&maker).unwrap();
+            for is_base in [true, false] {
+                runner
+                    .apply(&ModelOperation::Deposit {
+                        trader: maker,
+                        amount_atoms: 1_000_000_000_000,
+                        is_base,
+                    })
+                    .unwrap();
+            }
+            // Prices with several digits so most fills round.
+            for _ in 0..20 {
+                let is_bid: bool = rng.next() % 2 == 0;
+                let mantissa: u64 = if is_bid {
+                    rng.between(100, 1_000)
+                } else {
+                    rng.between(1_000, 10_000)
+                };
+                runner
+                    .apply(&ModelOperation::PlaceOrder {
+                        trader: maker,
+                        num_base_atoms: BaseAtoms::new(rng.between(1_000, 1_000_000)),
+                        price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(
+                            mantissa as u32,
+                            -3,
+                        )
+                        .unwrap(),
+                        is_bid,
+                        order_type: OrderType::Limit,
+                    })
+                    .unwrap();
+            }
+            let mut market: MarketValue = runner.market.clone();
+            while !market.has_two_free_blocks() {
+                market.dynamic.extend_from_slice(&[0; MARKET_BLOCK_SIZE]);
+                market.market_expand().unwrap();
+            }
+
+            for _ in 0..20 {
+                let is_base_in: bool = rng.next() % 2 == 0;
+                let out_atoms: u64 = rng.between(1, 20_000_000);
+                let quote: ExactOutQuote =
+                    exact_out_quote(&market, is_base_in, out_atoms, 0).unwrap();
+                let outcome: SwapOutcome = execute_swap(
+                    &mut market.clone(),
+                    &market_key,
+                    &Pubkey::new_unique(),
+                    &SwapParams::new(1_000_000_000_000, out_atoms, is_base_in, false),
+                    &[None, None],
+                    0,
+                )
+                .unwrap();
+                assert_eq!(
+                    (quote.in_atoms, quote.out_atoms),
+                    (outcome.in_atoms, outcome.out_atoms),
+                    "seed {seed} is_base_in {is_base_in} out_atoms {out_atoms}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_vwap_for_size() {
+        let maker: Pubkey = Pubkey::new_unique();
+        let mut runner: ModelRunner = ModelRunner::new(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        );
+        runner.claim_seat(&maker).unwrap();
+        runner
+            .apply(&ModelOperation::Deposit {
+                trader: maker,
+                amount_atoms: 1_000,
+                is_base: true,
+            })
+            .unwrap();
+        // 100 base at 2 and 100 base at 4.
+        for mantissa in [2, 4] {
+            runner
+                .apply(&ModelOperation::PlaceOrder {
+                    trader: maker,
+                    num_base_atoms: BaseAtoms::new(100),
+                    price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(mantissa, 0)
+                        .unwrap(),
+                    is_bid: false,
+                    order_type: OrderType::Limit,
+                })
+                .unwrap();
+        }
+
+        let fill: VwapFill = vwap_for_size(&runner.market, true, 150, true, 0).unwrap();
+        assert_eq!(
+            fill,
+            VwapFill {
+                base_atoms: 150,
+                quote_atoms: 400,
+                is_complete: true,
+            }
+        );
+        assert_eq!(fill.price(), Some(400.0 / 150.0));
+
+        let fill: VwapFill = vwap_for_size(&runner.market, true, 300, false, 0).unwrap();
+        assert_eq!(
+            fill,
+            VwapFill {
+                base_atoms: 125,
+                quote_atoms: 300,
+                is_complete: true,
+            }
+        );
+
+        let fill: VwapFill = vwap_for_size(&runner.market, true, 300, true, 0).unwrap();
+        assert_eq!(
+            fill,
+            VwapFill {
+                base_atoms: 200,
+                quote_atoms: 600,
+                is_complete: false,
+            }
+        );
+        assert!(
+            !vwap_for_size(&runner.market, true, 1_000, false, 0)
+                .unwrap()
+                .is_complete
+        );
+
+        // No bids to sell into.
+        let fill: VwapFill = vwap_for_size(&runner.market, false, 10, true, 0).unwrap();
+        assert_eq!(fill.price(), None);
+        assert!(!fill.is_complete);
+    }
+
+    #[test]
+    fn test_quote_cache() {
+        let market_key: Pubkey = Pubkey::new_unique();
+        let maker: Pubkey = Pubkey::new_unique();
+        let mut runner: ModelRunner =
+            ModelRunner::new(&market_key, &Pubkey::new_unique(), &Pubkey::new_unique());
+        runner.claim_seat(&maker).unwrap();
+        runner
+            .apply(&ModelOperation::Deposit {
+                trader: maker,
+                amount_atoms: 1_000,
+                is_base: true,
+            })
+            .unwrap();
+        // 100 base at 2.
+        runner
+            .apply(&ModelOperation::PlaceOrder {
+                trader: maker,
+                num_base_atoms: BaseAtoms::new(100),
+                price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(2, 0).unwrap(),
+                is_bid: false,
+                order_type: OrderType::Limit,
+            })
+            .unwrap();
+
+        let mut cache: QuoteCache = QuoteCache::new(runner.market.clone(), 16);
+        assert_eq!(cache.exact_quote(false, 50, 10).unwrap(), 25);
+        assert_eq!(cache.exact_quote(false, 50, 20).unwrap(), 25);
+        assert_eq!((cache.get_num_hits(), cache.get_num_misses()), (1, 1));
+
+        // A deposit changes the account but not the book.
+        runner
+            .apply(&ModelOperation::Deposit {
+                trader: maker,
+                amount_atoms: 1_000,
+                is_base: false,
+            })
+            .unwrap();
+        assert!(!cache.update(runner.market.clone()));
+        assert_eq!(cache.exact_quote(false, 50, 30).unwrap(), 25);
+        assert_eq!(cache.get_num_hits(), 2);
+
+        // A cheaper ask that expires after slot 40.
+        let mut market: MarketValue = runner.market.clone();
+        market.dynamic.extend_from_slice(&[0; MARKET_BLOCK_SIZE]);
+        market.market_expand().unwrap();
+        market
+            .place_order(AddOrderToMarketArgs {
+                market: market_key,
+                trader_index: market.get_trader_index(&maker),
+                num_base_atoms: BaseAtoms::new(100),
+                price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(1, 0).unwrap(),
+                is_bid: false,
+                last_valid_slot: 40,
+                order_type: OrderType::Limit,
+                global_trade_accounts_opts: &[None, None],
+                current_slot: Some(30),
+            })
+            .unwrap();
+        assert!(cache.update(market));
+        assert_eq!(cache.exact_quote(false, 50, 40).unwrap(), 50);
+        assert_eq!(cache.get_num_misses(), 2);
+        assert_eq!(cache.exact_quote(false, 50, 41).unwrap(), 25);
+        assert_eq!(cache.get_num_misses(), 3);
+    }
+}
@@ -0,0 +1,357 @@
+//! Rebuilding a seat's balances from its history, for investigating balance
+//! disputes. The events need to go back to when the seat was claimed, since
+//! there is no checkpoint to start from.
+
+use anyhow::{bail, Result};
+use manifest::{
+    logs::{DepositLog, WithdrawLog},
+    quantities::{BaseAtoms, QuoteAtoms, QuoteAtomsPerBaseAtom, WrapperU64},
+    state::{MarketValue, OrderType},
+};
+use solana_program::pubkey::Pubkey;
+use std::collections::{BTreeMap, HashSet};
+
+use crate::analytics::OrderEvent;
+
+#[derive(Clone, Copy)]
+pub enum SeatEvent {
+    Deposit { slot: u64, log: DepositLog },
+    Withdraw { slot: u64, log: WithdrawLog },
+    Order(OrderEvent),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayedOrder {
+    pub price: QuoteAtomsPerBaseAtom,
+    pub remaining_base_atoms: BaseAtoms,
+    pub is_bid: bool,
+    pub order_type: OrderType,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RebuiltSeat {
+    pub base_withdrawable: BaseAtoms,
+    pub quote_withdrawable: QuoteAtoms,
+    /// Orders still resting after the last event, by sequence number.
+    pub open_orders: BTreeMap<u64, ReplayedOrder>,
+}
+
+/// Live balances minus the rebuilt ones. Zero on both when the account
+/// matches its history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeatDiscrepancy {
+    pub base_atoms: i128,
+    pub quote_atoms: i128,
+}
+
+impl SeatDiscrepancy {
+    pub fn is_zero(&self) -> bool {
+        self.base_atoms == 0 && self.quote_atoms == 0
+    }
+}
+
+impl RebuiltSeat {
+    fn credit(&mut self, is_base: bool, amount_atoms: u64) -> Result<()> {
+        if is_base {
+            self.base_withdrawable = self
+                .base_withdrawable
+                .checked_add(BaseAtoms::new(amount_atoms))?;
+        } else {
+            self.quote_withdrawable = self
+                .quote_withdrawable
+                .checked_add(QuoteAtoms::new(amount_atoms))?;
+        }
+        Ok(())
+    }
+
+    fn debit(&mut self, is_base: bool, amount_atoms: u64, event_index: usize) -> Result<()> {
+        let balance: u64 = if is_base {
+            self.base_withdrawable.as_u64()
+        } else {
+            self.quote_withdrawable.as_u64()
+        };
+        if amount_atoms > balance {
+            bail!(
+                "Event {event_index} takes {amount_atoms} atoms from a balance of {balance}, \
+                 the events are incomplete"
+            );
+        }
+        if is_base {
+            self.base_withdrawable = BaseAtoms::new(balance - amount_atoms);
+        } else {
+            self.quote_withdrawable = QuoteAtoms::new(balance - amount_atoms);
+        }
+        Ok(())
+    }
+
+    /// Atoms an order locks, or gives back when cancelled. Global orders are
+    /// backed by the global account instead.
+    fn locked_atoms(order: &ReplayedOrder) -> Result<u64> {
+        Ok(if order.order_type == OrderType::Global {
+            0
+        } else if order.is_bid {
+            order
+                .price
+                .checked_quote_for_base(order.remaining_base_atoms, true)?
+                .as_u64()
+        } else {
+            order.remaining_base_atoms.as_u64()
+        })
+    }
+
+    pub fn compare(&self, market: &MarketValue, trader: &Pubkey) -> SeatDiscrepancy {
+        let (base_withdrawable, quote_withdrawable) = market.get_trader_balance(trader);
+        SeatDiscrepancy {
+            base_atoms: base_withdrawable.as_u64() as i128
+                - self.base_withdrawable.as_u64() as i128,
+            quote_atoms: quote_withdrawable.as_u64() as i128
+                - self.quote_withdrawable.as_u64() as i128,
+        }
+    }
+}
+
+/// Withdrawable balances `trader` should have on the market after `events`,
+/// which are the market's events in order. Fills as a taker only count for
+/// orders the trader placed, since swaps do not leave funds on the seat.
+///
+/// Reverse orders are not supported because the orders they place when
+/// filled are not logged. Expired orders that the program removed while
+/// matching are not logged either, so their funds stay locked here.
+pub fn rebuild_seat(
+    trader: &Pubkey,
+    base_mint: &Pubkey,
+    events: &[SeatEvent],
+) -> Result<RebuiltSeat> {
+    let placed_sequence_numbers: HashSet<u64> = events
+        .iter()
+        .filter_map(|event| match event {
+            SeatEvent::Order(OrderEvent::Place { log, .. }) if log.trader == *trader => {
+                Some(log.order_sequence_number)
+            }
+            _ => None,
+        })
+        .collect();
+
+    let mut seat: RebuiltSeat = RebuiltSeat::default();
+    // Base atoms of each order that were taken before it rested.
+    let mut taken_base_atoms: BTreeMap<u64, BaseAtoms> = BTreeMap::new();
+    for (event_index, event) in events.iter().enumerate() {
+        match event {
+            SeatEvent::Deposit { log, .. } if log.trader == *trader => {
+                seat.credit(log.mint == *base_mint, log.amount_atoms)?;
+            }
+            SeatEvent::Withdraw { log, .. } if log.trader == *trader => {
+                seat.debit(log.mint == *base_mint, log.amount_atoms, event_index)?;
+            }
+            SeatEvent::Order(OrderEvent::Place { log, .. }) if log.trader == *trader => {
+                let taken: BaseAtoms = taken_base_atoms
+                    .remove(&log.order_sequence_number)
+                    .unwrap_or_default();
+                let order: ReplayedOrder = ReplayedOrder {
+                    price: log.price,
+                    remaining_base_atoms: log.base_atoms.checked_sub(taken)?,
+                    is_bid: log.is_bid.0 != 0,
+                    order_type: log.order_type,
+                };
+                if order.order_type == OrderType::ImmediateOrCancel
+                    || order.remaining_base_atoms == BaseAtoms::ZERO
+                {
+                    continue;
+                }
+                seat.debit(
+                    !order.is_bid,
+                    RebuiltSeat::locked_atoms(&order)?,
+                    event_index,
+                )?;
+                seat.open_orders.insert(log.order_sequence_number, order);
+            }
+            SeatEvent::Order(OrderEvent::Fill { log, .. }) => {
+                let taker_is_buy: bool = log.taker_is_buy.0 != 0;
+                if log.taker == *trader
+                    && placed_sequence_numbers.contains(&log.taker_sequence_number)
+                {
+                    seat.credit(
+                        taker_is_buy,
+                        if taker_is_buy {
+                            log.base_atoms.as_u64()
+                        } else {
+                            log.quote_atoms.as_u64()
+                        },
+                    )?;
+                    seat.debit(
+                        !taker_is_buy,
+                        if taker_is_buy {
+                            log.quote_atoms.as_u64()
+                        } else {
+                            log.base_atoms.as_u64()
+                        },
+                        event_index,
+                    )?;
+                    let taken: &mut BaseAtoms = taken_base_atoms
+                        .entry(log.taker_sequence_number)
+                        .or_default();
+                    *taken = taken.checked_add(log.base_atoms)?;
+                }
+                if log.maker == *trader {
+                    let Some(order) = seat.open_orders.get_mut(&log.maker_sequence_number) else {
+                        bail!(
+                            "Event {event_index} fills order {} that was not placed in the events",
+                            log.maker_sequence_number
+                        );
+                    };
+                    if matches!(
+                        order.order_type,
+                        OrderType::Reverse | OrderType::ReverseTight
+                    ) {
+                        bail!("Event {event_index} fills a reverse order, which is not supported");
+                    }
+                    order.remaining_base_atoms =
+                        order.remaining_base_atoms.checked_sub(log.base_atoms)?;
+                    let is_bid: bool = order.is_bid;
+                    if order.remaining_base_atoms == BaseAtoms::ZERO {
+                        seat.open_orders.remove(&log.maker_sequence_number);
+                    }
+                    // The maker gets the other side, paid for by the locked
+                    // funds or the global account.
+                    seat.credit(
+                        is_bid,
+                        if is_bid {
+                            log.base_atoms.as_u64()
+                        } else {
+                            log.quote_atoms.as_u64()
+                        },
+                    )?;
+                }
+            }
+            SeatEvent::Order(OrderEvent::Cancel { log, .. }) if log.trader == *trader => {
+                let Some(order) = seat.open_orders.remove(&log.order_sequence_number) else {
+                    bail!(
+                        "Event {event_index} cancels order {} that was not placed in the events",
+                        log.order_sequence_number
+                    );
+                };
+                seat.credit(!order.is_bid, RebuiltSeat::locked_atoms(&order)?)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(seat)
+}
+
+#[cfg(all(test, feature = "model"))]
+mod test {
+    use super::*;
+    use crate::{
+        decoder::CancelReason,
+        model::{ModelOperation, ModelRunner},
+    };
+    use manifest::logs::{CancelOrderLog, FillLog, PlaceOrderLog};
+
+    #[test]
+    fn test_rebuild_seat() {
+        let market_key: Pubkey = Pubkey::new_unique();
+        let base_mint: Pubkey = Pubkey::new_unique();
+        let quote_mint: Pubkey = Pubkey::new_unique();
+        let trader: Pubkey = Pubkey::new_unique();
+        let price: QuoteAtomsPerBaseAtom =
+            QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(2, 0).unwrap();
+
+        let deposit = |mint: Pubkey| SeatEvent::Deposit {
+            slot: 1,
+            log: DepositLog {
+                market: market_key,
+                trader,
+                mint,
+                amount_atoms: 1_000,
+            },
+        };
+        let mut events: Vec<SeatEvent> = vec![deposit(base_mint), deposit(quote_mint)];
+
+        let mut runner: ModelRunner = ModelRunner::new(&market_key, &base_mint, &quote_mint);
+        runner.claim_seat(&trader).unwrap();
+        for is_base in [true, false] {
+            runner
+                .apply(&ModelOperation::Deposit {
+                    trader,
+                    amount_atoms: 1_000,
+                    is_base,
+                })
+                .unwrap();
+        }
+        let seat: RebuiltSeat = rebuild_seat(&trader, &base_mint, &events).unwrap();
+        assert!(seat.compare(&runner.market, &trader).is_zero());
+
+        // Bid 100 at 2, 40 of it filled, then the rest cancelled.
+        events.push(SeatEvent::Order(OrderEvent::Place {
+            slot: 2,
+            log: PlaceOrderLog {
+                market: market_key,
+                trader,
+                price,
+                base_atoms: BaseAtoms::new(100),
+                order_sequence_number: 7,
+                order_index: 0,
+                last_valid_slot: 0,
+                order_type: OrderType::Limit,
+                is_bid: true.into(),
+                _padding: [0; 6],
+            },
+        }));
+        events.push(SeatEvent::Order(OrderEvent::Fill {
+            slot: 3,
+            log: FillLog {
+                market: market_key,
+                maker: trader,
+                taker: Pubkey::new_unique(),
+                base_mint,
+                quote_mint,
+                price,
+                base_atoms: BaseAtoms::new(40),
+                quote_atoms: QuoteAtoms::new(80),
+                maker_sequence_number: 7,
+                taker_sequence_number: 8,
+                taker_is_buy: false.into(),
+                is_maker_global: false.into(),
+                _padding: [0; 14],
+            },
+        }));
+        let seat: RebuiltSeat = rebuild_seat(&trader, &base_mint, &events).unwrap();
+        assert_eq!(seat.base_withdrawable, BaseAtoms::new(1_040));
+        assert_eq!(seat.quote_withdrawable, QuoteAtoms::new(800));
+        assert_eq!(
+            seat.open_orders[&7].remaining_base_atoms,
+            BaseAtoms::new(60)
+        );
+
+        events.push(SeatEvent::Order(OrderEvent::Cancel {
+            slot: 4,
+            log: CancelOrderLog {
+                market: market_key,
+                trader,
+                order_sequence_number: 7,
+            },
+            reason: CancelReason::User,
+        }));
+        let seat: RebuiltSeat = rebuild_seat(&trader, &base_mint, &events).unwrap();
+        assert_eq!(seat.quote_withdrawable, QuoteAtoms::new(920));
+        assert!(seat.open_orders.is_empty());
+        assert_eq!(
+            seat.compare(&runner.market, &trader),
+            SeatDiscrepancy {
+                base_atoms: -40,
+                quote_atoms: 80,
+            }
+        );
+
+        events.push(SeatEvent::Withdraw {
+            slot: 5,
+            log: WithdrawLog {
+                market: market_key,
+                trader,
+                mint: quote_mint,
+                amount_atoms: 921,
+            },
+        });
+        assert!(rebuild_seat(&trader, &base_mint, &events).is_err());
+    }
+}
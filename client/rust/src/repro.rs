@@ -0,0 +1,366 @@
+//! Reproducible cases for bug reports. A `ModelRunner` created with
+//! `new_recording` keeps every seat claim and operation it is given, and the
+//! resulting `ReproCase` saves to a compact `.mfst-case` file that
+//! `manifest-cli repro <file>` replays step by step. Runners always start from
+//! an empty market, so the keys of the market and its mints are the whole
+//! initial state.
+
+use anyhow::{bail, Context, Error, Result};
+use manifest::{
+    quantities::{BaseAtoms, QuoteAtomsPerBaseAtom, WrapperU64},
+    state::OrderType,
+};
+use solana_program::pubkey::Pubkey;
+use std::{mem::size_of, path::Path};
+
+use crate::model::{ModelOperation, ModelRunner};
+
+pub const CASE_FILE_EXTENSION: &str = "mfst-case";
+
+const CASE_MAGIC: &[u8; 8] = b"MFSTCASE";
+const CASE_VERSION: u8 = 1;
+
+const CLAIM_SEAT_TAG: u8 = 0;
+const DEPOSIT_TAG: u8 = 1;
+const WITHDRAW_TAG: u8 = 2;
+const PLACE_ORDER_TAG: u8 = 3;
+const CANCEL_ORDER_TAG: u8 = 4;
+
+#[derive(Debug, Clone)]
+pub enum CaseStep {
+    ClaimSeat { trader: Pubkey },
+    Operation(ModelOperation),
+}
+
+impl CaseStep {
+    fn get_trader(&self) -> &Pubkey {
+        match self {
+            CaseStep::ClaimSeat { trader }
+            | CaseStep::Operation(ModelOperation::Deposit { trader, .. })
+            | CaseStep::Operation(ModelOperation::Withdraw { trader, .. })
+            | CaseStep::Operation(ModelOperation::PlaceOrder { trader, .. })
+            | CaseStep::Operation(ModelOperation::CancelOrder { trader, .. }) => trader,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReproCase {
+    pub market_key: Pubkey,
+    pub base_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    /// Every step given to the runner, including the ones that failed.
+    pub steps: Vec<CaseStep>,
+}
+
+impl ReproCase {
+    pub fn new(market_key: &Pubkey, base_mint: &Pubkey, quote_mint: &Pubkey) -> Self {
+        ReproCase {
+            market_key: *market_key,
+            base_mint: *base_mint,
+            quote_mint: *quote_mint,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Applies the steps to a fresh runner and stops at the first divergence
+    /// between the model and the market, naming the step it happened at.
+    pub fn replay(&self) -> Result<ModelRunner> {
+        let mut runner: ModelRunner =
+            ModelRunner::new(&self.market_key, &self.base_mint, &self.quote_mint);
+        for (step_index, step) in self.steps.iter().enumerate() {
+            match step {
+                CaseStep::ClaimSeat { trader } => runner.claim_seat(trader),
+                CaseStep::Operation(operation) => runner.apply(operation),
+            }
+            .with_context(|| format!("Step {step_index} of {}", self.steps.len()))?;
+        }
+        Ok(runner)
+    }
+
+    /// Traders are written once and referenced by index from the steps,
+    /// which keeps a case of a few thousand steps at a few tens of kilobytes.
+    ///
+    /// Layout is the magic and version, the market and mint keys, the trader
+    /// table with a u16 count, then a u32 step count and the steps as a tag
+    /// followed by their fields in little endian.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let mut traders: Vec<Pubkey> = Vec::new();
+        for step in self.steps.iter() {
+            if !traders.contains(step.get_trader()) {
+                traders.push(*step.get_trader());
+            }
+        }
+        if traders.len() > u16::MAX as usize {
+            bail!("Cases are limited to {} traders", u16::MAX);
+        }
+        let trader_index = |trader: &Pubkey| -> [u8; 2] {
+            (traders.iter().position(|key| key == trader).unwrap() as u16).to_le_bytes()
+        };
+
+        let mut bytes: Vec<u8> = Vec::new();
+        bytes.extend_from_slice(CASE_MAGIC);
+        bytes.push(CASE_VERSION);
+        for key in [&self.market_key, &self.base_mint, &self.quote_mint] {
+            bytes.extend_from_slice(key.as_ref());
+        }
+        bytes.extend_from_slice(&(traders.len() as u16).to_le_bytes());
+        for trader in traders.iter() {
+            bytes.extend_from_slice(trader.as_ref());
+        }
+        bytes.extend_from_slice(&(self.steps.len() as u32).to_le_bytes());
+        for step in self.steps.iter() {
+            match step {
+                CaseStep::ClaimSeat { trader } => {
+                    bytes.push(CLAIM_SEAT_TAG);
+                    bytes.extend_from_slice(&trader_index(trader));
+                }
+                CaseStep::Operation(ModelOperation::Deposit {
+                    trader,
+                    amount_atoms,
+                    is_base,
+                }) => {
+                    bytes.push(DEPOSIT_TAG);
+                    bytes.extend_from_slice(&trader_index(trader));
+                    bytes.extend_from_slice(&amount_atoms.to_le_bytes());
+                    bytes.push(*is_base as u8);
+                }
+                CaseStep::Operation(ModelOperation::Withdraw {
+                    trader,
+                    amount_atoms,
+                    is_base,
+                }) => {
+                    bytes.push(WITHDRAW_TAG);
+                    bytes.extend_from_slice(&trader_index(trader));
+                    bytes.extend_from_slice(&amount_atoms.to_le_bytes());
+                    bytes.push(*is_base as u8);
+                }
+                CaseStep::Operation(ModelOperation::PlaceOrder {
+                    trader,
+                    num_base_atoms,
+                    price,
+                    is_bid,
+                    order_type,
+                }) => {
+                    bytes.push(PLACE_ORDER_TAG);
+                    bytes.extend_from_slice(&trader_index(trader));
+                    bytes.extend_from_slice(&num_base_atoms.as_u64().to_le_bytes());
+                    bytes.extend_from_slice(bytemuck::bytes_of(price));
+                    bytes.push(*is_bid as u8);
+                    bytes.push((*order_type).into());
+                }
+                CaseStep::Operation(ModelOperation::CancelOrder {
+                    trader,
+                    order_sequence_number,
+                }) => {
+                    bytes.push(CANCEL_ORDER_TAG);
+                    bytes.extend_from_slice(&trader_index(trader));
+                    bytes.extend_from_slice(&order_sequence_number.to_le_bytes());
+                }
+            }
+        }
+        Ok(bytes)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut reader: CaseReader = CaseReader { bytes, offset: 0 };
+        if reader.take(CASE_MAGIC.len())? != CASE_MAGIC {
+            bail!("Not a Manifest case file");
+        }
+        let version: u8 = reader.read_u8()?;
+        if version != CASE_VERSION {
+            bail!("Unsupported case version {version}");
+        }
+        let mut case: ReproCase = ReproCase::new(
+            &reader.read_pubkey()?,
+            &reader.read_pubkey()?,
+            &reader.read_pubkey()?,
+        );
+        let num_traders: usize = reader.read_u16()? as usize;
+        let traders: Vec<Pubkey> = (0..num_traders)
+            .map(|_| reader.read_pubkey())
+            .collect::<Result<Vec<Pubkey>>>()?;
+        let num_steps: usize = reader.read_u32()? as usize;
+        for _ in 0..num_steps {
+            let tag: u8 = reader.read_u8()?;
+            let trader: Pubkey = *traders
+                .get(reader.read_u16()? as usize)
+                .ok_or_else(|| Error::msg("Trader index out of range"))?;
+            let step: CaseStep = match tag {
+                CLAIM_SEAT_TAG => CaseStep::ClaimSeat { trader },
+                DEPOSIT_TAG | WITHDRAW_TAG => {
+                    let amount_atoms: u64 = reader.read_u64()?;
+                    let is_base: bool = reader.read_bool()?;
+                    CaseStep::Operation(if tag == DEPOSIT_TAG {
+                        ModelOperation::Deposit {
+                            trader,
+                            amount_atoms,
+                            is_base,
+                        }
+                    } else {
+                        ModelOperation::Withdraw {
+                            trader,
+                            amount_atoms,
+                            is_base,
+                        }
+                    })
+                }
+                PLACE_ORDER_TAG => {
+                    let num_base_atoms: BaseAtoms = BaseAtoms::new(reader.read_u64()?);
+                    let price: QuoteAtomsPerBaseAtom = bytemuck::pod_read_unaligned(
+                        reader.take(size_of::<QuoteAtomsPerBaseAtom>())?,
+                    );
+                    let is_bid: bool = reader.read_bool()?;
+                    let order_type: OrderType = OrderType::try_from(reader.read_u8()?)
+                        .map_err(|e| Error::msg(format!("Invalid order type {}", e.number)))?;
+                    CaseStep::Operation(ModelOperation::PlaceOrder {
+                        trader,
+                        num_base_atoms,
+                        price,
+                        is_bid,
+                        order_type,
+                    })
+                }
+                CANCEL_ORDER_TAG => CaseStep::Operation(ModelOperation::CancelOrder {
+                    trader,
+                    order_sequence_number: reader.read_u64()?,
+                }),
+                _ => bail!("Invalid step tag {tag}"),
+            };
+            case.steps.push(step);
+        }
+        if reader.offset != bytes.len() {
+            bail!("{} trailing bytes", bytes.len() - reader.offset);
+        }
+        Ok(case)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, self.encode()?)?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::decode(&std::fs::read(path)?)
+    }
+}
+
+struct CaseReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> CaseReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let taken: &[u8] = self
+            .bytes
+            .get(self.offset..self.offset + len)
+            .ok_or_else(|| Error::msg("Truncated case"))?;
+        self.offset += len;
+        Ok(taken)
+    }
+
+    fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn read_bool(&mut self) -> Result<bool> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            byte => bail!("Invalid bool {byte}"),
+        }
+    }
+
+    fn read_u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn read_u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn read_pubkey(&mut self) -> Result<Pubkey> {
+        Ok(Pubkey::try_from(self.take(32)?)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_repro_case() {
+        let maker: Pubkey = Pubkey::new_unique();
+        let taker: Pubkey = Pubkey::new_unique();
+        let mut runner: ModelRunner = ModelRunner::new_recording(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        );
+        for trader in [maker, taker] {
+            runner.claim_seat(&trader).unwrap();
+            for is_base in [true, false] {
+                runner
+                    .apply(&ModelOperation::Deposit {
+                        trader,
+                        amount_atoms: 10_000,
+                        is_base,
+                    })
+                    .unwrap();
+            }
+        }
+        for (trader, is_bid, order_type) in [
+            (maker, false, OrderType::Limit),
+            (maker, false, OrderType::PostOnly),
+            (taker, true, OrderType::ImmediateOrCancel),
+        ] {
+            runner
+                .apply(&ModelOperation::PlaceOrder {
+                    trader,
+                    num_base_atoms: BaseAtoms::new(100),
+                    price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(95, -2).unwrap(),
+                    is_bid,
+                    order_type,
+                })
+                .unwrap();
+        }
+        // Failed steps are kept too.
+        runner
+            .apply(&ModelOperation::Withdraw {
+                trader: taker,
+                amount_atoms: 1_000_000,
+                is_base: true,
+            })
+            .unwrap();
+        runner
+            .apply(&ModelOperation::CancelOrder {
+                trader: maker,
+                order_sequence_number: 1,
+            })
+            .unwrap();
+
+        let case: &ReproCase = runner.get_recording().unwrap();
+        assert_eq!(case.steps.len(), 11);
+        let bytes: Vec<u8> = case.encode().unwrap();
+        let decoded: ReproCase = ReproCase::decode(&bytes).unwrap();
+        assert_eq!(decoded.encode().unwrap(), bytes);
+        assert_eq!(decoded.market_key, *runner.get_market_key());
+
+        let replayed: ModelRunner = decoded.replay().unwrap();
+        assert_eq!(replayed.model.asks(), runner.model.asks());
+        assert_eq!(replayed.model.bids(), runner.model.bids());
+        assert_eq!(
+            replayed.model.get_trader_balance(&taker),
+            runner.model.get_trader_balance(&taker)
+        );
+
+        assert!(ReproCase::decode(&bytes[..bytes.len() - 1]).is_err());
+        assert!(ReproCase::decode(&[bytes.as_slice(), &[0]].concat()).is_err());
+        assert!(ReproCase::decode(b"NOTACASE").is_err());
+    }
+}
@@ -0,0 +1,296 @@
+//! Account access for client flows. Implement `AccountFetcher` for whichever
+//! RPC client the application uses. `MockRpc` serves accounts from memory so
+//! the same flows run in tests without a validator. `QuoteSourcePolicy` falls
+//! back to fetching when a subscription stops keeping up.
+
+use anyhow::{anyhow, Result};
+use jupiter_amm_interface::{AccountMap, Amm, Quote, QuoteParams};
+use manifest::state::{GlobalFixed, GlobalValue, MarketFixed, MarketValue};
+use solana_sdk::{account::Account, pubkey::Pubkey, rent::Rent};
+use std::collections::HashMap;
+
+#[cfg(feature = "model")]
+use crate::model::ModelRunner;
+use crate::{
+    snapshot::dynamic_account_data,
+    sync::{QuoteGuard, Stamped, StateStamp},
+    ManifestMarket,
+};
+
+pub trait AccountFetcher {
+    fn get_slot(&self) -> Result<u64>;
+
+    /// Same length and order as `keys`, None for accounts that do not exist.
+    fn get_multiple_accounts(&self, keys: &[Pubkey]) -> Result<Vec<Option<Account>>>;
+
+    fn get_account(&self, key: &Pubkey) -> Result<Account> {
+        self.get_multiple_accounts(&[*key])?
+            .pop()
+            .flatten()
+            .ok_or_else(|| anyhow!("Account {key} not found"))
+    }
+}
+
+/// Accounts that do not exist are left out of the map, the same as a real RPC
+/// response.
+pub(crate) fn fetch_account_map<A: Amm, F: AccountFetcher>(
+    amm: &A,
+    fetcher: &F,
+) -> Result<AccountMap> {
+    let keys: Vec<Pubkey> = amm.get_accounts_to_update();
+    let accounts: Vec<Option<Account>> = fetcher.get_multiple_accounts(&keys)?;
+    Ok(keys
+        .into_iter()
+        .zip(accounts)
+        .filter_map(|(key, account)| Some((key, account?)))
+        .collect())
+}
+
+/// Fetch everything the amm asks for and update it.
+pub fn refresh_amm<A: Amm, F: AccountFetcher>(amm: &mut A, fetcher: &F) -> Result<()> {
+    let account_map: AccountMap = fetch_account_map(amm, fetcher)?;
+    amm.update(&account_map)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuoteSource {
+    /// The copy kept up to date by a subscription.
+    Subscription,
+    /// Accounts fetched for the quote because the subscription lagged.
+    Rpc,
+}
+
+#[derive(Debug, Clone)]
+pub struct SourcedQuote {
+    pub source: QuoteSource,
+    pub quote: Stamped<Quote>,
+}
+
+/// Quotes from the subscribed copy of a market while it keeps up, and from a
+/// fresh fetch once it falls more than `max_lag_slots` behind. Counts which
+/// source served each quote so a lagging subscription shows up in metrics.
+#[derive(Debug, Clone, Default)]
+pub struct QuoteSourcePolicy {
+    max_lag_slots: u64,
+    num_subscription_quotes: u64,
+    num_rpc_quotes: u64,
+}
+
+impl QuoteSourcePolicy {
+    pub fn new(max_lag_slots: u64) -> Self {
+        QuoteSourcePolicy {
+            max_lag_slots,
+            ..Self::default()
+        }
+    }
+
+    /// `market` is the subscribed copy and needs to be updated with
+    /// `update_with_stamp` for its lag to be known. The fetched state is
+    /// stamped with the slot the fetcher reports and is not written back.
+    pub fn quote<F: AccountFetcher>(
+        &mut self,
+        market: &ManifestMarket,
+        fetcher: &F,
+        quote_params: &QuoteParams,
+        current_slot: u64,
+    ) -> Result<SourcedQuote> {
+        if !QuoteGuard::is_stale(market.get_stamp(), current_slot, self.max_lag_slots) {
+            self.num_subscription_quotes += 1;
+            return Ok(SourcedQuote {
+                source: QuoteSource::Subscription,
+                quote: market.quote_with_stamp(quote_params)?,
+            });
+        }
+
+        let stamp: StateStamp = StateStamp {
+            slot: fetcher.get_slot()?,
+            write_version: 0,
+        };
+        let mut fetched: ManifestMarket = market.clone();
+        fetched.update_with_stamp(&fetch_account_map(market, fetcher)?, stamp)?;
+        self.num_rpc_quotes += 1;
+        Ok(SourcedQuote {
+            source: QuoteSource::Rpc,
+            quote: fetched.quote_with_stamp(quote_params)?,
+        })
+    }
+
+    pub fn get_num_quotes(&self, source: QuoteSource) -> u64 {
+        match source {
+            QuoteSource::Subscription => self.num_subscription_quotes,
+            QuoteSource::Rpc => self.num_rpc_quotes,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MockRpc {
+    accounts: HashMap<Pubkey, Account>,
+    slot: u64,
+}
+
+impl MockRpc {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_slot(&mut self, slot: u64) {
+        self.slot = slot;
+    }
+
+    pub fn set_account(&mut self, key: &Pubkey, account: Account) {
+        self.accounts.insert(*key, account);
+    }
+
+    pub fn remove_account(&mut self, key: &Pubkey) -> Option<Account> {
+        self.accounts.remove(key)
+    }
+
+    /// Rent exempt account owned by `owner`.
+    pub fn set_data(&mut self, key: &Pubkey, owner: &Pubkey, data: Vec<u8>) {
+        self.set_account(
+            key,
+            Account {
+                lamports: Rent::default().minimum_balance(data.len()),
+                data,
+                owner: *owner,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+    }
+
+    pub fn set_market(&mut self, key: &Pubkey, market: &MarketValue) {
+        self.set_data(
+            key,
+            &manifest::id(),
+            dynamic_account_data::<MarketFixed>(&market.fixed, &market.dynamic),
+        );
+    }
+
+    pub fn set_global(&mut self, key: &Pubkey, global: &GlobalValue) {
+        self.set_data(
+            key,
+            &manifest::id(),
+            dynamic_account_data::<GlobalFixed>(&global.fixed, &global.dynamic),
+        );
+    }
+
+    /// Only the owner of a mint is read by the client, so the data is empty.
+    pub fn set_mint(&mut self, key: &Pubkey, token_program: &Pubkey) {
+        self.set_data(key, token_program, Vec::new());
+    }
+
+    /// Serve the market of a model runner along with its mints as spl token
+    /// mints. Call again after applying operations to pick up the changes.
+    #[cfg(feature = "model")]
+    pub fn set_model(&mut self, runner: &ModelRunner) {
+        self.set_market(runner.get_market_key(), &runner.market);
+        self.set_mint(runner.market.get_base_mint(), &spl_token::id());
+        self.set_mint(runner.market.get_quote_mint(), &spl_token::id());
+    }
+}
+
+impl AccountFetcher for MockRpc {
+    fn get_slot(&self) -> Result<u64> {
+        Ok(self.slot)
+    }
+
+    fn get_multiple_accounts(&self, keys: &[Pubkey]) -> Result<Vec<Option<Account>>> {
+        Ok(keys
+            .iter()
+            .map(|key| self.accounts.get(key).cloned())
+            .collect())
+    }
+}
+
+#[cfg(all(test, feature = "model"))]
+mod test {
+    use super::*;
+    use crate::{model::ModelOperation, ManifestMarket};
+    use jupiter_amm_interface::{AmmContext, ClockRef, KeyedAccount, QuoteParams, SwapMode};
+    use manifest::{
+        quantities::{BaseAtoms, QuoteAtomsPerBaseAtom},
+        state::{OrderType, MARKET_BLOCK_SIZE},
+    };
+
+    #[test]
+    fn test_mock_rpc_quote() {
+        let market_key: Pubkey = Pubkey::new_unique();
+        let base_mint: Pubkey = Pubkey::new_unique();
+        let quote_mint: Pubkey = Pubkey::new_unique();
+        let maker: Pubkey = Pubkey::new_unique();
+        let mut runner: ModelRunner = ModelRunner::new(&market_key, &base_mint, &quote_mint);
+        runner.claim_seat(&maker).unwrap();
+        runner
+            .apply(&ModelOperation::Deposit {
+                trader: maker,
+                amount_atoms: 1_000,
+                is_base: true,
+            })
+            .unwrap();
+
+        let mut rpc: MockRpc = MockRpc::new();
+        rpc.set_model(&runner);
+        let mut manifest_market: ManifestMarket = ManifestMarket::from_keyed_account(
+            &KeyedAccount {
+                key: market_key,
+                account: rpc.get_account(&market_key).unwrap(),
+                params: None,
+            },
+            &AmmContext {
+                clock_ref: ClockRef::default(),
+            },
+        )
+        .unwrap();
+        refresh_amm(&mut manifest_market, &rpc).unwrap();
+
+        let quote_params: QuoteParams = QuoteParams {
+            amount: 100,
+            swap_mode: SwapMode::ExactIn,
+            input_mint: quote_mint,
+            output_mint: base_mint,
+        };
+        assert_eq!(manifest_market.quote(&quote_params).unwrap().out_amount, 0);
+
+        runner
+            .apply(&ModelOperation::PlaceOrder {
+                trader: maker,
+                num_base_atoms: BaseAtoms::new(1_000),
+                price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(1, 0).unwrap(),
+                is_bid: false,
+                order_type: OrderType::Limit,
+            })
+            .unwrap();
+        // Quotes need room to expand for reverse orders.
+        let mut market: MarketValue = runner.market.clone();
+        for _ in 0..2 {
+            market.dynamic.extend_from_slice(&[0; MARKET_BLOCK_SIZE]);
+            market.market_expand().unwrap();
+        }
+        rpc.set_market(&market_key, &market);
+        refresh_amm(&mut manifest_market, &rpc).unwrap();
+        assert!(manifest_market.quote(&quote_params).unwrap().out_amount > 0);
+
+        // Refreshed without a stamp, so the state counts as from slot zero.
+        manifest_market.set_max_age_slots(Some(10));
+        assert!(manifest_market.quote_at_slot(&quote_params, 10).is_ok());
+        assert!(manifest_market.quote_at_slot(&quote_params, 11).is_err());
+
+        let mut policy: QuoteSourcePolicy = QuoteSourcePolicy::new(10);
+        rpc.set_slot(20);
+        for current_slot in [5, 20] {
+            policy
+                .quote(&manifest_market, &rpc, &quote_params, current_slot)
+                .unwrap();
+        }
+        let sourced_quote: SourcedQuote = policy
+            .quote(&manifest_market, &rpc, &quote_params, 20)
+            .unwrap();
+        assert_eq!(sourced_quote.source, QuoteSource::Rpc);
+        assert_eq!(sourced_quote.quote.stamp.slot, 20);
+        assert!(sourced_quote.quote.value.out_amount > 0);
+        assert_eq!(policy.get_num_quotes(QuoteSource::Subscription), 1);
+        assert_eq!(policy.get_num_quotes(QuoteSource::Rpc), 2);
+    }
+}
@@ -0,0 +1,405 @@
+//! Differences between two snapshots of the same market. Used by
+//! `manifest-cli diff` when working out what happened to an order between two
+//! points in time.
+
+use anyhow::{bail, Result};
+use hypertree::{get_helper, get_mut_helper, Get, HyperTreeValueIteratorTrait};
+use manifest::{
+    quantities::{QuoteAtomsPerBaseAtom, WrapperU64},
+    state::{
+        claimed_seat::ClaimedSeat, DynamicAccount, MarketFixed, MarketValue, OrderType,
+        RestingOrder, MARKET_FIXED_DISCRIMINANT,
+    },
+};
+use solana_program::pubkey::Pubkey;
+use std::{collections::BTreeMap, fmt, mem::size_of};
+
+use crate::display::TraderRegistry;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OrderRecord {
+    pub trader: Pubkey,
+    pub sequence_number: u64,
+    pub is_bid: bool,
+    pub price: QuoteAtomsPerBaseAtom,
+    pub num_base_atoms: u64,
+    pub order_type: OrderType,
+    pub last_valid_slot: u32,
+}
+
+/// An order on the book in both snapshots that got smaller in between.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FilledOrder {
+    pub order: OrderRecord,
+    pub base_atoms_before: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BalanceChange {
+    pub trader: Pubkey,
+    pub base_before: u64,
+    pub base_after: u64,
+    pub quote_before: u64,
+    pub quote_after: u64,
+}
+
+/// Orders are matched by sequence number. A removed order was either fully
+/// filled or cancelled, the snapshots alone cannot tell which. Seats that only
+/// exist on one side show up as a balance change from or to zero.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MarketDiff {
+    pub added_orders: Vec<OrderRecord>,
+    pub removed_orders: Vec<OrderRecord>,
+    pub filled_orders: Vec<FilledOrder>,
+    pub balance_changes: Vec<BalanceChange>,
+}
+
+/// Parse the raw data of a market account, as written by
+/// `solana account <market> --output-file <path>`.
+pub fn load_market_snapshot(data: &[u8]) -> Result<MarketValue> {
+    if data.len() < size_of::<MarketFixed>() {
+        bail!("Snapshot is {} bytes, too short for a market", data.len());
+    }
+    let (header_bytes, dynamic_data) = data.split_at(size_of::<MarketFixed>());
+    let market_fixed: &MarketFixed = get_helper::<MarketFixed>(header_bytes, 0_u32);
+    if market_fixed.discriminant != MARKET_FIXED_DISCRIMINANT {
+        bail!("Snapshot is not a market account");
+    }
+    Ok(DynamicAccount::<MarketFixed, Vec<u8>> {
+        fixed: *market_fixed,
+        dynamic: dynamic_data.to_vec(),
+    })
+}
+
+/// Account data of a dynamic account, the inverse of `load_market_snapshot`.
+pub(crate) fn dynamic_account_data<Fixed: Get>(fixed: &Fixed, dynamic: &[u8]) -> Vec<u8> {
+    let mut data: Vec<u8> = vec![0; size_of::<Fixed>()];
+    *get_mut_helper::<Fixed>(&mut data, 0_u32) = *fixed;
+    data.extend_from_slice(dynamic);
+    data
+}
+
+fn collect_orders(market: &MarketValue) -> BTreeMap<u64, OrderRecord> {
+    let mut orders: BTreeMap<u64, OrderRecord> = BTreeMap::new();
+    for bookside in [market.get_bids(), market.get_asks()] {
+        for (_, order) in bookside.iter::<RestingOrder>() {
+            orders.insert(
+                order.get_sequence_number(),
+                OrderRecord {
+                    trader: *market.get_trader_key_by_index(order.get_trader_index()),
+                    sequence_number: order.get_sequence_number(),
+                    is_bid: order.get_is_bid(),
+                    price: order.get_price(),
+                    num_base_atoms: order.get_num_base_atoms().as_u64(),
+                    order_type: order.get_order_type(),
+                    last_valid_slot: order.get_last_valid_slot(),
+                },
+            );
+        }
+    }
+    orders
+}
+
+fn collect_balances(market: &MarketValue) -> BTreeMap<Pubkey, (u64, u64)> {
+    market
+        .get_claimed_seats()
+        .iter::<ClaimedSeat>()
+        .map(|(_, seat)| {
+            (
+                seat.trader,
+                (
+                    seat.base_withdrawable_balance.as_u64(),
+                    seat.quote_withdrawable_balance.as_u64(),
+                ),
+            )
+        })
+        .collect()
+}
+
+pub fn diff_markets(before: &MarketValue, after: &MarketValue) -> Result<MarketDiff> {
+    if before.get_base_mint() != after.get_base_mint()
+        || before.get_quote_mint() != after.get_quote_mint()
+    {
+        bail!("Snapshots are from different markets");
+    }
+
+    let mut diff: MarketDiff = MarketDiff::default();
+    let orders_before: BTreeMap<u64, OrderRecord> = collect_orders(before);
+    let orders_after: BTreeMap<u64, OrderRecord> = collect_orders(after);
+    for (sequence_number, order_before) in orders_before.iter() {
+        match orders_after.get(sequence_number) {
+            None => diff.removed_orders.push(*order_before),
+            Some(order_after) if order_after.num_base_atoms < order_before.num_base_atoms => {
+                diff.filled_orders.push(FilledOrder {
+                    order: *order_after,
+                    base_atoms_before: order_before.num_base_atoms,
+                })
+            }
+            Some(_) => {}
+        }
+    }
+    for (sequence_number, order_after) in orders_after.iter() {
+        if !orders_before.contains_key(sequence_number) {
+            diff.added_orders.push(*order_after);
+        }
+    }
+
+    let balances_before: BTreeMap<Pubkey, (u64, u64)> = collect_balances(before);
+    let balances_after: BTreeMap<Pubkey, (u64, u64)> = collect_balances(after);
+    let mut traders: Vec<&Pubkey> = balances_before
+        .keys()
+        .chain(balances_after.keys())
+        .collect();
+    traders.sort();
+    traders.dedup();
+    for trader in traders {
+        let (base_before, quote_before) = balances_before.get(trader).copied().unwrap_or_default();
+        let (base_after, quote_after) = balances_after.get(trader).copied().unwrap_or_default();
+        if (base_before, quote_before) != (base_after, quote_after) {
+            diff.balance_changes.push(BalanceChange {
+                trader: *trader,
+                base_before,
+                base_after,
+                quote_before,
+                quote_after,
+            });
+        }
+    }
+    Ok(diff)
+}
+
+fn side(is_bid: bool) -> &'static str {
+    if is_bid {
+        "bid"
+    } else {
+        "ask"
+    }
+}
+
+fn order_json(order: &OrderRecord) -> String {
+    format!(
+        "{{\"trader\":\"{}\",\"sequence_number\":{},\"side\":\"{}\",\"price\":{},\"num_base_atoms\":{},\"order_type\":\"{:?}\",\"last_valid_slot\":{}}}",
+        order.trader,
+        order.sequence_number,
+        side(order.is_bid),
+        order.price,
+        order.num_base_atoms,
+        order.order_type,
+        order.last_valid_slot,
+    )
+}
+
+impl MarketDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_orders.is_empty()
+            && self.removed_orders.is_empty()
+            && self.filled_orders.is_empty()
+            && self.balance_changes.is_empty()
+    }
+
+    /// Single line JSON with the same content as the Display output.
+    pub fn to_json(&self) -> String {
+        let added: Vec<String> = self.added_orders.iter().map(order_json).collect();
+        let removed: Vec<String> = self.removed_orders.iter().map(order_json).collect();
+        let filled: Vec<String> = self
+            .filled_orders
+            .iter()
+            .map(|filled| {
+                format!(
+                    "{{\"order\":{},\"base_atoms_before\":{}}}",
+                    order_json(&filled.order),
+                    filled.base_atoms_before
+                )
+            })
+            .collect();
+        let balances: Vec<String> = self
+            .balance_changes
+            .iter()
+            .map(|change| {
+                format!(
+                    "{{\"trader\":\"{}\",\"base_before\":{},\"base_after\":{},\"quote_before\":{},\"quote_after\":{}}}",
+                    change.trader,
+                    change.base_before,
+                    change.base_after,
+                    change.quote_before,
+                    change.quote_after,
+                )
+            })
+            .collect();
+        format!(
+            "{{\"added_orders\":[{}],\"removed_orders\":[{}],\"filled_orders\":[{}],\"balance_changes\":[{}]}}",
+            added.join(","),
+            removed.join(","),
+            filled.join(","),
+            balances.join(","),
+        )
+    }
+}
+
+impl OrderRecord {
+    fn fmt_with_labels(&self, f: &mut fmt::Formatter<'_>, labels: &TraderRegistry) -> fmt::Result {
+        write!(
+            f,
+            "#{} {} {} base atoms @ {} by {} ({:?}",
+            self.sequence_number,
+            side(self.is_bid),
+            self.num_base_atoms,
+            self.price,
+            labels.annotate(&self.trader),
+            self.order_type,
+        )?;
+        if self.last_valid_slot != 0 {
+            write!(f, ", valid through slot {}", self.last_valid_slot)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl fmt::Display for OrderRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_with_labels(f, &TraderRegistry::default())
+    }
+}
+
+/// Display of a diff with traders annotated from a registry.
+pub struct LabeledMarketDiff<'a> {
+    diff: &'a MarketDiff,
+    labels: &'a TraderRegistry,
+}
+
+impl MarketDiff {
+    pub fn with_labels<'a>(&'a self, labels: &'a TraderRegistry) -> LabeledMarketDiff<'a> {
+        LabeledMarketDiff { diff: self, labels }
+    }
+}
+
+impl fmt::Display for LabeledMarketDiff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let LabeledMarketDiff { diff, labels } = self;
+        if diff.is_empty() {
+            return writeln!(f, "No changes");
+        }
+        if !diff.added_orders.is_empty() {
+            writeln!(f, "Added orders:")?;
+            for order in diff.added_orders.iter() {
+                write!(f, "  + ")?;
+                order.fmt_with_labels(f, labels)?;
+                writeln!(f)?;
+            }
+        }
+        if !diff.removed_orders.is_empty() {
+            writeln!(f, "Removed orders (filled or cancelled):")?;
+            for order in diff.removed_orders.iter() {
+                write!(f, "  - ")?;
+                order.fmt_with_labels(f, labels)?;
+                writeln!(f)?;
+            }
+        }
+        if !diff.filled_orders.is_empty() {
+            writeln!(f, "Partially filled orders:")?;
+            for filled in diff.filled_orders.iter() {
+                write!(f, "  ~ ")?;
+                filled.order.fmt_with_labels(f, labels)?;
+                writeln!(f, ", was {} base atoms", filled.base_atoms_before)?;
+            }
+        }
+        if !diff.balance_changes.is_empty() {
+            writeln!(f, "Withdrawable balance changes:")?;
+            for change in diff.balance_changes.iter() {
+                writeln!(
+                    f,
+                    "  {} base {} -> {}, quote {} -> {}",
+                    labels.annotate(&change.trader),
+                    change.base_before,
+                    change.base_after,
+                    change.quote_before,
+                    change.quote_after
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for MarketDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.with_labels(&TraderRegistry::default()), f)
+    }
+}
+
+#[cfg(all(test, feature = "model"))]
+mod test {
+    use super::*;
+    use crate::model::{ModelOperation, ModelRunner};
+    use hypertree::get_mut_helper;
+    use manifest::quantities::BaseAtoms;
+
+    #[test]
+    fn test_diff_markets() {
+        let market_key: Pubkey = Pubkey::new_unique();
+        let maker: Pubkey = Pubkey::new_unique();
+        let taker: Pubkey = Pubkey::new_unique();
+        let mut runner: ModelRunner =
+            ModelRunner::new(&market_key, &Pubkey::new_unique(), &Pubkey::new_unique());
+        runner.claim_seat(&maker).unwrap();
+        runner
+            .apply(&ModelOperation::Deposit {
+                trader: maker,
+                amount_atoms: 1_000,
+                is_base: true,
+            })
+            .unwrap();
+        runner
+            .apply(&ModelOperation::PlaceOrder {
+                trader: maker,
+                num_base_atoms: BaseAtoms::new(100),
+                price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(1, 0).unwrap(),
+                is_bid: false,
+                order_type: OrderType::Limit,
+            })
+            .unwrap();
+        let before: MarketValue = runner.market.clone();
+
+        runner.claim_seat(&taker).unwrap();
+        runner
+            .apply(&ModelOperation::Deposit {
+                trader: taker,
+                amount_atoms: 40,
+                is_base: false,
+            })
+            .unwrap();
+        runner
+            .apply(&ModelOperation::PlaceOrder {
+                trader: taker,
+                num_base_atoms: BaseAtoms::new(40),
+                price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(1, 0).unwrap(),
+                is_bid: true,
+                order_type: OrderType::ImmediateOrCancel,
+            })
+            .unwrap();
+
+        let diff: MarketDiff = diff_markets(&before, &runner.market).unwrap();
+        assert!(diff.added_orders.is_empty());
+        assert!(diff.removed_orders.is_empty());
+        assert_eq!(diff.filled_orders.len(), 1);
+        assert_eq!(diff.filled_orders[0].base_atoms_before, 100);
+        assert_eq!(diff.filled_orders[0].order.num_base_atoms, 60);
+        assert_eq!(diff.filled_orders[0].order.trader, maker);
+        // Maker receives quote, taker trades quote for base.
+        assert_eq!(diff.balance_changes.len(), 2);
+        assert!(diff.to_string().contains("was 100 base atoms"));
+        let mut labels: TraderRegistry = TraderRegistry::new();
+        labels.insert(&maker, "maker");
+        assert!(diff
+            .with_labels(&labels)
+            .to_string()
+            .contains(&format!("by maker ({maker})")));
+        assert!(diff.to_json().contains("\"base_atoms_before\":100"));
+
+        let mut data: Vec<u8> = vec![0; size_of::<MarketFixed>()];
+        *get_mut_helper::<MarketFixed>(&mut data, 0_u32) = runner.market.fixed;
+        data.extend_from_slice(&runner.market.dynamic);
+        let loaded: MarketValue = load_market_snapshot(&data).unwrap();
+        assert!(diff_markets(&runner.market, &loaded).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,257 @@
+//! One interface for quoting a market wherever its state comes from. Routing
+//! written against `QuoteSource` runs unchanged on a `MarketValue` or
+//! `MarketRef` built from fixtures, on the `StampedMarket` a subscription
+//! keeps current, and with the `client` feature on `RpcMarket`, which fetches
+//! the market for every call.
+
+use anyhow::Result;
+use manifest::{
+    quantities::{BaseAtoms, QuoteAtoms, QuoteAtomsPerBaseAtom, WrapperU64},
+    state::{DerefOrBorrow, DynamicAccount, MarketFixed},
+};
+
+use crate::sync::{StampedMarket, StateStamp};
+
+/// Quotes are exact, with the same integer math as the swap instruction.
+/// Global orders are treated as if the global accounts were not passed,
+/// which stops the walk, the same as `quoting::exact_quote`.
+pub trait QuoteSource {
+    /// Out atoms for swapping exactly `in_atoms`.
+    fn quote_exact_in(&self, is_base_in: bool, in_atoms: u64, now_slot: u32) -> Result<u64>;
+
+    /// In atoms the swap instruction takes for exactly `out_atoms`. When the
+    /// book cannot pay out that much, this is what it takes for all it can.
+    fn quote_exact_out(&self, is_base_in: bool, out_atoms: u64, now_slot: u32) -> Result<u64>;
+
+    fn best_bid_ask(
+        &self,
+        now_slot: u32,
+    ) -> Result<(Option<QuoteAtomsPerBaseAtom>, Option<QuoteAtomsPerBaseAtom>)>;
+
+    /// Position of the state quotes are made on. Default for state that is
+    /// not stamped.
+    fn stamp(&self) -> Result<StateStamp>;
+}
+
+impl<Fixed: DerefOrBorrow<MarketFixed>, Dynamic: DerefOrBorrow<[u8]>> QuoteSource
+    for DynamicAccount<Fixed, Dynamic>
+{
+    fn quote_exact_in(&self, is_base_in: bool, in_atoms: u64, now_slot: u32) -> Result<u64> {
+        let out_atoms: u64 = if is_base_in {
+            self.impact_quote_atoms_with_slot(
+                false,
+                BaseAtoms::new(in_atoms),
+                &[None, None],
+                now_slot,
+            )?
+            .as_u64()
+        } else {
+            self.impact_base_atoms_with_slot(
+                true,
+                QuoteAtoms::new(in_atoms),
+                &[None, None],
+                now_slot,
+            )?
+            .as_u64()
+        };
+        Ok(out_atoms)
+    }
+
+    fn quote_exact_out(&self, is_base_in: bool, out_atoms: u64, now_slot: u32) -> Result<u64> {
+        let in_atoms: u64 = if is_base_in {
+            // Base atoms to sell for the quote out, rounded up like the
+            // instruction so the fill does not come up short.
+            self.impact_base_atoms_with_slot(
+                false,
+                QuoteAtoms::new(out_atoms),
+                &[None, None],
+                now_slot,
+            )?
+            .as_u64()
+        } else {
+            self.impact_quote_atoms_with_slot(
+                true,
+                BaseAtoms::new(out_atoms),
+                &[None, None],
+                now_slot,
+            )?
+            .as_u64()
+        };
+        Ok(in_atoms)
+    }
+
+    fn best_bid_ask(
+        &self,
+        now_slot: u32,
+    ) -> Result<(Option<QuoteAtomsPerBaseAtom>, Option<QuoteAtomsPerBaseAtom>)> {
+        Ok((
+            self.best_bid(&[None, None], now_slot)?,
+            self.best_ask(&[None, None], now_slot)?,
+        ))
+    }
+
+    fn stamp(&self) -> Result<StateStamp> {
+        Ok(StateStamp::default())
+    }
+}
+
+impl QuoteSource for StampedMarket {
+    fn quote_exact_in(&self, is_base_in: bool, in_atoms: u64, now_slot: u32) -> Result<u64> {
+        self.value.quote_exact_in(is_base_in, in_atoms, now_slot)
+    }
+
+    fn quote_exact_out(&self, is_base_in: bool, out_atoms: u64, now_slot: u32) -> Result<u64> {
+        self.value.quote_exact_out(is_base_in, out_atoms, now_slot)
+    }
+
+    fn best_bid_ask(
+        &self,
+        now_slot: u32,
+    ) -> Result<(Option<QuoteAtomsPerBaseAtom>, Option<QuoteAtomsPerBaseAtom>)> {
+        self.value.best_bid_ask(now_slot)
+    }
+
+    fn stamp(&self) -> Result<StateStamp> {
+        Ok(self.stamp)
+    }
+}
+
+/// Market read through an `AccountFetcher` on every call, so each answer is
+/// as fresh as the RPC node. Calls that need the market and the slot fetch
+/// both, which costs a round trip each.
+#[cfg(feature = "client")]
+pub struct RpcMarket<F: crate::rpc::AccountFetcher> {
+    fetcher: F,
+    market_key: solana_sdk::pubkey::Pubkey,
+}
+
+#[cfg(feature = "client")]
+impl<F: crate::rpc::AccountFetcher> RpcMarket<F> {
+    pub fn new(fetcher: F, market_key: &solana_sdk::pubkey::Pubkey) -> Self {
+        RpcMarket {
+            fetcher,
+            market_key: *market_key,
+        }
+    }
+
+    pub fn get_fetcher(&self) -> &F {
+        &self.fetcher
+    }
+
+    /// Market stamped with the slot the fetcher reports.
+    pub fn fetch(&self) -> Result<StampedMarket> {
+        let stamp: StateStamp = StateStamp {
+            slot: self.fetcher.get_slot()?,
+            write_version: 0,
+        };
+        let account: solana_sdk::account::Account = self.fetcher.get_account(&self.market_key)?;
+        Ok(StampedMarket::new(
+            stamp,
+            crate::snapshot::load_market_snapshot(&account.data)?,
+        ))
+    }
+}
+
+#[cfg(feature = "client")]
+impl<F: crate::rpc::AccountFetcher> QuoteSource for RpcMarket<F> {
+    fn quote_exact_in(&self, is_base_in: bool, in_atoms: u64, now_slot: u32) -> Result<u64> {
+        self.fetch()?.quote_exact_in(is_base_in, in_atoms, now_slot)
+    }
+
+    fn quote_exact_out(&self, is_base_in: bool, out_atoms: u64, now_slot: u32) -> Result<u64> {
+        self.fetch()?
+            .quote_exact_out(is_base_in, out_atoms, now_slot)
+    }
+
+    fn best_bid_ask(
+        &self,
+        now_slot: u32,
+    ) -> Result<(Option<QuoteAtomsPerBaseAtom>, Option<QuoteAtomsPerBaseAtom>)> {
+        self.fetch()?.best_bid_ask(now_slot)
+    }
+
+    fn stamp(&self) -> Result<StateStamp> {
+        Ok(StateStamp {
+            slot: self.fetcher.get_slot()?,
+            write_version: 0,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "client", feature = "model"))]
+mod test {
+    use super::*;
+    use crate::{
+        model::{ModelOperation, ModelRunner},
+        rpc::MockRpc,
+    };
+    use manifest::state::{MarketRef, OrderType};
+    use solana_sdk::pubkey::Pubkey;
+
+    // What a router would ask any source for.
+    fn route_quotes<S: QuoteSource>(source: &S) -> Vec<u64> {
+        let (best_bid_opt, best_ask_opt) = source.best_bid_ask(0).unwrap();
+        vec![
+            source.quote_exact_in(true, 50, 0).unwrap(),
+            source.quote_exact_in(false, 300, 0).unwrap(),
+            source.quote_exact_out(true, 25, 0).unwrap(),
+            source.quote_exact_out(false, 150, 0).unwrap(),
+            best_bid_opt.map_or(0, |price| price.as_f64() as u64),
+            best_ask_opt.map_or(0, |price| price.as_f64() as u64),
+        ]
+    }
+
+    #[test]
+    fn test_quote_sources_agree() {
+        let market_key: Pubkey = Pubkey::new_unique();
+        let maker: Pubkey = Pubkey::new_unique();
+        let mut runner: ModelRunner =
+            ModelRunner::new(&market_key, &Pubkey::new_unique(), &Pubkey::new_unique());
+        runner.claim_seat(&maker).unwrap();
+        for is_base in [true, false] {
+            runner
+                .apply(&ModelOperation::Deposit {
+                    trader: maker,
+                    amount_atoms: 1_000,
+                    is_base,
+                })
+                .unwrap();
+        }
+        // 100 base at 2 and 100 base at 4 offered, 100 base at 1 bid.
+        for (mantissa, is_bid) in [(2, false), (4, false), (1, true)] {
+            runner
+                .apply(&ModelOperation::PlaceOrder {
+                    trader: maker,
+                    num_base_atoms: BaseAtoms::new(100),
+                    price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(mantissa, 0)
+                        .unwrap(),
+                    is_bid,
+                    order_type: OrderType::Limit,
+                })
+                .unwrap();
+        }
+
+        let expected: Vec<u64> = vec![50, 125, 25, 400, 1, 2];
+        assert_eq!(route_quotes(&runner.market), expected);
+        let market_ref: MarketRef = MarketRef {
+            fixed: &runner.market.fixed,
+            dynamic: &runner.market.dynamic,
+        };
+        assert_eq!(route_quotes(&market_ref), expected);
+
+        let stamp: StateStamp = StateStamp {
+            slot: 7,
+            write_version: 1,
+        };
+        let stamped: StampedMarket = StampedMarket::new(stamp, runner.market.clone());
+        assert_eq!(route_quotes(&stamped), expected);
+        assert_eq!(stamped.stamp().unwrap(), stamp);
+
+        let mut rpc: MockRpc = MockRpc::new();
+        rpc.set_model(&runner);
+        rpc.set_slot(9);
+        let rpc_market: RpcMarket<MockRpc> = RpcMarket::new(rpc, &market_key);
+        assert_eq!(route_quotes(&rpc_market), expected);
+        assert_eq!(rpc_market.stamp().unwrap().slot, 9);
+    }
+}
@@ -0,0 +1,259 @@
+//! Order events for a single market with consumer filters applied before
+//! delivery, so a strategy that only cares about the touch is not woken up for
+//! activity deep in the book.
+
+use manifest::{
+    quantities::{BaseAtoms, QuoteAtomsPerBaseAtom},
+    state::MarketValue,
+};
+use solana_program::pubkey::Pubkey;
+use std::collections::HashMap;
+
+use crate::{analytics::OrderEvent, book::OrderSource};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventFilter {
+    /// Only places and fills at one of the best `n` price levels on their
+    /// side of the last book passed to `update_market`. Cancels pass when the
+    /// cancelled order was at one of those levels or is not on that book.
+    TopLevels(usize),
+    /// Only events where the trader placed or cancelled the order, or was the
+    /// maker or taker of the fill.
+    Trader(Pubkey),
+    /// Drops fills smaller than the given size. Other events are unaffected.
+    MinFillBaseAtoms(BaseAtoms),
+}
+
+pub struct MarketEventStream<I> {
+    events: I,
+    filters: Vec<EventFilter>,
+    /// Best first.
+    bid_levels: Vec<QuoteAtomsPerBaseAtom>,
+    ask_levels: Vec<QuoteAtomsPerBaseAtom>,
+    resting_orders: HashMap<u64, (bool, QuoteAtomsPerBaseAtom)>,
+}
+
+impl<I: Iterator<Item = OrderEvent>> MarketEventStream<I> {
+    pub fn new(events: I) -> Self {
+        MarketEventStream {
+            events,
+            filters: Vec::new(),
+            bid_levels: Vec::new(),
+            ask_levels: Vec::new(),
+            resting_orders: HashMap::new(),
+        }
+    }
+
+    /// Events are delivered only if every registered filter accepts them.
+    pub fn add_filter(&mut self, filter: EventFilter) {
+        self.filters.push(filter);
+    }
+
+    pub fn with_filter(mut self, filter: EventFilter) -> Self {
+        self.add_filter(filter);
+        self
+    }
+
+    /// Book used by `EventFilter::TopLevels`. Until this is called every book
+    /// side is empty, so all places and fills count as top of book.
+    pub fn update_market(&mut self, market: &MarketValue) {
+        self.update_books(&market.get_bids(), &market.get_asks());
+    }
+
+    /// Same as `update_market` for books read separately, for example from
+    /// `BooksideSnapshot`s.
+    pub fn update_books<S: OrderSource>(&mut self, bids: &S, asks: &S) {
+        self.resting_orders.clear();
+        for (is_bid, bookside) in [(true, bids), (false, asks)] {
+            let levels: &mut Vec<QuoteAtomsPerBaseAtom> = if is_bid {
+                &mut self.bid_levels
+            } else {
+                &mut self.ask_levels
+            };
+            levels.clear();
+            for order in bookside.resting_orders() {
+                let price: QuoteAtomsPerBaseAtom = order.get_price();
+                if levels.last() != Some(&price) {
+                    levels.push(price);
+                }
+                self.resting_orders
+                    .insert(order.get_sequence_number(), (is_bid, price));
+            }
+        }
+    }
+
+    fn is_within_top_levels(&self, n: usize, is_bid: bool, price: QuoteAtomsPerBaseAtom) -> bool {
+        let levels: &Vec<QuoteAtomsPerBaseAtom> = if is_bid {
+            &self.bid_levels
+        } else {
+            &self.ask_levels
+        };
+        match levels.get(n.saturating_sub(1)) {
+            None => n > 0,
+            Some(worst) if is_bid => price >= *worst,
+            Some(worst) => price <= *worst,
+        }
+    }
+
+    fn accepts_with(&self, filter: &EventFilter, event: &OrderEvent) -> bool {
+        match (filter, event) {
+            (EventFilter::TopLevels(n), OrderEvent::Place { log, .. }) => {
+                self.is_within_top_levels(*n, log.is_bid.0 != 0, log.price)
+            }
+            (EventFilter::TopLevels(n), OrderEvent::Fill { log, .. }) => {
+                // Taker buys match against resting asks.
+                self.is_within_top_levels(*n, log.taker_is_buy.0 == 0, log.price)
+            }
+            (EventFilter::TopLevels(n), OrderEvent::Cancel { log, .. }) => {
+                match self.resting_orders.get(&log.order_sequence_number) {
+                    Some((is_bid, price)) => self.is_within_top_levels(*n, *is_bid, *price),
+                    None => true,
+                }
+            }
+            (EventFilter::Trader(trader), OrderEvent::Place { log, .. }) => log.trader == *trader,
+            (EventFilter::Trader(trader), OrderEvent::Fill { log, .. }) => {
+                log.maker == *trader || log.taker == *trader
+            }
+            (EventFilter::Trader(trader), OrderEvent::Cancel { log, .. }) => log.trader == *trader,
+            (EventFilter::MinFillBaseAtoms(min_base_atoms), OrderEvent::Fill { log, .. }) => {
+                log.base_atoms >= *min_base_atoms
+            }
+            (EventFilter::MinFillBaseAtoms(_), _) => true,
+        }
+    }
+
+    pub fn accepts(&self, event: &OrderEvent) -> bool {
+        self.filters
+            .iter()
+            .all(|filter| self.accepts_with(filter, event))
+    }
+}
+
+impl<I: Iterator<Item = OrderEvent>> Iterator for MarketEventStream<I> {
+    type Item = OrderEvent;
+
+    fn next(&mut self) -> Option<OrderEvent> {
+        loop {
+            let event: OrderEvent = self.events.next()?;
+            if self.accepts(&event) {
+                return Some(event);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "model"))]
+mod test {
+    use super::*;
+    use crate::{
+        decoder::CancelReason,
+        model::{ModelOperation, ModelRunner},
+    };
+    use manifest::{
+        logs::{CancelOrderLog, FillLog, PlaceOrderLog},
+        quantities::QuoteAtoms,
+        state::OrderType,
+    };
+
+    #[test]
+    fn test_market_event_stream_filters() {
+        let market_key: Pubkey = Pubkey::new_unique();
+        let maker: Pubkey = Pubkey::new_unique();
+        let other: Pubkey = Pubkey::new_unique();
+        let mut runner: ModelRunner =
+            ModelRunner::new(&market_key, &Pubkey::new_unique(), &Pubkey::new_unique());
+        runner.claim_seat(&maker).unwrap();
+        runner
+            .apply(&ModelOperation::Deposit {
+                trader: maker,
+                amount_atoms: 1_000,
+                is_base: true,
+            })
+            .unwrap();
+        // Asks at 1, 2 and 3.
+        for mantissa in 1..=3 {
+            runner
+                .apply(&ModelOperation::PlaceOrder {
+                    trader: maker,
+                    num_base_atoms: BaseAtoms::new(10),
+                    price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(mantissa, 0)
+                        .unwrap(),
+                    is_bid: false,
+                    order_type: OrderType::Limit,
+                })
+                .unwrap();
+        }
+
+        let price = |mantissa: u32| {
+            QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(mantissa, 0).unwrap()
+        };
+        let place = |trader: Pubkey, mantissa: u32| OrderEvent::Place {
+            slot: 0,
+            log: PlaceOrderLog {
+                market: market_key,
+                trader,
+                price: price(mantissa),
+                base_atoms: BaseAtoms::new(10),
+                order_sequence_number: 100,
+                order_index: 0,
+                last_valid_slot: 0,
+                order_type: OrderType::Limit,
+                is_bid: false.into(),
+                _padding: [0; 6],
+            },
+        };
+        let fill = |base_atoms: u64| OrderEvent::Fill {
+            slot: 0,
+            log: FillLog {
+                market: market_key,
+                maker,
+                taker: other,
+                base_mint: Pubkey::new_unique(),
+                quote_mint: Pubkey::new_unique(),
+                price: price(1),
+                base_atoms: BaseAtoms::new(base_atoms),
+                quote_atoms: QuoteAtoms::new(base_atoms),
+                maker_sequence_number: 0,
+                taker_sequence_number: 101,
+                taker_is_buy: true.into(),
+                is_maker_global: false.into(),
+                _padding: [0; 14],
+            },
+        };
+        let cancel = |order_sequence_number: u64| OrderEvent::Cancel {
+            slot: 0,
+            log: CancelOrderLog {
+                market: market_key,
+                trader: maker,
+                order_sequence_number,
+            },
+            reason: CancelReason::User,
+        };
+
+        let events: Vec<OrderEvent> = vec![
+            place(maker, 3),
+            place(maker, 2),
+            place(other, 1),
+            fill(1),
+            fill(10),
+            cancel(0),
+            cancel(2),
+        ];
+        let mut stream: MarketEventStream<std::vec::IntoIter<OrderEvent>> =
+            MarketEventStream::new(events.into_iter())
+                .with_filter(EventFilter::TopLevels(2))
+                .with_filter(EventFilter::Trader(maker))
+                .with_filter(EventFilter::MinFillBaseAtoms(BaseAtoms::new(5)));
+        stream.update_market(&runner.market);
+
+        let delivered: Vec<OrderEvent> = stream.collect();
+        assert_eq!(delivered.len(), 3);
+        assert!(matches!(delivered[0], OrderEvent::Place { log, .. } if log.price == price(2)));
+        assert!(
+            matches!(delivered[1], OrderEvent::Fill { log, .. } if log.base_atoms == BaseAtoms::new(10))
+        );
+        assert!(
+            matches!(delivered[2], OrderEvent::Cancel { log, .. } if log.order_sequence_number == 0)
+        );
+    }
+}
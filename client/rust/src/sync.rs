@@ -0,0 +1,623 @@
+//! Keeping copies of Manifest accounts in sync across processes.
+
+use anyhow::{Error, Result};
+use manifest::state::{GlobalValue, MarketFixed, MarketValue};
+use solana_program::{
+    hash::{hash, Hash},
+    pubkey::Pubkey,
+};
+use std::collections::HashMap;
+
+use crate::snapshot::{dynamic_account_data, load_market_snapshot};
+
+/// Position of an account update in the chain history. Updates from
+/// different sources are ordered by slot and then by write version, so a
+/// consumer can tell which of two copies is newer.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StateStamp {
+    pub slot: u64,
+    pub write_version: u64,
+}
+
+/// Value together with the stamp of the account data it was derived from.
+#[derive(Debug, Clone)]
+pub struct Stamped<T> {
+    pub stamp: StateStamp,
+    pub value: T,
+}
+
+pub type StampedMarket = Stamped<MarketValue>;
+pub type StampedGlobal = Stamped<GlobalValue>;
+
+impl<T> Stamped<T> {
+    pub fn new(stamp: StateStamp, value: T) -> Self {
+        Stamped { stamp, value }
+    }
+
+    /// Take `other` unless it is older than the current value. Returns
+    /// whether it was taken. Equal stamps are taken so a resend is harmless.
+    pub fn update(&mut self, other: Stamped<T>) -> bool {
+        if other.stamp < self.stamp {
+            return false;
+        }
+        *self = other;
+        true
+    }
+}
+
+/// Staleness limit for quoting. A book whose account stopped updating, for
+/// example because the subscription dropped, keeps producing quotes that
+/// can no longer be filled.
+pub struct QuoteGuard;
+
+impl QuoteGuard {
+    /// Slots the state is behind `current_slot`. Zero for state stamped after
+    /// `current_slot`, which happens when the slot source lags the feed.
+    pub fn age_slots(stamp: StateStamp, current_slot: u64) -> u64 {
+        current_slot.saturating_sub(stamp.slot)
+    }
+
+    pub fn is_stale(stamp: StateStamp, current_slot: u64, max_age_slots: u64) -> bool {
+        Self::age_slots(stamp, current_slot) > max_age_slots
+    }
+
+    /// Slots a global account is behind the market it is quoted with. Zero
+    /// when the global is newer.
+    pub fn global_lag_slots(market_stamp: StateStamp, global_stamp: StateStamp) -> u64 {
+        market_stamp.slot.saturating_sub(global_stamp.slot)
+    }
+
+    /// Age of the state, or an error if it is older than `max_age_slots`.
+    pub fn check(stamp: StateStamp, current_slot: u64, max_age_slots: u64) -> Result<u64> {
+        let age_slots: u64 = Self::age_slots(stamp, current_slot);
+        if age_slots > max_age_slots {
+            return Err(Error::msg(format!(
+                "State from slot {} is {age_slots} slots old, more than the {max_age_slots} allowed",
+                stamp.slot
+            )));
+        }
+        Ok(age_slots)
+    }
+}
+
+/// Drops account notifications that carry the same data as the last one
+/// seen for the account, which is most of them when several providers feed
+/// one store. Data is compared by hash, so only a hash per account is kept.
+/// A duplicate can still have a newer stamp, which a consumer can apply to
+/// its copy without recomputing anything derived from the data.
+#[derive(Debug, Clone, Default)]
+pub struct NotificationDedup {
+    last_hashes: HashMap<Pubkey, Hash>,
+    num_notifications: u64,
+    num_duplicates: u64,
+}
+
+impl NotificationDedup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether `data` differs from the last data seen for `key`. Returning
+    /// to an earlier state is not a duplicate, only repeats in a row are.
+    pub fn is_new(&mut self, key: &Pubkey, data: &[u8]) -> bool {
+        self.num_notifications += 1;
+        let data_hash: Hash = hash(data);
+        if self.last_hashes.insert(*key, data_hash) == Some(data_hash) {
+            self.num_duplicates += 1;
+            return false;
+        }
+        true
+    }
+
+    /// Forget `key`, for example after resubscribing, so its next
+    /// notification is new.
+    pub fn forget(&mut self, key: &Pubkey) {
+        self.last_hashes.remove(key);
+    }
+
+    pub fn get_num_notifications(&self) -> u64 {
+        self.num_notifications
+    }
+
+    pub fn get_num_duplicates(&self) -> u64 {
+        self.num_duplicates
+    }
+
+    /// Share of notifications dropped as duplicates, zero before the first.
+    pub fn get_dedup_rate(&self) -> f64 {
+        if self.num_notifications == 0 {
+            return 0.0;
+        }
+        self.num_duplicates as f64 / self.num_notifications as f64
+    }
+}
+
+/// Index of a provider in a `RedundantFeed`, in the order they were added.
+pub type ProviderId = usize;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProviderHealth {
+    pub name: String,
+    /// Newest stamp the provider delivered, None before its first update.
+    pub last_stamp_opt: Option<StateStamp>,
+    pub num_updates: u64,
+    /// Updates that were newer than the state of the feed and replaced it.
+    pub num_taken: u64,
+}
+
+impl ProviderHealth {
+    /// Whether the provider delivered an update within `max_lag_slots` of
+    /// `current_slot`.
+    pub fn is_healthy(&self, current_slot: u64, max_lag_slots: u64) -> bool {
+        self.last_stamp_opt
+            .is_some_and(|stamp| !QuoteGuard::is_stale(stamp, current_slot, max_lag_slots))
+    }
+}
+
+/// One market fed by several websocket or gRPC providers. Every update is
+/// pushed with the provider it came from and the newest by stamp wins, so
+/// when a provider stalls the others take over without any switch. Health
+/// of each provider is kept to alert on, and to resubscribe a provider that
+/// stopped delivering.
+#[derive(Debug, Clone)]
+pub struct RedundantFeed {
+    market_key: Pubkey,
+    max_lag_slots: u64,
+    providers: Vec<ProviderHealth>,
+    market_opt: Option<StampedMarket>,
+    active_provider_opt: Option<ProviderId>,
+}
+
+impl RedundantFeed {
+    /// Providers more than `max_lag_slots` behind the current slot count as
+    /// unhealthy.
+    pub fn new(market_key: &Pubkey, max_lag_slots: u64) -> Self {
+        RedundantFeed {
+            market_key: *market_key,
+            max_lag_slots,
+            providers: Vec::new(),
+            market_opt: None,
+            active_provider_opt: None,
+        }
+    }
+
+    pub fn add_provider(&mut self, name: &str) -> ProviderId {
+        self.providers.push(ProviderHealth {
+            name: name.to_string(),
+            last_stamp_opt: None,
+            num_updates: 0,
+            num_taken: 0,
+        });
+        self.providers.len() - 1
+    }
+
+    /// Account data of the market from `provider_id`. Returns whether it
+    /// replaced the state of the feed, which only a strictly newer stamp
+    /// does, so the same update arriving from every provider is decoded once.
+    pub fn push(
+        &mut self,
+        provider_id: ProviderId,
+        stamp: StateStamp,
+        data: &[u8],
+    ) -> Result<bool> {
+        let num_providers: usize = self.providers.len();
+        let provider: &mut ProviderHealth =
+            self.providers.get_mut(provider_id).ok_or_else(|| {
+                Error::msg(format!(
+                    "Provider {provider_id} is not one of the {num_providers} providers"
+                ))
+            })?;
+        provider.num_updates += 1;
+        let is_newer: bool = self
+            .market_opt
+            .as_ref()
+            .is_none_or(|market| market.stamp < stamp);
+        if is_newer {
+            // Data that does not decode does not count toward health.
+            let market: MarketValue = load_market_snapshot(data)?;
+            provider.num_taken += 1;
+            self.market_opt = Some(Stamped::new(stamp, market));
+            self.active_provider_opt = Some(provider_id);
+        }
+        provider.last_stamp_opt = provider.last_stamp_opt.max(Some(stamp));
+        Ok(is_newer)
+    }
+
+    pub fn get_market_key(&self) -> &Pubkey {
+        &self.market_key
+    }
+
+    /// Newest state from any provider, None before the first update.
+    pub fn get_market(&self) -> Option<&StampedMarket> {
+        self.market_opt.as_ref()
+    }
+
+    /// Provider that delivered the current state.
+    pub fn get_active_provider(&self) -> Option<ProviderId> {
+        self.active_provider_opt
+    }
+
+    pub fn get_provider_health(&self, provider_id: ProviderId) -> Option<&ProviderHealth> {
+        self.providers.get(provider_id)
+    }
+
+    pub fn get_providers(&self) -> &[ProviderHealth] {
+        &self.providers
+    }
+
+    pub fn unhealthy_providers(&self, current_slot: u64) -> Vec<ProviderId> {
+        (0..self.providers.len())
+            .filter(|provider_id| {
+                !self.providers[*provider_id].is_healthy(current_slot, self.max_lag_slots)
+            })
+            .collect()
+    }
+
+    /// Whether every provider is behind, in which case the state of the feed
+    /// is too and should not be quoted from.
+    pub fn is_stale(&self, current_slot: u64) -> bool {
+        self.unhealthy_providers(current_slot).len() == self.providers.len()
+    }
+}
+
+/// Persistence for the latest copy of each market, so a quoting service can
+/// warm-start from its last state after a restart and only needs to catch up
+/// on newer updates.
+pub trait StateStore {
+    fn save_market(&self, market_key: &Pubkey, market: &StampedMarket) -> Result<()>;
+
+    /// None if nothing was saved for the market yet.
+    fn load_market(&self, market_key: &Pubkey) -> Result<Option<StampedMarket>>;
+}
+
+const STAMP_LEN: usize = 16;
+
+/// Slot and write version followed by the market account data.
+pub fn encode_stamped_market(market: &StampedMarket) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::with_capacity(
+        STAMP_LEN + std::mem::size_of::<MarketFixed>() + market.value.dynamic.len(),
+    );
+    bytes.extend_from_slice(&market.stamp.slot.to_le_bytes());
+    bytes.extend_from_slice(&market.stamp.write_version.to_le_bytes());
+    bytes.extend_from_slice(&dynamic_account_data::<MarketFixed>(
+        &market.value.fixed,
+        &market.value.dynamic,
+    ));
+    bytes
+}
+
+pub fn decode_stamped_market(bytes: &[u8]) -> Result<StampedMarket> {
+    if bytes.len() < STAMP_LEN {
+        return Err(Error::msg("Truncated stamp"));
+    }
+    let (stamp_bytes, market_bytes) = bytes.split_at(STAMP_LEN);
+    let stamp: StateStamp = StateStamp {
+        slot: u64::from_le_bytes(stamp_bytes[..8].try_into()?),
+        write_version: u64::from_le_bytes(stamp_bytes[8..].try_into()?),
+    };
+    Ok(Stamped::new(stamp, load_market_snapshot(market_bytes)?))
+}
+
+/// One file per market in a directory. Writes go to a temporary file first
+/// so a crash mid save leaves the previous copy intact.
+#[cfg(feature = "fs-store")]
+pub struct FileStateStore {
+    dir: std::path::PathBuf,
+}
+
+#[cfg(feature = "fs-store")]
+impl FileStateStore {
+    pub fn new(dir: impl Into<std::path::PathBuf>) -> Result<Self> {
+        let dir: std::path::PathBuf = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(FileStateStore { dir })
+    }
+
+    fn market_path(&self, market_key: &Pubkey) -> std::path::PathBuf {
+        self.dir.join(format!("{market_key}.market"))
+    }
+}
+
+#[cfg(feature = "fs-store")]
+impl StateStore for FileStateStore {
+    fn save_market(&self, market_key: &Pubkey, market: &StampedMarket) -> Result<()> {
+        let path: std::path::PathBuf = self.market_path(market_key);
+        let tmp_path: std::path::PathBuf = path.with_extension("market.tmp");
+        std::fs::write(&tmp_path, encode_stamped_market(market))?;
+        std::fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    fn load_market(&self, market_key: &Pubkey) -> Result<Option<StampedMarket>> {
+        match std::fs::read(self.market_path(market_key)) {
+            Ok(bytes) => Ok(Some(decode_stamped_market(&bytes)?)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// One key per market, `<prefix><market>`. Opens a connection per call, so
+/// wrap it if saves are frequent enough for that to matter.
+#[cfg(feature = "redis-store")]
+pub struct RedisStateStore {
+    client: redis::Client,
+    key_prefix: String,
+}
+
+#[cfg(feature = "redis-store")]
+impl RedisStateStore {
+    pub fn new(url: &str, key_prefix: &str) -> Result<Self> {
+        Ok(RedisStateStore {
+            client: redis::Client::open(url)?,
+            key_prefix: key_prefix.to_string(),
+        })
+    }
+
+    fn market_key(&self, market_key: &Pubkey) -> String {
+        format!("{}{}", self.key_prefix, market_key)
+    }
+}
+
+#[cfg(feature = "redis-store")]
+impl StateStore for RedisStateStore {
+    fn save_market(&self, market_key: &Pubkey, market: &StampedMarket) -> Result<()> {
+        use redis::Commands;
+        let mut connection: redis::Connection = self.client.get_connection()?;
+        connection.set::<String, Vec<u8>, ()>(
+            self.market_key(market_key),
+            encode_stamped_market(market),
+        )?;
+        Ok(())
+    }
+
+    fn load_market(&self, market_key: &Pubkey) -> Result<Option<StampedMarket>> {
+        use redis::Commands;
+        let mut connection: redis::Connection = self.client.get_connection()?;
+        let bytes: Option<Vec<u8>> = connection.get(self.market_key(market_key))?;
+        bytes.map(|bytes| decode_stamped_market(&bytes)).transpose()
+    }
+}
+
+// Bytes of framing per region. Unchanged gaps up to this size are cheaper to
+// resend than to split the region on.
+const REGION_HEADER_LEN: usize = 8;
+const PATCH_HEADER_LEN: usize = 8;
+
+fn read_u32(patch: &[u8], offset: usize) -> Result<usize> {
+    let bytes: [u8; 4] = patch
+        .get(offset..offset + 4)
+        .ok_or_else(|| Error::msg("Truncated patch"))?
+        .try_into()?;
+    Ok(u32::from_le_bytes(bytes) as usize)
+}
+
+/// Encode `new_bytes` as the regions that differ from `old_bytes`. Most
+/// updates to a market touch a few nodes and the header, so the patch is
+/// usually a small fraction of the account size.
+///
+/// Layout is the new length and region count, then for every region its
+/// offset, length and the new bytes. All integers are little endian u32.
+pub fn compress_update(old_bytes: &[u8], new_bytes: &[u8]) -> Vec<u8> {
+    let mut regions: Vec<(usize, usize)> = Vec::new();
+    let mut index: usize = 0;
+    while index < new_bytes.len() {
+        if old_bytes.get(index) == Some(&new_bytes[index]) {
+            index += 1;
+            continue;
+        }
+        let start: usize = index;
+        while index < new_bytes.len() && old_bytes.get(index) != Some(&new_bytes[index]) {
+            index += 1;
+        }
+        match regions.last_mut() {
+            Some((_, end)) if start - *end <= REGION_HEADER_LEN => *end = index,
+            _ => regions.push((start, index)),
+        }
+    }
+
+    let mut patch: Vec<u8> = Vec::with_capacity(
+        PATCH_HEADER_LEN
+            + regions
+                .iter()
+                .map(|(start, end)| REGION_HEADER_LEN + end - start)
+                .sum::<usize>(),
+    );
+    patch.extend_from_slice(&(new_bytes.len() as u32).to_le_bytes());
+    patch.extend_from_slice(&(regions.len() as u32).to_le_bytes());
+    for (start, end) in regions {
+        patch.extend_from_slice(&(start as u32).to_le_bytes());
+        patch.extend_from_slice(&((end - start) as u32).to_le_bytes());
+        patch.extend_from_slice(&new_bytes[start..end]);
+    }
+    patch
+}
+
+/// Rebuild the new account bytes from the old ones and a patch made by
+/// `compress_update`.
+pub fn apply_update(old_bytes: &[u8], patch: &[u8]) -> Result<Vec<u8>> {
+    let new_len: usize = read_u32(patch, 0)?;
+    let num_regions: usize = read_u32(patch, 4)?;
+
+    let mut new_bytes: Vec<u8> = old_bytes[..old_bytes.len().min(new_len)].to_vec();
+    new_bytes.resize(new_len, 0);
+
+    let mut offset: usize = PATCH_HEADER_LEN;
+    for _ in 0..num_regions {
+        let start: usize = read_u32(patch, offset)?;
+        let len: usize = read_u32(patch, offset + 4)?;
+        offset += REGION_HEADER_LEN;
+        let region: &[u8] = patch
+            .get(offset..offset + len)
+            .ok_or_else(|| Error::msg("Truncated patch region"))?;
+        new_bytes
+            .get_mut(start..start + len)
+            .ok_or_else(|| Error::msg("Patch region out of bounds"))?
+            .copy_from_slice(region);
+        offset += len;
+    }
+    if offset != patch.len() {
+        return Err(Error::msg("Trailing bytes in patch"));
+    }
+    Ok(new_bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_compress_update_round_trip() {
+        let old_bytes: Vec<u8> = (0..4096).map(|i| (i % 251) as u8).collect();
+        let mut new_bytes: Vec<u8> = old_bytes.clone();
+        new_bytes[10] ^= 1;
+        new_bytes[14] ^= 1;
+        new_bytes[2000..2064].fill(7);
+
+        let patch: Vec<u8> = compress_update(&old_bytes, &new_bytes);
+        // Two regions, the first one spanning the small gap.
+        assert_eq!(
+            patch.len(),
+            PATCH_HEADER_LEN + 2 * REGION_HEADER_LEN + 5 + 64
+        );
+        assert_eq!(apply_update(&old_bytes, &patch).unwrap(), new_bytes);
+
+        // Growing and shrinking like a market expand.
+        let mut grown: Vec<u8> = new_bytes.clone();
+        grown.extend_from_slice(&[0, 0, 9, 9]);
+        let patch: Vec<u8> = compress_update(&new_bytes, &grown);
+        assert_eq!(apply_update(&new_bytes, &patch).unwrap(), grown);
+        let patch: Vec<u8> = compress_update(&grown, &old_bytes);
+        assert_eq!(apply_update(&grown, &patch).unwrap(), old_bytes);
+
+        assert!(apply_update(&old_bytes, &patch[..patch.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_stamped_discards_out_of_order() {
+        let stamp = |slot: u64, write_version: u64| StateStamp {
+            slot,
+            write_version,
+        };
+        let mut value: Stamped<u64> = Stamped::new(stamp(10, 5), 1);
+        assert!(!value.update(Stamped::new(stamp(10, 4), 2)));
+        assert!(!value.update(Stamped::new(stamp(9, 100), 3)));
+        assert_eq!(value.value, 1);
+        assert!(value.update(Stamped::new(stamp(11, 0), 4)));
+        assert_eq!(value.value, 4);
+        assert_eq!(value.stamp, stamp(11, 0));
+    }
+
+    #[cfg(feature = "model")]
+    #[test]
+    fn test_stamped_market_round_trip() {
+        let runner: crate::model::ModelRunner = crate::model::ModelRunner::new(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        );
+        let market: StampedMarket = Stamped::new(
+            StateStamp {
+                slot: 12,
+                write_version: 3,
+            },
+            runner.market.clone(),
+        );
+        let decoded: StampedMarket =
+            decode_stamped_market(&encode_stamped_market(&market)).unwrap();
+        assert_eq!(decoded.stamp, market.stamp);
+        assert_eq!(decoded.value.dynamic, market.value.dynamic);
+        assert_eq!(decoded.value.get_base_mint(), market.value.get_base_mint());
+        assert!(decode_stamped_market(&[0; 8]).is_err());
+    }
+
+    #[test]
+    fn test_quote_guard() {
+        let stamp: StateStamp = StateStamp {
+            slot: 100,
+            write_version: 0,
+        };
+        assert_eq!(QuoteGuard::check(stamp, 110, 10).unwrap(), 10);
+        assert!(QuoteGuard::check(stamp, 111, 10).is_err());
+        assert!(QuoteGuard::is_stale(stamp, 111, 10));
+        assert!(!QuoteGuard::is_stale(stamp, 90, 0));
+        assert_eq!(
+            QuoteGuard::global_lag_slots(
+                stamp,
+                StateStamp {
+                    slot: 80,
+                    write_version: 7,
+                }
+            ),
+            20
+        );
+        assert_eq!(QuoteGuard::global_lag_slots(stamp, stamp), 0);
+    }
+
+    #[test]
+    fn test_notification_dedup() {
+        let market_key: Pubkey = Pubkey::new_unique();
+        let global_key: Pubkey = Pubkey::new_unique();
+        let mut dedup: NotificationDedup = NotificationDedup::new();
+        assert_eq!(dedup.get_dedup_rate(), 0.0);
+
+        assert!(dedup.is_new(&market_key, &[1, 2, 3]));
+        assert!(!dedup.is_new(&market_key, &[1, 2, 3]));
+        // Same data on another account.
+        assert!(dedup.is_new(&global_key, &[1, 2, 3]));
+        assert!(dedup.is_new(&market_key, &[1, 2, 4]));
+        assert!(dedup.is_new(&market_key, &[1, 2, 3]));
+        assert!(!dedup.is_new(&market_key, &[1, 2, 3]));
+        assert_eq!(dedup.get_num_notifications(), 6);
+        assert_eq!(dedup.get_num_duplicates(), 2);
+        assert_eq!(dedup.get_dedup_rate(), 2.0 / 6.0);
+
+        dedup.forget(&market_key);
+        assert!(dedup.is_new(&market_key, &[1, 2, 3]));
+    }
+
+    #[cfg(feature = "model")]
+    #[test]
+    fn test_redundant_feed() {
+        let stamp = |slot: u64, write_version: u64| StateStamp {
+            slot,
+            write_version,
+        };
+        let runner: crate::model::ModelRunner = crate::model::ModelRunner::new(
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+        );
+        let data: Vec<u8> =
+            dynamic_account_data::<MarketFixed>(&runner.market.fixed, &runner.market.dynamic);
+        let mut feed: RedundantFeed = RedundantFeed::new(&Pubkey::new_unique(), 10);
+        let websocket: ProviderId = feed.add_provider("websocket");
+        let grpc: ProviderId = feed.add_provider("grpc");
+        assert!(feed.get_market().is_none());
+        assert!(feed.is_stale(0));
+
+        assert!(feed.push(websocket, stamp(100, 1), &data).unwrap());
+        assert!(!feed.push(grpc, stamp(100, 1), &data).unwrap());
+        assert!(feed.push(grpc, stamp(100, 2), &data).unwrap());
+        assert!(!feed.push(websocket, stamp(100, 2), &data).unwrap());
+        assert_eq!(feed.get_active_provider(), Some(grpc));
+
+        // The websocket stalls and the feed keeps following grpc.
+        assert!(feed.push(grpc, stamp(120, 0), &data).unwrap());
+        assert_eq!(feed.get_market().unwrap().stamp, stamp(120, 0));
+        assert_eq!(feed.unhealthy_providers(120), [websocket]);
+        assert!(!feed.is_stale(120));
+        assert!(feed.is_stale(131));
+
+        let health: &ProviderHealth = feed.get_provider_health(websocket).unwrap();
+        assert_eq!(health.name, "websocket");
+        assert_eq!(health.num_updates, 2);
+        assert_eq!(health.num_taken, 1);
+        assert_eq!(health.last_stamp_opt, Some(stamp(100, 2)));
+
+        assert!(feed.push(2, stamp(200, 0), &data).is_err());
+        assert!(feed.push(websocket, stamp(200, 0), &[0; 8]).is_err());
+        assert_eq!(feed.get_active_provider(), Some(grpc));
+        assert!(feed.unhealthy_providers(120).contains(&websocket));
+    }
+}
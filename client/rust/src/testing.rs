@@ -0,0 +1,537 @@
+//! In memory global accounts for tests of code that quotes against global
+//! orders. `GlobalFixture` is the global counterpart of `model::ModelRunner`:
+//! it changes the account the way the global instructions do and hands it to
+//! the quoting APIs either as account data or as `GlobalTradeAccounts`.
+//! `SyntheticBook` builds large books with both kinds of orders for
+//! benchmarks and capacity tests.
+
+use anyhow::{bail, Error, Result};
+use hypertree::{get_helper, DataIndex};
+use manifest::{
+    quantities::{BaseAtoms, GlobalAtoms, QuoteAtomsPerBaseAtom, WrapperU64},
+    state::{
+        AddOrderToMarketArgs, DynamicAccount, GlobalFixed, GlobalValue, MarketValue, OrderType,
+        GLOBAL_BLOCK_SIZE, GLOBAL_FIXED_DISCRIMINANT, MARKET_BLOCK_SIZE, MAX_GLOBAL_SEATS,
+        NO_EXPIRATION_LAST_VALID_SLOT,
+    },
+    validation::{get_global_address, loaders::GlobalTradeAccounts, ManifestAccountInfo, Signer},
+};
+use solana_program::account_info::AccountInfo;
+use solana_program::pubkey::Pubkey;
+#[cfg(feature = "client")]
+use solana_sdk::account::Account;
+use std::{cell::RefCell, mem::size_of, rc::Rc};
+
+use crate::{model::ModelRunner, snapshot::dynamic_account_data};
+
+#[derive(Clone)]
+pub struct GlobalFixture {
+    key: Pubkey,
+    global: GlobalValue,
+}
+
+impl GlobalFixture {
+    pub fn new(mint: &Pubkey) -> Self {
+        GlobalFixture {
+            key: get_global_address(mint).0,
+            global: GlobalValue {
+                fixed: GlobalFixed::new_empty(mint),
+                dynamic: Vec::new(),
+            },
+        }
+    }
+
+    /// Restore a fixture from the data of a global account, as returned by
+    /// `snapshot` or read from chain.
+    pub fn restore(data: &[u8]) -> Result<Self> {
+        if data.len() < size_of::<GlobalFixed>() {
+            bail!("Snapshot is {} bytes, too short for a global", data.len());
+        }
+        let (header_bytes, dynamic_data) = data.split_at(size_of::<GlobalFixed>());
+        let global_fixed: &GlobalFixed = get_helper::<GlobalFixed>(header_bytes, 0_u32);
+        if global_fixed.discriminant != GLOBAL_FIXED_DISCRIMINANT {
+            bail!("Snapshot is not a global account");
+        }
+        Ok(GlobalFixture {
+            key: get_global_address(global_fixed.get_mint()).0,
+            global: DynamicAccount::<GlobalFixed, Vec<u8>> {
+                fixed: *global_fixed,
+                dynamic: dynamic_data.to_vec(),
+            },
+        })
+    }
+
+    pub fn get_key(&self) -> &Pubkey {
+        &self.key
+    }
+
+    pub fn get_global(&self) -> &GlobalValue {
+        &self.global
+    }
+
+    pub fn get_balance_atoms(&self, trader: &Pubkey) -> u64 {
+        self.global.get_balance_atoms(trader).as_u64()
+    }
+
+    /// Claim a seat, growing the account like GlobalAddTrader does. Fails
+    /// once all `MAX_GLOBAL_SEATS` are taken, after which seats only change
+    /// hands through `evict`.
+    pub fn add_trader(&mut self, trader: &Pubkey) -> Result<()> {
+        if self.global.fixed.needs_eviction() {
+            bail!("All {MAX_GLOBAL_SEATS} global seats are claimed, evict instead");
+        }
+        if self.global.has_global_seat(trader) {
+            bail!("{trader} already has a global seat");
+        }
+        self.global
+            .dynamic
+            .resize(self.global.dynamic.len() + 2 * GLOBAL_BLOCK_SIZE, 0);
+        self.global.global_expand()?;
+        self.global.add_trader(trader)?;
+        Ok(())
+    }
+
+    pub fn deposit(&mut self, trader: &Pubkey, amount_atoms: u64) -> Result<()> {
+        self.global
+            .deposit_global(trader, GlobalAtoms::new(amount_atoms))?;
+        Ok(())
+    }
+
+    pub fn withdraw(&mut self, trader: &Pubkey, amount_atoms: u64) -> Result<()> {
+        self.global
+            .withdraw_global(trader, GlobalAtoms::new(amount_atoms))?;
+        Ok(())
+    }
+
+    /// Same checks and state changes as GlobalEvict. The evictee has to hold
+    /// the smallest deposit and less than `amount_atoms`, which the evictor
+    /// deposits into the seat. Returns the atoms paid out to the evictee.
+    pub fn evict(&mut self, evictee: &Pubkey, evictor: &Pubkey, amount_atoms: u64) -> Result<u64> {
+        if !self.global.fixed.needs_eviction() {
+            bail!("Eviction is only allowed when the global is at capacity");
+        }
+        let evictee_balance: GlobalAtoms = self.global.get_balance_atoms(evictee);
+        if evictee_balance >= GlobalAtoms::new(amount_atoms) {
+            bail!(
+                "Evictee balance {} is not less than the evictor deposit {amount_atoms}",
+                evictee_balance.as_u64()
+            );
+        }
+        self.global.verify_min_balance(evictee)?;
+
+        // Work on a copy so a failed eviction leaves the fixture unchanged.
+        let mut global: GlobalValue = self.global.clone();
+        global.withdraw_global(evictee, evictee_balance)?;
+        global.evict_and_take_seat(evictee, evictor)?;
+        global.deposit_global(evictor, GlobalAtoms::new(amount_atoms))?;
+        self.global = global;
+        Ok(evictee_balance.as_u64())
+    }
+
+    /// Data of the global account.
+    pub fn snapshot(&self) -> Vec<u8> {
+        dynamic_account_data::<GlobalFixed>(&self.global.fixed, &self.global.dynamic)
+    }
+
+    /// The global as an account to pass to `Amm::update`.
+    #[cfg(feature = "client")]
+    pub fn to_account(&self) -> Account {
+        Account {
+            lamports: 0,
+            data: self.snapshot(),
+            owner: manifest::id(),
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    /// Runs `f` with trade accounts for the global on `market`, as needed by
+    /// the impact and place order functions on a market. Placing a global
+    /// order also needs the key of the trader as `gas_payer_opt`. Nothing
+    /// else is set, so anything that moves tokens or lamports is out of
+    /// reach, and changes to the global by `f` are dropped.
+    pub fn with_trade_accounts<T>(
+        &self,
+        market: &Pubkey,
+        gas_payer_opt: Option<&Pubkey>,
+        f: impl FnOnce(GlobalTradeAccounts) -> T,
+    ) -> Result<T> {
+        let owner: Pubkey = manifest::id();
+        let mut data: Vec<u8> = self.snapshot();
+        let mut lamports: u64 = 0;
+        let global_info: AccountInfo = AccountInfo {
+            key: &self.key,
+            lamports: Rc::new(RefCell::new(&mut lamports)),
+            data: Rc::new(RefCell::new(&mut data[..])),
+            owner: &owner,
+            rent_epoch: 0,
+            is_signer: false,
+            is_writable: false,
+            executable: false,
+        };
+        let mut gas_payer_lamports: u64 = 0;
+        let gas_payer_info_opt: Option<AccountInfo> = gas_payer_opt.map(|gas_payer| AccountInfo {
+            key: gas_payer,
+            lamports: Rc::new(RefCell::new(&mut gas_payer_lamports)),
+            data: Rc::new(RefCell::new(&mut [])),
+            owner: &owner,
+            rent_epoch: 0,
+            is_signer: true,
+            is_writable: false,
+            executable: false,
+        });
+        let global_trade_accounts: GlobalTradeAccounts = GlobalTradeAccounts {
+            mint_opt: None,
+            global: ManifestAccountInfo::new(&global_info).map_err(Error::msg)?,
+            global_vault_opt: None,
+            market_vault_opt: None,
+            token_program_opt: None,
+            system_program: None,
+            gas_payer_opt: gas_payer_info_opt
+                .as_ref()
+                .map(Signer::new)
+                .transpose()
+                .map_err(Error::msg)?,
+            gas_receiver_opt: None,
+            market: *market,
+        };
+        Ok(f(global_trade_accounts))
+    }
+}
+
+/// Base atoms of the orders at the best level, deeper levels scale this by
+/// the depth decay.
+const TOP_LEVEL_BASE_ATOMS: u64 = 1_000_000_000;
+// Prices are mantissas with this exponent, so 1 bps is 10 and the mid of
+// one quote atom per base atom is 100_000.
+const PRICE_EXPONENT: i8 = -5;
+const MID_PRICE_MANTISSA: u64 = 100_000;
+
+/// A market with `levels` orders a side around a mid of one quote atom per
+/// base atom, made the same way every time so runs are comparable. Every
+/// order rests at its own price, the best ones `spread_bps` apart and each
+/// further level one bps deeper. Market backed orders belong to one maker
+/// and global orders to another, whose deposits in the two globals back
+/// them.
+#[derive(Clone)]
+pub struct SyntheticBook {
+    market_key: Pubkey,
+    market: MarketValue,
+    base_global: GlobalFixture,
+    quote_global: GlobalFixture,
+}
+
+impl SyntheticBook {
+    /// The order at level `i` is for `depth_decay^i` times the base atoms
+    /// of the best level, so below one the book thins out with depth.
+    /// `global_ratio` of the levels on each side, spread evenly through the
+    /// book, hold global orders.
+    pub fn from_profile(
+        spread_bps: u32,
+        depth_decay: f64,
+        levels: u32,
+        global_ratio: f64,
+    ) -> Result<Self> {
+        if !(depth_decay.is_finite() && depth_decay > 0.0) {
+            bail!("Depth decay {depth_decay} is not positive");
+        }
+        if !(0.0..=1.0).contains(&global_ratio) {
+            bail!("Global ratio {global_ratio} is not between 0 and 1");
+        }
+        // Offsets from the mid are in tenths of a bps, half the spread at the
+        // best level.
+        let worst_offset: u64 = 5 * spread_bps as u64 + 10 * levels.saturating_sub(1) as u64;
+        if worst_offset >= MID_PRICE_MANTISSA {
+            bail!("{levels} levels with a {spread_bps} bps spread take bids below zero");
+        }
+        // Books that grow with depth are capped so the deposits stay in range.
+        let level_base_atoms: Vec<u64> = (0..levels)
+            .map(|level| (TOP_LEVEL_BASE_ATOMS as f64 * depth_decay.powi(level as i32)).round())
+            .map(|base_atoms| base_atoms.clamp(1.0, u32::MAX as f64 * 1_000.0) as u64)
+            .collect();
+        let is_global_level: Vec<bool> = (0..levels)
+            .map(|level| {
+                (((level + 1) as f64 * global_ratio) as u64)
+                    > ((level as f64 * global_ratio) as u64)
+            })
+            .collect();
+
+        let market_key: Pubkey = Pubkey::new_unique();
+        let base_mint: Pubkey = Pubkey::new_unique();
+        let quote_mint: Pubkey = Pubkey::new_unique();
+        let mut market: MarketValue = ModelRunner::new(&market_key, &base_mint, &quote_mint).market;
+        let mut base_global: GlobalFixture = GlobalFixture::new(&base_mint);
+        let mut quote_global: GlobalFixture = GlobalFixture::new(&quote_mint);
+
+        // Bids are below the mid, so base atoms cover their quote too.
+        let total_base_atoms: u64 = level_base_atoms.iter().sum();
+        let maker: Pubkey = Pubkey::new_unique();
+        let global_maker: Pubkey = Pubkey::new_unique();
+        for trader in [&maker, &global_maker] {
+            Self::ensure_free_block(&mut market)?;
+            market.claim_seat(trader)?;
+        }
+        let maker_index: DataIndex = market.get_trader_index(&maker);
+        market.deposit(maker_index, total_base_atoms, true)?;
+        market.deposit(maker_index, total_base_atoms, false)?;
+        for global in [&mut base_global, &mut quote_global] {
+            global.add_trader(&global_maker)?;
+            global.deposit(&global_maker, total_base_atoms)?;
+        }
+        let global_maker_index: DataIndex = market.get_trader_index(&global_maker);
+
+        for level in 0..levels as usize {
+            let offset: u64 = 5 * spread_bps as u64 + 10 * level as u64;
+            for is_bid in [true, false] {
+                let price_mantissa: u64 = if is_bid {
+                    MID_PRICE_MANTISSA - offset
+                } else {
+                    MID_PRICE_MANTISSA + offset
+                };
+                let price: QuoteAtomsPerBaseAtom =
+                    QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(
+                        price_mantissa as u32,
+                        PRICE_EXPONENT,
+                    )
+                    .map_err(|_| Error::msg(format!("Level {level} has no valid price")))?;
+                let is_global: bool = is_global_level[level];
+                let trader_index: DataIndex = if is_global {
+                    global_maker_index
+                } else {
+                    maker_index
+                };
+                let num_base_atoms: BaseAtoms = BaseAtoms::new(level_base_atoms[level]);
+                Self::ensure_free_block(&mut market)?;
+                if !is_global {
+                    market.place_order(order_args(
+                        &market_key,
+                        trader_index,
+                        num_base_atoms,
+                        price,
+                        is_bid,
+                        OrderType::Limit,
+                        &[None, None],
+                    ))?;
+                    continue;
+                }
+                // Bids are backed by quote and asks by base.
+                let global: &GlobalFixture = if is_bid { &quote_global } else { &base_global };
+                global.with_trade_accounts(
+                    &market_key,
+                    Some(&global_maker),
+                    |global_trade_accounts| {
+                        let global_trade_accounts_opts: [Option<GlobalTradeAccounts>; 2] = if is_bid
+                        {
+                            [None, Some(global_trade_accounts)]
+                        } else {
+                            [Some(global_trade_accounts), None]
+                        };
+                        market.place_order(order_args(
+                            &market_key,
+                            trader_index,
+                            num_base_atoms,
+                            price,
+                            is_bid,
+                            OrderType::Global,
+                            &global_trade_accounts_opts,
+                        ))
+                    },
+                )??;
+            }
+        }
+
+        Ok(SyntheticBook {
+            market_key,
+            market,
+            base_global,
+            quote_global,
+        })
+    }
+
+    fn ensure_free_block(market: &mut MarketValue) -> Result<()> {
+        while !market.has_two_free_blocks() {
+            market.dynamic.extend_from_slice(&[0; MARKET_BLOCK_SIZE]);
+            market.market_expand().map_err(Error::msg)?;
+        }
+        Ok(())
+    }
+
+    pub fn get_market_key(&self) -> &Pubkey {
+        &self.market_key
+    }
+
+    pub fn get_market(&self) -> &MarketValue {
+        &self.market
+    }
+
+    /// Global of the base mint, which backs the global asks.
+    pub fn get_base_global(&self) -> &GlobalFixture {
+        &self.base_global
+    }
+
+    /// Global of the quote mint, which backs the global bids.
+    pub fn get_quote_global(&self) -> &GlobalFixture {
+        &self.quote_global
+    }
+}
+
+fn order_args<'a, 'info>(
+    market_key: &Pubkey,
+    trader_index: DataIndex,
+    num_base_atoms: BaseAtoms,
+    price: QuoteAtomsPerBaseAtom,
+    is_bid: bool,
+    order_type: OrderType,
+    global_trade_accounts_opts: &'a [Option<GlobalTradeAccounts<'a, 'info>>; 2],
+) -> AddOrderToMarketArgs<'a, 'info> {
+    AddOrderToMarketArgs {
+        market: *market_key,
+        trader_index,
+        num_base_atoms,
+        price,
+        is_bid,
+        last_valid_slot: NO_EXPIRATION_LAST_VALID_SLOT,
+        order_type,
+        global_trade_accounts_opts,
+        current_slot: Some(0),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hypertree::HyperTreeValueIteratorTrait;
+    use manifest::{quantities::QuoteAtoms, state::RestingOrder};
+
+    #[test]
+    fn test_global_fixture() {
+        let market_key: Pubkey = Pubkey::new_unique();
+        let base_mint: Pubkey = Pubkey::new_unique();
+        let quote_mint: Pubkey = Pubkey::new_unique();
+        let mut fixture: GlobalFixture = GlobalFixture::new(&quote_mint);
+
+        // Fill every seat, the last trader has the smallest deposit.
+        let traders: Vec<Pubkey> = (0..MAX_GLOBAL_SEATS)
+            .map(|_| Pubkey::new_unique())
+            .collect();
+        for (index, trader) in traders.iter().enumerate() {
+            fixture.add_trader(trader).unwrap();
+            fixture.deposit(trader, 1_000_000 - index as u64).unwrap();
+        }
+        let maker: Pubkey = traders[0];
+        let smallest: Pubkey = traders[traders.len() - 1];
+        let evictor: Pubkey = Pubkey::new_unique();
+        assert!(fixture.add_trader(&evictor).is_err());
+
+        let restored: GlobalFixture = GlobalFixture::restore(&fixture.snapshot()).unwrap();
+        assert_eq!(restored.get_key(), fixture.get_key());
+        assert_eq!(restored.snapshot(), fixture.snapshot());
+
+        // Only the smallest deposit can be evicted, and only by more.
+        assert!(fixture.evict(&maker, &evictor, 2_000_000).is_err());
+        assert!(fixture.evict(&smallest, &evictor, 1_000).is_err());
+        let paid_out: u64 = fixture.evict(&smallest, &evictor, 2_000_000).unwrap();
+        assert_eq!(paid_out, 1_000_000 - (traders.len() as u64 - 1));
+        assert_eq!(fixture.get_balance_atoms(&smallest), 0);
+        assert_eq!(fixture.get_balance_atoms(&evictor), 2_000_000);
+        assert!(restored.get_balance_atoms(&smallest) > 0);
+
+        // A global bid for 1_000 base atoms at 1 quote atom each, backed by
+        // the maker's global deposit.
+        let mut runner: ModelRunner = ModelRunner::new(&market_key, &base_mint, &quote_mint);
+        runner.claim_seat(&maker).unwrap();
+        let trader_index: DataIndex = runner.market.get_trader_index(&maker);
+        fixture
+            .with_trade_accounts(&market_key, Some(&maker), |global_trade_accounts| {
+                runner
+                    .market
+                    .place_order(AddOrderToMarketArgs {
+                        market: market_key,
+                        trader_index,
+                        num_base_atoms: BaseAtoms::new(1_000),
+                        price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(1, 0).unwrap(),
+                        is_bid: true,
+                        last_valid_slot: NO_EXPIRATION_LAST_VALID_SLOT,
+                        order_type: OrderType::Global,
+                        global_trade_accounts_opts: &[None, Some(global_trade_accounts)],
+                        current_slot: Some(0),
+                    })
+                    .unwrap();
+            })
+            .unwrap();
+
+        let quote_atoms = |fixture: &GlobalFixture| -> QuoteAtoms {
+            fixture
+                .with_trade_accounts(&market_key, None, |global_trade_accounts| {
+                    runner
+                        .market
+                        .impact_quote_atoms_with_slot(
+                            false,
+                            BaseAtoms::new(500),
+                            &[None, Some(global_trade_accounts)],
+                            0,
+                        )
+                        .unwrap()
+                })
+                .unwrap()
+        };
+        assert_eq!(quote_atoms(&fixture), QuoteAtoms::new(500));
+
+        // Once the maker cannot back the order, it is skipped.
+        fixture.withdraw(&maker, 1_000_000 - 100).unwrap();
+        assert_eq!(quote_atoms(&fixture), QuoteAtoms::ZERO);
+    }
+
+    #[test]
+    fn test_synthetic_book() {
+        let book: SyntheticBook = SyntheticBook::from_profile(20, 0.9, 50, 0.2).unwrap();
+        let market: &MarketValue = book.get_market();
+        let bids: Vec<RestingOrder> = market
+            .get_bids()
+            .iter::<RestingOrder>()
+            .map(|(_, order)| *order)
+            .collect();
+        let asks: Vec<RestingOrder> = market
+            .get_asks()
+            .iter::<RestingOrder>()
+            .map(|(_, order)| *order)
+            .collect();
+        assert_eq!(bids.len(), 50);
+        assert_eq!(asks.len(), 50);
+        assert_eq!(bids.iter().filter(|order| order.is_global()).count(), 10);
+        assert_eq!(asks.iter().filter(|order| order.is_global()).count(), 10);
+        assert_eq!(
+            bids[0].get_price(),
+            QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(99_900, -5).unwrap()
+        );
+        assert_eq!(
+            asks[49].get_price(),
+            QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(100_590, -5).unwrap()
+        );
+        assert_eq!(bids[0].get_num_base_atoms(), BaseAtoms::new(1_000_000_000));
+        assert_eq!(bids[1].get_num_base_atoms(), BaseAtoms::new(900_000_000));
+
+        // Global orders are backed, so a taker fills through them.
+        let all_asks: BaseAtoms = asks
+            .iter()
+            .map(|order| order.get_num_base_atoms())
+            .fold(BaseAtoms::ZERO, |total, base_atoms| total + base_atoms);
+        let out_atoms: BaseAtoms = book
+            .get_base_global()
+            .with_trade_accounts(book.get_market_key(), None, |global_trade_accounts| {
+                market
+                    .impact_base_atoms_with_slot(
+                        true,
+                        QuoteAtoms::new(u64::MAX / 2),
+                        &[Some(global_trade_accounts), None],
+                        0,
+                    )
+                    .unwrap()
+            })
+            .unwrap();
+        assert_eq!(out_atoms, all_asks);
+
+        assert!(SyntheticBook::from_profile(20_000, 0.9, 10, 0.2).is_err());
+        assert!(SyntheticBook::from_profile(20, 0.9, 10, 1.5).is_err());
+    }
+}
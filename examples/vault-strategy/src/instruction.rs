@@ -0,0 +1,83 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use num_enum::TryFromPrimitive;
+
+#[repr(u8)]
+#[derive(TryFromPrimitive, Debug, Copy, Clone, PartialEq, Eq)]
+#[rustfmt::skip]
+pub enum VaultInstruction {
+    /// Create the vault for a market and claim a seat for its trader.
+    /// 0. `[writable, signer]` authority, pays for the accounts
+    /// 1. `[writable]` vault, seeds `["vault", market]`
+    /// 2. `[writable]` trader, seeds `["trader", vault]`
+    /// 3. `[writable]` market
+    /// 4. `[]` manifest program
+    /// 5. `[]` system program
+    Initialize = 0,
+
+    /// Deposit both tokens for shares.
+    /// 0. `[writable, signer]` depositor
+    /// 1. `[writable]` vault
+    /// 2. `[writable]` position, seeds `["position", vault, depositor]`
+    /// 3. `[writable]` trader
+    /// 4. `[writable]` market
+    /// 5. `[]` manifest program
+    /// 6. `[]` system program
+    /// 7. `[]` token program
+    /// 8. `[writable]` depositor base token account
+    /// 9. `[writable]` depositor quote token account
+    /// 10. `[writable]` trader base token account
+    /// 11. `[writable]` trader quote token account
+    /// 12. `[writable]` market base vault
+    /// 13. `[writable]` market quote vault
+    /// 14. `[]` base mint
+    /// 15. `[]` quote mint
+    Deposit = 1,
+
+    /// Burn shares for both tokens. Same accounts as Deposit.
+    Withdraw = 2,
+
+    /// Replace the vault orders with a Reverse bid and a Reverse ask.
+    /// 0. `[writable, signer]` authority
+    /// 1. `[]` vault
+    /// 2. `[writable]` trader
+    /// 3. `[writable]` market
+    /// 4. `[]` manifest program
+    /// 5. `[]` system program
+    Rebalance = 3,
+}
+
+impl VaultInstruction {
+    pub fn to_vec(&self) -> Vec<u8> {
+        vec![*self as u8]
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct InitializeParams {
+    /// Lamports for the trader to pay for market expansion.
+    pub trader_lamports: u64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct DepositParams {
+    pub max_base_atoms: u64,
+    pub max_quote_atoms: u64,
+    pub min_shares: u64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct WithdrawParams {
+    pub shares: u64,
+    pub min_base_atoms: u64,
+    pub min_quote_atoms: u64,
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct RebalanceParams {
+    pub bid_price_mantissa: u32,
+    pub bid_price_exponent: i8,
+    pub ask_price_mantissa: u32,
+    pub ask_price_exponent: i8,
+    /// Spread of the flipped orders in units of 1/100_000.
+    pub spread: u16,
+}
@@ -0,0 +1,184 @@
+use borsh::BorshSerialize;
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program_pack::Pack,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction, system_program,
+};
+use spl_token::{instruction::initialize_account3, state::Account as TokenAccount};
+
+use crate::{
+    instruction::{
+        DepositParams, InitializeParams, RebalanceParams, VaultInstruction, WithdrawParams,
+    },
+    state::{get_position_address, get_trader_address, get_vault_address},
+};
+
+pub fn initialize_instruction(
+    market: &Pubkey,
+    authority: &Pubkey,
+    trader_lamports: u64,
+) -> Instruction {
+    let (vault, _) = get_vault_address(market);
+    let (trader, _) = get_trader_address(&vault);
+    Instruction {
+        program_id: crate::id(),
+        accounts: vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(vault, false),
+            AccountMeta::new(trader, false),
+            AccountMeta::new(*market, false),
+            AccountMeta::new_readonly(manifest::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: [
+            VaultInstruction::Initialize.to_vec(),
+            InitializeParams { trader_lamports }.try_to_vec().unwrap(),
+        ]
+        .concat(),
+    }
+}
+
+/// Instructions for the payer to create a token account of the vault trader
+/// at `token_account`, which signs with the payer.
+pub fn create_trader_token_account_instructions(
+    market: &Pubkey,
+    payer: &Pubkey,
+    token_account: &Pubkey,
+    mint: &Pubkey,
+) -> Vec<Instruction> {
+    let (vault, _) = get_vault_address(market);
+    let (trader, _) = get_trader_address(&vault);
+    vec![
+        system_instruction::create_account(
+            payer,
+            token_account,
+            Rent::default().minimum_balance(TokenAccount::LEN),
+            TokenAccount::LEN as u64,
+            &spl_token::id(),
+        ),
+        initialize_account3(&spl_token::id(), token_account, mint, &trader).unwrap(),
+    ]
+}
+
+/// Token accounts and mints of a deposit or withdraw, base first.
+pub struct VaultTokenAccounts {
+    pub base_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    pub depositor_base: Pubkey,
+    pub depositor_quote: Pubkey,
+    pub trader_base: Pubkey,
+    pub trader_quote: Pubkey,
+}
+
+fn transfer_instruction(
+    instruction: VaultInstruction,
+    market: &Pubkey,
+    depositor: &Pubkey,
+    token_accounts: &VaultTokenAccounts,
+    params: Vec<u8>,
+) -> Instruction {
+    let (vault, _) = get_vault_address(market);
+    let (trader, _) = get_trader_address(&vault);
+    let (position, _) = get_position_address(&vault, depositor);
+    Instruction {
+        program_id: crate::id(),
+        accounts: vec![
+            AccountMeta::new(*depositor, true),
+            AccountMeta::new(vault, false),
+            AccountMeta::new(position, false),
+            AccountMeta::new(trader, false),
+            AccountMeta::new(*market, false),
+            AccountMeta::new_readonly(manifest::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new(token_accounts.depositor_base, false),
+            AccountMeta::new(token_accounts.depositor_quote, false),
+            AccountMeta::new(token_accounts.trader_base, false),
+            AccountMeta::new(token_accounts.trader_quote, false),
+            AccountMeta::new(
+                manifest::validation::get_vault_address(market, &token_accounts.base_mint).0,
+                false,
+            ),
+            AccountMeta::new(
+                manifest::validation::get_vault_address(market, &token_accounts.quote_mint).0,
+                false,
+            ),
+            AccountMeta::new_readonly(token_accounts.base_mint, false),
+            AccountMeta::new_readonly(token_accounts.quote_mint, false),
+        ],
+        data: [instruction.to_vec(), params].concat(),
+    }
+}
+
+pub fn deposit_instruction(
+    market: &Pubkey,
+    depositor: &Pubkey,
+    token_accounts: &VaultTokenAccounts,
+    max_base_atoms: u64,
+    max_quote_atoms: u64,
+    min_shares: u64,
+) -> Instruction {
+    transfer_instruction(
+        VaultInstruction::Deposit,
+        market,
+        depositor,
+        token_accounts,
+        DepositParams {
+            max_base_atoms,
+            max_quote_atoms,
+            min_shares,
+        }
+        .try_to_vec()
+        .unwrap(),
+    )
+}
+
+pub fn withdraw_instruction(
+    market: &Pubkey,
+    depositor: &Pubkey,
+    token_accounts: &VaultTokenAccounts,
+    shares: u64,
+    min_base_atoms: u64,
+    min_quote_atoms: u64,
+) -> Instruction {
+    transfer_instruction(
+        VaultInstruction::Withdraw,
+        market,
+        depositor,
+        token_accounts,
+        WithdrawParams {
+            shares,
+            min_base_atoms,
+            min_quote_atoms,
+        }
+        .try_to_vec()
+        .unwrap(),
+    )
+}
+
+pub fn rebalance_instruction(
+    market: &Pubkey,
+    authority: &Pubkey,
+    params: RebalanceParams,
+) -> Instruction {
+    let (vault, _) = get_vault_address(market);
+    let (trader, _) = get_trader_address(&vault);
+    Instruction {
+        program_id: crate::id(),
+        accounts: vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(vault, false),
+            AccountMeta::new(trader, false),
+            AccountMeta::new(*market, false),
+            AccountMeta::new_readonly(manifest::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+        data: [
+            VaultInstruction::Rebalance.to_vec(),
+            params.try_to_vec().unwrap(),
+        ]
+        .concat(),
+    }
+}
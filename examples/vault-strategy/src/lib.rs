@@ -0,0 +1,40 @@
+//! Example liquidity provider vault over Manifest. Pools deposits in one seat
+//! and keeps them quoted with Reverse orders. See the README.
+
+pub mod instruction;
+pub mod instruction_builders;
+pub mod processor;
+pub mod state;
+
+use instruction::VaultInstruction;
+use solana_program::{
+    account_info::AccountInfo, declare_id, entrypoint::ProgramResult, msg,
+    program_error::ProgramError, pubkey::Pubkey,
+};
+
+declare_id!("8eAXuGKLJb1K52npJ37oZUsDDzdtTw4fEE4MwGwtykpt");
+
+#[cfg(not(feature = "no-entrypoint"))]
+solana_program::entrypoint!(process_instruction);
+
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    instruction_data: &[u8],
+) -> ProgramResult {
+    let (tag, data) = instruction_data
+        .split_first()
+        .ok_or(ProgramError::InvalidInstructionData)?;
+
+    let instruction: VaultInstruction =
+        VaultInstruction::try_from(*tag).or(Err(ProgramError::InvalidInstructionData))?;
+
+    msg!("Instruction: {:?}", instruction);
+
+    match instruction {
+        VaultInstruction::Initialize => processor::process_initialize(program_id, accounts, data),
+        VaultInstruction::Deposit => processor::process_deposit(program_id, accounts, data),
+        VaultInstruction::Withdraw => processor::process_withdraw(program_id, accounts, data),
+        VaultInstruction::Rebalance => processor::process_rebalance(program_id, accounts, data),
+    }
+}
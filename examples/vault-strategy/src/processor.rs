@@ -0,0 +1,597 @@
+use std::{cell::Ref, mem::size_of};
+
+use borsh::BorshDeserialize;
+use manifest::{
+    program::{
+        batch_update::{CancelOrderParams, PlaceOrderParams},
+        claim_seat_instruction, get_dynamic_account, PdaTrader,
+    },
+    quantities::{QuoteAtoms, QuoteAtomsPerBaseAtom, WrapperU64},
+    require,
+    state::{MarketFixed, MarketRef, OrderType},
+    utils::create_account,
+    validation::{ManifestAccountInfo, Program, Signer, TokenAccountInfo},
+};
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    program::invoke,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction, system_program,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    instruction::{DepositParams, InitializeParams, RebalanceParams, WithdrawParams},
+    state::{
+        atoms_for_withdraw, get_holdings, get_trader_address, get_vault_address,
+        shares_for_deposit, Holdings, VaultPosition, VaultState, POSITION_SEED, TRADER_SEED,
+        VAULT_SEED,
+    },
+};
+
+fn load_vault_state(
+    program_id: &Pubkey,
+    vault: &AccountInfo,
+    market: &Pubkey,
+) -> Result<VaultState, ProgramError> {
+    require!(
+        vault.owner == program_id,
+        ProgramError::IllegalOwner,
+        "Vault {:?} is not owned by this program",
+        vault.key
+    )?;
+    let vault_data: Ref<&mut [u8]> = vault.try_borrow_data()?;
+    require!(
+        vault_data.len() == size_of::<VaultState>(),
+        ProgramError::InvalidAccountData,
+        "Vault {:?} has the wrong size",
+        vault.key
+    )?;
+    let vault_state: VaultState = *bytemuck::from_bytes::<VaultState>(&vault_data);
+    require!(
+        vault_state.market == *market,
+        ProgramError::InvalidAccountData,
+        "Vault is for market {:?}, not {:?}",
+        vault_state.market,
+        market
+    )?;
+    Ok(vault_state)
+}
+
+fn save_vault_state(vault: &AccountInfo, vault_state: &VaultState) -> ProgramResult {
+    vault
+        .try_borrow_mut_data()?
+        .copy_from_slice(bytemuck::bytes_of(vault_state));
+    Ok(())
+}
+
+/// Trader PDA of the vault, checked against the account that was passed.
+fn load_trader(
+    program_id: &Pubkey,
+    vault: &Pubkey,
+    vault_state: &VaultState,
+    trader: &AccountInfo,
+) -> Result<PdaTrader, ProgramError> {
+    let pda_trader: PdaTrader = PdaTrader::new(
+        program_id,
+        vec![
+            TRADER_SEED.to_vec(),
+            vault.to_bytes().to_vec(),
+            vec![vault_state.trader_bump],
+        ],
+    )?;
+    require!(
+        pda_trader.get_key() == trader.key,
+        ProgramError::InvalidSeeds,
+        "Trader {:?} is not the vault trader",
+        trader.key
+    )?;
+    Ok(pda_trader)
+}
+
+fn read_holdings(
+    market: &ManifestAccountInfo<MarketFixed>,
+    trader: &Pubkey,
+) -> Result<Holdings, ProgramError> {
+    let market_data: &Ref<&mut [u8]> = &market.try_borrow_data()?;
+    let market_ref: MarketRef = get_dynamic_account(market_data);
+    get_holdings(&market_ref, trader)
+}
+
+/// Cancel `cancels` and then place `orders` in one batch update signed by the
+/// vault trader.
+fn batch_update<'a, 'info>(
+    pda_trader: &PdaTrader,
+    trader: &'a AccountInfo<'info>,
+    market: &ManifestAccountInfo<'a, 'info, MarketFixed>,
+    manifest_program: &Program<'a, 'info>,
+    system_program: &Program<'a, 'info>,
+    cancels: Vec<CancelOrderParams>,
+    orders: Vec<PlaceOrderParams>,
+) -> ProgramResult {
+    if cancels.is_empty() && orders.is_empty() {
+        return Ok(());
+    }
+    pda_trader.invoke(
+        &pda_trader.batch_update_instruction(market.key, None, cancels, orders),
+        &[
+            manifest_program.info.clone(),
+            trader.clone(),
+            market.info.clone(),
+            system_program.info.clone(),
+        ],
+    )
+}
+
+pub(crate) fn process_initialize(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    let account_iter: &mut std::slice::Iter<AccountInfo> = &mut accounts.iter();
+    let authority: Signer = Signer::new_payer(next_account_info(account_iter)?)?;
+    let vault: &AccountInfo = next_account_info(account_iter)?;
+    let trader: &AccountInfo = next_account_info(account_iter)?;
+    let market: ManifestAccountInfo<MarketFixed> =
+        ManifestAccountInfo::<MarketFixed>::new(next_account_info(account_iter)?)?;
+    let manifest_program: Program =
+        Program::new(next_account_info(account_iter)?, &manifest::id())?;
+    let system_program: Program =
+        Program::new(next_account_info(account_iter)?, &system_program::id())?;
+
+    let InitializeParams { trader_lamports } = InitializeParams::try_from_slice(data)?;
+
+    let (vault_key, vault_bump) = get_vault_address(market.key);
+    require!(
+        vault_key == *vault.key,
+        ProgramError::InvalidSeeds,
+        "Vault {:?} is not the vault of market {:?}",
+        vault.key,
+        market.key
+    )?;
+    let (_, trader_bump) = get_trader_address(&vault_key);
+
+    create_account(
+        authority.info,
+        vault,
+        system_program.info,
+        program_id,
+        &Rent::get()?,
+        size_of::<VaultState>() as u64,
+        vec![
+            VAULT_SEED.to_vec(),
+            market.key.to_bytes().to_vec(),
+            vec![vault_bump],
+        ],
+    )?;
+    let vault_state: VaultState = {
+        let market_fixed: Ref<MarketFixed> = market.get_fixed()?;
+        VaultState::new(
+            market.key,
+            authority.key,
+            market_fixed.get_base_mint(),
+            market_fixed.get_quote_mint(),
+            vault_bump,
+            trader_bump,
+        )
+    };
+    save_vault_state(vault, &vault_state)?;
+
+    let pda_trader: PdaTrader = load_trader(program_id, vault.key, &vault_state, trader)?;
+    invoke(
+        &system_instruction::transfer(authority.key, trader.key, trader_lamports),
+        &[
+            authority.info.clone(),
+            trader.clone(),
+            system_program.info.clone(),
+        ],
+    )?;
+    pda_trader.invoke(
+        &claim_seat_instruction(market.key, trader.key),
+        &[
+            manifest_program.info.clone(),
+            trader.clone(),
+            market.info.clone(),
+            system_program.info.clone(),
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// Accounts shared by deposit and withdraw.
+struct TransferContext<'a, 'info> {
+    depositor: Signer<'a, 'info>,
+    vault: &'a AccountInfo<'info>,
+    vault_state: VaultState,
+    position: &'a AccountInfo<'info>,
+    trader: &'a AccountInfo<'info>,
+    pda_trader: PdaTrader,
+    market: ManifestAccountInfo<'a, 'info, MarketFixed>,
+    manifest_program: Program<'a, 'info>,
+    system_program: Program<'a, 'info>,
+    token_program: Program<'a, 'info>,
+    // Base then quote.
+    depositor_token_accounts: [TokenAccountInfo<'a, 'info>; 2],
+    trader_token_accounts: [TokenAccountInfo<'a, 'info>; 2],
+    market_vaults: [&'a AccountInfo<'info>; 2],
+    mints: [&'a AccountInfo<'info>; 2],
+}
+
+impl<'a, 'info> TransferContext<'a, 'info> {
+    fn load(program_id: &Pubkey, accounts: &'a [AccountInfo<'info>]) -> Result<Self, ProgramError> {
+        let account_iter: &mut std::slice::Iter<AccountInfo<'info>> = &mut accounts.iter();
+        let depositor: Signer = Signer::new_payer(next_account_info(account_iter)?)?;
+        let vault: &AccountInfo = next_account_info(account_iter)?;
+        let position: &AccountInfo = next_account_info(account_iter)?;
+        let trader: &AccountInfo = next_account_info(account_iter)?;
+        let market: ManifestAccountInfo<MarketFixed> =
+            ManifestAccountInfo::<MarketFixed>::new(next_account_info(account_iter)?)?;
+        let manifest_program: Program =
+            Program::new(next_account_info(account_iter)?, &manifest::id())?;
+        let system_program: Program =
+            Program::new(next_account_info(account_iter)?, &system_program::id())?;
+        let token_program: Program =
+            Program::new(next_account_info(account_iter)?, &spl_token::id())?;
+
+        let vault_state: VaultState = load_vault_state(program_id, vault, market.key)?;
+        let pda_trader: PdaTrader = load_trader(program_id, vault.key, &vault_state, trader)?;
+        let depositor_base: TokenAccountInfo = TokenAccountInfo::new_with_owner(
+            next_account_info(account_iter)?,
+            &vault_state.base_mint,
+            depositor.key,
+        )?;
+        let depositor_quote: TokenAccountInfo = TokenAccountInfo::new_with_owner(
+            next_account_info(account_iter)?,
+            &vault_state.quote_mint,
+            depositor.key,
+        )?;
+        let trader_base: TokenAccountInfo = TokenAccountInfo::new_with_owner(
+            next_account_info(account_iter)?,
+            &vault_state.base_mint,
+            trader.key,
+        )?;
+        let trader_quote: TokenAccountInfo = TokenAccountInfo::new_with_owner(
+            next_account_info(account_iter)?,
+            &vault_state.quote_mint,
+            trader.key,
+        )?;
+        // Manifest checks the market vaults in the CPI.
+        let market_base_vault: &AccountInfo = next_account_info(account_iter)?;
+        let market_quote_vault: &AccountInfo = next_account_info(account_iter)?;
+        let base_mint: &AccountInfo = next_account_info(account_iter)?;
+        let quote_mint: &AccountInfo = next_account_info(account_iter)?;
+        require!(
+            *base_mint.key == vault_state.base_mint && *quote_mint.key == vault_state.quote_mint,
+            ProgramError::InvalidAccountData,
+            "Mints do not match the vault",
+        )?;
+
+        Ok(TransferContext {
+            depositor,
+            vault,
+            vault_state,
+            position,
+            trader,
+            pda_trader,
+            market,
+            manifest_program,
+            system_program,
+            token_program,
+            depositor_token_accounts: [depositor_base, depositor_quote],
+            trader_token_accounts: [trader_base, trader_quote],
+            market_vaults: [market_base_vault, market_quote_vault],
+            mints: [base_mint, quote_mint],
+        })
+    }
+
+    fn load_position(&self, program_id: &Pubkey) -> Result<VaultPosition, ProgramError> {
+        require!(
+            self.position.owner == program_id,
+            ProgramError::IllegalOwner,
+            "Position {:?} is not owned by this program",
+            self.position.key
+        )?;
+        let position: VaultPosition =
+            *bytemuck::from_bytes::<VaultPosition>(&self.position.try_borrow_data()?);
+        require!(
+            position.vault == *self.vault.key && position.owner == *self.depositor.key,
+            ProgramError::InvalidAccountData,
+            "Position {:?} belongs to a different vault or owner",
+            self.position.key
+        )?;
+        Ok(position)
+    }
+
+    fn save(&self, position: &VaultPosition) -> ProgramResult {
+        save_vault_state(self.vault, &self.vault_state)?;
+        self.position
+            .try_borrow_mut_data()?
+            .copy_from_slice(bytemuck::bytes_of(position));
+        Ok(())
+    }
+
+    fn manifest_account_infos(&self, side: usize) -> [AccountInfo<'info>; 7] {
+        [
+            self.manifest_program.info.clone(),
+            self.trader.clone(),
+            self.market.info.clone(),
+            self.trader_token_accounts[side].info.clone(),
+            self.market_vaults[side].clone(),
+            self.token_program.info.clone(),
+            self.mints[side].clone(),
+        ]
+    }
+}
+
+pub(crate) fn process_deposit(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    let mut context: TransferContext = TransferContext::load(program_id, accounts)?;
+    let DepositParams {
+        max_base_atoms,
+        max_quote_atoms,
+        min_shares,
+    } = DepositParams::try_from_slice(data)?;
+
+    let holdings: Holdings = read_holdings(&context.market, context.trader.key)?;
+    let (shares, base_atoms, quote_atoms) = shares_for_deposit(
+        context.vault_state.total_shares,
+        &holdings,
+        max_base_atoms,
+        max_quote_atoms,
+    )
+    .ok_or(ProgramError::InvalidArgument)?;
+    require!(
+        shares >= min_shares,
+        ProgramError::InvalidArgument,
+        "Deposit mints {} shares, less than the minimum {}",
+        shares,
+        min_shares
+    )?;
+
+    for (side, amount_atoms) in [base_atoms, quote_atoms].into_iter().enumerate() {
+        if amount_atoms == 0 {
+            continue;
+        }
+        invoke(
+            &spl_token::instruction::transfer(
+                &spl_token::id(),
+                context.depositor_token_accounts[side].info.key,
+                context.trader_token_accounts[side].info.key,
+                context.depositor.key,
+                &[],
+                amount_atoms,
+            )?,
+            &[
+                context.token_program.info.clone(),
+                context.depositor_token_accounts[side].info.clone(),
+                context.trader_token_accounts[side].info.clone(),
+                context.depositor.info.clone(),
+            ],
+        )?;
+        context.pda_trader.invoke(
+            &context.pda_trader.deposit_instruction(
+                context.market.key,
+                context.mints[side].key,
+                amount_atoms,
+                context.trader_token_accounts[side].info.key,
+                spl_token::id(),
+                None,
+            ),
+            &context.manifest_account_infos(side),
+        )?;
+    }
+
+    let mut position: VaultPosition = if context.position.data_is_empty() {
+        let (position_key, position_bump) = Pubkey::find_program_address(
+            &[
+                POSITION_SEED,
+                context.vault.key.as_ref(),
+                context.depositor.key.as_ref(),
+            ],
+            program_id,
+        );
+        require!(
+            position_key == *context.position.key,
+            ProgramError::InvalidSeeds,
+            "Position {:?} is not the position of {:?}",
+            context.position.key,
+            context.depositor.key
+        )?;
+        create_account(
+            context.depositor.info,
+            context.position,
+            context.system_program.info,
+            program_id,
+            &Rent::get()?,
+            size_of::<VaultPosition>() as u64,
+            vec![
+                POSITION_SEED.to_vec(),
+                context.vault.key.to_bytes().to_vec(),
+                context.depositor.key.to_bytes().to_vec(),
+                vec![position_bump],
+            ],
+        )?;
+        VaultPosition {
+            vault: *context.vault.key,
+            owner: *context.depositor.key,
+            shares: 0,
+        }
+    } else {
+        context.load_position(program_id)?
+    };
+    position.shares += shares;
+    context.vault_state.total_shares += shares;
+    context.save(&position)
+}
+
+pub(crate) fn process_withdraw(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    let mut context: TransferContext = TransferContext::load(program_id, accounts)?;
+    let WithdrawParams {
+        shares,
+        min_base_atoms,
+        min_quote_atoms,
+    } = WithdrawParams::try_from_slice(data)?;
+
+    let mut position: VaultPosition = context.load_position(program_id)?;
+    require!(
+        shares > 0 && shares <= position.shares,
+        ProgramError::InsufficientFunds,
+        "Withdrawing {} shares from a position of {}",
+        shares,
+        position.shares
+    )?;
+
+    // Unlock everything so the payout can come from the withdrawable
+    // balance. The authority places new orders with the next rebalance.
+    let holdings: Holdings = read_holdings(&context.market, context.trader.key)?;
+    batch_update(
+        &context.pda_trader,
+        context.trader,
+        &context.market,
+        &context.manifest_program,
+        &context.system_program,
+        holdings
+            .order_sequence_numbers
+            .iter()
+            .map(|sequence_number| CancelOrderParams::new(*sequence_number))
+            .collect(),
+        Vec::new(),
+    )?;
+    let holdings: Holdings = read_holdings(&context.market, context.trader.key)?;
+    let (base_atoms, quote_atoms) =
+        atoms_for_withdraw(context.vault_state.total_shares, &holdings, shares);
+    require!(
+        base_atoms >= min_base_atoms && quote_atoms >= min_quote_atoms,
+        ProgramError::InvalidArgument,
+        "Withdraw pays {} base and {} quote atoms, less than the minimum",
+        base_atoms,
+        quote_atoms
+    )?;
+
+    for (side, amount_atoms) in [base_atoms, quote_atoms].into_iter().enumerate() {
+        if amount_atoms == 0 {
+            continue;
+        }
+        context.pda_trader.invoke(
+            &context.pda_trader.withdraw_instruction(
+                context.market.key,
+                context.mints[side].key,
+                amount_atoms,
+                context.trader_token_accounts[side].info.key,
+                spl_token::id(),
+                None,
+            ),
+            &context.manifest_account_infos(side),
+        )?;
+        context.pda_trader.invoke(
+            &spl_token::instruction::transfer(
+                &spl_token::id(),
+                context.trader_token_accounts[side].info.key,
+                context.depositor_token_accounts[side].info.key,
+                context.trader.key,
+                &[],
+                amount_atoms,
+            )?,
+            &[
+                context.token_program.info.clone(),
+                context.trader_token_accounts[side].info.clone(),
+                context.depositor_token_accounts[side].info.clone(),
+                context.trader.clone(),
+            ],
+        )?;
+    }
+
+    position.shares -= shares;
+    context.vault_state.total_shares -= shares;
+    context.save(&position)
+}
+
+pub(crate) fn process_rebalance(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    let account_iter: &mut std::slice::Iter<AccountInfo> = &mut accounts.iter();
+    let authority: Signer = Signer::new(next_account_info(account_iter)?)?;
+    let vault: &AccountInfo = next_account_info(account_iter)?;
+    let trader: &AccountInfo = next_account_info(account_iter)?;
+    let market: ManifestAccountInfo<MarketFixed> =
+        ManifestAccountInfo::<MarketFixed>::new(next_account_info(account_iter)?)?;
+    let manifest_program: Program =
+        Program::new(next_account_info(account_iter)?, &manifest::id())?;
+    let system_program: Program =
+        Program::new(next_account_info(account_iter)?, &system_program::id())?;
+
+    let vault_state: VaultState = load_vault_state(program_id, vault, market.key)?;
+    require!(
+        vault_state.authority == *authority.key,
+        ProgramError::MissingRequiredSignature,
+        "Only the vault authority can rebalance",
+    )?;
+    let pda_trader: PdaTrader = load_trader(program_id, vault.key, &vault_state, trader)?;
+    let RebalanceParams {
+        bid_price_mantissa,
+        bid_price_exponent,
+        ask_price_mantissa,
+        ask_price_exponent,
+        spread,
+    } = RebalanceParams::try_from_slice(data)?;
+
+    // Cancels in a batch update run before the places, so the new orders
+    // can use everything the old ones had locked.
+    let holdings: Holdings = read_holdings(&market, trader.key)?;
+    let bid_base_atoms: u64 = QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(
+        bid_price_mantissa,
+        bid_price_exponent,
+    )?
+    .checked_base_for_quote(QuoteAtoms::new(holdings.quote_atoms), false)?
+    .as_u64();
+    let orders: Vec<PlaceOrderParams> = [
+        (bid_base_atoms, bid_price_mantissa, bid_price_exponent, true),
+        (
+            holdings.base_atoms,
+            ask_price_mantissa,
+            ask_price_exponent,
+            false,
+        ),
+    ]
+    .into_iter()
+    .filter(|(base_atoms, ..)| *base_atoms > 0)
+    .map(|(base_atoms, price_mantissa, price_exponent, is_bid)| {
+        // Reverse orders take the spread in place of the last valid slot.
+        PlaceOrderParams::new(
+            base_atoms,
+            price_mantissa,
+            price_exponent,
+            is_bid,
+            OrderType::Reverse,
+            spread as u32,
+        )
+    })
+    .collect();
+
+    batch_update(
+        &pda_trader,
+        trader,
+        &market,
+        &manifest_program,
+        &system_program,
+        holdings
+            .order_sequence_numbers
+            .iter()
+            .map(|sequence_number| CancelOrderParams::new(*sequence_number))
+            .collect(),
+        orders,
+    )
+}
@@ -0,0 +1,201 @@
+use bytemuck::{Pod, Zeroable};
+use hypertree::{DataIndex, HyperTreeValueIteratorTrait};
+use manifest::{
+    quantities::{BaseAtoms, QuoteAtoms, WrapperU64},
+    state::{MarketRef, RestingOrder},
+};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+pub const VAULT_SEED: &[u8] = b"vault";
+pub const TRADER_SEED: &[u8] = b"trader";
+pub const POSITION_SEED: &[u8] = b"position";
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Pod, Zeroable)]
+pub struct VaultState {
+    pub market: Pubkey,
+    pub authority: Pubkey,
+    pub base_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    pub total_shares: u64,
+    pub vault_bump: u8,
+    pub trader_bump: u8,
+    _padding: [u8; 6],
+}
+
+impl VaultState {
+    pub fn new(
+        market: &Pubkey,
+        authority: &Pubkey,
+        base_mint: &Pubkey,
+        quote_mint: &Pubkey,
+        vault_bump: u8,
+        trader_bump: u8,
+    ) -> Self {
+        VaultState {
+            market: *market,
+            authority: *authority,
+            base_mint: *base_mint,
+            quote_mint: *quote_mint,
+            total_shares: 0,
+            vault_bump,
+            trader_bump,
+            _padding: [0; 6],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default, Pod, Zeroable)]
+pub struct VaultPosition {
+    pub vault: Pubkey,
+    pub owner: Pubkey,
+    pub shares: u64,
+}
+
+pub fn get_vault_address(market: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[VAULT_SEED, market.as_ref()], &crate::id())
+}
+
+pub fn get_trader_address(vault: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[TRADER_SEED, vault.as_ref()], &crate::id())
+}
+
+pub fn get_position_address(vault: &Pubkey, owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[POSITION_SEED, vault.as_ref(), owner.as_ref()],
+        &crate::id(),
+    )
+}
+
+/// Everything the vault owns on the market, withdrawable or locked in its
+/// orders, and the orders to cancel to unlock it.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Holdings {
+    pub base_atoms: u64,
+    pub quote_atoms: u64,
+    pub order_sequence_numbers: Vec<u64>,
+}
+
+pub fn get_holdings(market: &MarketRef, trader: &Pubkey) -> Result<Holdings, ProgramError> {
+    let (base_withdrawable, quote_withdrawable): (BaseAtoms, QuoteAtoms) =
+        market.get_trader_balance(trader);
+    let mut holdings: Holdings = Holdings {
+        base_atoms: base_withdrawable.as_u64(),
+        quote_atoms: quote_withdrawable.as_u64(),
+        order_sequence_numbers: Vec::new(),
+    };
+    let trader_index: DataIndex = market.get_trader_index(trader);
+    for (_, order) in market
+        .get_bids()
+        .iter::<RestingOrder>()
+        .chain(market.get_asks().iter::<RestingOrder>())
+    {
+        if order.get_trader_index() != trader_index {
+            continue;
+        }
+        if order.get_is_bid() {
+            holdings.quote_atoms += order
+                .get_price()
+                .checked_quote_for_base(order.get_num_base_atoms(), true)?
+                .as_u64();
+        } else {
+            holdings.base_atoms += order.get_num_base_atoms().as_u64();
+        }
+        holdings
+            .order_sequence_numbers
+            .push(order.get_sequence_number());
+    }
+    Ok(holdings)
+}
+
+/// Shares minted for depositing at most `max_base_atoms` and
+/// `max_quote_atoms`, with the atoms actually taken. Deposits keep the ratio
+/// the vault holds, rounding in favor of the vault. The first deposit mints
+/// the geometric mean of the amounts. None if no shares would be minted.
+pub fn shares_for_deposit(
+    total_shares: u64,
+    holdings: &Holdings,
+    max_base_atoms: u64,
+    max_quote_atoms: u64,
+) -> Option<(u64, u64, u64)> {
+    if total_shares == 0 {
+        let shares: u64 =
+            u64::try_from((max_base_atoms as u128 * max_quote_atoms as u128).isqrt()).ok()?;
+        return (shares > 0).then_some((shares, max_base_atoms, max_quote_atoms));
+    }
+    let shares_for = |amount_atoms: u64, total_atoms: u64| -> u128 {
+        if total_atoms == 0 {
+            u128::MAX
+        } else {
+            amount_atoms as u128 * total_shares as u128 / total_atoms as u128
+        }
+    };
+    let shares: u128 = shares_for(max_base_atoms, holdings.base_atoms)
+        .min(shares_for(max_quote_atoms, holdings.quote_atoms));
+    if shares == 0 || shares == u128::MAX {
+        return None;
+    }
+    let atoms_for = |total_atoms: u64| -> u64 {
+        (shares * total_atoms as u128).div_ceil(total_shares as u128) as u64
+    };
+    Some((
+        u64::try_from(shares).ok()?,
+        atoms_for(holdings.base_atoms),
+        atoms_for(holdings.quote_atoms),
+    ))
+}
+
+/// Base and quote atoms paid out for burning `shares`, rounded down.
+pub fn atoms_for_withdraw(total_shares: u64, holdings: &Holdings, shares: u64) -> (u64, u64) {
+    let atoms_for = |total_atoms: u64| -> u64 {
+        (shares as u128 * total_atoms as u128 / total_shares as u128) as u64
+    };
+    (
+        atoms_for(holdings.base_atoms),
+        atoms_for(holdings.quote_atoms),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_share_math() {
+        let empty: Holdings = Holdings::default();
+        assert_eq!(
+            shares_for_deposit(0, &empty, 4_000, 9_000),
+            Some((6_000, 4_000, 9_000))
+        );
+        assert_eq!(shares_for_deposit(0, &empty, 0, 9_000), None);
+
+        // Fills moved the vault to 2_000 base and 12_000 quote.
+        let holdings: Holdings = Holdings {
+            base_atoms: 2_000,
+            quote_atoms: 12_000,
+            order_sequence_numbers: vec![],
+        };
+        // Quote is the limit, half the vault.
+        assert_eq!(
+            shares_for_deposit(6_000, &holdings, 5_000, 6_000),
+            Some((3_000, 1_000, 6_000))
+        );
+        // Rounding takes at least the value of the shares.
+        assert_eq!(shares_for_deposit(6_000, &holdings, 1, 2), Some((1, 1, 2)));
+
+        assert_eq!(atoms_for_withdraw(6_000, &holdings, 3_000), (1_000, 6_000));
+        assert_eq!(atoms_for_withdraw(6_000, &holdings, 1), (0, 2));
+
+        // A vault that holds only quote takes no base.
+        let quote_only: Holdings = Holdings {
+            base_atoms: 0,
+            quote_atoms: 12_000,
+            order_sequence_numbers: vec![],
+        };
+        assert_eq!(
+            shares_for_deposit(6_000, &quote_only, 5_000, 6_000),
+            Some((3_000, 0, 6_000))
+        );
+    }
+}
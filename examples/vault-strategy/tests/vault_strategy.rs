@@ -0,0 +1,277 @@
+use std::mem::size_of;
+
+use manifest::{
+    program::create_market_instructions,
+    state::{MarketFixed, MarketRef},
+};
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account, instruction::Instruction, program_pack::Pack, pubkey::Pubkey, rent::Rent,
+    signature::Keypair, signer::Signer, system_instruction, transaction::Transaction,
+};
+use spl_token::state::{Account as TokenAccount, Mint};
+use vault_strategy::{
+    instruction::RebalanceParams,
+    instruction_builders::{
+        create_trader_token_account_instructions, deposit_instruction, initialize_instruction,
+        rebalance_instruction, withdraw_instruction, VaultTokenAccounts,
+    },
+    state::{get_holdings, get_trader_address, get_vault_address, Holdings},
+};
+
+const SOL_UNIT_SIZE: u64 = 1_000_000_000;
+const USDC_UNIT_SIZE: u64 = 1_000_000;
+
+async fn send(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) {
+    let payer: Keypair = context.payer.insecure_clone();
+    let blockhash = context.get_new_latest_blockhash().await.unwrap();
+    let transaction: Transaction = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&payer.pubkey()),
+        &[&[&payer], signers].concat(),
+        blockhash,
+    );
+    context
+        .banks_client
+        .process_transaction(transaction)
+        .await
+        .unwrap();
+}
+
+async fn get_account(context: &mut ProgramTestContext, key: &Pubkey) -> Account {
+    context
+        .banks_client
+        .get_account(*key)
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+async fn get_balance_atoms(context: &mut ProgramTestContext, token_account: &Pubkey) -> u64 {
+    TokenAccount::unpack(&get_account(context, token_account).await.data)
+        .unwrap()
+        .amount
+}
+
+async fn create_mint(context: &mut ProgramTestContext, decimals: u8) -> Pubkey {
+    let mint: Keypair = Keypair::new();
+    let payer: Pubkey = context.payer.pubkey();
+    send(
+        context,
+        &[
+            system_instruction::create_account(
+                &payer,
+                &mint.pubkey(),
+                Rent::default().minimum_balance(Mint::LEN),
+                Mint::LEN as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_mint2(
+                &spl_token::id(),
+                &mint.pubkey(),
+                &payer,
+                None,
+                decimals,
+            )
+            .unwrap(),
+        ],
+        &[&mint],
+    )
+    .await;
+    mint.pubkey()
+}
+
+async fn create_funded_token_account(
+    context: &mut ProgramTestContext,
+    mint: &Pubkey,
+    amount_atoms: u64,
+) -> Pubkey {
+    let token_account: Keypair = Keypair::new();
+    let payer: Pubkey = context.payer.pubkey();
+    send(
+        context,
+        &[
+            system_instruction::create_account(
+                &payer,
+                &token_account.pubkey(),
+                Rent::default().minimum_balance(TokenAccount::LEN),
+                TokenAccount::LEN as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_account3(
+                &spl_token::id(),
+                &token_account.pubkey(),
+                mint,
+                &payer,
+            )
+            .unwrap(),
+            spl_token::instruction::mint_to(
+                &spl_token::id(),
+                mint,
+                &token_account.pubkey(),
+                &payer,
+                &[],
+                amount_atoms,
+            )
+            .unwrap(),
+        ],
+        &[&token_account],
+    )
+    .await;
+    token_account.pubkey()
+}
+
+async fn get_vault_holdings(context: &mut ProgramTestContext, market: &Pubkey) -> Holdings {
+    let (vault, _) = get_vault_address(market);
+    let (trader, _) = get_trader_address(&vault);
+    let market_account: Account = get_account(context, market).await;
+    let (fixed_data, dynamic) = market_account.data.split_at(size_of::<MarketFixed>());
+    let market_ref: MarketRef = MarketRef {
+        fixed: bytemuck::from_bytes::<MarketFixed>(fixed_data),
+        dynamic,
+    };
+    get_holdings(&market_ref, &trader).unwrap()
+}
+
+#[tokio::test]
+async fn test_vault_strategy() {
+    let mut program: ProgramTest = ProgramTest::new(
+        "vault_strategy",
+        vault_strategy::id(),
+        processor!(vault_strategy::process_instruction),
+    );
+    program.add_program(
+        "manifest",
+        manifest::id(),
+        processor!(manifest::process_instruction),
+    );
+    let mut context: ProgramTestContext = program.start_with_context().await;
+    let payer: Pubkey = context.payer.pubkey();
+
+    let base_mint: Pubkey = create_mint(&mut context, 9).await;
+    let quote_mint: Pubkey = create_mint(&mut context, 6).await;
+    let market: Keypair = Keypair::new();
+    send(
+        &mut context,
+        &create_market_instructions(&market.pubkey(), &base_mint, &quote_mint, &payer).unwrap(),
+        &[&market],
+    )
+    .await;
+    let market: Pubkey = market.pubkey();
+
+    send(
+        &mut context,
+        &[initialize_instruction(&market, &payer, SOL_UNIT_SIZE)],
+        &[],
+    )
+    .await;
+
+    let trader_base: Keypair = Keypair::new();
+    let trader_quote: Keypair = Keypair::new();
+    send(
+        &mut context,
+        &[
+            create_trader_token_account_instructions(
+                &market,
+                &payer,
+                &trader_base.pubkey(),
+                &base_mint,
+            ),
+            create_trader_token_account_instructions(
+                &market,
+                &payer,
+                &trader_quote.pubkey(),
+                &quote_mint,
+            ),
+        ]
+        .concat(),
+        &[&trader_base, &trader_quote],
+    )
+    .await;
+    let token_accounts: VaultTokenAccounts = VaultTokenAccounts {
+        base_mint,
+        quote_mint,
+        depositor_base: create_funded_token_account(&mut context, &base_mint, 10 * SOL_UNIT_SIZE)
+            .await,
+        depositor_quote: create_funded_token_account(
+            &mut context,
+            &quote_mint,
+            1_000 * USDC_UNIT_SIZE,
+        )
+        .await,
+        trader_base: trader_base.pubkey(),
+        trader_quote: trader_quote.pubkey(),
+    };
+
+    // 1 SOL and 150 USDC, the first deposit mints their geometric mean.
+    send(
+        &mut context,
+        &[deposit_instruction(
+            &market,
+            &payer,
+            &token_accounts,
+            SOL_UNIT_SIZE,
+            150 * USDC_UNIT_SIZE,
+            387_298_334,
+        )],
+        &[],
+    )
+    .await;
+    assert_eq!(
+        get_balance_atoms(&mut context, &token_accounts.depositor_base).await,
+        9 * SOL_UNIT_SIZE
+    );
+
+    // Quote the pool at 149 / 151 USDC per SOL.
+    send(
+        &mut context,
+        &[rebalance_instruction(
+            &market,
+            &payer,
+            RebalanceParams {
+                bid_price_mantissa: 149,
+                bid_price_exponent: -3,
+                ask_price_mantissa: 151,
+                ask_price_exponent: -3,
+                spread: 100,
+            },
+        )],
+        &[],
+    )
+    .await;
+    let holdings: Holdings = get_vault_holdings(&mut context, &market).await;
+    assert_eq!(holdings.order_sequence_numbers.len(), 2);
+    assert_eq!(holdings.base_atoms, SOL_UNIT_SIZE);
+    assert_eq!(holdings.quote_atoms, 150 * USDC_UNIT_SIZE);
+
+    // The only depositor gets everything back and the book is empty.
+    send(
+        &mut context,
+        &[withdraw_instruction(
+            &market,
+            &payer,
+            &token_accounts,
+            387_298_334,
+            SOL_UNIT_SIZE,
+            150 * USDC_UNIT_SIZE,
+        )],
+        &[],
+    )
+    .await;
+    assert_eq!(
+        get_balance_atoms(&mut context, &token_accounts.depositor_base).await,
+        10 * SOL_UNIT_SIZE
+    );
+    assert_eq!(
+        get_balance_atoms(&mut context, &token_accounts.depositor_quote).await,
+        1_000 * USDC_UNIT_SIZE
+    );
+    assert_eq!(
+        get_vault_holdings(&mut context, &market).await,
+        Holdings::default()
+    );
+}
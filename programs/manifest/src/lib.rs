@@ -1,22 +1,40 @@
 //! Manifest is a limit order book exchange on the Solana blockchain.
 //!
 
+pub mod quantities;
+
+#[cfg(not(feature = "quantities-only"))]
 pub mod logs;
+#[cfg(not(feature = "quantities-only"))]
 pub mod program;
-pub mod quantities;
+#[cfg(not(feature = "quantities-only"))]
 pub mod state;
+#[cfg(not(feature = "quantities-only"))]
 pub mod utils;
+#[cfg(not(feature = "quantities-only"))]
 pub mod validation;
 
+// Price math on its own for other programs that need to compute the same
+// prices and amounts as manifest. Only the errors that quantities return are
+// kept from the program module.
+#[cfg(feature = "quantities-only")]
+pub mod program {
+    pub mod error;
+    pub use error::*;
+}
+
 // re-export dependencies to allow easy access in dependent packages
 pub mod deps {
     pub use hypertree;
+    pub use solana_program;
 }
 
 #[cfg(feature = "certora")]
 pub mod certora;
 
+#[cfg(not(feature = "quantities-only"))]
 use hypertree::trace;
+#[cfg(not(feature = "quantities-only"))]
 use program::{
     batch_update::process_batch_update, claim_seat::process_claim_seat,
     create_market::process_create_market, deposit::process_deposit,
@@ -26,8 +44,10 @@ use program::{
     global_withdraw::process_global_withdraw, process_swap, withdraw::process_withdraw,
     ManifestInstruction,
 };
+use solana_program::declare_id;
+#[cfg(not(feature = "quantities-only"))]
 use solana_program::{
-    account_info::AccountInfo, declare_id, entrypoint::ProgramResult, program_error::ProgramError,
+    account_info::AccountInfo, entrypoint::ProgramResult, program_error::ProgramError,
     pubkey::Pubkey,
 };
 
@@ -98,6 +118,7 @@ declare_id!("MNFSTqtC93rEfYHB6hF82sKdZpUDFWkViLByLd1k1Ms");
 #[cfg(not(feature = "no-entrypoint"))]
 solana_program::entrypoint!(process_instruction);
 
+#[cfg(not(feature = "quantities-only"))]
 pub fn process_instruction(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
@@ -181,6 +181,96 @@ pub struct GlobalCleanupLog {
     pub amount_deposited: GlobalAtoms,
 }
 
+/// Version written into the records emitted by the log helpers. Bump when the
+/// layout of `CompactFillLog` or `QuoteLog` changes.
+pub const LOG_RECORD_VERSION: u8 = 1;
+
+/// Smaller version of `FillLog` for programs composing with Manifest. Drops
+/// the mints and sequence numbers which are not known outside of matching.
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod, ShankAccount)]
+pub struct CompactFillLog {
+    pub market: Pubkey,
+    pub maker: Pubkey,
+    pub taker: Pubkey,
+    pub price: QuoteAtomsPerBaseAtom,
+    pub base_atoms: BaseAtoms,
+    pub quote_atoms: QuoteAtoms,
+    pub version: u8,
+    pub taker_is_buy: PodBool,
+    pub _padding: [u8; 14],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Zeroable, Pod, ShankAccount)]
+pub struct QuoteLog {
+    pub market: Pubkey,
+    pub trader: Pubkey,
+    pub in_atoms: u64,
+    pub out_atoms: u64,
+    pub version: u8,
+    pub is_base_in: PodBool,
+    pub is_exact_in: PodBool,
+    pub _padding: [u8; 5],
+}
+
+/// Emit a `CompactFillLog` from a program composing with Manifest.
+#[cfg(feature = "log-helpers")]
+pub fn emit_fill_log(
+    market: &Pubkey,
+    maker: &Pubkey,
+    taker: &Pubkey,
+    price: QuoteAtomsPerBaseAtom,
+    base_atoms: BaseAtoms,
+    quote_atoms: QuoteAtoms,
+    taker_is_buy: bool,
+) -> Result<(), ProgramError> {
+    emit_stack(CompactFillLog {
+        market: *market,
+        maker: *maker,
+        taker: *taker,
+        price,
+        base_atoms,
+        quote_atoms,
+        version: LOG_RECORD_VERSION,
+        taker_is_buy: PodBool::from(taker_is_buy),
+        _padding: [0; 14],
+    })
+}
+
+/// Emit a `QuoteLog` describing the amounts a wrapper expects to swap.
+#[cfg(feature = "log-helpers")]
+pub fn emit_quote_log(
+    market: &Pubkey,
+    trader: &Pubkey,
+    in_atoms: u64,
+    out_atoms: u64,
+    is_base_in: bool,
+    is_exact_in: bool,
+) -> Result<(), ProgramError> {
+    emit_stack(QuoteLog {
+        market: *market,
+        trader: *trader,
+        in_atoms,
+        out_atoms,
+        version: LOG_RECORD_VERSION,
+        is_base_in: PodBool::from(is_base_in),
+        is_exact_in: PodBool::from(is_exact_in),
+        _padding: [0; 5],
+    })
+}
+
+/// Decode a log record written by `emit_stack`. Returns None when the
+/// discriminant does not match or the data is too short.
+pub fn decode_log<T: Pod + Discriminant>(data: &[u8]) -> Option<T> {
+    if data.len() < 8 + std::mem::size_of::<T>() || data[..8] != T::discriminant() {
+        return None;
+    }
+    Some(bytemuck::pod_read_unaligned::<T>(
+        &data[8..8 + std::mem::size_of::<T>()],
+    ))
+}
+
 pub trait Discriminant {
     fn discriminant() -> [u8; 8];
 }
@@ -218,6 +308,8 @@ const GLOBAL_DEPOSIT_LOG_DISCRIMINANT: [u8; 8] = [16, 26, 72, 1, 145, 232, 182,
 const GLOBAL_WITHDRAW_LOG_DISCRIMINANT: [u8; 8] = [206, 118, 67, 64, 124, 109, 157, 201];
 const GLOBAL_EVICT_LOG_DISCRIMINANT: [u8; 8] = [250, 180, 155, 38, 98, 223, 82, 223];
 const GLOBAL_CLEANUP_LOG_DISCRIMINANT: [u8; 8] = [193, 249, 115, 186, 42, 126, 196, 82];
+const COMPACT_FILL_LOG_DISCRIMINANT: [u8; 8] = [224, 145, 119, 232, 63, 249, 137, 62];
+const QUOTE_LOG_DISCRIMINANT: [u8; 8] = [67, 131, 223, 217, 164, 241, 138, 220];
 
 discriminant!(
     CreateMarketLog,
@@ -282,3 +374,32 @@ discriminant!(
     GLOBAL_CLEANUP_LOG_DISCRIMINANT,
     test_global_cleanup_log
 );
+discriminant!(
+    CompactFillLog,
+    COMPACT_FILL_LOG_DISCRIMINANT,
+    test_compact_fill_log
+);
+discriminant!(QuoteLog, QUOTE_LOG_DISCRIMINANT, test_quote_log);
+
+#[test]
+fn test_decode_log() {
+    let log: QuoteLog = QuoteLog {
+        market: Pubkey::new_unique(),
+        trader: Pubkey::new_unique(),
+        in_atoms: 1_000,
+        out_atoms: 10,
+        version: LOG_RECORD_VERSION,
+        is_base_in: PodBool::from(true),
+        is_exact_in: PodBool::from(false),
+        _padding: [0; 5],
+    };
+    let mut data: Vec<u8> = QuoteLog::discriminant().to_vec();
+    data.extend_from_slice(bytemuck::bytes_of(&log));
+
+    let decoded: QuoteLog = decode_log::<QuoteLog>(&data).unwrap();
+    assert_eq!(decoded.market, log.market);
+    assert_eq!(decoded.in_atoms, 1_000);
+    assert_eq!(decoded.version, LOG_RECORD_VERSION);
+    assert!(decode_log::<CompactFillLog>(&data).is_none());
+    assert!(decode_log::<QuoteLog>(&data[..20]).is_none());
+}
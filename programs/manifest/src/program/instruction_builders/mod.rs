@@ -9,6 +9,7 @@ pub mod global_create_instruction;
 pub mod global_deposit_instruction;
 pub mod global_evict_instruction;
 pub mod global_withdraw_instruction;
+pub mod pda_trader;
 pub mod swap_instruction;
 pub mod swap_v2_instruction;
 pub mod withdraw_instruction;
@@ -24,6 +25,7 @@ pub use global_create_instruction::*;
 pub use global_deposit_instruction::*;
 pub use global_evict_instruction::*;
 pub use global_withdraw_instruction::*;
+pub use pda_trader::*;
 pub use swap_instruction::*;
 pub use swap_v2_instruction::*;
 pub use withdraw_instruction::*;
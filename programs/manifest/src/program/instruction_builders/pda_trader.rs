@@ -0,0 +1,141 @@
+use crate::program::batch_update::{CancelOrderParams, PlaceOrderParams};
+use hypertree::DataIndex;
+use solana_program::{
+    account_info::AccountInfo, entrypoint::ProgramResult, instruction::Instruction,
+    program::invoke_signed, program_error::ProgramError, pubkey::Pubkey,
+};
+
+use super::{batch_update_instruction, deposit_instruction, withdraw_instruction};
+
+/// Trader that is a PDA of the calling program, for example a treasury that
+/// market makes without a hot wallet. Instructions are built exactly like for
+/// a keypair trader and then sent through `invoke` with the PDA seeds.
+#[derive(Debug, Clone)]
+pub struct PdaTrader {
+    pub program_id: Pubkey,
+    /// Seeds including the bump.
+    pub seeds: Vec<Vec<u8>>,
+    key: Pubkey,
+}
+
+impl PdaTrader {
+    pub fn new(program_id: &Pubkey, seeds: Vec<Vec<u8>>) -> Result<Self, ProgramError> {
+        let key: Pubkey = Pubkey::create_program_address(
+            &seeds
+                .iter()
+                .map(|seed| seed.as_slice())
+                .collect::<Vec<&[u8]>>(),
+            program_id,
+        )
+        .map_err(|_| ProgramError::InvalidSeeds)?;
+        Ok(PdaTrader {
+            program_id: *program_id,
+            seeds,
+            key,
+        })
+    }
+
+    pub fn get_key(&self) -> &Pubkey {
+        &self.key
+    }
+
+    pub fn deposit_instruction(
+        &self,
+        market: &Pubkey,
+        mint: &Pubkey,
+        amount_atoms: u64,
+        trader_token_account: &Pubkey,
+        token_program: Pubkey,
+        trader_index_hint: Option<DataIndex>,
+    ) -> Instruction {
+        deposit_instruction(
+            market,
+            &self.key,
+            mint,
+            amount_atoms,
+            trader_token_account,
+            token_program,
+            trader_index_hint,
+        )
+    }
+
+    pub fn withdraw_instruction(
+        &self,
+        market: &Pubkey,
+        mint: &Pubkey,
+        amount_atoms: u64,
+        trader_token_account: &Pubkey,
+        token_program: Pubkey,
+        trader_index_hint: Option<DataIndex>,
+    ) -> Instruction {
+        withdraw_instruction(
+            market,
+            &self.key,
+            mint,
+            amount_atoms,
+            trader_token_account,
+            token_program,
+            trader_index_hint,
+        )
+    }
+
+    /// The PDA is also the payer, so it needs lamports if the market has to
+    /// expand for the new orders.
+    pub fn batch_update_instruction(
+        &self,
+        market: &Pubkey,
+        trader_index_hint: Option<DataIndex>,
+        cancels: Vec<CancelOrderParams>,
+        orders: Vec<PlaceOrderParams>,
+    ) -> Instruction {
+        batch_update_instruction(
+            market,
+            &self.key,
+            trader_index_hint,
+            cancels,
+            orders,
+            None,
+            None,
+            None,
+            None,
+        )
+    }
+
+    /// CPI into manifest signing as the PDA.
+    pub fn invoke(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+    ) -> ProgramResult {
+        invoke_signed(
+            instruction,
+            account_infos,
+            &[self
+                .seeds
+                .iter()
+                .map(|seed| seed.as_slice())
+                .collect::<Vec<&[u8]>>()
+                .as_slice()],
+        )
+    }
+}
+
+#[test]
+fn test_pda_trader_signs_deposit() {
+    let program_id: Pubkey = Pubkey::new_unique();
+    let (key, bump) = Pubkey::find_program_address(&[b"treasury"], &program_id);
+    let trader: PdaTrader =
+        PdaTrader::new(&program_id, vec![b"treasury".to_vec(), vec![bump]]).unwrap();
+    assert_eq!(*trader.get_key(), key);
+
+    let instruction: Instruction = trader.deposit_instruction(
+        &Pubkey::new_unique(),
+        &Pubkey::new_unique(),
+        1,
+        &Pubkey::new_unique(),
+        spl_token::id(),
+        None,
+    );
+    assert_eq!(instruction.accounts[0].pubkey, key);
+    assert!(instruction.accounts[0].is_signer);
+}
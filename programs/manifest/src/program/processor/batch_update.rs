@@ -89,6 +89,14 @@ impl PlaceOrderParams {
         self.base_atoms
     }
 
+    pub fn price_mantissa(&self) -> u32 {
+        self.price_mantissa
+    }
+
+    pub fn price_exponent(&self) -> i8 {
+        self.price_exponent
+    }
+
     pub fn try_price(&self) -> Result<QuoteAtomsPerBaseAtom, PriceConversionError> {
         if self.price_exponent > self.order_type().max_exponent() {
             return Err(PriceConversionError(0x3));
@@ -3,7 +3,11 @@ use borsh::{BorshDeserialize as Deserialize, BorshSerialize as Serialize};
 use bytemuck::{Pod, Zeroable};
 use hypertree::trace;
 use shank::ShankAccount;
-use solana_program::program_error::ProgramError;
+use solana_program::{account_info::AccountInfo, program_error::ProgramError};
+use spl_token_2022::{
+    extension::{transfer_fee::TransferFeeConfig, BaseStateWithExtensions, StateWithExtensions},
+    state::Mint,
+};
 use static_assertions::const_assert;
 use std::{
     cmp::Ordering,
@@ -363,6 +367,25 @@ impl QuoteAtomsPerBaseAtom {
         Ok(Self::from_mantissa_and_exponent_(mantissa, exponent))
     }
 
+    /// Inverse of `try_from_mantissa_and_exponent`, using the largest
+    /// exponent that keeps the price exact. Fails for prices no pair can
+    /// express, like the effective price of a fill.
+    pub fn try_to_mantissa_and_exponent(&self) -> Result<(u32, i8), PriceConversionError> {
+        let inner: u128 = u64_slice_to_u128(self.inner);
+        // The last constant is 1, so there is always a match.
+        let (offset, power): (usize, &u128) = DECIMAL_CONSTANTS
+            .iter()
+            .enumerate()
+            .find(|(_, power)| inner % **power == 0)
+            .unwrap();
+        let mantissa: u128 = inner / power;
+        if mantissa > u32::MAX as u128 {
+            trace!("price {inner} has no mantissa and exponent");
+            return Err(PriceConversionError(0x12));
+        }
+        Ok((mantissa as u32, Self::MAX_EXP - offset as i8))
+    }
+
     #[inline(always)]
     pub fn checked_base_for_quote(
         self,
@@ -464,6 +487,36 @@ impl std::fmt::Debug for QuoteAtomsPerBaseAtom {
     }
 }
 
+impl QuoteAtomsPerBaseAtom {
+    /// Lossy conversion for display and off-chain analytics.
+    pub fn as_f64(&self) -> f64 {
+        u64_slice_to_u128(self.inner) as f64 / D18F
+    }
+}
+
+/// Whether a bid at `bid_price` and an ask at `ask_price` trade with each
+/// other. Equal prices cross, the same as in matching.
+#[inline(always)]
+pub fn crosses(bid_price: QuoteAtomsPerBaseAtom, ask_price: QuoteAtomsPerBaseAtom) -> bool {
+    bid_price >= ask_price
+}
+
+/// Whether an order at `order_price` would take against `book_touch`, the
+/// best price on the other side of the book, None when that side is empty.
+/// A PostOnly order for which this is true fails.
+#[inline(always)]
+pub fn would_cross(
+    book_touch: Option<QuoteAtomsPerBaseAtom>,
+    order_price: QuoteAtomsPerBaseAtom,
+    is_bid: bool,
+) -> bool {
+    match book_touch {
+        None => false,
+        Some(touch) if is_bid => crosses(order_price, touch),
+        Some(touch) => crosses(touch, order_price),
+    }
+}
+
 #[derive(Debug)]
 pub struct PriceConversionError(pub u32);
 
@@ -525,6 +578,149 @@ impl TryFrom<f64> for QuoteAtomsPerBaseAtom {
     }
 }
 
+/// Price as a u64 with a fixed decimal exponent, for systems that store
+/// prices in fixed width columns. `value * 10^exponent` is the price in quote
+/// tokens per base token, so mint decimals are already applied.
+///
+/// Conversion from `QuoteAtomsPerBaseAtom` is exact when the exponent is at
+/// most `lossless_exponent`, which is `base_decimals - quote_decimals - 18`.
+/// Every step up in exponent gives up one digit of precision for 10x more
+/// range. Largest price that fits at the lossless exponent:
+///
+/// | base / quote decimals | lossless exponent | max price |
+/// |-----------------------|-------------------|-----------|
+/// | 9 / 6                 | -15               | ~18_446   |
+/// | 8 / 6                 | -16               | ~1_844    |
+/// | 6 / 6, 9 / 9          | -18               | ~18.4     |
+/// | 6 / 9                 | -21               | ~0.0184   |
+#[cfg(not(feature = "certora"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScaledPrice {
+    pub value: u64,
+    pub exponent: i8,
+}
+
+#[cfg(not(feature = "certora"))]
+impl ScaledPrice {
+    pub fn lossless_exponent(base_decimals: u8, quote_decimals: u8) -> i32 {
+        base_decimals as i32 - quote_decimals as i32 - 18
+    }
+
+    // Power of ten that takes the inner price representation to value.
+    fn shift(exponent: i8, base_decimals: u8, quote_decimals: u8) -> i32 {
+        Self::lossless_exponent(base_decimals, quote_decimals) - exponent as i32
+    }
+
+    pub fn try_from_price(
+        price: QuoteAtomsPerBaseAtom,
+        base_decimals: u8,
+        quote_decimals: u8,
+        exponent: i8,
+        round_up: bool,
+    ) -> Result<Self, PriceConversionError> {
+        let inner: u128 = u64_slice_to_u128(price.inner);
+        let shift: i32 = Self::shift(exponent, base_decimals, quote_decimals);
+        let value: u128 = if shift >= 0 {
+            10u128
+                .checked_pow(shift as u32)
+                .and_then(|scale| inner.checked_mul(scale))
+                .ok_or(PriceConversionError(0x10))?
+        } else {
+            match 10u128.checked_pow(shift.unsigned_abs()) {
+                Some(scale) if round_up => inner.div_ceil(scale),
+                Some(scale) => inner.div(scale),
+                // Scale is beyond u128, so only the rounding is left.
+                None => (round_up && inner > 0) as u128,
+            }
+        };
+        if value > ATOM_LIMIT {
+            trace!("price {price} does not fit at exponent {exponent}");
+            return Err(PriceConversionError(0x10));
+        }
+        Ok(ScaledPrice {
+            value: value as u64,
+            exponent,
+        })
+    }
+
+    /// Rounds down when the exponent is above the lossless exponent, so a
+    /// round trip is only guaranteed at or below it.
+    pub fn try_to_price(
+        &self,
+        base_decimals: u8,
+        quote_decimals: u8,
+    ) -> Result<QuoteAtomsPerBaseAtom, PriceConversionError> {
+        let value: u128 = self.value as u128;
+        let shift: i32 = Self::shift(self.exponent, base_decimals, quote_decimals);
+        let inner: u128 = if shift <= 0 {
+            10u128
+                .checked_pow(shift.unsigned_abs())
+                .and_then(|scale| value.checked_mul(scale))
+                .ok_or(PriceConversionError(0x11))?
+        } else {
+            match 10u128.checked_pow(shift as u32) {
+                Some(scale) => value.div(scale),
+                None => 0,
+            }
+        };
+        let price: QuoteAtomsPerBaseAtom = QuoteAtomsPerBaseAtom {
+            inner: u128_to_u64_slice(inner),
+        };
+        if price > QuoteAtomsPerBaseAtom::MAX {
+            trace!("scaled price {self} is above the max price");
+            return Err(PriceConversionError(0x11));
+        }
+        Ok(price)
+    }
+}
+
+#[cfg(not(feature = "certora"))]
+impl Display for ScaledPrice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.exponent >= 0 {
+            return write!(f, "{}{}", self.value, "0".repeat(self.exponent as usize));
+        }
+        let decimals: usize = self.exponent.unsigned_abs() as usize;
+        let digits: String = format!("{:0>width$}", self.value, width = decimals + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - decimals);
+        write!(f, "{whole}.{fraction}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferDirection {
+    /// `atoms` leave the sender, the result is what arrives.
+    Forward,
+    /// `atoms` have to arrive, the result is what the sender has to send.
+    Inverse,
+}
+
+/// Applies the Token-2022 transfer fee of the mint in effect at `epoch`.
+/// Mints of the original token program and mints without the extension
+/// transfer `atoms` unchanged. The fee is in basis points of the sent amount,
+/// rounded up and capped at the maximum fee of the mint.
+pub fn net_after_transfer(
+    mint_info: &AccountInfo,
+    atoms: u64,
+    direction: TransferDirection,
+    epoch: u64,
+) -> Result<u64, ProgramError> {
+    if *mint_info.owner != spl_token_2022::id() {
+        return Ok(atoms);
+    }
+    let mint_data = mint_info.try_borrow_data()?;
+    let mint: StateWithExtensions<Mint> = StateWithExtensions::<Mint>::unpack(&mint_data)?;
+    let Ok(transfer_fee_config) = mint.get_extension::<TransferFeeConfig>() else {
+        return Ok(atoms);
+    };
+    let transfer_fee = transfer_fee_config.get_epoch_fee(epoch);
+    let net_atoms: Option<u64> = match direction {
+        TransferDirection::Forward => transfer_fee.calculate_post_fee_amount(atoms),
+        TransferDirection::Inverse => transfer_fee.calculate_pre_fee_amount(atoms),
+    };
+    Ok(net_atoms.ok_or(ManifestError::Overflow)?)
+}
+
 impl BaseAtoms {
     #[inline(always)]
     pub fn checked_mul(
@@ -755,3 +951,136 @@ fn test_debug() {
         }
     );
 }
+
+#[test]
+fn test_scaled_price() {
+    // 1.2345 quote atoms per base atom is 1234.5 for 9 / 6 decimals.
+    let price: QuoteAtomsPerBaseAtom =
+        QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(12345, -4).unwrap();
+    assert_eq!(ScaledPrice::lossless_exponent(9, 6), -15);
+
+    let lossless: ScaledPrice = ScaledPrice::try_from_price(price, 9, 6, -15, false).unwrap();
+    assert_eq!(lossless.value, 1_234_500_000_000_000_000);
+    assert_eq!(lossless.to_string(), "1234.500000000000000");
+    assert_eq!(lossless.try_to_price(9, 6).unwrap(), price);
+
+    let cents: ScaledPrice = ScaledPrice::try_from_price(price, 9, 6, -2, false).unwrap();
+    assert_eq!(cents.value, 123_450);
+    assert_eq!(cents.to_string(), "1234.50");
+    assert_eq!(cents.try_to_price(9, 6).unwrap(), price);
+
+    // Lossy exponent rounds in the requested direction.
+    assert_eq!(
+        ScaledPrice::try_from_price(price, 9, 6, 1, false)
+            .unwrap()
+            .value,
+        123
+    );
+    assert_eq!(
+        ScaledPrice::try_from_price(price, 9, 6, 1, true)
+            .unwrap()
+            .value,
+        124
+    );
+
+    // Out of range at the lossless exponent.
+    assert!(ScaledPrice::try_from_price(QuoteAtomsPerBaseAtom::MAX, 9, 6, -15, false).is_err());
+}
+
+#[test]
+fn test_crosses() {
+    let one: QuoteAtomsPerBaseAtom =
+        QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(1, 0).unwrap();
+    let two: QuoteAtomsPerBaseAtom =
+        QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(2, 0).unwrap();
+    assert!(crosses(two, one));
+    assert!(crosses(one, one));
+    assert!(!crosses(one, two));
+
+    assert!(would_cross(Some(one), two, true));
+    assert!(!would_cross(Some(two), one, true));
+    assert!(would_cross(Some(two), one, false));
+    assert!(would_cross(Some(one), one, false));
+    assert!(!would_cross(None, one, false));
+}
+
+#[test]
+fn test_net_after_transfer() {
+    use solana_program::pubkey::Pubkey;
+    use spl_token_2022::extension::{
+        transfer_fee::TransferFee, ExtensionType, StateWithExtensionsMut,
+    };
+
+    let mut data: Vec<u8> =
+        vec![
+            0;
+            ExtensionType::try_calculate_account_len::<Mint>(&[ExtensionType::TransferFeeConfig])
+                .unwrap()
+        ];
+    let mut mint: StateWithExtensionsMut<Mint> =
+        StateWithExtensionsMut::<Mint>::unpack_uninitialized(&mut data).unwrap();
+    let transfer_fee_config: &mut TransferFeeConfig =
+        mint.init_extension::<TransferFeeConfig>(true).unwrap();
+    // 1% up to 1_000 atoms, then 5% up to 50 atoms from epoch 10.
+    transfer_fee_config.older_transfer_fee = TransferFee {
+        epoch: 0.into(),
+        maximum_fee: 1_000.into(),
+        transfer_fee_basis_points: 100.into(),
+    };
+    transfer_fee_config.newer_transfer_fee = TransferFee {
+        epoch: 10.into(),
+        maximum_fee: 50.into(),
+        transfer_fee_basis_points: 500.into(),
+    };
+    mint.base.is_initialized = true;
+    mint.pack_base();
+    mint.init_account_type().unwrap();
+
+    let key: Pubkey = Pubkey::new_unique();
+    let mut lamports: u64 = 0;
+    let token_program: Pubkey = spl_token_2022::id();
+    let mint_info: AccountInfo = AccountInfo::new(
+        &key,
+        false,
+        false,
+        &mut lamports,
+        &mut data,
+        &token_program,
+        false,
+        0,
+    );
+    let net = |atoms: u64, direction: TransferDirection, epoch: u64| -> u64 {
+        net_after_transfer(&mint_info, atoms, direction, epoch).unwrap()
+    };
+    assert_eq!(net(10_000, TransferDirection::Forward, 5), 9_900);
+    assert_eq!(net(9_900, TransferDirection::Inverse, 5), 10_000);
+    assert_eq!(net(10_000, TransferDirection::Forward, 10), 9_950);
+    assert_eq!(net(9_950, TransferDirection::Inverse, 10), 10_000);
+    assert_eq!(net(0, TransferDirection::Forward, 10), 0);
+}
+
+#[test]
+fn test_try_to_mantissa_and_exponent() {
+    for (mantissa, exponent) in [(1, 0), (95, -2), (123_456, -18), (u32::MAX, 8), (7, 3)] {
+        let price: QuoteAtomsPerBaseAtom =
+            QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(mantissa, exponent).unwrap();
+        let (round_trip_mantissa, round_trip_exponent) =
+            price.try_to_mantissa_and_exponent().unwrap();
+        assert_eq!(
+            QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(
+                round_trip_mantissa,
+                round_trip_exponent
+            )
+            .unwrap(),
+            price
+        );
+    }
+    let price: QuoteAtomsPerBaseAtom =
+        QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(950, -3).unwrap();
+    assert_eq!(price.try_to_mantissa_and_exponent().unwrap(), (95, -2));
+
+    let unrepresentable: QuoteAtomsPerBaseAtom = QuoteAtomsPerBaseAtom {
+        inner: u128_to_u64_slice(u32::MAX as u128 + 1),
+    };
+    assert!(unrepresentable.try_to_mantissa_and_exponent().is_err());
+}
@@ -1,15 +1,16 @@
 use std::mem::size_of;
 
-#[cfg(feature = "certora")]
-use crate::quantities::WrapperU64;
-use crate::quantities::{BaseAtoms, QuoteAtoms};
+use crate::quantities::{BaseAtoms, QuoteAtoms, WrapperU64};
 use bytemuck::{Pod, Zeroable};
 use shank::ShankType;
-use solana_program::pubkey::Pubkey;
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
 use static_assertions::const_assert_eq;
 use std::cmp::Ordering;
 
-use super::constants::CLAIMED_SEAT_SIZE;
+use super::{
+    constants::CLAIMED_SEAT_SIZE,
+    safe_parse::{ByteReader, SafeParse},
+};
 
 #[repr(C)]
 #[derive(Default, Debug, Copy, Clone, Zeroable, Pod, ShankType)]
@@ -45,6 +46,19 @@ impl ClaimedSeat {
     }
 }
 
+impl SafeParse for ClaimedSeat {
+    fn safe_parse(data: &[u8]) -> Result<Self, ProgramError> {
+        let mut reader: ByteReader = ByteReader::new(data);
+        Ok(ClaimedSeat {
+            trader: reader.read_pubkey()?,
+            base_withdrawable_balance: BaseAtoms::new(reader.read_u64()?),
+            quote_withdrawable_balance: QuoteAtoms::new(reader.read_u64()?),
+            quote_volume: QuoteAtoms::new(reader.read_u64()?),
+            _padding: reader.read_array()?,
+        })
+    }
+}
+
 #[cfg(feature = "certora")]
 impl nondet::Nondet for ClaimedSeat {
     fn nondet() -> Self {
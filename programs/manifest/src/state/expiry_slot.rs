@@ -0,0 +1,90 @@
+//! Expirations of resting orders. Orders store the last slot they can match
+//! in as a u32 to stay small, while the Clock slot is a u64. Comparisons are
+//! done in u64 so a current slot past the u32 range never wraps around and
+//! makes an expired order look live again.
+
+use super::constants::NO_EXPIRATION_LAST_VALID_SLOT;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ExpirySlot {
+    last_valid_slot: u32,
+}
+
+impl ExpirySlot {
+    pub const NONE: ExpirySlot = ExpirySlot {
+        last_valid_slot: NO_EXPIRATION_LAST_VALID_SLOT,
+    };
+
+    /// From the value stored on orders and in instructions, where zero means
+    /// no expiration.
+    pub fn from_last_valid_slot(last_valid_slot: u32) -> Self {
+        ExpirySlot { last_valid_slot }
+    }
+
+    /// Expiration after `last_valid_slot` from the Clock. None when orders
+    /// cannot store it, which is past the u32 range and slot zero.
+    pub fn try_from_slot(last_valid_slot: u64) -> Option<Self> {
+        match u32::try_from(last_valid_slot) {
+            Ok(NO_EXPIRATION_LAST_VALID_SLOT) | Err(_) => None,
+            Ok(last_valid_slot) => Some(ExpirySlot { last_valid_slot }),
+        }
+    }
+
+    /// Expiration for an order that can match for `num_slots` slots starting
+    /// at `current_slot`. None for zero slots.
+    pub fn after(current_slot: u64, num_slots: u64) -> Option<Self> {
+        if num_slots == 0 {
+            return None;
+        }
+        Self::try_from_slot(current_slot.checked_add(num_slots - 1)?)
+    }
+
+    pub fn has_expiration(&self) -> bool {
+        self.last_valid_slot != NO_EXPIRATION_LAST_VALID_SLOT
+    }
+
+    /// The value stored on orders, zero when there is no expiration.
+    pub fn get_last_valid_slot(&self) -> u32 {
+        self.last_valid_slot
+    }
+
+    /// Whether an order can no longer match at `current_slot`.
+    pub fn is_expired(&self, current_slot: u64) -> bool {
+        self.has_expiration() && (self.last_valid_slot as u64) < current_slot
+    }
+
+    /// Whether an order can be placed at `current_slot`. Stricter than not
+    /// being expired, orders that would only be valid for the current slot
+    /// are rejected.
+    pub fn can_place(&self, current_slot: u64) -> bool {
+        !self.has_expiration() || (self.last_valid_slot as u64) > current_slot
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_expiry_slot() {
+        assert!(!ExpirySlot::NONE.is_expired(u64::MAX));
+        assert!(ExpirySlot::NONE.can_place(u64::MAX));
+
+        let expiry_slot: ExpirySlot = ExpirySlot::after(100, 10).unwrap();
+        assert_eq!(expiry_slot.get_last_valid_slot(), 109);
+        assert!(!expiry_slot.is_expired(109));
+        assert!(expiry_slot.is_expired(110));
+        assert!(expiry_slot.can_place(108));
+        assert!(!expiry_slot.can_place(109));
+        // Would be slot 5 if the current slot were truncated to u32.
+        assert!(expiry_slot.is_expired((1 << 32) + 5));
+
+        assert_eq!(ExpirySlot::try_from_slot(0), None);
+        assert_eq!(ExpirySlot::try_from_slot(1 << 32), None);
+        assert_eq!(
+            ExpirySlot::try_from_slot(u32::MAX as u64),
+            Some(ExpirySlot::from_last_valid_slot(u32::MAX))
+        );
+        assert_eq!(ExpirySlot::after(100, 0), None);
+    }
+}
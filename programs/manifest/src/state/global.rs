@@ -9,10 +9,13 @@ use std::{cmp::Ordering, mem::size_of};
 use bytemuck::{Pod, Zeroable};
 use hypertree::{
     get_helper, get_mut_helper, DataIndex, FreeList, Get, HyperTreeReadOperations,
-    HyperTreeWriteOperations, RBNode, RedBlackTree, RedBlackTreeReadOnly, NIL,
+    HyperTreeValueIteratorTrait, HyperTreeWriteOperations, RBNode, RedBlackTree,
+    RedBlackTreeReadOnly, NIL,
 };
 use shank::ShankType;
-use solana_program::{entrypoint::ProgramResult, pubkey::Pubkey};
+use solana_program::{
+    entrypoint::ProgramResult, program_error::ProgramError, pubkey::Pubkey, rent::Rent,
+};
 use static_assertions::const_assert_eq;
 
 use crate::{
@@ -22,6 +25,7 @@ use crate::{
 };
 
 use super::{
+    safe_parse::{ByteReader, SafeParse},
     DerefOrBorrow, DerefOrBorrowMut, DynamicAccount, RestingOrder, GLOBAL_BLOCK_SIZE,
     GLOBAL_DEPOSIT_SIZE, GLOBAL_FIXED_DISCRIMINANT, GLOBAL_FIXED_SIZE, GLOBAL_FREE_LIST_BLOCK_SIZE,
     GLOBAL_TRADER_SIZE, MAX_GLOBAL_SEATS,
@@ -79,6 +83,25 @@ const_assert_eq!(size_of::<GlobalFixed>(), GLOBAL_FIXED_SIZE);
 const_assert_eq!(size_of::<GlobalFixed>() % 8, 0);
 impl Get for GlobalFixed {}
 
+impl SafeParse for GlobalFixed {
+    fn safe_parse(data: &[u8]) -> Result<Self, ProgramError> {
+        let mut reader: ByteReader = ByteReader::new(data);
+        Ok(GlobalFixed {
+            discriminant: reader.read_u64()?,
+            mint: reader.read_pubkey()?,
+            vault: reader.read_pubkey()?,
+            global_traders_root_index: reader.read_u32()?,
+            global_deposits_root_index: reader.read_u32()?,
+            global_deposits_max_index: reader.read_u32()?,
+            free_list_head_index: reader.read_u32()?,
+            num_bytes_allocated: reader.read_u32()?,
+            vault_bump: reader.read_u8()?,
+            global_bump: reader.read_u8()?,
+            num_seats_claimed: reader.read_u16()?,
+        })
+    }
+}
+
 #[repr(C, packed)]
 #[derive(Default, Copy, Clone, Pod, Zeroable)]
 struct GlobalUnusedFreeListPadding {
@@ -190,6 +213,9 @@ impl GlobalFixed {
     pub fn get_vault_bump(&self) -> u8 {
         self.vault_bump
     }
+    pub fn get_global_bump(&self) -> u8 {
+        self.global_bump
+    }
     pub fn needs_eviction(&self) -> bool {
         self.num_seats_claimed >= MAX_GLOBAL_SEATS
     }
@@ -218,6 +244,14 @@ impl GlobalTrader {
             _padding2: 0,
         }
     }
+
+    pub fn get_trader(&self) -> &Pubkey {
+        &self.trader
+    }
+
+    pub fn get_deposit_index(&self) -> DataIndex {
+        self.deposit_index
+    }
 }
 
 impl GlobalDeposit {
@@ -261,6 +295,15 @@ impl<Fixed: DerefOrBorrow<GlobalFixed>, Dynamic: DerefOrBorrow<[u8]>>
         get_global_deposit(fixed, dynamic, trader).is_some()
     }
 
+    /// Lamports held by the global account above its rent exemption. Gas
+    /// prepayments are not tracked per trader because orders can be removed
+    /// without passing the global account, so this is the sum of outstanding
+    /// prepayments and ones that were forfeited and are stranded here.
+    pub fn total_gas_escrowed(&self, global_lamports: u64, rent: &Rent) -> u64 {
+        let DynamicAccount { dynamic, .. } = self.borrow_global();
+        global_lamports.saturating_sub(rent.minimum_balance(GLOBAL_FIXED_SIZE + dynamic.len()))
+    }
+
     pub fn get_balance_atoms(&self, trader: &Pubkey) -> GlobalAtoms {
         let DynamicAccount { fixed, dynamic } = self.borrow_global();
         // If the trader got evicted, then they wont be found.
@@ -272,6 +315,41 @@ impl<Fixed: DerefOrBorrow<GlobalFixed>, Dynamic: DerefOrBorrow<[u8]>>
         }
     }
 
+    /// Whether each trader has at least the requested atoms deposited, the
+    /// same answers as `get_balance_atoms` per request. Walks the trader tree
+    /// once rather than looking up every trader, which is cheaper when
+    /// checking all the global orders on a book.
+    pub fn batch_can_back(&self, requests: &[(Pubkey, GlobalAtoms)]) -> Vec<bool> {
+        let DynamicAccount { fixed, dynamic } = self.borrow_global();
+        let mut can_back: Vec<bool> = vec![false; requests.len()];
+
+        // The tree iterates from the largest trader down, so visit requests
+        // in the same order.
+        let mut request_indexes: Vec<usize> = (0..requests.len()).collect();
+        request_indexes.sort_unstable_by(|a, b| requests[*b].0.cmp(&requests[*a].0));
+
+        let global_trader_tree: GlobalTraderTreeReadOnly =
+            GlobalTraderTreeReadOnly::new(dynamic, fixed.global_traders_root_index, NIL);
+        let mut global_traders = global_trader_tree.iter::<GlobalTrader>().peekable();
+        for request_index in request_indexes {
+            let (trader, desired_global_atoms) = &requests[request_index];
+            while global_traders
+                .next_if(|(_, global_trader)| global_trader.trader > *trader)
+                .is_some()
+            {}
+            let Some((_, global_trader)) = global_traders.peek() else {
+                break;
+            };
+            if global_trader.trader == *trader {
+                let global_deposit: &GlobalDeposit =
+                    get_helper::<RBNode<GlobalDeposit>>(dynamic, global_trader.deposit_index)
+                        .get_value();
+                can_back[request_index] = *desired_global_atoms <= global_deposit.balance_atoms;
+            }
+        }
+        can_back
+    }
+
     pub fn verify_min_balance(&self, trader: &Pubkey) -> ProgramResult {
         let DynamicAccount { fixed, dynamic } = self.borrow_global();
 
@@ -527,6 +605,16 @@ impl<Fixed: DerefOrBorrowMut<GlobalFixed>, Dynamic: DerefOrBorrowMut<[u8]>>
 
         Ok(())
     }
+
+    /// Overwrite the balance of a trader that has a global seat. No tokens
+    /// move, so the vault is out of sync afterwards. Only for setting up test
+    /// scenarios like unbacked global orders.
+    #[cfg(feature = "test")]
+    pub fn set_balance_atoms(&mut self, trader: &Pubkey, num_atoms: GlobalAtoms) -> ProgramResult {
+        let current_atoms: GlobalAtoms = self.get_balance_atoms(trader);
+        self.withdraw_global(trader, current_atoms)?;
+        self.deposit_global(trader, num_atoms)
+    }
 }
 
 fn get_free_address_on_global_fixed(fixed: &mut GlobalFixed, dynamic: &mut [u8]) -> DataIndex {
@@ -593,7 +681,7 @@ fn get_global_deposit<'a>(
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::quantities::WrapperU64;
+    use crate::{quantities::WrapperU64, state::GAS_DEPOSIT_LAMPORTS};
 
     #[test]
     fn test_display_trader() {
@@ -623,4 +711,52 @@ mod test {
         assert!(global_deposit1 > global_deposit2);
         assert!(global_deposit1 != global_deposit2);
     }
+
+    #[test]
+    fn test_total_gas_escrowed() {
+        let global: GlobalValue = GlobalValue {
+            fixed: GlobalFixed::new_empty(&Pubkey::new_unique()),
+            dynamic: vec![0; GLOBAL_BLOCK_SIZE],
+        };
+        let rent: Rent = Rent::default();
+        let rent_exempt: u64 = rent.minimum_balance(GLOBAL_FIXED_SIZE + GLOBAL_BLOCK_SIZE);
+        assert_eq!(
+            global.total_gas_escrowed(rent_exempt + 3 * GAS_DEPOSIT_LAMPORTS, &rent),
+            3 * GAS_DEPOSIT_LAMPORTS
+        );
+        assert_eq!(global.total_gas_escrowed(rent_exempt - 1, &rent), 0);
+    }
+
+    #[test]
+    fn test_batch_can_back() {
+        let mut global: GlobalValue = GlobalValue {
+            fixed: GlobalFixed::new_empty(&Pubkey::new_unique()),
+            dynamic: vec![0; GLOBAL_BLOCK_SIZE * 6],
+        };
+        let traders: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        for (num_atoms, trader) in traders.iter().enumerate() {
+            global.global_expand().unwrap();
+            global.add_trader(trader).unwrap();
+            global
+                .deposit_global(trader, GlobalAtoms::new(100 * num_atoms as u64))
+                .unwrap();
+        }
+
+        let requests: Vec<(Pubkey, GlobalAtoms)> = vec![
+            (traders[1], GlobalAtoms::new(100)),
+            (traders[2], GlobalAtoms::new(201)),
+            (Pubkey::new_unique(), GlobalAtoms::ZERO),
+            (traders[0], GlobalAtoms::ZERO),
+            (traders[1], GlobalAtoms::new(101)),
+            (traders[2], GlobalAtoms::new(200)),
+        ];
+        let expected: Vec<bool> = requests
+            .iter()
+            .map(|(trader, num_atoms)| {
+                global.has_global_seat(trader) && *num_atoms <= global.get_balance_atoms(trader)
+            })
+            .collect();
+        assert_eq!(expected, vec![true, false, false, true, false, true]);
+        assert_eq!(global.batch_can_back(&requests), expected);
+    }
 }
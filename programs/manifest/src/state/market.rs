@@ -12,7 +12,10 @@ use hypertree::{
     RedBlackTreeReadOnly,
 };
 use shank::ShankType;
-use solana_program::{entrypoint::ProgramResult, program_error::ProgramError, pubkey::Pubkey};
+use solana_program::{
+    account_info::AccountInfo, clock::Clock, entrypoint::ProgramResult,
+    program_error::ProgramError, pubkey::Pubkey, sysvar::Sysvar,
+};
 use static_assertions::const_assert_eq;
 use std::mem::size_of;
 
@@ -42,6 +45,9 @@ use super::{
     MARKET_FREE_LIST_BLOCK_SIZE, NO_EXPIRATION_LAST_VALID_SLOT,
 };
 
+#[cfg(not(feature = "certora"))]
+use super::safe_parse::{ByteReader, SafeParse};
+
 #[path = "market_helpers.rs"]
 pub mod market_helpers;
 use market_helpers::*;
@@ -115,6 +121,305 @@ pub struct AddOrderToMarketResult {
     pub quote_atoms_traded: QuoteAtoms,
 }
 
+/// What the impact functions do at a global order when the global account for
+/// it was not passed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum GlobalShortfall {
+    /// Stop walking, which is what a swap without the global account does.
+    #[default]
+    Stop,
+    /// Walk past the order and keep quoting the depth behind it. A swap only
+    /// gets that depth if it passes the global account, so this is for
+    /// integrators that add the account when the quote needs it.
+    Skip,
+}
+
+/// Restricts which makers the impact functions walk through, so a router can
+/// quote without its own resting liquidity or without blocked makers.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OrderFilter {
+    excluded_traders: Vec<Pubkey>,
+    included_traders: Option<Vec<Pubkey>>,
+    skip_global: bool,
+    global_shortfall: GlobalShortfall,
+    max_orders: Option<usize>,
+}
+
+impl OrderFilter {
+    /// Only orders from makers not in `traders`.
+    pub fn exclude_traders(traders: &[Pubkey]) -> Self {
+        OrderFilter {
+            excluded_traders: traders.to_vec(),
+            included_traders: None,
+            skip_global: false,
+            global_shortfall: GlobalShortfall::Stop,
+            max_orders: None,
+        }
+    }
+
+    /// Only orders from makers in `traders`.
+    pub fn include_traders(traders: &[Pubkey]) -> Self {
+        OrderFilter {
+            excluded_traders: Vec::new(),
+            included_traders: Some(traders.to_vec()),
+            skip_global: false,
+            global_shortfall: GlobalShortfall::Stop,
+            max_orders: None,
+        }
+    }
+
+    /// Walk past global orders instead of stopping at the first one. For
+    /// integrators that never pass global accounts and still want all of
+    /// the non-global depth.
+    pub fn skip_global_orders(mut self) -> Self {
+        self.skip_global = true;
+        self
+    }
+
+    pub fn get_skip_global(&self) -> bool {
+        self.skip_global
+    }
+
+    pub fn with_global_shortfall(mut self, global_shortfall: GlobalShortfall) -> Self {
+        self.global_shortfall = global_shortfall;
+        self
+    }
+
+    pub fn get_global_shortfall(&self) -> GlobalShortfall {
+        self.global_shortfall
+    }
+
+    /// Stop the walk after `max_orders` orders, counting expired and
+    /// filtered out ones, to bound the time a quote can take on a book full
+    /// of small orders. The bounded impact functions report when this cut
+    /// the walk short.
+    pub fn with_max_orders(mut self, max_orders: usize) -> Self {
+        self.max_orders = Some(max_orders);
+        self
+    }
+
+    pub fn get_max_orders(&self) -> Option<usize> {
+        self.max_orders
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.excluded_traders.is_empty() && self.included_traders.is_none() && !self.skip_global
+    }
+
+    pub fn accepts(&self, trader: &Pubkey) -> bool {
+        if self.excluded_traders.contains(trader) {
+            return false;
+        }
+        match &self.included_traders {
+            Some(included_traders) => included_traders.contains(trader),
+            None => true,
+        }
+    }
+}
+
+/// Atoms committed to resting orders. Asks commit base and bids commit quote,
+/// rounded up the same way as when the order was placed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LockedAmounts {
+    pub num_orders: u64,
+    pub base_atoms: BaseAtoms,
+    pub quote_atoms: QuoteAtoms,
+}
+
+impl LockedAmounts {
+    #[cfg(not(feature = "certora"))]
+    fn add_order(&mut self, resting_order: &RestingOrder) -> ProgramResult {
+        self.num_orders += 1;
+        if resting_order.get_is_bid() {
+            self.quote_atoms = self.quote_atoms.checked_add(
+                resting_order
+                    .get_price()
+                    .checked_quote_for_base(resting_order.get_num_base_atoms(), true)?,
+            )?;
+        } else {
+            self.base_atoms = self
+                .base_atoms
+                .checked_add(resting_order.get_num_base_atoms())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LockedValue {
+    /// Held in the market vaults.
+    pub market_backed: LockedAmounts,
+    /// Held in global accounts, and only for as long as the makers keep
+    /// enough deposited there. Not part of the market vault balances.
+    pub global_backed: LockedAmounts,
+    /// Indexed by `OrderType`.
+    pub by_order_type: [LockedAmounts; 6],
+}
+
+impl LockedValue {
+    pub fn get_total(&self) -> Result<LockedAmounts, ProgramError> {
+        Ok(LockedAmounts {
+            num_orders: self.market_backed.num_orders + self.global_backed.num_orders,
+            base_atoms: self
+                .market_backed
+                .base_atoms
+                .checked_add(self.global_backed.base_atoms)?,
+            quote_atoms: self
+                .market_backed
+                .quote_atoms
+                .checked_add(self.global_backed.quote_atoms)?,
+        })
+    }
+
+    pub fn get_order_type(&self, order_type: OrderType) -> LockedAmounts {
+        self.by_order_type[order_type as usize]
+    }
+}
+
+/// Where the dynamic bytes of a market go. Every resting order, claimed seat
+/// and free block takes one `MARKET_BLOCK_SIZE` block.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MarketMemoryReport {
+    pub num_orders: u32,
+    pub num_seats: u32,
+    pub num_free_blocks: u32,
+    pub order_bytes: u32,
+    pub seat_bytes: u32,
+    pub free_bytes: u32,
+    pub num_bytes_allocated: u32,
+}
+
+impl MarketMemoryReport {
+    /// Orders or seats that fit before the market has to expand. Each takes
+    /// a free block, and the program expands only once none are left.
+    pub fn orders_until_expand(&self) -> u32 {
+        self.num_free_blocks
+    }
+
+    /// Allocated bytes not accounted for by orders, seats or the free list.
+    /// Anything but zero means the account is corrupt.
+    pub fn unaccounted_bytes(&self) -> u32 {
+        self.num_bytes_allocated
+            .saturating_sub(self.order_bytes)
+            .saturating_sub(self.seat_bytes)
+            .saturating_sub(self.free_bytes)
+    }
+}
+
+/// Exact in quote together with the exact out quote of its output, see
+/// `quote_both`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DualQuote {
+    pub in_atoms: u64,
+    pub out_atoms: u64,
+    /// Input the book asks for to pay out exactly `out_atoms`.
+    pub inverse_in_atoms: u64,
+}
+
+impl DualQuote {
+    /// Input of the exact in quote that the exact out quote does without.
+    /// Only rounding makes this more than a few atoms, and it is negative
+    /// when the exact out quote asks for more.
+    pub fn get_discrepancy_atoms(&self) -> i128 {
+        self.in_atoms as i128 - self.inverse_in_atoms as i128
+    }
+}
+
+/// Liquidity near the mid price, see `liquidity_within_bps`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LiquidityWithinBps {
+    pub bid_base_atoms: BaseAtoms,
+    pub bid_quote_atoms: QuoteAtoms,
+    pub ask_base_atoms: BaseAtoms,
+    pub ask_quote_atoms: QuoteAtoms,
+}
+
+/// Orders of each side summed per price, best price first, see
+/// `l2_snapshot`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct L2Snapshot {
+    pub bids: Vec<(QuoteAtomsPerBaseAtom, BaseAtoms)>,
+    pub asks: Vec<(QuoteAtomsPerBaseAtom, BaseAtoms)>,
+}
+
+/// What a taker walk of the book matched, see `impact_quote_atoms_result`
+/// and `impact_base_atoms_result`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct QuoteResult {
+    pub is_bid: bool,
+    pub base_atoms: BaseAtoms,
+    pub quote_atoms: QuoteAtoms,
+    /// Price of the first order matched.
+    pub top_of_book_price_opt: Option<QuoteAtomsPerBaseAtom>,
+    pub num_orders_crossed: u32,
+    /// Whether the walk ran out of orders it could match with size left.
+    /// A global order without its global account ends the walk like the end
+    /// of the book does.
+    pub is_book_exhausted: bool,
+    /// Whether the walk stopped at the `max_orders` of the filter.
+    pub is_truncated: bool,
+}
+
+impl QuoteResult {
+    fn record_match(
+        &mut self,
+        price: QuoteAtomsPerBaseAtom,
+        base_atoms: BaseAtoms,
+        quote_atoms: QuoteAtoms,
+    ) -> Result<(), ProgramError> {
+        if base_atoms == BaseAtoms::ZERO {
+            return Ok(());
+        }
+        self.top_of_book_price_opt.get_or_insert(price);
+        self.num_orders_crossed += 1;
+        self.base_atoms = self.base_atoms.checked_add(base_atoms)?;
+        self.quote_atoms = self.quote_atoms.checked_add(quote_atoms)?;
+        Ok(())
+    }
+
+    /// Atoms the taker pays, quote when buying and base when selling.
+    pub fn get_in_atoms(&self) -> u64 {
+        if self.is_bid {
+            self.quote_atoms.as_u64()
+        } else {
+            self.base_atoms.as_u64()
+        }
+    }
+
+    pub fn get_out_atoms(&self) -> u64 {
+        if self.is_bid {
+            self.base_atoms.as_u64()
+        } else {
+            self.quote_atoms.as_u64()
+        }
+    }
+
+    /// Quote atoms per base atom. None when nothing matched.
+    pub fn get_average_price(&self) -> Option<f64> {
+        if self.base_atoms == BaseAtoms::ZERO {
+            return None;
+        }
+        Some(self.quote_atoms.as_u64() as f64 / self.base_atoms.as_u64() as f64)
+    }
+
+    /// How much worse than the top of the book the average price is, in bps.
+    /// Includes rounding, so a fill of a single order can be a little off
+    /// zero.
+    pub fn get_price_impact_bps(&self) -> Option<f64> {
+        let top_price: f64 = self.top_of_book_price_opt?.as_f64();
+        let average_price: f64 = self.get_average_price()?;
+        if top_price == 0.0 {
+            return None;
+        }
+        let impact: f64 = if self.is_bid {
+            average_price - top_price
+        } else {
+            top_price - average_price
+        };
+        Some(impact / top_price * 10_000.0)
+    }
+}
+
 #[repr(C, packed)]
 #[derive(Default, Copy, Clone, Pod, Zeroable)]
 pub struct MarketUnusedFreeListPadding {
@@ -233,6 +538,37 @@ const_assert_eq!(size_of::<MarketFixed>(), MARKET_FIXED_SIZE);
 const_assert_eq!(size_of::<MarketFixed>() % 8, 0);
 impl Get for MarketFixed {}
 
+#[cfg(not(feature = "certora"))]
+impl SafeParse for MarketFixed {
+    fn safe_parse(data: &[u8]) -> Result<Self, ProgramError> {
+        let mut reader: ByteReader = ByteReader::new(data);
+        Ok(MarketFixed {
+            discriminant: reader.read_u64()?,
+            version: reader.read_u8()?,
+            base_mint_decimals: reader.read_u8()?,
+            quote_mint_decimals: reader.read_u8()?,
+            base_vault_bump: reader.read_u8()?,
+            quote_vault_bump: reader.read_u8()?,
+            _padding1: reader.read_array()?,
+            base_mint: reader.read_pubkey()?,
+            quote_mint: reader.read_pubkey()?,
+            base_vault: reader.read_pubkey()?,
+            quote_vault: reader.read_pubkey()?,
+            order_sequence_number: reader.read_u64()?,
+            num_bytes_allocated: reader.read_u32()?,
+            bids_root_index: reader.read_u32()?,
+            bids_best_index: reader.read_u32()?,
+            asks_root_index: reader.read_u32()?,
+            asks_best_index: reader.read_u32()?,
+            claimed_seats_root_index: reader.read_u32()?,
+            free_list_head_index: reader.read_u32()?,
+            _padding2: [reader.read_u32()?],
+            quote_volume: QuoteAtoms::new(reader.read_u64()?),
+            _padding3: reader.read_u64_array()?,
+        })
+    }
+}
+
 impl MarketFixed {
     pub fn new_empty(
         base_mint: &MintAccountInfo,
@@ -344,6 +680,15 @@ impl MarketFixed {
     pub fn get_quote_volume(&self) -> QuoteAtoms {
         self.quote_volume
     }
+    pub fn get_version(&self) -> u8 {
+        self.version
+    }
+    pub fn get_order_sequence_number(&self) -> u64 {
+        self.order_sequence_number
+    }
+    pub fn get_num_bytes_allocated(&self) -> u32 {
+        self.num_bytes_allocated
+    }
 
     // Used only in this file to construct iterator
     pub(crate) fn get_bids_root_index(&self) -> DataIndex {
@@ -504,6 +849,24 @@ impl<Fixed: DerefOrBorrow<MarketFixed>, Dynamic: DerefOrBorrow<[u8]>>
         )
     }
 
+    /// Same as `impact_quote_atoms` with the slot read from `clock`, the
+    /// Clock sysvar account passed to the instruction.
+    pub fn impact_quote_atoms_now(
+        &self,
+        clock: &AccountInfo,
+        is_bid: bool,
+        limit_base_atoms: BaseAtoms,
+        global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2],
+    ) -> Result<QuoteAtoms, ProgramError> {
+        let now_slot: u32 = Clock::from_account_info(clock)?.slot as u32;
+        self.impact_quote_atoms_with_slot(
+            is_bid,
+            limit_base_atoms,
+            global_trade_accounts_opts,
+            now_slot,
+        )
+    }
+
     pub fn impact_quote_atoms_with_slot(
         &self,
         is_bid: bool,
@@ -511,19 +874,124 @@ impl<Fixed: DerefOrBorrow<MarketFixed>, Dynamic: DerefOrBorrow<[u8]>>
         global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2],
         now_slot: u32,
     ) -> Result<QuoteAtoms, ProgramError> {
+        // Note that when there are not enough orders on the market to use up or
+        // to receive the desired number of base atoms, this returns just the
+        // full amount on the bookside without differentiating that return.
+        Ok(self
+            .impact_quote_atoms_result(
+                is_bid,
+                limit_base_atoms,
+                global_trade_accounts_opts,
+                now_slot,
+                &OrderFilter::default(),
+            )?
+            .quote_atoms)
+    }
+
+    /// Same as `impact_quote_atoms_with_slot` but orders from makers rejected
+    /// by the filter are skipped as if they were not on the book.
+    pub fn impact_quote_atoms_with_filter(
+        &self,
+        is_bid: bool,
+        limit_base_atoms: BaseAtoms,
+        global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2],
+        now_slot: u32,
+        filter: &OrderFilter,
+    ) -> Result<QuoteAtoms, ProgramError> {
+        Ok(self
+            .impact_quote_atoms_result(
+                is_bid,
+                limit_base_atoms,
+                global_trade_accounts_opts,
+                now_slot,
+                filter,
+            )?
+            .quote_atoms)
+    }
+
+    /// Same as `impact_quote_atoms_with_filter`, also returning whether the
+    /// walk stopped at the `max_orders` of the filter with base atoms left.
+    pub fn impact_quote_atoms_bounded(
+        &self,
+        is_bid: bool,
+        limit_base_atoms: BaseAtoms,
+        global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2],
+        now_slot: u32,
+        filter: &OrderFilter,
+    ) -> Result<(QuoteAtoms, bool), ProgramError> {
+        let quote_result: QuoteResult = self.impact_quote_atoms_result(
+            is_bid,
+            limit_base_atoms,
+            global_trade_accounts_opts,
+            now_slot,
+            filter,
+        )?;
+        Ok((quote_result.quote_atoms, quote_result.is_truncated))
+    }
+
+    /// Base atoms matched together with the quote atoms
+    /// `impact_quote_atoms_with_slot` returns, from the same single walk of
+    /// the book. Fewer base atoms than `limit_base_atoms` when the book runs
+    /// out first.
+    pub fn impact_atoms_for_base_with_slot(
+        &self,
+        is_bid: bool,
+        limit_base_atoms: BaseAtoms,
+        global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2],
+        now_slot: u32,
+    ) -> Result<(BaseAtoms, QuoteAtoms), ProgramError> {
+        let quote_result: QuoteResult = self.impact_quote_atoms_result(
+            is_bid,
+            limit_base_atoms,
+            global_trade_accounts_opts,
+            now_slot,
+            &OrderFilter::default(),
+        )?;
+        Ok((quote_result.base_atoms, quote_result.quote_atoms))
+    }
+
+    /// The walk behind the quote atom impact functions, with the breakdown of
+    /// what it matched. Orders from makers the filter rejects are skipped as
+    /// if they were not on the book.
+    pub fn impact_quote_atoms_result(
+        &self,
+        is_bid: bool,
+        limit_base_atoms: BaseAtoms,
+        global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2],
+        now_slot: u32,
+        filter: &OrderFilter,
+    ) -> Result<QuoteResult, ProgramError> {
         let book: BooksideReadOnly = if is_bid {
             self.get_asks()
         } else {
             self.get_bids()
         };
 
-        let mut total_matched_quote_atoms: QuoteAtoms = QuoteAtoms::ZERO;
+        let mut quote_result: QuoteResult = QuoteResult {
+            is_bid,
+            ..QuoteResult::default()
+        };
         let mut remaining_base_atoms: BaseAtoms = limit_base_atoms;
+        let mut num_orders_walked: usize = 0;
+        let mut truncated: bool = false;
+        let mut filled: bool = false;
         for (_, resting_order) in book.iter::<RestingOrder>() {
+            if filter
+                .get_max_orders()
+                .is_some_and(|max_orders| num_orders_walked >= max_orders)
+            {
+                truncated = true;
+                break;
+            }
+            num_orders_walked += 1;
+
             // Skip expired orders
             if resting_order.is_expired(now_slot) {
                 continue;
             }
+            if self.is_filtered_out(resting_order, filter) {
+                continue;
+            }
             let matched_price: QuoteAtomsPerBaseAtom = resting_order.get_price();
 
             // Either fill the entire resting order, or only the
@@ -540,10 +1008,261 @@ impl<Fixed: DerefOrBorrow<MarketFixed>, Dynamic: DerefOrBorrow<[u8]>>
                 is_bid != did_fully_match_resting_order,
             )?;
 
-            // Stop walking if missing the needed global account.
+            // Stop walking if missing the needed global account, unless the
+            // filter asks to walk past it.
             if self.is_missing_global_account(&resting_order, is_bid, global_trade_accounts_opts) {
+                match filter.get_global_shortfall() {
+                    GlobalShortfall::Stop => break,
+                    GlobalShortfall::Skip => continue,
+                }
+            }
+
+            // Skip unbacked global orders.
+            if self.is_unbacked_global_order(
+                &resting_order,
+                is_bid,
+                global_trade_accounts_opts,
+                matched_base_atoms,
+                matched_quote_atoms,
+            ) {
+                continue;
+            }
+
+            quote_result.record_match(matched_price, matched_base_atoms, matched_quote_atoms)?;
+
+            if !did_fully_match_resting_order {
+                filled = true;
+                break;
+            }
+
+            // prepare for next iteration
+            remaining_base_atoms = remaining_base_atoms.checked_sub(matched_base_atoms)?;
+        }
+
+        // Note that when there are not enough orders on the market to use up or
+        // to receive the desired number of base atoms, the total is just the
+        // full amount on the bookside, which `is_book_exhausted` tells apart.
+        quote_result.is_truncated = truncated;
+        quote_result.is_book_exhausted =
+            !truncated && !filled && remaining_base_atoms > BaseAtoms::ZERO;
+        Ok(quote_result)
+    }
+
+    // Simplified version for certora. Those checks are actually stronger than
+    // needed since it shows invariants hold on swap even when impact_base_atoms
+    // returns a wrong value.
+    #[cfg(feature = "certora")]
+    pub fn impact_base_atoms(
+        &self,
+        is_bid: bool,
+        limit_quote_atoms: QuoteAtoms,
+        global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2],
+    ) -> Result<BaseAtoms, ProgramError> {
+        crate::certora::summaries::impact_base_atoms::impact_base_atoms(
+            self,
+            is_bid,
+            limit_quote_atoms,
+            global_trade_accounts_opts,
+        )
+    }
+
+    #[cfg(not(feature = "certora"))]
+    pub fn impact_base_atoms(
+        &self,
+        is_bid: bool,
+        limit_quote_atoms: QuoteAtoms,
+        global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2],
+    ) -> Result<BaseAtoms, ProgramError> {
+        let now_slot: u32 = get_now_slot();
+        self.impact_base_atoms_with_slot(
+            is_bid,
+            limit_quote_atoms,
+            global_trade_accounts_opts,
+            now_slot,
+        )
+    }
+
+    /// Same as `impact_base_atoms` with the slot read from `clock`, the Clock
+    /// sysvar account passed to the instruction.
+    #[cfg(not(feature = "certora"))]
+    pub fn impact_base_atoms_now(
+        &self,
+        clock: &AccountInfo,
+        is_bid: bool,
+        limit_quote_atoms: QuoteAtoms,
+        global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2],
+    ) -> Result<BaseAtoms, ProgramError> {
+        let now_slot: u32 = Clock::from_account_info(clock)?.slot as u32;
+        self.impact_base_atoms_with_slot(
+            is_bid,
+            limit_quote_atoms,
+            global_trade_accounts_opts,
+            now_slot,
+        )
+    }
+
+    /// How many base atoms you get when you trade in limit_quote_atoms.
+    #[cfg(not(feature = "certora"))]
+    pub fn impact_base_atoms_with_slot(
+        &self,
+        is_bid: bool,
+        limit_quote_atoms: QuoteAtoms,
+        global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2],
+        now_slot: u32,
+    ) -> Result<BaseAtoms, ProgramError> {
+        // Note that when there are not enough orders on the market to use up or
+        // to receive the desired number of quote atoms, this returns just the
+        // full amount on the bookside without differentiating that return.
+        Ok(self
+            .impact_base_atoms_result(
+                is_bid,
+                limit_quote_atoms,
+                global_trade_accounts_opts,
+                now_slot,
+                &OrderFilter::default(),
+            )?
+            .base_atoms)
+    }
+
+    /// Same as `impact_base_atoms_with_slot` but orders from makers rejected
+    /// by the filter are skipped as if they were not on the book.
+    #[cfg(not(feature = "certora"))]
+    pub fn impact_base_atoms_with_filter(
+        &self,
+        is_bid: bool,
+        limit_quote_atoms: QuoteAtoms,
+        global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2],
+        now_slot: u32,
+        filter: &OrderFilter,
+    ) -> Result<BaseAtoms, ProgramError> {
+        Ok(self
+            .impact_base_atoms_result(
+                is_bid,
+                limit_quote_atoms,
+                global_trade_accounts_opts,
+                now_slot,
+                filter,
+            )?
+            .base_atoms)
+    }
+
+    /// Same as `impact_base_atoms_with_filter`, also returning whether the
+    /// walk stopped at the `max_orders` of the filter with quote atoms left.
+    #[cfg(not(feature = "certora"))]
+    pub fn impact_base_atoms_bounded(
+        &self,
+        is_bid: bool,
+        limit_quote_atoms: QuoteAtoms,
+        global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2],
+        now_slot: u32,
+        filter: &OrderFilter,
+    ) -> Result<(BaseAtoms, bool), ProgramError> {
+        let quote_result: QuoteResult = self.impact_base_atoms_result(
+            is_bid,
+            limit_quote_atoms,
+            global_trade_accounts_opts,
+            now_slot,
+            filter,
+        )?;
+        Ok((quote_result.base_atoms, quote_result.is_truncated))
+    }
+
+    /// Base atoms `impact_base_atoms_with_slot` returns together with the
+    /// quote atoms they match for, from the same single walk of the book.
+    #[cfg(not(feature = "certora"))]
+    pub fn impact_atoms_for_quote_with_slot(
+        &self,
+        is_bid: bool,
+        limit_quote_atoms: QuoteAtoms,
+        global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2],
+        now_slot: u32,
+    ) -> Result<(BaseAtoms, QuoteAtoms), ProgramError> {
+        let quote_result: QuoteResult = self.impact_base_atoms_result(
+            is_bid,
+            limit_quote_atoms,
+            global_trade_accounts_opts,
+            now_slot,
+            &OrderFilter::default(),
+        )?;
+        Ok((quote_result.base_atoms, quote_result.quote_atoms))
+    }
+
+    /// The walk behind the base atom impact functions, with the breakdown of
+    /// what it matched. Orders from makers the filter rejects are skipped as
+    /// if they were not on the book.
+    #[cfg(not(feature = "certora"))]
+    pub fn impact_base_atoms_result(
+        &self,
+        is_bid: bool,
+        limit_quote_atoms: QuoteAtoms,
+        global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2],
+        now_slot: u32,
+        filter: &OrderFilter,
+    ) -> Result<QuoteResult, ProgramError> {
+        let book: RedBlackTreeReadOnly<'_, RestingOrder> = if is_bid {
+            self.get_asks()
+        } else {
+            self.get_bids()
+        };
+
+        let mut quote_result: QuoteResult = QuoteResult {
+            is_bid,
+            ..QuoteResult::default()
+        };
+        let mut remaining_quote_atoms: QuoteAtoms = limit_quote_atoms;
+        let mut num_orders_walked: usize = 0;
+        let mut truncated: bool = false;
+        let mut filled: bool = false;
+
+        for (_, resting_order) in book.iter::<RestingOrder>() {
+            if filter
+                .get_max_orders()
+                .is_some_and(|max_orders| num_orders_walked >= max_orders)
+            {
+                truncated = true;
                 break;
             }
+            num_orders_walked += 1;
+
+            // Skip expired orders.
+            if resting_order.is_expired(now_slot) {
+                continue;
+            }
+            if self.is_filtered_out(resting_order, filter) {
+                continue;
+            }
+
+            let matched_price: QuoteAtomsPerBaseAtom = resting_order.get_price();
+            // base_atoms_limit is the number of base atoms that you get if you
+            // were to trade all of the remaining quote atoms at the current
+            // price. Rounding is done in the taker favor because at the limit,
+            // it is a full match. So if you are checking against asks with 100
+            // quote remaining against price 1.001, then the answer should be
+            // 100, because the rounding is in favor of the taker. It takes 100
+            // base atoms to exhaust 100 quote atoms at that price.
+            let base_atoms_limit: BaseAtoms =
+                matched_price.checked_base_for_quote(remaining_quote_atoms, !is_bid)?;
+            // Either fill the entire resting order, or only the
+            // base_atoms_limit, in which case, this is the last iteration.
+            let matched_base_atoms: BaseAtoms =
+                resting_order.get_num_base_atoms().min(base_atoms_limit);
+            let did_fully_match_resting_order: bool =
+                base_atoms_limit >= resting_order.get_num_base_atoms();
+            // Number of quote atoms matched exactly. Round in taker favor if
+            // fully matching.
+            let matched_quote_atoms: QuoteAtoms = matched_price.checked_quote_for_base(
+                matched_base_atoms,
+                is_bid != did_fully_match_resting_order,
+            )?;
+
+            // Stop walking if missing the needed global account, unless the
+            // filter asks to walk past it.
+            if self.is_missing_global_account(resting_order, is_bid, global_trade_accounts_opts) {
+                match filter.get_global_shortfall() {
+                    GlobalShortfall::Stop => break,
+                    GlobalShortfall::Skip => continue,
+                }
+            }
 
             // Skip unbacked global orders.
             if self.is_unbacked_global_order(
@@ -556,141 +1275,464 @@ impl<Fixed: DerefOrBorrow<MarketFixed>, Dynamic: DerefOrBorrow<[u8]>>
                 continue;
             }
 
-            total_matched_quote_atoms =
-                total_matched_quote_atoms.checked_add(matched_quote_atoms)?;
+            quote_result.record_match(matched_price, matched_base_atoms, matched_quote_atoms)?;
 
             if !did_fully_match_resting_order {
+                filled = true;
+                break;
+            }
+
+            // Prepare for next iteration
+            remaining_quote_atoms = remaining_quote_atoms.checked_sub(matched_quote_atoms)?;
+
+            // we can match exactly in base atoms but also deplete all quote atoms at the same time
+            if remaining_quote_atoms == QuoteAtoms::ZERO {
+                break;
+            }
+        }
+
+        // Note that when there are not enough orders on the market to use up or
+        // to receive the desired number of quote atoms, the total is just the
+        // full amount on the bookside, which `is_book_exhausted` tells apart.
+        quote_result.is_truncated = truncated;
+        quote_result.is_book_exhausted =
+            !truncated && !filled && remaining_quote_atoms > QuoteAtoms::ZERO;
+        Ok(quote_result)
+    }
+
+    /// Exact in quotes for several sizes from a single walk of the book. Bids
+    /// trade in quote atoms for base atoms, asks base atoms for quote atoms.
+    /// Each output matches `impact_base_atoms_with_slot` or
+    /// `impact_quote_atoms_with_slot` for the same amount, in the order of
+    /// `in_atoms`.
+    #[cfg(not(feature = "certora"))]
+    pub fn quote_tiers(
+        &self,
+        is_bid: bool,
+        in_atoms: &[u64],
+        global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2],
+        now_slot: u32,
+    ) -> Result<Vec<u64>, ProgramError> {
+        let book: BooksideReadOnly = if is_bid {
+            self.get_asks()
+        } else {
+            self.get_bids()
+        };
+
+        let mut remaining_atoms: Vec<u64> = in_atoms.to_vec();
+        let mut out_atoms: Vec<u64> = vec![0; in_atoms.len()];
+        let mut is_done: Vec<bool> = in_atoms.iter().map(|&atoms| atoms == 0).collect();
+        let mut num_done: usize = is_done.iter().filter(|&&done| done).count();
+
+        for (_, resting_order) in book.iter::<RestingOrder>() {
+            if num_done == in_atoms.len() {
+                break;
+            }
+            if resting_order.is_expired(now_slot) {
+                continue;
+            }
+            // Same for every tier, so checked once per order.
+            if self.is_missing_global_account(resting_order, is_bid, global_trade_accounts_opts) {
                 break;
             }
 
-            // prepare for next iteration
-            remaining_base_atoms = remaining_base_atoms.checked_sub(matched_base_atoms)?;
-        }
+            let matched_price: QuoteAtomsPerBaseAtom = resting_order.get_price();
+            let order_base_atoms: BaseAtoms = resting_order.get_num_base_atoms();
+            // Full matches look the same from every tier that reaches past
+            // the order, so the backing of the full order is checked at most
+            // once.
+            let full_quote_atoms: QuoteAtoms =
+                matched_price.checked_quote_for_base(order_base_atoms, !is_bid)?;
+            let mut is_full_unbacked: Option<bool> = None;
+
+            for tier in 0..in_atoms.len() {
+                if is_done[tier] {
+                    continue;
+                }
+                let base_atoms_limit: BaseAtoms = if is_bid {
+                    matched_price
+                        .checked_base_for_quote(QuoteAtoms::new(remaining_atoms[tier]), !is_bid)?
+                } else {
+                    BaseAtoms::new(remaining_atoms[tier])
+                };
+                let did_fully_match_resting_order: bool = base_atoms_limit >= order_base_atoms;
+                let (matched_base_atoms, matched_quote_atoms): (BaseAtoms, QuoteAtoms) =
+                    if did_fully_match_resting_order {
+                        let is_unbacked: bool = *is_full_unbacked.get_or_insert_with(|| {
+                            self.is_unbacked_global_order(
+                                resting_order,
+                                is_bid,
+                                global_trade_accounts_opts,
+                                order_base_atoms,
+                                full_quote_atoms,
+                            )
+                        });
+                        if is_unbacked {
+                            continue;
+                        }
+                        (order_base_atoms, full_quote_atoms)
+                    } else {
+                        let matched_quote_atoms: QuoteAtoms =
+                            matched_price.checked_quote_for_base(base_atoms_limit, is_bid)?;
+                        if self.is_unbacked_global_order(
+                            resting_order,
+                            is_bid,
+                            global_trade_accounts_opts,
+                            base_atoms_limit,
+                            matched_quote_atoms,
+                        ) {
+                            continue;
+                        }
+                        (base_atoms_limit, matched_quote_atoms)
+                    };
+
+                let (matched_in_atoms, matched_out_atoms): (u64, u64) = if is_bid {
+                    (matched_quote_atoms.as_u64(), matched_base_atoms.as_u64())
+                } else {
+                    (matched_base_atoms.as_u64(), matched_quote_atoms.as_u64())
+                };
+                out_atoms[tier] = out_atoms[tier]
+                    .checked_add(matched_out_atoms)
+                    .ok_or(ManifestError::Overflow)?;
+                if !did_fully_match_resting_order {
+                    is_done[tier] = true;
+                    num_done += 1;
+                    continue;
+                }
+                remaining_atoms[tier] = remaining_atoms[tier]
+                    .checked_sub(matched_in_atoms)
+                    .ok_or(ManifestError::Overflow)?;
+                if remaining_atoms[tier] == 0 {
+                    is_done[tier] = true;
+                    num_done += 1;
+                }
+            }
+        }
+
+        Ok(out_atoms)
+    }
+
+    /// Exact in quote of `in_atoms` and the exact out quote of its output,
+    /// for spotting books where rounding makes the two disagree by more
+    /// than an atom. Bids trade in quote atoms for base atoms, asks base
+    /// atoms for quote atoms. No global accounts are used, so the walk stops
+    /// at the first global order.
+    #[cfg(not(feature = "certora"))]
+    pub fn quote_both(
+        &self,
+        is_bid: bool,
+        in_atoms: u64,
+        now_slot: u32,
+    ) -> Result<DualQuote, ProgramError> {
+        let global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2] = &[None, None];
+        let (out_atoms, inverse_in_atoms): (u64, u64) = if is_bid {
+            let out_atoms: BaseAtoms = self.impact_base_atoms_with_slot(
+                true,
+                QuoteAtoms::new(in_atoms),
+                global_trade_accounts_opts,
+                now_slot,
+            )?;
+            let inverse_in_atoms: QuoteAtoms = self.impact_quote_atoms_with_slot(
+                true,
+                out_atoms,
+                global_trade_accounts_opts,
+                now_slot,
+            )?;
+            (out_atoms.as_u64(), inverse_in_atoms.as_u64())
+        } else {
+            let out_atoms: QuoteAtoms = self.impact_quote_atoms_with_slot(
+                false,
+                BaseAtoms::new(in_atoms),
+                global_trade_accounts_opts,
+                now_slot,
+            )?;
+            let inverse_in_atoms: BaseAtoms = self.impact_base_atoms_with_slot(
+                false,
+                out_atoms,
+                global_trade_accounts_opts,
+                now_slot,
+            )?;
+            (out_atoms.as_u64(), inverse_in_atoms.as_u64())
+        };
+        Ok(DualQuote {
+            in_atoms,
+            out_atoms,
+            inverse_in_atoms,
+        })
+    }
+
+    /// Up to `depth` price levels of each side with the base atoms resting at
+    /// each. Every order on the book counts, including global orders and
+    /// expired orders that have not been removed yet.
+    #[cfg(not(feature = "certora"))]
+    pub fn l2_snapshot(&self, depth: usize) -> Result<L2Snapshot, ProgramError> {
+        Ok(L2Snapshot {
+            bids: aggregate_levels(self.get_bids(), depth)?,
+            asks: aggregate_levels(self.get_asks(), depth)?,
+        })
+    }
+
+    /// Price of the first bid a taker could sell into, skipping expired and
+    /// unbacked global orders like `impact_quote_atoms` does. None when the
+    /// side is empty or the walk reaches a global order without its global
+    /// account.
+    #[cfg(not(feature = "certora"))]
+    pub fn best_bid(
+        &self,
+        global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2],
+        now_slot: u32,
+    ) -> Result<Option<QuoteAtomsPerBaseAtom>, ProgramError> {
+        self.best_price(true, global_trade_accounts_opts, now_slot)
+    }
 
-        // Note that when there are not enough orders on the market to use up or
-        // to receive the desired number of base atoms, this returns just the
-        // full amount on the bookside without differentiating that return.
+    /// Price of the first ask a taker could buy from, see `best_bid`.
+    #[cfg(not(feature = "certora"))]
+    pub fn best_ask(
+        &self,
+        global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2],
+        now_slot: u32,
+    ) -> Result<Option<QuoteAtomsPerBaseAtom>, ProgramError> {
+        self.best_price(false, global_trade_accounts_opts, now_slot)
+    }
 
-        return Ok(total_matched_quote_atoms);
+    /// Halfway between `best_bid` and `best_ask` in quote atoms per base
+    /// atom. None unless both sides have a price.
+    #[cfg(not(feature = "certora"))]
+    pub fn mid_price(
+        &self,
+        global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2],
+        now_slot: u32,
+    ) -> Result<Option<f64>, ProgramError> {
+        let best_bid_opt: Option<QuoteAtomsPerBaseAtom> =
+            self.best_bid(global_trade_accounts_opts, now_slot)?;
+        let best_ask_opt: Option<QuoteAtomsPerBaseAtom> =
+            self.best_ask(global_trade_accounts_opts, now_slot)?;
+        Ok(match (best_bid_opt, best_ask_opt) {
+            (Some(best_bid), Some(best_ask)) => Some((best_bid.as_f64() + best_ask.as_f64()) / 2.0),
+            _ => None,
+        })
     }
 
-    // Simplified version for certora. Those checks are actually stronger than
-    // needed since it shows invariants hold on swap even when impact_base_atoms
-    // returns a wrong value.
-    #[cfg(feature = "certora")]
-    pub fn impact_base_atoms(
+    /// Distance between `best_bid` and `best_ask` in basis points of
+    /// `mid_price`.
+    #[cfg(not(feature = "certora"))]
+    pub fn spread_bps(
         &self,
-        is_bid: bool,
-        limit_quote_atoms: QuoteAtoms,
         global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2],
-    ) -> Result<BaseAtoms, ProgramError> {
-        crate::certora::summaries::impact_base_atoms::impact_base_atoms(
-            self,
-            is_bid,
-            limit_quote_atoms,
-            global_trade_accounts_opts,
-        )
+        now_slot: u32,
+    ) -> Result<Option<f64>, ProgramError> {
+        let best_bid_opt: Option<QuoteAtomsPerBaseAtom> =
+            self.best_bid(global_trade_accounts_opts, now_slot)?;
+        let best_ask_opt: Option<QuoteAtomsPerBaseAtom> =
+            self.best_ask(global_trade_accounts_opts, now_slot)?;
+        Ok(match (best_bid_opt, best_ask_opt) {
+            (Some(best_bid), Some(best_ask)) => {
+                let (bid_price, ask_price): (f64, f64) = (best_bid.as_f64(), best_ask.as_f64());
+                Some((ask_price - bid_price) / ((bid_price + ask_price) / 2.0) * 10_000.0)
+            }
+            _ => None,
+        })
     }
 
+    /// Base atoms and their value in quote atoms of the orders within `bps`
+    /// basis points of `mid_price` on each side. Orders count under the same
+    /// rules as in `best_bid`, and a side stops at a global order without
+    /// its global account. None when there is no mid price.
     #[cfg(not(feature = "certora"))]
-    pub fn impact_base_atoms(
+    pub fn liquidity_within_bps(
         &self,
-        is_bid: bool,
-        limit_quote_atoms: QuoteAtoms,
+        bps: u32,
         global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2],
-    ) -> Result<BaseAtoms, ProgramError> {
-        let now_slot: u32 = get_now_slot();
-        self.impact_base_atoms_with_slot(
-            is_bid,
-            limit_quote_atoms,
+        now_slot: u32,
+    ) -> Result<Option<LiquidityWithinBps>, ProgramError> {
+        let Some(mid_price) = self.mid_price(global_trade_accounts_opts, now_slot)? else {
+            return Ok(None);
+        };
+        let band: f64 = mid_price * bps as f64 / 10_000.0;
+        let (bid_base_atoms, bid_quote_atoms): (BaseAtoms, QuoteAtoms) =
+            self.liquidity_within(true, mid_price - band, global_trade_accounts_opts, now_slot)?;
+        let (ask_base_atoms, ask_quote_atoms): (BaseAtoms, QuoteAtoms) = self.liquidity_within(
+            false,
+            mid_price + band,
             global_trade_accounts_opts,
             now_slot,
-        )
+        )?;
+        Ok(Some(LiquidityWithinBps {
+            bid_base_atoms,
+            bid_quote_atoms,
+            ask_base_atoms,
+            ask_quote_atoms,
+        }))
     }
 
-    /// How many base atoms you get when you trade in limit_quote_atoms.
     #[cfg(not(feature = "certora"))]
-    pub fn impact_base_atoms_with_slot(
+    fn liquidity_within(
         &self,
-        is_bid: bool,
-        limit_quote_atoms: QuoteAtoms,
+        is_bids: bool,
+        limit_price: f64,
         global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2],
         now_slot: u32,
-    ) -> Result<BaseAtoms, ProgramError> {
-        let book: RedBlackTreeReadOnly<'_, RestingOrder> = if is_bid {
-            self.get_asks()
-        } else {
+    ) -> Result<(BaseAtoms, QuoteAtoms), ProgramError> {
+        let book: BooksideReadOnly = if is_bids {
             self.get_bids()
+        } else {
+            self.get_asks()
         };
-
-        let mut total_matched_base_atoms: BaseAtoms = BaseAtoms::ZERO;
-        let mut remaining_quote_atoms: QuoteAtoms = limit_quote_atoms;
-
+        // Side of the taker that would match.
+        let is_bid: bool = !is_bids;
+        let mut base_atoms: BaseAtoms = BaseAtoms::ZERO;
+        let mut quote_atoms: QuoteAtoms = QuoteAtoms::ZERO;
         for (_, resting_order) in book.iter::<RestingOrder>() {
-            // Skip expired orders.
+            let price: f64 = resting_order.get_price().as_f64();
+            if (is_bids && price < limit_price) || (!is_bids && price > limit_price) {
+                break;
+            }
             if resting_order.is_expired(now_slot) {
                 continue;
             }
-
-            let matched_price: QuoteAtomsPerBaseAtom = resting_order.get_price();
-            // base_atoms_limit is the number of base atoms that you get if you
-            // were to trade all of the remaining quote atoms at the current
-            // price. Rounding is done in the taker favor because at the limit,
-            // it is a full match. So if you are checking against asks with 100
-            // quote remaining against price 1.001, then the answer should be
-            // 100, because the rounding is in favor of the taker. It takes 100
-            // base atoms to exhaust 100 quote atoms at that price.
-            let base_atoms_limit: BaseAtoms =
-                matched_price.checked_base_for_quote(remaining_quote_atoms, !is_bid)?;
-            // Either fill the entire resting order, or only the
-            // base_atoms_limit, in which case, this is the last iteration.
-            let matched_base_atoms: BaseAtoms =
-                resting_order.get_num_base_atoms().min(base_atoms_limit);
-            let did_fully_match_resting_order: bool =
-                base_atoms_limit >= resting_order.get_num_base_atoms();
-            // Number of quote atoms matched exactly. Round in taker favor if
-            // fully matching.
-            let matched_quote_atoms: QuoteAtoms = matched_price.checked_quote_for_base(
-                matched_base_atoms,
-                is_bid != did_fully_match_resting_order,
-            )?;
-
-            // Stop walking if missing the needed global account.
             if self.is_missing_global_account(resting_order, is_bid, global_trade_accounts_opts) {
                 break;
             }
-
-            // Skip unbacked global orders.
+            let num_base_atoms: BaseAtoms = resting_order.get_num_base_atoms();
+            // Full matches round in taker favor, which is up against bids.
+            let num_quote_atoms: QuoteAtoms = resting_order
+                .get_price()
+                .checked_quote_for_base(num_base_atoms, is_bids)?;
             if self.is_unbacked_global_order(
-                &resting_order,
+                resting_order,
                 is_bid,
                 global_trade_accounts_opts,
-                matched_base_atoms,
-                matched_quote_atoms,
+                num_base_atoms,
+                num_quote_atoms,
             ) {
                 continue;
             }
+            base_atoms = base_atoms.checked_add(num_base_atoms)?;
+            quote_atoms = quote_atoms.checked_add(num_quote_atoms)?;
+        }
+        Ok((base_atoms, quote_atoms))
+    }
 
-            total_matched_base_atoms = total_matched_base_atoms.checked_add(matched_base_atoms)?;
-
-            if !did_fully_match_resting_order {
-                break;
+    #[cfg(not(feature = "certora"))]
+    fn best_price(
+        &self,
+        is_bids: bool,
+        global_trade_accounts_opts: &[Option<GlobalTradeAccounts>; 2],
+        now_slot: u32,
+    ) -> Result<Option<QuoteAtomsPerBaseAtom>, ProgramError> {
+        let book: BooksideReadOnly = if is_bids {
+            self.get_bids()
+        } else {
+            self.get_asks()
+        };
+        // Side of the taker that would match.
+        let is_bid: bool = !is_bids;
+        for (_, resting_order) in book.iter::<RestingOrder>() {
+            if resting_order.is_expired(now_slot) {
+                continue;
             }
+            if self.is_missing_global_account(resting_order, is_bid, global_trade_accounts_opts) {
+                return Ok(None);
+            }
+            // Backed means backed for the whole order, as a full match
+            // would need.
+            let num_base_atoms: BaseAtoms = resting_order.get_num_base_atoms();
+            let num_quote_atoms: QuoteAtoms = resting_order
+                .get_price()
+                .checked_quote_for_base(num_base_atoms, !is_bid)?;
+            if self.is_unbacked_global_order(
+                resting_order,
+                is_bid,
+                global_trade_accounts_opts,
+                num_base_atoms,
+                num_quote_atoms,
+            ) {
+                continue;
+            }
+            return Ok(Some(resting_order.get_price()));
+        }
+        Ok(None)
+    }
 
-            // Prepare for next iteration
-            remaining_quote_atoms = remaining_quote_atoms.checked_sub(matched_quote_atoms)?;
-
-            // we can match exactly in base atoms but also deplete all quote atoms at the same time
-            if remaining_quote_atoms == QuoteAtoms::ZERO {
-                break;
+    /// Base atoms and the quote atoms a taker would trade against them, for
+    /// all orders on one side that are neither expired nor global.
+    #[cfg(not(feature = "certora"))]
+    pub fn non_global_depth(
+        &self,
+        is_bid: bool,
+        now_slot: u32,
+    ) -> Result<(BaseAtoms, QuoteAtoms), ProgramError> {
+        let book: BooksideReadOnly = if is_bid {
+            self.get_bids()
+        } else {
+            self.get_asks()
+        };
+        let mut base_atoms: BaseAtoms = BaseAtoms::ZERO;
+        let mut quote_atoms: QuoteAtoms = QuoteAtoms::ZERO;
+        for (_, resting_order) in book.iter::<RestingOrder>() {
+            if resting_order.is_expired(now_slot) || resting_order.is_global() {
+                continue;
             }
+            base_atoms = base_atoms.checked_add(resting_order.get_num_base_atoms())?;
+            // Full matches round in taker favor, which is up against bids.
+            quote_atoms = quote_atoms.checked_add(
+                resting_order
+                    .get_price()
+                    .checked_quote_for_base(resting_order.get_num_base_atoms(), is_bid)?,
+            )?;
         }
+        Ok((base_atoms, quote_atoms))
+    }
 
-        // Note that when there are not enough orders on the market to use up or
-        // to receive the desired number of quote atoms, this returns just the
-        // full amount on the bookside without differentiating that return.
+    /// Atoms committed to resting orders that have not expired by `now_slot`.
+    /// Expired orders still hold their funds until they are removed, but are
+    /// left out since nobody can trade against them.
+    #[cfg(not(feature = "certora"))]
+    pub fn locked_value(&self, now_slot: u32) -> Result<LockedValue, ProgramError> {
+        let mut locked_value: LockedValue = LockedValue::default();
+        for book in [self.get_bids(), self.get_asks()] {
+            for (_, resting_order) in book.iter::<RestingOrder>() {
+                if resting_order.is_expired(now_slot) {
+                    continue;
+                }
+                if resting_order.is_global() {
+                    locked_value.global_backed.add_order(resting_order)?;
+                } else {
+                    locked_value.market_backed.add_order(resting_order)?;
+                }
+                locked_value.by_order_type[resting_order.get_order_type() as usize]
+                    .add_order(resting_order)?;
+            }
+        }
+        Ok(locked_value)
+    }
 
-        return Ok(total_matched_base_atoms);
+    /// Bytes used by orders, seats and free blocks against the allocation,
+    /// for capacity alerts.
+    #[cfg(not(feature = "certora"))]
+    pub fn memory_report(&self) -> MarketMemoryReport {
+        let DynamicAccount { fixed, dynamic } = self.borrow_market();
+        let num_orders: u32 = (self.get_bids().iter::<RestingOrder>().count()
+            + self.get_asks().iter::<RestingOrder>().count()) as u32;
+        let num_seats: u32 = self.get_claimed_seats().iter::<ClaimedSeat>().count() as u32;
+        let mut num_free_blocks: u32 = 0;
+        let mut current_index: DataIndex = fixed.free_list_head_index;
+        while current_index != NIL {
+            num_free_blocks += 1;
+            current_index =
+                get_helper::<FreeListNode<MarketUnusedFreeListPadding>>(dynamic, current_index)
+                    .get_next_index();
+        }
+        let block_size: u32 = MARKET_BLOCK_SIZE as u32;
+        MarketMemoryReport {
+            num_orders,
+            num_seats,
+            num_free_blocks,
+            order_bytes: num_orders * block_size,
+            seat_bytes: num_seats * block_size,
+            free_bytes: num_free_blocks * block_size,
+            num_bytes_allocated: fixed.num_bytes_allocated,
+        }
     }
 
     #[cfg(not(feature = "certora"))]
@@ -755,6 +1797,21 @@ impl<Fixed: DerefOrBorrow<MarketFixed>, Dynamic: DerefOrBorrow<[u8]>>
         )
     }
 
+    pub fn get_claimed_seats(&self) -> ClaimedSeatTreeReadOnly {
+        let DynamicAccount { dynamic, fixed } = self.borrow_market();
+        ClaimedSeatTreeReadOnly::new(dynamic, fixed.claimed_seats_root_index, NIL)
+    }
+
+    fn is_filtered_out(&self, resting_order: &RestingOrder, filter: &OrderFilter) -> bool {
+        if filter.is_empty() {
+            return false;
+        }
+        if filter.get_skip_global() && resting_order.get_order_type() == OrderType::Global {
+            return true;
+        }
+        !filter.accepts(self.get_trader_key_by_index(resting_order.get_trader_index()))
+    }
+
     fn is_missing_global_account(
         &self,
         resting_order: &RestingOrder,
@@ -1847,6 +2904,30 @@ fn get_next_candidate_match_index(
     }
 }
 
+#[cfg(not(feature = "certora"))]
+fn aggregate_levels(
+    book: BooksideReadOnly,
+    depth: usize,
+) -> Result<Vec<(QuoteAtomsPerBaseAtom, BaseAtoms)>, ProgramError> {
+    let mut levels: Vec<(QuoteAtomsPerBaseAtom, BaseAtoms)> = Vec::new();
+    for (_, resting_order) in book.iter::<RestingOrder>() {
+        let price: QuoteAtomsPerBaseAtom = resting_order.get_price();
+        match levels.last_mut() {
+            Some((level_price, level_base_atoms)) if *level_price == price => {
+                *level_base_atoms =
+                    level_base_atoms.checked_add(resting_order.get_num_base_atoms())?;
+            }
+            _ => {
+                if levels.len() == depth {
+                    break;
+                }
+                levels.push((price, resting_order.get_num_base_atoms()));
+            }
+        }
+    }
+    Ok(levels)
+}
+
 fn remove_and_update_balances(
     fixed: &mut MarketFixed,
     dynamic: &mut [u8],
@@ -1905,7 +2986,6 @@ pub fn create_empty_market(
     market_key: &Pubkey,
 ) -> MarketFixed {
     // Values on the mints are not important.
-    use solana_program::account_info::AccountInfo;
     use spl_token_2022::state::Mint;
     use std::{cell::RefCell, rc::Rc, str::FromStr};
     let mut lamports: u64 = 0;
@@ -1952,3 +3032,551 @@ pub fn create_empty_market(
     let market_fixed: MarketFixed = MarketFixed::new_empty(&base_mint, &quote_mint, market_key);
     market_fixed
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn price(mantissa: u32, exponent: i8) -> QuoteAtomsPerBaseAtom {
+        QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(mantissa, exponent).unwrap()
+    }
+
+    /// Rest a limit order from `trader`, claiming a seat when needed and
+    /// depositing exactly what the order locks.
+    fn place_order_for(
+        market: &mut MarketValue,
+        trader: &Pubkey,
+        price: QuoteAtomsPerBaseAtom,
+        num_base_atoms: u64,
+        is_bid: bool,
+        last_valid_slot: u32,
+    ) {
+        if market.get_trader_index(trader) == NIL {
+            market.claim_seat(trader).unwrap();
+        }
+        let trader_index: DataIndex = market.get_trader_index(trader);
+        let amount_atoms: u64 = if is_bid {
+            price
+                .checked_quote_for_base(BaseAtoms::new(num_base_atoms), true)
+                .unwrap()
+                .as_u64()
+        } else {
+            num_base_atoms
+        };
+        market.deposit(trader_index, amount_atoms, !is_bid).unwrap();
+        market
+            .place_order(AddOrderToMarketArgs {
+                market: Pubkey::default(),
+                trader_index,
+                num_base_atoms: BaseAtoms::new(num_base_atoms),
+                price,
+                is_bid,
+                last_valid_slot,
+                order_type: OrderType::Limit,
+                global_trade_accounts_opts: &[None, None],
+                current_slot: Some(0),
+            })
+            .unwrap();
+    }
+
+    /// SOL/USDC market expanded to 8 blocks with `orders` of (price, base
+    /// atoms, is_bid) resting from one maker.
+    fn market_with_orders(orders: &[(QuoteAtomsPerBaseAtom, u64, bool)]) -> MarketValue {
+        let mut market: MarketValue = MarketValue {
+            fixed: create_empty_market(
+                "So11111111111111111111111111111111111111112",
+                "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+                9,
+                6,
+                &Pubkey::new_unique(),
+                &Pubkey::new_unique(),
+            ),
+            dynamic: vec![0; MARKET_BLOCK_SIZE * 8],
+        };
+        market.market_expand_n(8).unwrap();
+        let maker: Pubkey = Pubkey::new_unique();
+        for &(price, num_base_atoms, is_bid) in orders {
+            place_order_for(
+                &mut market,
+                &maker,
+                price,
+                num_base_atoms,
+                is_bid,
+                NO_EXPIRATION_LAST_VALID_SLOT,
+            );
+        }
+        market
+    }
+
+    #[test]
+    fn test_impact_with_order_filter() {
+        let router: Pubkey = Pubkey::new_unique();
+        // Router asks at 1, maker asks at 2.
+        let mut market: MarketValue = market_with_orders(&[(price(2, 0), 100, false)]);
+        place_order_for(
+            &mut market,
+            &router,
+            price(1, 0),
+            100,
+            false,
+            NO_EXPIRATION_LAST_VALID_SLOT,
+        );
+
+        let quote_atoms = |filter: &OrderFilter| {
+            market
+                .impact_quote_atoms_with_filter(true, BaseAtoms::new(50), &[None, None], 0, filter)
+                .unwrap()
+        };
+        assert_eq!(quote_atoms(&OrderFilter::default()), QuoteAtoms::new(50));
+        assert_eq!(
+            quote_atoms(&OrderFilter::exclude_traders(&[router])),
+            QuoteAtoms::new(100)
+        );
+        assert_eq!(
+            quote_atoms(&OrderFilter::include_traders(&[router])),
+            QuoteAtoms::new(50)
+        );
+        // Without global orders on the book there is nothing to skip.
+        assert_eq!(
+            quote_atoms(&OrderFilter::default().with_global_shortfall(GlobalShortfall::Skip)),
+            QuoteAtoms::new(50)
+        );
+        assert_eq!(
+            market
+                .impact_base_atoms_with_filter(
+                    true,
+                    QuoteAtoms::new(100),
+                    &[None, None],
+                    0,
+                    &OrderFilter::exclude_traders(&[router]),
+                )
+                .unwrap(),
+            BaseAtoms::new(50)
+        );
+
+        // One order covers 50 base, 150 needs the second.
+        let bounded_quote_atoms = |limit_base_atoms: u64, max_orders: usize| {
+            market
+                .impact_quote_atoms_bounded(
+                    true,
+                    BaseAtoms::new(limit_base_atoms),
+                    &[None, None],
+                    0,
+                    &OrderFilter::default().with_max_orders(max_orders),
+                )
+                .unwrap()
+        };
+        assert_eq!(bounded_quote_atoms(50, 1), (QuoteAtoms::new(50), false));
+        assert_eq!(bounded_quote_atoms(150, 1), (QuoteAtoms::new(100), true));
+        assert_eq!(bounded_quote_atoms(150, 2), (QuoteAtoms::new(200), false));
+        assert_eq!(
+            market
+                .impact_base_atoms_bounded(
+                    true,
+                    QuoteAtoms::new(300),
+                    &[None, None],
+                    0,
+                    &OrderFilter::default().with_max_orders(1),
+                )
+                .unwrap(),
+            (BaseAtoms::new(100), true)
+        );
+
+        assert_eq!(
+            market.non_global_depth(false, 0).unwrap(),
+            (BaseAtoms::new(200), QuoteAtoms::new(300))
+        );
+        assert_eq!(
+            market.non_global_depth(true, 0).unwrap(),
+            (BaseAtoms::ZERO, QuoteAtoms::ZERO)
+        );
+
+        let locked_value: LockedValue = market.locked_value(0).unwrap();
+        let expected: LockedAmounts = LockedAmounts {
+            num_orders: 2,
+            base_atoms: BaseAtoms::new(200),
+            quote_atoms: QuoteAtoms::ZERO,
+        };
+        assert_eq!(locked_value.market_backed, expected);
+        assert_eq!(locked_value.global_backed, LockedAmounts::default());
+        assert_eq!(locked_value.get_order_type(OrderType::Limit), expected);
+        assert_eq!(locked_value.get_total().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_impact_now() {
+        use solana_program::sysvar;
+        use std::{cell::RefCell, rc::Rc};
+
+        let mut market: MarketValue = market_with_orders(&[]);
+        place_order_for(
+            &mut market,
+            &Pubkey::new_unique(),
+            price(1, 0),
+            100,
+            false,
+            10,
+        );
+
+        let quote_atoms = |key: &Pubkey, slot: u64| {
+            // Clock is the slot followed by four other 8 byte fields.
+            let mut data: Vec<u8> = [slot.to_le_bytes(), [0; 8], [0; 8], [0; 8], [0; 8]].concat();
+            let mut lamports: u64 = 0;
+            let owner: Pubkey = sysvar::id();
+            let clock: AccountInfo = AccountInfo {
+                key,
+                lamports: Rc::new(RefCell::new(&mut lamports)),
+                data: Rc::new(RefCell::new(&mut data)),
+                owner: &owner,
+                rent_epoch: 0,
+                is_signer: false,
+                is_writable: false,
+                executable: false,
+            };
+            market.impact_quote_atoms_now(&clock, true, BaseAtoms::new(50), &[None, None])
+        };
+        assert_eq!(
+            quote_atoms(&sysvar::clock::id(), 5).unwrap(),
+            QuoteAtoms::new(50)
+        );
+        assert_eq!(
+            quote_atoms(&sysvar::clock::id(), 11).unwrap(),
+            QuoteAtoms::ZERO
+        );
+        // Does not wrap around to slot 5.
+        assert_eq!(
+            quote_atoms(&sysvar::clock::id(), (1 << 32) + 5).unwrap(),
+            QuoteAtoms::ZERO
+        );
+        assert!(quote_atoms(&Pubkey::new_unique(), 5).is_err());
+    }
+
+    #[test]
+    fn test_quote_tiers() {
+        // Asks at 1.5 and 2.5, bids at 0.5 and 0.25.
+        let market: MarketValue = market_with_orders(&[
+            (price(15, -1), 101, false),
+            (price(25, -1), 101, false),
+            (price(5, -1), 101, true),
+            (price(25, -2), 101, true),
+        ]);
+
+        let in_atoms: [u64; 7] = [0, 1, 50, 152, 153, 400, 10_000];
+        let bid_tiers: Vec<u64> = market
+            .quote_tiers(true, &in_atoms, &[None, None], 0)
+            .unwrap();
+        let ask_tiers: Vec<u64> = market
+            .quote_tiers(false, &in_atoms, &[None, None], 0)
+            .unwrap();
+        for (tier, &atoms) in in_atoms.iter().enumerate() {
+            assert_eq!(
+                bid_tiers[tier],
+                market
+                    .impact_base_atoms_with_slot(true, QuoteAtoms::new(atoms), &[None, None], 0)
+                    .unwrap()
+                    .as_u64()
+            );
+            assert_eq!(
+                ask_tiers[tier],
+                market
+                    .impact_quote_atoms_with_slot(false, BaseAtoms::new(atoms), &[None, None], 0)
+                    .unwrap()
+                    .as_u64()
+            );
+        }
+        // Everything on the book once the size runs past it.
+        assert_eq!(bid_tiers[6], 202);
+        assert_eq!(ask_tiers[6], 77);
+    }
+
+    #[test]
+    fn test_memory_report() {
+        let mut market: MarketValue = market_with_orders(&[]);
+        assert_eq!(market.memory_report().orders_until_expand(), 8);
+
+        place_order_for(
+            &mut market,
+            &Pubkey::new_unique(),
+            price(1, 0),
+            100,
+            false,
+            NO_EXPIRATION_LAST_VALID_SLOT,
+        );
+
+        let memory_report: MarketMemoryReport = market.borrow_market().memory_report();
+        assert_eq!(
+            memory_report,
+            MarketMemoryReport {
+                num_orders: 1,
+                num_seats: 1,
+                num_free_blocks: 6,
+                order_bytes: MARKET_BLOCK_SIZE as u32,
+                seat_bytes: MARKET_BLOCK_SIZE as u32,
+                free_bytes: 6 * MARKET_BLOCK_SIZE as u32,
+                num_bytes_allocated: 8 * MARKET_BLOCK_SIZE as u32,
+            }
+        );
+        assert_eq!(memory_report.orders_until_expand(), 6);
+        assert_eq!(memory_report.unaccounted_bytes(), 0);
+    }
+
+    #[test]
+    fn test_quote_both() {
+        // Ask at 1.5 and bid at 0.5.
+        let market: MarketValue =
+            market_with_orders(&[(price(15, -1), 100, false), (price(5, -1), 100, true)]);
+
+        let exact: DualQuote = market.quote_both(true, 9, 0).unwrap();
+        assert_eq!(exact.out_atoms, 6);
+        assert_eq!(exact.get_discrepancy_atoms(), 0);
+
+        // 10 quote buys 6 base, which only takes 9 quote.
+        let bid: DualQuote = market.quote_both(true, 10, 0).unwrap();
+        assert_eq!(
+            bid,
+            DualQuote {
+                in_atoms: 10,
+                out_atoms: 6,
+                inverse_in_atoms: 9,
+            }
+        );
+        assert_eq!(bid.get_discrepancy_atoms(), 1);
+
+        // 11 base sells for 5 quote, which only takes 10 base.
+        let ask: DualQuote = market.quote_both(false, 11, 0).unwrap();
+        assert_eq!(ask.out_atoms, 5);
+        assert_eq!(ask.inverse_in_atoms, 10);
+        assert_eq!(ask.get_discrepancy_atoms(), 1);
+    }
+
+    #[test]
+    fn test_l2_snapshot() {
+        // Two asks at 1.5 make one level, and the bid at 0.4 is behind the 0.5.
+        let market: MarketValue = market_with_orders(&[
+            (price(15, -1), 100, false),
+            (price(15, -1), 50, false),
+            (price(20, -1), 10, false),
+            (price(4, -1), 30, true),
+            (price(5, -1), 20, true),
+        ]);
+
+        let snapshot: L2Snapshot = market.l2_snapshot(10).unwrap();
+        assert_eq!(
+            snapshot.asks,
+            vec![
+                (price(15, -1), BaseAtoms::new(150)),
+                (price(20, -1), BaseAtoms::new(10))
+            ]
+        );
+        assert_eq!(
+            snapshot.bids,
+            vec![
+                (price(5, -1), BaseAtoms::new(20)),
+                (price(4, -1), BaseAtoms::new(30))
+            ]
+        );
+
+        let top: L2Snapshot = market.l2_snapshot(1).unwrap();
+        assert_eq!(top.asks, vec![(price(15, -1), BaseAtoms::new(150))]);
+        assert_eq!(top.bids, vec![(price(5, -1), BaseAtoms::new(20))]);
+        assert_eq!(market.l2_snapshot(0).unwrap(), L2Snapshot::default());
+    }
+
+    #[test]
+    fn test_best_prices() {
+        let empty: MarketValue = market_with_orders(&[]);
+        assert_eq!(empty.best_bid(&[None, None], 0).unwrap(), None);
+        assert_eq!(empty.mid_price(&[None, None], 0).unwrap(), None);
+
+        // Ask at 1.5, bid at 0.5 and a bid at 0.8 that expires after slot 10.
+        let mut market: MarketValue =
+            market_with_orders(&[(price(15, -1), 100, false), (price(5, -1), 100, true)]);
+        place_order_for(
+            &mut market,
+            &Pubkey::new_unique(),
+            price(8, -1),
+            100,
+            true,
+            10,
+        );
+
+        assert_eq!(
+            market.best_bid(&[None, None], 10).unwrap(),
+            Some(price(8, -1))
+        );
+        assert_eq!(
+            market.best_bid(&[None, None], 11).unwrap(),
+            Some(price(5, -1))
+        );
+        assert_eq!(
+            market.best_ask(&[None, None], 11).unwrap(),
+            Some(price(15, -1))
+        );
+        let mid_price: f64 = market.mid_price(&[None, None], 11).unwrap().unwrap();
+        assert!((mid_price - 1.0).abs() < 1e-9);
+        let spread_bps: f64 = market.spread_bps(&[None, None], 11).unwrap().unwrap();
+        assert!((spread_bps - 10_000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_liquidity_within_bps() {
+        assert_eq!(
+            market_with_orders(&[])
+                .liquidity_within_bps(100, &[None, None], 0)
+                .unwrap(),
+            None
+        );
+
+        // Mid of 10 with levels 0.5% and 5% away on each side.
+        let market: MarketValue = market_with_orders(&[
+            (price(995, -2), 100, true),
+            (price(950, -2), 100, true),
+            (price(1005, -2), 100, false),
+            (price(1050, -2), 100, false),
+        ]);
+
+        assert_eq!(
+            market.liquidity_within_bps(100, &[None, None], 0).unwrap(),
+            Some(LiquidityWithinBps {
+                bid_base_atoms: BaseAtoms::new(100),
+                bid_quote_atoms: QuoteAtoms::new(995),
+                ask_base_atoms: BaseAtoms::new(100),
+                ask_quote_atoms: QuoteAtoms::new(1_005),
+            })
+        );
+        let wide: LiquidityWithinBps = market
+            .liquidity_within_bps(1_000, &[None, None], 0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(wide.bid_base_atoms, BaseAtoms::new(200));
+        assert_eq!(wide.ask_quote_atoms, QuoteAtoms::new(2_055));
+    }
+
+    #[test]
+    fn test_quote_result() {
+        // 100 base at 1 and 100 base at 2 offered.
+        let market: MarketValue =
+            market_with_orders(&[(price(1, 0), 100, false), (price(2, 0), 100, false)]);
+        let filter: OrderFilter = OrderFilter::default();
+
+        let quote_result: QuoteResult = market
+            .impact_quote_atoms_result(true, BaseAtoms::new(150), &[None, None], 0, &filter)
+            .unwrap();
+        assert_eq!(
+            quote_result,
+            QuoteResult {
+                is_bid: true,
+                base_atoms: BaseAtoms::new(150),
+                quote_atoms: QuoteAtoms::new(200),
+                top_of_book_price_opt: Some(price(1, 0)),
+                num_orders_crossed: 2,
+                is_book_exhausted: false,
+                is_truncated: false,
+            }
+        );
+        assert_eq!(quote_result.get_in_atoms(), 200);
+        assert_eq!(quote_result.get_out_atoms(), 150);
+        let price_impact_bps: f64 = quote_result.get_price_impact_bps().unwrap();
+        assert!((price_impact_bps - 10_000.0 / 3.0).abs() < 1e-6);
+
+        // Same totals as the single number versions.
+        let quote_result: QuoteResult = market
+            .impact_base_atoms_result(true, QuoteAtoms::new(250), &[None, None], 0, &filter)
+            .unwrap();
+        assert_eq!(quote_result.base_atoms, BaseAtoms::new(175));
+        assert_eq!(quote_result.quote_atoms, QuoteAtoms::new(250));
+        assert_eq!(
+            market
+                .impact_base_atoms_with_slot(true, QuoteAtoms::new(250), &[None, None], 0)
+                .unwrap(),
+            quote_result.base_atoms
+        );
+        assert!(!quote_result.is_book_exhausted);
+
+        let quote_result: QuoteResult = market
+            .impact_quote_atoms_result(true, BaseAtoms::new(300), &[None, None], 0, &filter)
+            .unwrap();
+        assert_eq!(quote_result.base_atoms, BaseAtoms::new(200));
+        assert!(quote_result.is_book_exhausted);
+        let quote_result: QuoteResult = market
+            .impact_quote_atoms_result(
+                true,
+                BaseAtoms::new(300),
+                &[None, None],
+                0,
+                &OrderFilter::default().with_max_orders(1),
+            )
+            .unwrap();
+        assert!(quote_result.is_truncated && !quote_result.is_book_exhausted);
+
+        // Nothing to sell into.
+        let quote_result: QuoteResult = market
+            .impact_quote_atoms_result(false, BaseAtoms::new(10), &[None, None], 0, &filter)
+            .unwrap();
+        assert!(quote_result.is_book_exhausted);
+        assert_eq!(quote_result.num_orders_crossed, 0);
+        assert_eq!(quote_result.get_average_price(), None);
+        assert_eq!(quote_result.get_price_impact_bps(), None);
+    }
+
+    #[test]
+    fn test_impact_atoms_single_walk() {
+        // Prices that round: asks at 1.3 and 1.7, a bid at 0.7.
+        let market: MarketValue = market_with_orders(&[
+            (price(13, -1), 101, false),
+            (price(17, -1), 101, false),
+            (price(7, -1), 101, true),
+        ]);
+
+        for is_bid in [true, false] {
+            for atoms in [0, 1, 77, 101, 150, 202, 250, 400] {
+                let (base_atoms, quote_atoms) = market
+                    .impact_atoms_for_base_with_slot(
+                        is_bid,
+                        BaseAtoms::new(atoms),
+                        &[None, None],
+                        0,
+                    )
+                    .unwrap();
+                assert_eq!(
+                    quote_atoms,
+                    market
+                        .impact_quote_atoms_with_slot(
+                            is_bid,
+                            BaseAtoms::new(atoms),
+                            &[None, None],
+                            0
+                        )
+                        .unwrap()
+                );
+                assert!(base_atoms <= BaseAtoms::new(atoms));
+
+                let (base_atoms, quote_atoms) = market
+                    .impact_atoms_for_quote_with_slot(
+                        is_bid,
+                        QuoteAtoms::new(atoms),
+                        &[None, None],
+                        0,
+                    )
+                    .unwrap();
+                assert_eq!(
+                    base_atoms,
+                    market
+                        .impact_base_atoms_with_slot(
+                            is_bid,
+                            QuoteAtoms::new(atoms),
+                            &[None, None],
+                            0
+                        )
+                        .unwrap()
+                );
+                assert_eq!(
+                    quote_atoms,
+                    market
+                        .impact_quote_atoms_with_slot(is_bid, base_atoms, &[None, None], 0)
+                        .unwrap()
+                );
+            }
+        }
+    }
+}
@@ -232,9 +232,7 @@ impl<'a, 'b, 'info> AddSingleOrderCtx<'a, 'b, 'info> {
         }
 
         // Stop trying to match if price no longer satisfies limit.
-        if (is_bid && other_order.get_price() > price)
-            || (!is_bid && other_order.get_price() < price)
-        {
+        if !crate::quantities::would_cross(Some(other_order.get_price()), price, is_bid) {
             return Ok(AddOrderToMarketInnerResult {
                 next_order_index: NIL,
                 status: AddOrderStatus::Unmatched,
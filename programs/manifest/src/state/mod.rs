@@ -1,13 +1,20 @@
 pub mod claimed_seat;
 pub mod constants;
 pub mod dynamic_account;
+pub mod expiry_slot;
 pub mod global;
 pub mod market;
+pub mod recover;
 pub mod resting_order;
+pub mod safe_parse;
 pub mod utils;
+pub mod views;
 
 pub use constants::*;
 pub use dynamic_account::*;
+pub use expiry_slot::*;
 pub use global::*;
 pub use market::*;
 pub use resting_order::*;
+pub use safe_parse::*;
+pub use views::*;
@@ -0,0 +1,164 @@
+//! Best effort recovery of market contents when the tree indices can no
+//! longer be trusted. Every block of the dynamic region is inspected on its
+//! own, so a broken root or child pointer does not hide the rest of the book.
+
+use bytemuck::pod_read_unaligned;
+use hypertree::{DataIndex, RBNode, RBTREE_OVERHEAD_BYTES};
+use solana_program::pubkey::Pubkey;
+use std::mem::size_of;
+
+use crate::{
+    program::batch_update::MarketDataTreeNodeType,
+    quantities::{QuoteAtomsPerBaseAtom, WrapperU64},
+    state::{OrderType, RestingOrder},
+};
+
+use super::{
+    claimed_seat::ClaimedSeat,
+    constants::{MARKET_BLOCK_SIZE, MARKET_FIXED_SIZE},
+};
+
+// Byte offsets inside a market block. The node header is left, right, parent
+// (4 bytes each), then color and payload type.
+const COLOR_OFFSET: usize = 12;
+const PAYLOAD_TYPE_OFFSET: usize = 13;
+// price(16) + num_base_atoms(8) + sequence_number(8) + trader_index(4) +
+// last_valid_slot(4) + is_bid(1)
+const IS_BID_OFFSET: usize = RBTREE_OVERHEAD_BYTES + 40;
+const ORDER_TYPE_OFFSET: usize = RBTREE_OVERHEAD_BYTES + 41;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SalvagedSeat {
+    pub index: DataIndex,
+    pub seat: ClaimedSeat,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SalvagedOrder {
+    pub index: DataIndex,
+    pub order: RestingOrder,
+    /// Owner of the order when its trader_index points at a salvaged seat.
+    pub trader: Option<Pubkey>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SalvageReport {
+    pub seats: Vec<SalvagedSeat>,
+    pub orders: Vec<SalvagedOrder>,
+}
+
+/// Scan the full market account data for plausible seats and resting orders
+/// without following any tree or free list pointers. Free blocks are zeroed
+/// when released, so stale entries do not show up, but a block corrupted in a
+/// way that still looks valid will be reported. Results are for incident
+/// response only and should be checked by hand.
+pub fn salvage_orders(data: &[u8]) -> SalvageReport {
+    let dynamic: &[u8] = data.get(MARKET_FIXED_SIZE..).unwrap_or(&[]);
+    let mut report: SalvageReport = SalvageReport::default();
+
+    for (block_number, block) in dynamic.chunks_exact(MARKET_BLOCK_SIZE).enumerate() {
+        let index: DataIndex = (block_number * MARKET_BLOCK_SIZE) as DataIndex;
+
+        // Color is an enum, so anything else cannot be read as a node.
+        if block[COLOR_OFFSET] > 1 {
+            continue;
+        }
+        let payload_type: u8 = block[PAYLOAD_TYPE_OFFSET];
+        if payload_type == MarketDataTreeNodeType::ClaimedSeat as u8 {
+            let node: RBNode<ClaimedSeat> =
+                pod_read_unaligned(&block[..size_of::<RBNode<ClaimedSeat>>()]);
+            let seat: ClaimedSeat = *node.get_value();
+            if seat.trader == Pubkey::default() {
+                continue;
+            }
+            report.seats.push(SalvagedSeat { index, seat });
+        } else if payload_type == MarketDataTreeNodeType::RestingOrder as u8 {
+            if block[IS_BID_OFFSET] > 1 || OrderType::try_from(block[ORDER_TYPE_OFFSET]).is_err() {
+                continue;
+            }
+            let node: RBNode<RestingOrder> =
+                pod_read_unaligned(&block[..size_of::<RBNode<RestingOrder>>()]);
+            let order: RestingOrder = *node.get_value();
+            if order.get_num_base_atoms().as_u64() == 0
+                || order.get_price() == QuoteAtomsPerBaseAtom::ZERO
+            {
+                continue;
+            }
+            report.orders.push(SalvagedOrder {
+                index,
+                order,
+                trader: None,
+            });
+        }
+    }
+
+    for salvaged_order in report.orders.iter_mut() {
+        salvaged_order.trader = report
+            .seats
+            .iter()
+            .find(|salvaged_seat| salvaged_seat.index == salvaged_order.order.get_trader_index())
+            .map(|salvaged_seat| salvaged_seat.seat.trader);
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        quantities::BaseAtoms,
+        state::{
+            create_empty_market, AddOrderToMarketArgs, MarketFixed, MarketValue,
+            NO_EXPIRATION_LAST_VALID_SLOT,
+        },
+    };
+
+    #[test]
+    fn test_salvage_orders_with_broken_pointers() {
+        let trader: Pubkey = Pubkey::new_unique();
+        let market_key: Pubkey = Pubkey::new_unique();
+        let mut market: MarketValue = MarketValue {
+            fixed: create_empty_market(
+                "So11111111111111111111111111111111111111112",
+                "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+                9,
+                6,
+                &Pubkey::new_unique(),
+                &market_key,
+            ),
+            dynamic: vec![0; MARKET_BLOCK_SIZE * 3],
+        };
+        market.market_expand_n(3).unwrap();
+        market.claim_seat(&trader).unwrap();
+        let trader_index: DataIndex = market.get_trader_index(&trader);
+        market.deposit(trader_index, 1_000, true).unwrap();
+        market
+            .place_order(AddOrderToMarketArgs {
+                market: market_key,
+                trader_index,
+                num_base_atoms: BaseAtoms::new(100),
+                price: QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(1, 0).unwrap(),
+                is_bid: false,
+                last_valid_slot: NO_EXPIRATION_LAST_VALID_SLOT,
+                order_type: OrderType::Limit,
+                global_trade_accounts_opts: &[None, None],
+                current_slot: Some(0),
+            })
+            .unwrap();
+
+        let mut data: Vec<u8> = bytemuck::bytes_of::<MarketFixed>(&market.fixed).to_vec();
+        data.extend_from_slice(&market.dynamic);
+        // Scribble over the left, right and parent pointers of every block.
+        for block in data[MARKET_FIXED_SIZE..].chunks_exact_mut(MARKET_BLOCK_SIZE) {
+            block[..COLOR_OFFSET].fill(0xAB);
+        }
+
+        let report: SalvageReport = salvage_orders(&data);
+        assert_eq!(report.seats.len(), 1);
+        assert_eq!(report.seats[0].seat.trader, trader);
+        assert_eq!(report.orders.len(), 1);
+        assert_eq!(report.orders[0].order.get_num_base_atoms(), 100);
+        assert_eq!(report.orders[0].trader, Some(trader));
+    }
+}
@@ -1,10 +1,11 @@
 use std::mem::size_of;
 
+#[cfg(feature = "certora")]
+use crate::quantities::QuoteAtoms;
+use crate::quantities::WrapperU64;
 use crate::quantities::{
     u64_slice_to_u128, BaseAtoms, PriceConversionError, QuoteAtomsPerBaseAtom,
 };
-#[cfg(feature = "certora")]
-use crate::quantities::{QuoteAtoms, WrapperU64};
 use borsh::{BorshDeserialize, BorshSerialize};
 use bytemuck::{Pod, Zeroable};
 use hypertree::{DataIndex, PodBool};
@@ -14,7 +15,12 @@ use solana_program::{entrypoint::ProgramResult, program_error::ProgramError};
 use static_assertions::const_assert_eq;
 use std::cmp::Ordering;
 
-use super::{constants::NO_EXPIRATION_LAST_VALID_SLOT, RESTING_ORDER_SIZE};
+use super::{
+    constants::NO_EXPIRATION_LAST_VALID_SLOT,
+    expiry_slot::ExpirySlot,
+    safe_parse::{ByteReader, SafeParse},
+    RESTING_ORDER_SIZE,
+};
 
 #[derive(
     Debug,
@@ -150,6 +156,10 @@ impl RestingOrder {
     pub fn set_sequence_number(&mut self, sequence_number: u64) {
         self.sequence_number = sequence_number;
     }
+    pub fn get_last_valid_slot(&self) -> u32 {
+        self.last_valid_slot
+    }
+
     #[cfg(any(test, feature = "no-clock"))]
     pub fn set_last_valid_slot(&mut self, last_valid_slot: u32) {
         self.last_valid_slot = last_valid_slot;
@@ -205,8 +215,12 @@ impl RestingOrder {
         self.sequence_number
     }
 
+    pub fn get_expiry_slot(&self) -> ExpirySlot {
+        ExpirySlot::from_last_valid_slot(self.last_valid_slot)
+    }
+
     pub fn is_expired(&self, current_slot: u32) -> bool {
-        self.last_valid_slot != NO_EXPIRATION_LAST_VALID_SLOT && self.last_valid_slot < current_slot
+        self.get_expiry_slot().is_expired(current_slot as u64)
     }
 
     pub fn get_is_bid(&self) -> bool {
@@ -239,6 +253,26 @@ impl RestingOrder {
     }
 }
 
+impl SafeParse for RestingOrder {
+    fn safe_parse(data: &[u8]) -> Result<Self, ProgramError> {
+        let mut reader: ByteReader = ByteReader::new(data);
+        Ok(RestingOrder {
+            price: QuoteAtomsPerBaseAtom {
+                inner: reader.read_u64_array()?,
+            },
+            num_base_atoms: BaseAtoms::new(reader.read_u64()?),
+            sequence_number: reader.read_u64()?,
+            trader_index: reader.read_u32()?,
+            last_valid_slot: reader.read_u32()?,
+            is_bid: PodBool(reader.read_u8()?),
+            order_type: OrderType::try_from(reader.read_u8()?)
+                .map_err(|_| ProgramError::InvalidAccountData)?,
+            reverse_spread: reader.read_u16()?,
+            _padding: reader.read_array()?,
+        })
+    }
+}
+
 impl Ord for RestingOrder {
     fn cmp(&self, other: &Self) -> Ordering {
         // We only compare bids with bids or asks with asks. If you want to
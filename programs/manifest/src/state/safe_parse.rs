@@ -0,0 +1,120 @@
+//! Reading account headers field by field instead of casting the bytes. The
+//! cast needs the data to be aligned for the struct, which some wasm hosts
+//! and audit requirements do not allow. With the `safe-parse` feature,
+//! `read_state` uses the field by field reads. Only the fixed headers read
+//! through `read_state` are covered. Orders and tree nodes in the dynamic
+//! region are still cast in place by `get_helper`.
+
+use bytemuck::Pod;
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+use std::mem::size_of;
+
+use crate::require;
+
+pub(crate) struct ByteReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        ByteReader { data, offset: 0 }
+    }
+
+    pub(crate) fn read_array<const N: usize>(&mut self) -> Result<[u8; N], ProgramError> {
+        let bytes: &[u8] = self
+            .data
+            .get(self.offset..self.offset + N)
+            .ok_or(ProgramError::AccountDataTooSmall)?;
+        self.offset += N;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    pub(crate) fn read_u8(&mut self) -> Result<u8, ProgramError> {
+        Ok(self.read_array::<1>()?[0])
+    }
+
+    pub(crate) fn read_u16(&mut self) -> Result<u16, ProgramError> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    pub(crate) fn read_u32(&mut self) -> Result<u32, ProgramError> {
+        Ok(u32::from_le_bytes(self.read_array()?))
+    }
+
+    pub(crate) fn read_u64(&mut self) -> Result<u64, ProgramError> {
+        Ok(u64::from_le_bytes(self.read_array()?))
+    }
+
+    pub(crate) fn read_u64_array<const N: usize>(&mut self) -> Result<[u64; N], ProgramError> {
+        let mut values: [u64; N] = [0; N];
+        for value in values.iter_mut() {
+            *value = self.read_u64()?;
+        }
+        Ok(values)
+    }
+
+    pub(crate) fn read_pubkey(&mut self) -> Result<Pubkey, ProgramError> {
+        Ok(Pubkey::new_from_array(self.read_array()?))
+    }
+}
+
+pub trait SafeParse: Sized {
+    /// Copy of the struct at the start of `data`, read one field at a time.
+    /// Fails instead of producing an enum with an unknown variant.
+    fn safe_parse(data: &[u8]) -> Result<Self, ProgramError>;
+}
+
+/// Copy of the header at the start of `data`. Reads field by field with the
+/// `safe-parse` feature and casts otherwise, with the same result for any
+/// valid account. Without the feature, unaligned data is an error.
+pub fn read_state<T: SafeParse + Pod>(data: &[u8]) -> Result<T, ProgramError> {
+    require!(
+        data.len() >= size_of::<T>(),
+        ProgramError::AccountDataTooSmall,
+        "Expected at least {} bytes, got {}",
+        size_of::<T>(),
+        data.len(),
+    )?;
+    if cfg!(feature = "safe-parse") {
+        T::safe_parse(data)
+    } else {
+        bytemuck::try_from_bytes::<T>(&data[..size_of::<T>()])
+            .copied()
+            .map_err(|_| ProgramError::InvalidAccountData)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{ClaimedSeat, GlobalFixed, MarketFixed, OrderType, RestingOrder};
+
+    fn patterned_bytes(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + 3) as u8).collect()
+    }
+
+    fn assert_parity<T: SafeParse + Pod>(bytes: &[u8]) {
+        let parsed: T = T::safe_parse(bytes).unwrap();
+        let cast: T = *bytemuck::from_bytes::<T>(bytes);
+        assert_eq!(bytemuck::bytes_of(&parsed), bytemuck::bytes_of(&cast));
+        assert_eq!(bytemuck::bytes_of(&read_state::<T>(bytes).unwrap()), bytes);
+    }
+
+    #[test]
+    fn test_safe_parse_parity() {
+        assert_parity::<MarketFixed>(&patterned_bytes(size_of::<MarketFixed>()));
+        assert_parity::<GlobalFixed>(&patterned_bytes(size_of::<GlobalFixed>()));
+        assert_parity::<ClaimedSeat>(&patterned_bytes(size_of::<ClaimedSeat>()));
+
+        let mut order_bytes: Vec<u8> = patterned_bytes(size_of::<RestingOrder>());
+        // Order type is after price, base atoms, sequence number, trader
+        // index, last valid slot and is_bid.
+        order_bytes[41] = OrderType::Global as u8;
+        assert_parity::<RestingOrder>(&order_bytes);
+
+        order_bytes[41] = u8::MAX;
+        assert!(RestingOrder::safe_parse(&order_bytes).is_err());
+        assert!(read_state::<MarketFixed>(&[0; 8]).is_err());
+    }
+}
@@ -23,8 +23,7 @@ use spl_token_2022::{
 };
 
 use super::{
-    order_type_can_take, GlobalRefMut, OrderType, RestingOrder, GAS_DEPOSIT_LAMPORTS,
-    NO_EXPIRATION_LAST_VALID_SLOT,
+    order_type_can_take, ExpirySlot, GlobalRefMut, OrderType, RestingOrder, GAS_DEPOSIT_LAMPORTS,
 };
 
 pub fn get_now_slot() -> u32 {
@@ -198,7 +197,7 @@ pub(crate) fn assert_can_take(order_type: OrderType) -> ProgramResult {
 
 pub(crate) fn assert_not_already_expired(last_valid_slot: u32, now_slot: u32) -> ProgramResult {
     require!(
-        last_valid_slot == NO_EXPIRATION_LAST_VALID_SLOT || last_valid_slot > now_slot,
+        ExpirySlot::from_last_valid_slot(last_valid_slot).can_place(now_slot as u64),
         crate::program::ManifestError::AlreadyExpired,
         "Placing an already expired order. now: {} last_valid: {}",
         now_slot,
@@ -0,0 +1,166 @@
+//! Plain copies of the account structs for code outside the program. The
+//! account structs are read in place from account data, so taking a reference
+//! to one of their fields can produce an unaligned reference. Views copy
+//! every field out once and have a plain C layout, which also makes them
+//! usable from other languages. Fields go largest first, so the only padding
+//! is the trailing bytes that round `MarketFixedView` and `RestingOrderView`
+//! up to their 8 byte alignment.
+
+use hypertree::DataIndex;
+use solana_program::pubkey::Pubkey;
+
+use crate::quantities::{u64_slice_to_u128, WrapperU64};
+
+use super::{ClaimedSeat, MarketFixed, RestingOrder};
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MarketFixedView {
+    pub base_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    pub base_vault: Pubkey,
+    pub quote_vault: Pubkey,
+    pub order_sequence_number: u64,
+    pub quote_volume: u64,
+    pub num_bytes_allocated: u32,
+    pub version: u8,
+    pub base_mint_decimals: u8,
+    pub quote_mint_decimals: u8,
+    pub base_vault_bump: u8,
+    pub quote_vault_bump: u8,
+}
+
+impl From<&MarketFixed> for MarketFixedView {
+    fn from(fixed: &MarketFixed) -> Self {
+        MarketFixedView {
+            base_mint: *fixed.get_base_mint(),
+            quote_mint: *fixed.get_quote_mint(),
+            base_vault: *fixed.get_base_vault(),
+            quote_vault: *fixed.get_quote_vault(),
+            order_sequence_number: fixed.get_order_sequence_number(),
+            quote_volume: fixed.get_quote_volume().as_u64(),
+            num_bytes_allocated: fixed.get_num_bytes_allocated(),
+            version: fixed.get_version(),
+            base_mint_decimals: fixed.get_base_mint_decimals(),
+            quote_mint_decimals: fixed.get_quote_mint_decimals(),
+            base_vault_bump: fixed.get_base_vault_bump(),
+            quote_vault_bump: fixed.get_quote_vault_bump(),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RestingOrderView {
+    /// Quote atoms per base atom times 10^18, in two halves because u128 has
+    /// no stable C layout.
+    pub price_d18_low: u64,
+    pub price_d18_high: u64,
+    pub num_base_atoms: u64,
+    pub sequence_number: u64,
+    pub trader_index: DataIndex,
+    pub last_valid_slot: u32,
+    /// `OrderType` as its u8 value.
+    pub order_type: u8,
+    pub is_bid: bool,
+    pub reverse_spread: u16,
+}
+
+impl RestingOrderView {
+    pub fn get_price_d18(&self) -> u128 {
+        ((self.price_d18_high as u128) << 64) | self.price_d18_low as u128
+    }
+}
+
+impl From<&RestingOrder> for RestingOrderView {
+    fn from(order: &RestingOrder) -> Self {
+        let price_d18: u128 = u64_slice_to_u128(order.get_price().inner);
+        RestingOrderView {
+            price_d18_low: price_d18 as u64,
+            price_d18_high: (price_d18 >> 64) as u64,
+            num_base_atoms: order.get_num_base_atoms().as_u64(),
+            sequence_number: order.get_sequence_number(),
+            trader_index: order.get_trader_index(),
+            last_valid_slot: order.get_last_valid_slot(),
+            order_type: order.get_order_type().into(),
+            is_bid: order.get_is_bid(),
+            reverse_spread: order.get_reverse_spread(),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ClaimedSeatView {
+    pub trader: Pubkey,
+    pub base_withdrawable_balance: u64,
+    pub quote_withdrawable_balance: u64,
+    pub quote_volume: u64,
+}
+
+impl From<&ClaimedSeat> for ClaimedSeatView {
+    fn from(seat: &ClaimedSeat) -> Self {
+        ClaimedSeatView {
+            trader: seat.trader,
+            base_withdrawable_balance: seat.base_withdrawable_balance.as_u64(),
+            quote_withdrawable_balance: seat.quote_withdrawable_balance.as_u64(),
+            quote_volume: seat.quote_volume.as_u64(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        quantities::{BaseAtoms, QuoteAtoms, QuoteAtomsPerBaseAtom},
+        state::{OrderType, NO_EXPIRATION_LAST_VALID_SLOT},
+    };
+
+    #[test]
+    fn test_views() {
+        let price: QuoteAtomsPerBaseAtom =
+            QuoteAtomsPerBaseAtom::try_from_mantissa_and_exponent(15, 2).unwrap();
+        let mut order: RestingOrder = RestingOrder::new(
+            7,
+            BaseAtoms::new(1_000),
+            price,
+            42,
+            NO_EXPIRATION_LAST_VALID_SLOT,
+            true,
+            OrderType::Limit,
+        )
+        .unwrap();
+        order.set_reverse_spread(3);
+        let view: RestingOrderView = RestingOrderView::from(&order);
+        assert_eq!(view.get_price_d18(), u64_slice_to_u128(price.inner));
+        assert_eq!(view.get_price_d18(), 1_500 * 10_u128.pow(18));
+        assert_eq!(view.num_base_atoms, 1_000);
+        assert_eq!(view.sequence_number, 42);
+        assert_eq!(view.trader_index, 7);
+        assert_eq!(view.order_type, u8::from(OrderType::Limit));
+        assert!(view.is_bid);
+        assert_eq!(view.reverse_spread, 3);
+
+        let trader: Pubkey = Pubkey::new_unique();
+        let mut seat: ClaimedSeat = ClaimedSeat::new_empty(trader);
+        seat.quote_withdrawable_balance = QuoteAtoms::new(5);
+        assert_eq!(
+            ClaimedSeatView::from(&seat),
+            ClaimedSeatView {
+                trader,
+                quote_withdrawable_balance: 5,
+                ..Default::default()
+            }
+        );
+
+        assert_eq!(
+            MarketFixedView::from(&MarketFixed::default()),
+            MarketFixedView::default()
+        );
+
+        // 153 and 44 bytes of fields plus trailing padding.
+        assert_eq!(std::mem::size_of::<MarketFixedView>(), 160);
+        assert_eq!(std::mem::size_of::<RestingOrderView>(), 48);
+    }
+}
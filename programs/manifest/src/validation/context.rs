@@ -0,0 +1,208 @@
+//! Collects every failed account check instead of stopping at the first one,
+//! so an integrator with several wrong account metas sees all of them.
+
+use std::{cell::Ref, fmt, slice::Iter};
+
+use solana_program::{
+    account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey, system_program,
+};
+
+use crate::state::MarketFixed;
+
+use super::{ManifestAccountInfo, Program, Signer, TokenAccountInfo, TokenProgram};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationFailure {
+    pub label: String,
+    pub error: ProgramError,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Context {
+    failures: Vec<ValidationFailure>,
+}
+
+impl Context {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the result of a checker. Returns the checked value so later
+    /// checks that depend on it can still run.
+    pub fn check<T>(&mut self, label: &str, result: Result<T, ProgramError>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.failures.push(ValidationFailure {
+                    label: label.to_string(),
+                    error,
+                });
+                None
+            }
+        }
+    }
+
+    /// Same as the `require!` macro except that it keeps going.
+    pub fn require(&mut self, test: bool, label: &str, error: impl Into<ProgramError>) -> bool {
+        if !test {
+            self.failures.push(ValidationFailure {
+                label: label.to_string(),
+                error: error.into(),
+            });
+        }
+        test
+    }
+
+    pub fn get_failures(&self) -> &[ValidationFailure] {
+        &self.failures
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// Combined diagnostic with one line per failed check.
+    pub fn render(&self) -> String {
+        self.to_string()
+    }
+
+    /// The first failure, matching what the loader would have returned.
+    pub fn into_result(self) -> Result<(), ProgramError> {
+        match self.failures.into_iter().next() {
+            Some(failure) => Err(failure.error),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Context {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.failures.is_empty() {
+            return write!(f, "All account checks passed");
+        }
+        write!(f, "{} account check(s) failed", self.failures.len())?;
+        for failure in self.failures.iter() {
+            write!(f, "\n  {}: {:?}", failure.label, failure.error)?;
+        }
+        Ok(())
+    }
+}
+
+fn next_account<'a, 'info>(
+    context: &mut Context,
+    account_iter: &mut Iter<'a, AccountInfo<'info>>,
+    label: &str,
+) -> Option<&'a AccountInfo<'info>> {
+    let next: Option<&AccountInfo> = account_iter.next();
+    context.require(next.is_some(), label, ProgramError::NotEnoughAccountKeys);
+    next
+}
+
+/// Run the checks of the swap loader on the required accounts and report all
+/// of the problems at once. Optional trailing accounts are not inspected.
+pub fn diagnose_swap_accounts(accounts: &[AccountInfo]) -> Context {
+    let mut context: Context = Context::new();
+    let account_iter: &mut Iter<AccountInfo> = &mut accounts.iter();
+
+    let payer_opt: Option<&AccountInfo> = next_account(&mut context, account_iter, "payer");
+    let owner_or_market_opt: Option<&AccountInfo> =
+        next_account(&mut context, account_iter, "owner or market");
+    let (Some(payer), Some(owner_or_market)) = (payer_opt, owner_or_market_opt) else {
+        return context;
+    };
+
+    let (owner, market_info_opt) = if *owner_or_market.owner == crate::ID {
+        (payer, Some(owner_or_market))
+    } else {
+        (
+            owner_or_market,
+            next_account(&mut context, account_iter, "market"),
+        )
+    };
+    context.check("owner", Signer::new(owner));
+    let market_opt: Option<ManifestAccountInfo<MarketFixed>> = market_info_opt
+        .and_then(|info| context.check("market", ManifestAccountInfo::<MarketFixed>::new(info)));
+
+    if let Some(info) = next_account(&mut context, account_iter, "system program") {
+        context.check("system program", Program::new(info, &system_program::id()));
+    }
+
+    // Token account checks need the mints and vaults from the market.
+    let market_keys_opt: Option<[Pubkey; 4]> = market_opt.and_then(|market| {
+        let market_fixed: Ref<MarketFixed> = context.check("market", market.get_fixed())?;
+        Some([
+            *market_fixed.get_base_mint(),
+            *market_fixed.get_quote_mint(),
+            *market_fixed.get_base_vault(),
+            *market_fixed.get_quote_vault(),
+        ])
+    });
+
+    let trader_base_opt: Option<&AccountInfo> =
+        next_account(&mut context, account_iter, "trader base");
+    let trader_quote_opt: Option<&AccountInfo> =
+        next_account(&mut context, account_iter, "trader quote");
+    let base_vault_opt: Option<&AccountInfo> =
+        next_account(&mut context, account_iter, "base vault");
+    let quote_vault_opt: Option<&AccountInfo> =
+        next_account(&mut context, account_iter, "quote vault");
+    if let Some([base_mint, quote_mint, base_vault, quote_vault]) = market_keys_opt {
+        if let Some(info) = trader_base_opt {
+            context.check("trader base", TokenAccountInfo::new(info, &base_mint));
+        }
+        if let Some(info) = trader_quote_opt {
+            context.check("trader quote", TokenAccountInfo::new(info, &quote_mint));
+        }
+        if let Some(info) = base_vault_opt {
+            context.check(
+                "base vault",
+                TokenAccountInfo::new_with_owner_and_key(
+                    info,
+                    &base_mint,
+                    &base_vault,
+                    &base_vault,
+                ),
+            );
+        }
+        if let Some(info) = quote_vault_opt {
+            context.check(
+                "quote vault",
+                TokenAccountInfo::new_with_owner_and_key(
+                    info,
+                    &quote_mint,
+                    &quote_vault,
+                    &quote_vault,
+                ),
+            );
+        }
+    }
+
+    if let Some(info) = next_account(&mut context, account_iter, "token program base") {
+        context.check("token program base", TokenProgram::new(info));
+    }
+
+    context
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::program::ManifestError;
+
+    #[test]
+    fn test_context_collects_all_failures() {
+        let mut context: Context = Context::new();
+        assert_eq!(context.check("first", Ok::<u64, ProgramError>(1)), Some(1));
+        assert!(context.is_ok());
+
+        context.check::<()>("second", Err(ProgramError::MissingRequiredSignature));
+        context.require(false, "third", ManifestError::IncorrectAccount);
+        assert_eq!(context.get_failures().len(), 2);
+        assert!(context.render().contains("2 account check(s) failed"));
+        assert!(context.render().contains("third"));
+        assert_eq!(
+            context.into_result(),
+            Err(ProgramError::MissingRequiredSignature)
+        );
+    }
+}
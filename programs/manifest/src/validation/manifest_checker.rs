@@ -6,7 +6,7 @@ use solana_program::{
 };
 use std::{cell::Ref, mem::size_of, ops::Deref};
 
-use crate::require;
+use crate::{require, validation::ProgramIdSet};
 
 /// Validation for manifest accounts.
 #[derive(Clone)]
@@ -25,7 +25,28 @@ impl<'a, 'info, T: ManifestAccount + Get + Clone> ManifestAccountInfo<'a, 'info,
         info: &'a AccountInfo<'info>,
     ) -> Result<ManifestAccountInfo<'a, 'info, T>, ProgramError> {
         verify_owned_by_manifest(info.owner)?;
+        Self::new_with_verified_owner(info)
+    }
+
+    /// Same as `new`, but accepts accounts owned by any of `program_ids`.
+    /// Returns the program that owns the account along with it, so callers
+    /// that span a migration can tell legacy accounts apart.
+    pub fn new_with_program_ids(
+        info: &'a AccountInfo<'info>,
+        program_ids: &ProgramIdSet,
+    ) -> Result<(ManifestAccountInfo<'a, 'info, T>, Pubkey), ProgramError> {
+        require!(
+            program_ids.contains(info.owner),
+            ProgramError::IllegalOwner,
+            "Account must be owned by a Manifest program actual:{}",
+            info.owner
+        )?;
+        Ok((Self::new_with_verified_owner(info)?, *info.owner))
+    }
 
+    fn new_with_verified_owner(
+        info: &'a AccountInfo<'info>,
+    ) -> Result<ManifestAccountInfo<'a, 'info, T>, ProgramError> {
         let bytes: Ref<&mut [u8]> = info.try_borrow_data()?;
         let (header_bytes, _) = bytes.split_at(size_of::<T>());
         let header: &T = get_helper::<T>(header_bytes, 0_u32);
@@ -104,8 +125,13 @@ fn verify_uninitialized<T: Pod + ManifestAccount>(info: &AccountInfo) -> Program
 
 #[cfg(test)]
 mod test {
-    use crate::state::{
-        GlobalFixed, MarketFixed, GLOBAL_FIXED_DISCRIMINANT, MARKET_FIXED_DISCRIMINANT,
+    use super::*;
+    use crate::{
+        state::{GlobalFixed, MarketFixed, GLOBAL_FIXED_DISCRIMINANT, MARKET_FIXED_DISCRIMINANT},
+        validation::{
+            get_global_vault_address, get_global_vault_address_with_bump, get_vault_address,
+            get_vault_address_with_bump,
+        },
     };
 
     #[test]
@@ -114,11 +140,64 @@ mod test {
         assert_eq!(discriminant, MARKET_FIXED_DISCRIMINANT);
     }
 
+    #[test]
+    fn test_pda_with_bump() {
+        let market: Pubkey = Pubkey::new_unique();
+        let mint: Pubkey = Pubkey::new_unique();
+        let (vault, vault_bump) = get_vault_address(&market, &mint);
+        assert_eq!(
+            get_vault_address_with_bump(&market, &mint, vault_bump).unwrap(),
+            vault
+        );
+        let (global_vault, global_vault_bump) = get_global_vault_address(&mint);
+        assert_eq!(
+            get_global_vault_address_with_bump(&mint, global_vault_bump).unwrap(),
+            global_vault
+        );
+
+        let (global, global_bump) = get_global_address(&mint);
+        assert_eq!(
+            get_global_address_with_bump(&mint, global_bump).unwrap(),
+            global
+        );
+        assert!(get_global_address_with_bump(&mint, 0).is_err());
+    }
+
     #[test]
     fn test_global_fixed_discriminant() {
         let discriminant: u64 = crate::utils::get_discriminant::<GlobalFixed>().unwrap();
         assert_eq!(discriminant, GLOBAL_FIXED_DISCRIMINANT);
     }
+
+    #[test]
+    fn test_new_with_program_ids() {
+        let legacy: Pubkey = Pubkey::new_unique();
+        let program_ids: ProgramIdSet = ProgramIdSet::new(vec![legacy]);
+        let key: Pubkey = Pubkey::new_unique();
+        let mut data: Vec<u8> =
+            bytemuck::bytes_of(&GlobalFixed::new_empty(&Pubkey::new_unique())).to_vec();
+        let mut lamports: u64 = 0;
+        let info: AccountInfo = AccountInfo {
+            key: &key,
+            lamports: std::rc::Rc::new(std::cell::RefCell::new(&mut lamports)),
+            data: std::rc::Rc::new(std::cell::RefCell::new(&mut data[..])),
+            owner: &legacy,
+            rent_epoch: 0,
+            is_signer: false,
+            is_writable: false,
+            executable: false,
+        };
+
+        assert!(ManifestAccountInfo::<GlobalFixed>::new(&info).is_err());
+        let (_, owner) =
+            ManifestAccountInfo::<GlobalFixed>::new_with_program_ids(&info, &program_ids).unwrap();
+        assert_eq!(owner, legacy);
+        assert!(ManifestAccountInfo::<GlobalFixed>::new_with_program_ids(
+            &info,
+            &ProgramIdSet::default()
+        )
+        .is_err());
+    }
 }
 
 macro_rules! global_seeds {
@@ -134,6 +213,21 @@ macro_rules! global_seeds_with_bump {
     };
 }
 
+/// Global account of `mint` and its bump.
 pub fn get_global_address(mint: &Pubkey) -> (Pubkey, u8) {
     Pubkey::find_program_address(global_seeds!(mint), &crate::ID)
 }
+
+/// Global address for a bump read from the global. Hashes once instead of
+/// searching for the bump. Globals that predate the stored bump have a zero
+/// there, which usually fails here and needs `get_global_address`.
+pub fn get_global_address_with_bump(
+    mint: &Pubkey,
+    global_bump: u8,
+) -> Result<Pubkey, ProgramError> {
+    if global_bump == 0 {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let seeds: &[&[&[u8]]] = global_seeds_with_bump!(mint, global_bump);
+    Pubkey::create_program_address(seeds[0], &crate::ID).map_err(|_| ProgramError::InvalidSeeds)
+}
@@ -0,0 +1,137 @@
+//! Constants for programs that are hardcoded to a single market, so they can
+//! check vaults against known addresses instead of calling
+//! `find_program_address` on every instruction.
+//!
+//! Deriving a PDA needs the curve check, which cannot run in a const context,
+//! so the addresses and bumps are generated once off chain with
+//! `market_constants_source` and pasted into the program. The generated
+//! module has a `verify` function for a unit test to catch a typo or a
+//! market for a different program.
+
+#[cfg(not(target_os = "solana"))]
+use crate::validation::{get_global_address, get_global_vault_address, get_vault_address};
+#[cfg(not(target_os = "solana"))]
+use solana_program::pubkey::Pubkey;
+
+#[macro_export]
+macro_rules! declare_market {
+    (
+        $name:ident {
+            market: $market:literal,
+            base_mint: $base_mint:literal,
+            quote_mint: $quote_mint:literal,
+            base_vault: ($base_vault:literal, $base_vault_bump:literal),
+            quote_vault: ($quote_vault:literal, $quote_vault_bump:literal),
+            base_global: ($base_global:literal, $base_global_bump:literal),
+            base_global_vault: ($base_global_vault:literal, $base_global_vault_bump:literal),
+            quote_global: ($quote_global:literal, $quote_global_bump:literal),
+            quote_global_vault: ($quote_global_vault:literal, $quote_global_vault_bump:literal) $(,)?
+        }
+    ) => {
+        pub mod $name {
+            use $crate::deps::solana_program::pubkey::Pubkey;
+
+            pub const MARKET: Pubkey = Pubkey::from_str_const($market);
+            pub const BASE_MINT: Pubkey = Pubkey::from_str_const($base_mint);
+            pub const QUOTE_MINT: Pubkey = Pubkey::from_str_const($quote_mint);
+            pub const BASE_VAULT: Pubkey = Pubkey::from_str_const($base_vault);
+            pub const BASE_VAULT_BUMP: u8 = $base_vault_bump;
+            pub const QUOTE_VAULT: Pubkey = Pubkey::from_str_const($quote_vault);
+            pub const QUOTE_VAULT_BUMP: u8 = $quote_vault_bump;
+            pub const BASE_GLOBAL: Pubkey = Pubkey::from_str_const($base_global);
+            pub const BASE_GLOBAL_BUMP: u8 = $base_global_bump;
+            pub const BASE_GLOBAL_VAULT: Pubkey = Pubkey::from_str_const($base_global_vault);
+            pub const BASE_GLOBAL_VAULT_BUMP: u8 = $base_global_vault_bump;
+            pub const QUOTE_GLOBAL: Pubkey = Pubkey::from_str_const($quote_global);
+            pub const QUOTE_GLOBAL_BUMP: u8 = $quote_global_bump;
+            pub const QUOTE_GLOBAL_VAULT: Pubkey = Pubkey::from_str_const($quote_global_vault);
+            pub const QUOTE_GLOBAL_VAULT_BUMP: u8 = $quote_global_vault_bump;
+
+            /// Whether every address and bump matches what the program
+            /// derives. Expensive, meant for a unit test of the program that
+            /// embeds the constants.
+            #[cfg(not(target_os = "solana"))]
+            pub fn verify() -> bool {
+                $crate::validation::get_vault_address(&MARKET, &BASE_MINT)
+                    == (BASE_VAULT, BASE_VAULT_BUMP)
+                    && $crate::validation::get_vault_address(&MARKET, &QUOTE_MINT)
+                        == (QUOTE_VAULT, QUOTE_VAULT_BUMP)
+                    && $crate::validation::get_global_address(&BASE_MINT)
+                        == (BASE_GLOBAL, BASE_GLOBAL_BUMP)
+                    && $crate::validation::get_global_vault_address(&BASE_MINT)
+                        == (BASE_GLOBAL_VAULT, BASE_GLOBAL_VAULT_BUMP)
+                    && $crate::validation::get_global_address(&QUOTE_MINT)
+                        == (QUOTE_GLOBAL, QUOTE_GLOBAL_BUMP)
+                    && $crate::validation::get_global_vault_address(&QUOTE_MINT)
+                        == (QUOTE_GLOBAL_VAULT, QUOTE_GLOBAL_VAULT_BUMP)
+            }
+        }
+    };
+}
+
+/// Source of a `declare_market!` invocation for the market, with every PDA
+/// derived.
+#[cfg(not(target_os = "solana"))]
+pub fn market_constants_source(
+    name: &str,
+    market: &Pubkey,
+    base_mint: &Pubkey,
+    quote_mint: &Pubkey,
+) -> String {
+    let pda = |(address, bump): (Pubkey, u8)| format!("(\"{address}\", {bump})");
+    format!(
+        "manifest::declare_market! {{\n    \
+         {name} {{\n        \
+         market: \"{market}\",\n        \
+         base_mint: \"{base_mint}\",\n        \
+         quote_mint: \"{quote_mint}\",\n        \
+         base_vault: {},\n        \
+         quote_vault: {},\n        \
+         base_global: {},\n        \
+         base_global_vault: {},\n        \
+         quote_global: {},\n        \
+         quote_global_vault: {},\n    \
+         }}\n\
+         }}\n",
+        pda(get_vault_address(market, base_mint)),
+        pda(get_vault_address(market, quote_mint)),
+        pda(get_global_address(base_mint)),
+        pda(get_global_vault_address(base_mint)),
+        pda(get_global_address(quote_mint)),
+        pda(get_global_vault_address(quote_mint)),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    crate::declare_market! {
+        sol_usdc {
+            market: "ENhU8LsaR7vDD2G1CsWcsuSGNrih9Cv5WZEk7q9kPapQ",
+            base_mint: "So11111111111111111111111111111111111111112",
+            quote_mint: "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+            base_vault: ("AKjfJDv4ywdpCDrj7AURuNkGA3696GTVFgrMwk4TjkKs", 254),
+            quote_vault: ("FN9K6rTdWtRDUPmLTN2FnGvLZpHVNRN2MeRghKknSGDs", 255),
+            base_global: ("7mR36vj6pvg1U1cRatvUbLG57yqsd1ojLbrgxb6azaQ1", 255),
+            base_global_vault: ("E1mBVQyt7BHK8SaBSfME7usYxx94T4DtHEjbUpEBhZx", 253),
+            quote_global: ("4SMRzaLXsuvTi2B5cSVs4LuCUUqQhB2uByTWUvbixjfF", 251),
+            quote_global_vault: ("6KzHa7L1MDTkaUb4qGiJqMvnmAy1qztGDeLvSHdS9ooU", 255),
+        }
+    }
+
+    #[test]
+    fn test_market_constants_source() {
+        let source: String = market_constants_source(
+            "sol_usdc",
+            &sol_usdc::MARKET,
+            &sol_usdc::BASE_MINT,
+            &sol_usdc::QUOTE_MINT,
+        );
+        assert!(source.starts_with("manifest::declare_market! {\n    sol_usdc {\n"));
+        assert!(
+            source.contains("base_vault: (\"AKjfJDv4ywdpCDrj7AURuNkGA3696GTVFgrMwk4TjkKs\", 254),")
+        );
+        assert!(sol_usdc::verify());
+    }
+}
@@ -1,8 +1,14 @@
+pub mod context;
 pub mod loaders;
 pub mod manifest_checker;
+pub mod market_constants;
+pub mod program_ids;
 pub mod solana_checkers;
 pub mod token_checkers;
 
+pub use context::*;
 pub use manifest_checker::*;
+pub use market_constants::*;
+pub use program_ids::*;
 pub use solana_checkers::*;
 pub use token_checkers::*;
@@ -0,0 +1,55 @@
+//! Program IDs recognized as owners of Manifest accounts.
+//!
+//! The program itself only accepts accounts owned by `crate::ID`. Off chain
+//! tools that span a program migration, like indexers, pass a `ProgramIdSet`
+//! with the legacy deployments to `ManifestAccountInfo::new_with_program_ids`
+//! and the client decoders, so that accounts owned by either deployment load
+//! and report which one owns them. The instruction contexts in `loaders`
+//! stay on `crate::ID`, since vaults and globals are derived from it and a
+//! deployment never processes accounts of another one.
+
+use solana_program::pubkey::Pubkey;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProgramIdSet {
+    legacy: Vec<Pubkey>,
+}
+
+impl ProgramIdSet {
+    /// The current deployment, `crate::ID`, is always included.
+    pub fn new(legacy: Vec<Pubkey>) -> Self {
+        ProgramIdSet { legacy }
+    }
+
+    pub fn get_current(&self) -> &Pubkey {
+        &crate::ID
+    }
+
+    pub fn get_legacy(&self) -> &[Pubkey] {
+        &self.legacy
+    }
+
+    pub fn contains(&self, program_id: &Pubkey) -> bool {
+        crate::ID == *program_id || self.legacy.contains(program_id)
+    }
+
+    pub fn is_legacy(&self, program_id: &Pubkey) -> bool {
+        crate::ID != *program_id && self.legacy.contains(program_id)
+    }
+}
+
+#[test]
+fn test_program_id_set() {
+    let legacy: Pubkey = Pubkey::new_unique();
+    let program_id_set: ProgramIdSet = ProgramIdSet::new(vec![legacy]);
+    assert_eq!(program_id_set.get_current(), &crate::ID);
+    assert!(program_id_set.contains(&crate::ID));
+    assert!(program_id_set.contains(&legacy));
+    assert!(program_id_set.is_legacy(&legacy));
+    assert!(!program_id_set.is_legacy(&crate::ID));
+    assert!(!program_id_set.contains(&Pubkey::new_unique()));
+
+    // Listing the current deployment as legacy does not make it legacy.
+    assert!(!ProgramIdSet::new(vec![crate::ID]).is_legacy(&crate::ID));
+    assert!(!ProgramIdSet::default().contains(&legacy));
+}
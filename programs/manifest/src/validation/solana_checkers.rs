@@ -68,6 +68,8 @@ pub struct Signer<'a, 'info> {
 }
 
 impl<'a, 'info> Signer<'a, 'info> {
+    /// PDA traders pass this check too, since the owning program signs for
+    /// them with invoke_signed.
     pub fn new(info: &'a AccountInfo<'info>) -> Result<Signer<'a, 'info>, ProgramError> {
         require!(
             info.is_signer,
@@ -140,6 +140,7 @@ macro_rules! global_vault_seeds_with_bump {
     };
 }
 
+/// Vault of `mint` on `market` and its bump.
 pub fn get_vault_address(market: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
     Pubkey::find_program_address(market_vault_seeds!(market, mint), &crate::ID)
 }
@@ -147,3 +148,19 @@ pub fn get_vault_address(market: &Pubkey, mint: &Pubkey) -> (Pubkey, u8) {
 pub fn get_global_vault_address(mint: &Pubkey) -> (Pubkey, u8) {
     Pubkey::find_program_address(global_vault_seeds!(mint), &crate::ID)
 }
+
+/// Vault address for a bump that is already known, like the one stored on
+/// the market. Hashes once instead of searching for the bump.
+pub fn get_vault_address_with_bump(
+    market: &Pubkey,
+    mint: &Pubkey,
+    bump: u8,
+) -> Result<Pubkey, ProgramError> {
+    let seeds: &[&[&[u8]]] = market_vault_seeds_with_bump!(market, mint, bump);
+    Pubkey::create_program_address(seeds[0], &crate::ID).map_err(|_| ProgramError::InvalidSeeds)
+}
+
+pub fn get_global_vault_address_with_bump(mint: &Pubkey, bump: u8) -> Result<Pubkey, ProgramError> {
+    let seeds: &[&[&[u8]]] = global_vault_seeds_with_bump!(mint, bump);
+    Pubkey::create_program_address(seeds[0], &crate::ID).map_err(|_| ProgramError::InvalidSeeds)
+}
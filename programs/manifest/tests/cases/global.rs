@@ -712,6 +712,78 @@ async fn global_insufficient() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "test")]
+#[tokio::test]
+async fn global_insufficient_with_set_balances() -> anyhow::Result<()> {
+    let mut test_fixture: TestFixture = TestFixture::new().await;
+    let payer: Pubkey = test_fixture.payer();
+    test_fixture.claim_seat().await?;
+
+    test_fixture.global_add_trader().await?;
+    test_fixture.global_deposit(1_000_000).await?;
+
+    test_fixture
+        .batch_update_with_global_for_keypair(
+            None,
+            vec![],
+            vec![PlaceOrderParams::new(
+                100,
+                1,
+                0,
+                true,
+                OrderType::Global,
+                NO_EXPIRATION_LAST_VALID_SLOT,
+            )],
+            &test_fixture.payer_keypair().insecure_clone(),
+        )
+        .await?;
+
+    // Same scenario as global_insufficient without the withdraw and deposit.
+    test_fixture
+        .global_fixture
+        .set_global_balance(&payer, 0)
+        .await;
+    test_fixture
+        .market_fixture
+        .credit_seat(&payer, 1_000_000, 0)
+        .await;
+
+    test_fixture
+        .batch_update_with_global_for_keypair(
+            None,
+            vec![],
+            vec![PlaceOrderParams::new(
+                100,
+                9,
+                -1,
+                false,
+                OrderType::ImmediateOrCancel,
+                NO_EXPIRATION_LAST_VALID_SLOT,
+            )],
+            &test_fixture.payer_keypair().insecure_clone(),
+        )
+        .await?;
+
+    let orders: Vec<RestingOrder> = test_fixture.market_fixture.get_resting_orders().await;
+    assert_eq!(orders.len(), 0, "Order still on orderbook");
+    assert_eq!(
+        test_fixture
+            .market_fixture
+            .get_base_balance_atoms(&payer)
+            .await,
+        1_000_000
+    );
+    assert_eq!(
+        test_fixture
+            .market_fixture
+            .get_quote_balance_atoms(&payer)
+            .await,
+        0
+    );
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn global_get_balance_not_in_global() -> anyhow::Result<()> {
     let mut test_fixture: TestFixture = TestFixture::new().await;
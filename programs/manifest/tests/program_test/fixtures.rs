@@ -902,6 +902,26 @@ impl MarketFixture {
         self.market.get_trader_voume(trader).as_u64()
     }
 
+    /// Add to the withdrawable balances of a trader that already has a seat
+    /// without depositing tokens. The vaults are not touched, so only use this
+    /// to set up scenarios that would otherwise need full deposit flows.
+    #[cfg(feature = "test")]
+    pub async fn credit_seat(&mut self, trader: &Pubkey, base_atoms: u64, quote_atoms: u64) {
+        self.reload().await;
+        let trader_index: DataIndex = self.market.get_trader_index(trader);
+        self.market.deposit(trader_index, base_atoms, true).unwrap();
+        self.market
+            .deposit(trader_index, quote_atoms, false)
+            .unwrap();
+        write_dynamic_account(
+            &self.context,
+            &self.key,
+            bytemuck::bytes_of(&self.market.fixed),
+            &self.market.dynamic,
+        )
+        .await;
+    }
+
     pub async fn get_resting_orders(&mut self) -> Vec<RestingOrder> {
         self.reload().await;
         let mut bids_vec: Vec<RestingOrder> = self
@@ -921,6 +941,29 @@ impl MarketFixture {
     }
 }
 
+/// Replace the data of a program account in place, keeping its lamports and
+/// owner.
+#[cfg(feature = "test")]
+async fn write_dynamic_account(
+    context: &Rc<RefCell<ProgramTestContext>>,
+    key: &Pubkey,
+    fixed: &[u8],
+    dynamic: &[u8],
+) {
+    let mut account: Account = context
+        .borrow_mut()
+        .banks_client
+        .get_account(*key)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(account.data.len(), fixed.len() + dynamic.len());
+    account.data = [fixed, dynamic].concat();
+    context
+        .borrow_mut()
+        .set_account(key, &solana_sdk::account::AccountSharedData::from(account));
+}
+
 #[derive(Clone)]
 pub struct GlobalFixture {
     pub context: Rc<RefCell<ProgramTestContext>>,
@@ -982,6 +1025,24 @@ impl GlobalFixture {
         let global: GlobalValue = get_dynamic_value(global_account.data.as_slice());
         self.global = global;
     }
+
+    /// Overwrite the deposit of a trader that already has a global seat. The
+    /// vault is not touched, which makes it easy to leave global orders
+    /// unbacked.
+    #[cfg(feature = "test")]
+    pub async fn set_global_balance(&mut self, trader: &Pubkey, num_atoms: u64) {
+        self.reload().await;
+        self.global
+            .set_balance_atoms(trader, manifest::quantities::GlobalAtoms::new(num_atoms))
+            .unwrap();
+        write_dynamic_account(
+            &self.context,
+            &self.key,
+            bytemuck::bytes_of(&self.global.fixed),
+            &self.global.dynamic,
+        )
+        .await;
+    }
 }
 
 #[derive(Clone)]
@@ -14,7 +14,10 @@ use manifest::{
         claim_seat_instruction, deposit_instruction, expand_market_instruction,
         get_dynamic_account, get_mut_dynamic_account, invoke, ManifestInstruction,
     },
-    quantities::{BaseAtoms, QuoteAtoms, QuoteAtomsPerBaseAtom, WrapperU64},
+    quantities::{
+        net_after_transfer, BaseAtoms, QuoteAtoms, QuoteAtomsPerBaseAtom, TransferDirection,
+        WrapperU64,
+    },
     require,
     state::{claimed_seat::ClaimedSeat, DynamicAccount, MarketFixed, MarketRef, OrderType},
     validation::{ManifestAccountInfo, Program, Signer},
@@ -30,10 +33,7 @@ use solana_program::{
     sysvar::{clock::Clock, Sysvar},
 };
 use spl_token_2022::{
-    extension::{
-        transfer_fee::TransferFeeConfig, transfer_hook::TransferHook, BaseStateWithExtensions,
-        StateWithExtensions,
-    },
+    extension::{transfer_hook::TransferHook, BaseStateWithExtensions, StateWithExtensions},
     state::Mint,
 };
 
@@ -297,14 +297,12 @@ pub(crate) fn process_place_order(
             }
         }
 
-        if let Ok(extension) = deposit_mint.get_extension::<TransferFeeConfig>() {
-            let epoch_fee = extension.get_epoch_fee(Clock::get()?.epoch);
-            epoch_fee
-                .calculate_pre_fee_amount(missing_amount_atoms)
-                .unwrap()
-        } else {
-            missing_amount_atoms
-        }
+        net_after_transfer(
+            mint,
+            missing_amount_atoms,
+            TransferDirection::Inverse,
+            Clock::get()?.epoch,
+        )?
     } else {
         missing_amount_atoms
     };
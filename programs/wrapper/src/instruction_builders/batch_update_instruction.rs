@@ -5,6 +5,7 @@ use crate::{
     },
 };
 use borsh::BorshSerialize;
+use manifest::program::batch_update::PlaceOrderParams;
 use solana_program::{
     instruction::{AccountMeta, Instruction},
     pubkey::Pubkey,
@@ -37,3 +38,105 @@ pub fn batch_update_instruction(
         .concat(),
     }
 }
+
+/// Order for `batch_update_with_client_order_ids_instruction`. Leave the
+/// client order id empty to have the builder assign one.
+pub struct ClientPlaceOrderParams {
+    pub client_order_id: Option<u64>,
+    pub params: PlaceOrderParams,
+}
+
+/// Same as `batch_update_instruction` but cancels by client order id and
+/// fills in missing client order ids counting up from
+/// `next_client_order_id`, skipping ids given explicitly in the batch. Returns
+/// the client order id of every order in the same order as `orders`, which is
+/// what later cancels need.
+pub fn batch_update_with_client_order_ids_instruction(
+    market: &Pubkey,
+    owner: &Pubkey,
+    wrapper_state: &Pubkey,
+    cancel_client_order_ids: &[u64],
+    cancel_all: bool,
+    orders: &[ClientPlaceOrderParams],
+    next_client_order_id: &mut u64,
+) -> (Instruction, Vec<u64>) {
+    let explicit_client_order_ids: Vec<u64> = orders
+        .iter()
+        .filter_map(|order| order.client_order_id)
+        .collect();
+    let client_order_ids: Vec<u64> = orders
+        .iter()
+        .map(|order| {
+            order.client_order_id.unwrap_or_else(|| {
+                while explicit_client_order_ids.contains(next_client_order_id) {
+                    *next_client_order_id += 1;
+                }
+                let client_order_id: u64 = *next_client_order_id;
+                *next_client_order_id += 1;
+                client_order_id
+            })
+        })
+        .collect();
+
+    let instruction: Instruction = batch_update_instruction(
+        market,
+        owner,
+        wrapper_state,
+        cancel_client_order_ids
+            .iter()
+            .map(|client_order_id| WrapperCancelOrderParams::new(*client_order_id))
+            .collect(),
+        cancel_all,
+        orders
+            .iter()
+            .zip(client_order_ids.iter())
+            .map(|(order, client_order_id)| {
+                WrapperPlaceOrderParams::from_core(*client_order_id, &order.params)
+            })
+            .collect(),
+    );
+    (instruction, client_order_ids)
+}
+
+#[test]
+fn test_batch_update_with_client_order_ids() {
+    use borsh::BorshDeserialize;
+    use manifest::state::{OrderType, NO_EXPIRATION_LAST_VALID_SLOT};
+
+    let place = |client_order_id: Option<u64>| ClientPlaceOrderParams {
+        client_order_id,
+        params: PlaceOrderParams::new(
+            1_000,
+            5,
+            -1,
+            true,
+            OrderType::Limit,
+            NO_EXPIRATION_LAST_VALID_SLOT,
+        ),
+    };
+    let mut next_client_order_id: u64 = 7;
+    let (instruction, client_order_ids) = batch_update_with_client_order_ids_instruction(
+        &Pubkey::new_unique(),
+        &Pubkey::new_unique(),
+        &Pubkey::new_unique(),
+        &[3],
+        false,
+        &[place(None), place(Some(8)), place(None)],
+        &mut next_client_order_id,
+    );
+    assert_eq!(client_order_ids, vec![7, 8, 9]);
+    assert_eq!(next_client_order_id, 10);
+
+    let params: WrapperBatchUpdateParams =
+        WrapperBatchUpdateParams::try_from_slice(&instruction.data[1..]).unwrap();
+    assert_eq!(params.cancels.len(), 1);
+    assert_eq!(params.cancels[0].get_client_order_id(), 3);
+    assert_eq!(
+        params
+            .orders
+            .iter()
+            .map(|order| order.get_client_order_id())
+            .collect::<Vec<u64>>(),
+        client_order_ids
+    );
+}
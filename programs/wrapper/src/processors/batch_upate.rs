@@ -73,6 +73,22 @@ impl WrapperPlaceOrderParams {
             order_type,
         }
     }
+
+    pub fn from_core(client_order_id: u64, params: &PlaceOrderParams) -> Self {
+        WrapperPlaceOrderParams {
+            client_order_id,
+            base_atoms: params.base_atoms(),
+            price_mantissa: params.price_mantissa(),
+            price_exponent: params.price_exponent(),
+            is_bid: params.is_bid(),
+            last_valid_slot: params.last_valid_slot(),
+            order_type: params.order_type(),
+        }
+    }
+
+    pub fn get_client_order_id(&self) -> u64 {
+        self.client_order_id
+    }
 }
 
 // TODO: Note that this does not cancel reverse orders which have been created
@@ -85,6 +101,10 @@ impl WrapperCancelOrderParams {
     pub fn new(client_order_id: u64) -> Self {
         WrapperCancelOrderParams { client_order_id }
     }
+
+    pub fn get_client_order_id(&self) -> u64 {
+        self.client_order_id
+    }
 }
 
 #[derive(BorshDeserialize, BorshSerialize)]